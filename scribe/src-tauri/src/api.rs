@@ -211,6 +211,49 @@ pub async fn chat_stream(
     system_prompt: Option<String>,
     image_base64: Option<serde_json::Value>,
     history: Option<String>,
+) -> Result<String, String> {
+    // Prepare chat request
+    let chat_request = ChatRequest {
+        user_message,
+        system_prompt,
+        image_base64,
+        history,
+    };
+
+    let full_response = send_chat_request(&app, &chat_request, |content| {
+        let _ = app.emit("chat_stream_chunk", content);
+    })
+    .await?;
+
+    // Emit completion event
+    let _ = app.emit("chat_stream_complete", &full_response);
+
+    Ok(full_response)
+}
+
+/// Complete a chat with the selected model without emitting stream events to the webview
+pub async fn chat_completion(
+    app: &AppHandle,
+    user_message: String,
+    system_prompt: Option<String>,
+    history: Option<String>,
+) -> Result<String, String> {
+    let chat_request = ChatRequest {
+        user_message,
+        system_prompt,
+        image_base64: None,
+        history,
+    };
+
+    send_chat_request(app, &chat_request, |_| {}).await
+}
+
+/// Send a chat request to the backend and collect the streamed reply,
+/// passing each chunk to `on_chunk` as it arrives
+async fn send_chat_request(
+    app: &AppHandle,
+    chat_request: &ChatRequest,
+    mut on_chunk: impl FnMut(&str),
 ) -> Result<String, String> {
     // Get environment variables
     let app_endpoint = get_app_endpoint()?;
@@ -222,14 +265,6 @@ pub async fn chat_stream(
         (Some(m.provider.clone()), Some(m.model.clone()))
     });
 
-    // Prepare chat request
-    let chat_request = ChatRequest {
-        user_message,
-        system_prompt,
-        image_base64,
-        history,
-    };
-
     // Make HTTP request to chat endpoint with streaming
     let client = reqwest::Client::new();
     let url = format!("{}/api/v1/chat?stream=true", app_endpoint);
@@ -243,7 +278,7 @@ pub async fn chat_stream(
         .header("provider", &provider.unwrap_or("None".to_string()))
         .header("model", &model.unwrap_or("None".to_string()))
         .header("machine_id", &machine_id)
-        .json(&chat_request)
+        .json(chat_request)
         .send()
         .await
         .map_err(|e| {
@@ -369,7 +404,7 @@ pub async fn chat_stream(
 
                             if let Some(content) = find_text(&parsed) {
                                 full_response.push_str(&content);
-                                let _ = app.emit("chat_stream_chunk", &content);
+                                on_chunk(&content);
                             }
                         }
                         }
@@ -385,9 +420,6 @@ pub async fn chat_stream(
         }
     }
 
-    // Emit completion event
-    let _ = app.emit("chat_stream_complete", &full_response);
-
     Ok(full_response)
 }
 
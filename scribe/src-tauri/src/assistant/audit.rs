@@ -1,11 +1,13 @@
+use crate::assistant::store;
 use crate::assistant::types::*;
+use rusqlite::{params, Connection, OptionalExtension};
 use sha2::{Sha256, Digest};
 use serde_json;
 use tauri::AppHandle;
 
 /// Audit log manager
-/// Note: Database operations are handled via IPC from frontend
-/// This is a simplified implementation that will be called from IPC commands
+/// Entries are chained: each row stores the signature of the previous row
+/// in `prev_hash`, and its own signature covers its entry and that hash.
 pub struct AuditLog {
     app: AppHandle,
 }
@@ -16,11 +18,17 @@ impl AuditLog {
     }
 
     /// Get the hash of the last audit log entry (for chaining)
-    /// This reads from a local file cache
-    async fn get_last_hash(&self) -> Result<String, String> {
-        // For now, return empty string - actual hash chain will be maintained in database
-        // which is accessed via frontend IPC
-        Ok("".to_string())
+    fn get_last_hash(conn: &Connection) -> Result<String, String> {
+        let last: Option<String> = conn
+            .query_row(
+                "SELECT signature FROM audit_logs ORDER BY rowid DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to read last audit entry: {}", e))?;
+
+        Ok(last.unwrap_or_default())
     }
 
     /// Generate signature for audit entry (simple hash for MVP)
@@ -48,36 +56,182 @@ impl AuditLog {
             })
         };
 
-        let entry_str = serde_json::to_string(&entry_json)
+        self.append_json(&entry_json, &action_plan.schema.id)
+    }
+
+    /// Record that a previously executed plan was undone
+    pub async fn append_undo(
+        &self,
+        action_plan: &ActionPlan,
+        restored_paths: &[String],
+    ) -> Result<String, String> {
+        let entry_json = serde_json::json!({
+            "undo": {
+                "plan_id": action_plan.schema.id,
+                "restored_paths": restored_paths,
+            },
+        });
+
+        self.append_json(&entry_json, &action_plan.schema.id)
+    }
+
+    /// Find the most recent successful execution of a plan.
+    /// Returns `None` if the plan was never executed or has already been undone.
+    pub async fn find_execution(
+        &self,
+        plan_id: &str,
+    ) -> Result<Option<(ActionPlan, ActionResult)>, String> {
+        let conn = store::open(&self.app)?;
+
+        let mut stmt = conn
+            .prepare("SELECT entry_json FROM audit_logs WHERE action_id = ?1 ORDER BY rowid DESC")
+            .map_err(|e| format!("Failed to query audit log: {}", e))?;
+
+        let rows = stmt
+            .query_map(params![plan_id], |row| row.get::<_, String>(0))
+            .map_err(|e| format!("Failed to query audit log: {}", e))?;
+
+        for row in rows {
+            let entry_str = row.map_err(|e| format!("Failed to read audit entry: {}", e))?;
+            let entry: serde_json::Value = serde_json::from_str(&entry_str)
+                .map_err(|e| format!("Failed to parse audit entry: {}", e))?;
+
+            // Newest entry wins: an undo means there is nothing left to undo
+            if entry.get("undo").is_some() {
+                return Ok(None);
+            }
+
+            let result: Option<ActionResult> = entry
+                .get("result")
+                .and_then(|r| serde_json::from_value(r.clone()).ok());
+
+            if let Some(result) = result.filter(|r| r.success) {
+                let plan: ActionPlan = serde_json::from_value(entry["action_plan"].clone())
+                    .map_err(|e| format!("Failed to parse audited plan: {}", e))?;
+                return Ok(Some((plan, result)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Chain and insert a serialized entry
+    fn append_json(&self, entry_json: &serde_json::Value, action_id: &str) -> Result<String, String> {
+        let entry_str = serde_json::to_string(entry_json)
             .map_err(|e| format!("Failed to serialize entry: {}", e))?;
 
-        let prev_hash = self.get_last_hash().await?;
+        let mut conn = store::open(&self.app)?;
+
+        // Read the previous hash and insert in one transaction so concurrent
+        // writers cannot fork the chain
+        let tx = conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(|e| format!("Failed to start audit transaction: {}", e))?;
+
+        let prev_hash = Self::get_last_hash(&tx)?;
         let signature = Self::generate_signature(&entry_str, &prev_hash);
         let timestamp = chrono::Utc::now().timestamp();
         let id = uuid::Uuid::new_v4().to_string();
 
-        // Database insertion will be handled by frontend via IPC
-        // This function just prepares the entry
-        // The actual database write happens in the IPC command handler
+        tx.execute(
+            "INSERT INTO audit_logs (id, entry_json, timestamp, prev_hash, signature, action_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![id, entry_str, timestamp, prev_hash, signature, action_id],
+        )
+        .map_err(|e| format!("Failed to insert audit entry: {}", e))?;
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit audit entry: {}", e))?;
 
         Ok(id)
     }
 
-    /// Get audit history
-    /// Note: Actual database query is handled by frontend
-    /// This is a placeholder that returns empty vec
-    pub async fn get_history(&self, _limit: i32) -> Result<Vec<AuditEntry>, String> {
-        // Database query will be handled by frontend via IPC
-        // Return empty for now - frontend will handle the actual query
-        Ok(vec![])
+    /// Get audit history (newest first)
+    pub async fn get_history(&self, limit: i32) -> Result<Vec<AuditEntry>, String> {
+        let conn = store::open(&self.app)?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, entry_json, timestamp, prev_hash, signature, action_id
+                 FROM audit_logs ORDER BY rowid DESC LIMIT ?1",
+            )
+            .map_err(|e| format!("Failed to query audit history: {}", e))?;
+
+        let rows = stmt
+            .query_map(params![limit], Self::entry_from_row)
+            .map_err(|e| format!("Failed to query audit history: {}", e))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read audit entry: {}", e))
+    }
+
+    /// Verify audit log integrity (walk the hash chain from the first entry)
+    pub async fn verify_integrity(&self) -> Result<AuditIntegrityReport, String> {
+        let conn = store::open(&self.app)?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, entry_json, timestamp, prev_hash, signature, action_id
+                 FROM audit_logs ORDER BY rowid ASC",
+            )
+            .map_err(|e| format!("Failed to query audit log: {}", e))?;
+
+        let rows = stmt
+            .query_map([], Self::entry_from_row)
+            .map_err(|e| format!("Failed to query audit log: {}", e))?;
+
+        let mut expected_prev = String::new();
+        let mut entries_checked = 0;
+
+        for row in rows {
+            let entry = row.map_err(|e| format!("Failed to read audit entry: {}", e))?;
+            entries_checked += 1;
+
+            if entry.prev_hash != expected_prev {
+                return Ok(AuditIntegrityReport::broken(
+                    entries_checked,
+                    entry.id,
+                    "prev_hash does not match the signature of the preceding entry",
+                ));
+            }
+
+            if Self::generate_signature(&entry.entry_json, &entry.prev_hash) != entry.signature {
+                return Ok(AuditIntegrityReport::broken(
+                    entries_checked,
+                    entry.id,
+                    "signature does not match entry contents",
+                ));
+            }
+
+            expected_prev = entry.signature;
+        }
+
+        Ok(AuditIntegrityReport {
+            valid: true,
+            entries_checked,
+            first_broken_entry: None,
+            reason: None,
+        })
     }
 
-    /// Verify audit log integrity (check hash chain)
-    /// Note: Actual verification is handled by frontend
-    pub async fn verify_integrity(&self) -> Result<bool, String> {
-        // Database query will be handled by frontend
-        // Return true as placeholder
-        Ok(true)
+    fn entry_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<AuditEntry> {
+        Ok(AuditEntry {
+            id: row.get(0)?,
+            entry_json: row.get(1)?,
+            timestamp: row.get(2)?,
+            prev_hash: row.get(3)?,
+            signature: row.get(4)?,
+            action_id: row.get(5)?,
+        })
     }
 }
 
+impl AuditIntegrityReport {
+    fn broken(entries_checked: usize, entry_id: String, reason: &str) -> Self {
+        Self {
+            valid: false,
+            entries_checked,
+            first_broken_entry: Some(entry_id),
+            reason: Some(reason.to_string()),
+        }
+    }
+}
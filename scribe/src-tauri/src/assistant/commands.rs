@@ -1,72 +1,80 @@
 use crate::assistant::*;
-use crate::assistant::planner::{deterministic, verifier};
-use crate::assistant::executor::worker;
+use crate::assistant::planner::{deterministic, llm, selector, verifier};
+use crate::assistant::executor::{archive, dry_run, edit, worker};
 use crate::assistant::policy;
+use crate::assistant::references;
+use crate::assistant::sandbox;
+use crate::assistant::validator;
 use crate::assistant::audit::AuditLog;
+use crate::assistant::settings::{self, AssistantSettings};
+use crate::assistant::store;
+use crate::assistant::schema;
+use std::path::PathBuf;
 use tauri::AppHandle;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 
 /// Parse user intent using deterministic parser
 #[tauri::command]
 pub async fn parse_intent(
-    _app: AppHandle,
+    app: AppHandle,
     user_input: String,
 ) -> Result<ActionPlan, String> {
-    let user_home = dirs::home_dir()
-        .ok_or("Failed to get user home directory".to_string())?;
+    let resolver = settings::path_resolver(&app)?;
 
-    deterministic::parse_intent(&user_input, &user_home)
+    deterministic::parse_intent(&user_input, &resolver)
 }
 
-/// Validate an LLM-generated action plan (LLM planning happens in frontend)
+/// Plan a request with the configured chat model, then verify the plan
 #[tauri::command]
 pub async fn plan_with_llm(
-    _app: AppHandle,
-    plan: ActionPlan, // LLM-generated plan from frontend
+    app: AppHandle,
+    user_input: String,
+    source: Option<ActionSource>,
+    history: Option<String>,
 ) -> Result<VerifiedPlan, String> {
-    // Validate the LLM-generated plan
-    let user_home = dirs::home_dir()
-        .ok_or("Failed to get user home directory".to_string())?;
+    let plan = llm::plan(&app, &user_input, source.unwrap_or(ActionSource::Ui), history).await?;
+    let ctx = settings::execution_context(&app)?;
 
-    verifier::verify_action_plan(&plan, &user_home)
+    verifier::verify_action_plan(&plan, &ctx)
 }
 
 /// Verify an action plan
 #[tauri::command]
 pub async fn verify_action_plan(
-    _app: AppHandle,
+    app: AppHandle,
     plan: ActionPlan,
 ) -> Result<VerifiedPlan, String> {
-    let user_home = dirs::home_dir()
-        .ok_or("Failed to get user home directory".to_string())?;
+    let ctx = settings::execution_context(&app)?;
 
-    verifier::verify_action_plan(&plan, &user_home)
+    verifier::verify_action_plan(&plan, &ctx)
 }
 
 /// Preview an action plan (returns preview with risk score)
 #[tauri::command]
 pub async fn preview_action_plan(
-    _app: AppHandle,
+    app: AppHandle,
     plan: ActionPlan,
 ) -> Result<PreviewResult, String> {
-    let user_home = dirs::home_dir()
-        .ok_or("Failed to get user home directory".to_string())?;
+    let ctx = settings::execution_context(&app)?;
 
-    // Verify the plan
-    let verified = verifier::verify_action_plan(&plan, &user_home)?;
+    // Verify the plan, expanding selectors into one action per file
+    let verified = verifier::verify_action_plan(&plan, &ctx)?;
+    let actions = &verified.plan.schema.actions;
 
     // Build preview result
     let mut affected_items = Vec::new();
     let mut missing_paths = Vec::new();
-    let warnings: Vec<String> = verified.verification_notes.clone();
+    let mut warnings: Vec<String> = verified.verification_notes.clone();
+    warnings.extend(sandbox::confinement_warning());
 
-    for action in &plan.schema.actions {
+    for action in actions {
         let mut needs_path = false;
         
         if let Some(path_value) = action.args.get("path") {
             if let Some(path_str) = path_value.as_str() {
                 if path_str == "__PROMPT_PATH__" {
                     needs_path = true;
-                    missing_paths.push(action.id.clone());
+                    missing_paths.push(missing_path(action, "path"));
                     affected_items.push(AffectedItem {
                         path: "[Path needed]".to_string(),
                         operation: format!("{:?}", action.action_type),
@@ -76,7 +84,7 @@ pub async fn preview_action_plan(
                     affected_items.push(AffectedItem {
                         path: path_str.to_string(),
                         operation: format!("{:?}", action.action_type),
-                        preview: None,
+                        preview: edit::preview_diff(action, &ctx),
                     });
                 }
             }
@@ -86,7 +94,7 @@ pub async fn preview_action_plan(
             if let Some(dst_path_str) = dst_path_value.as_str() {
                 if dst_path_str == "__PROMPT_PATH__" {
                     needs_path = true;
-                    missing_paths.push(action.id.clone());
+                    missing_paths.push(missing_path(action, "destination_path"));
                     affected_items.push(AffectedItem {
                         path: "[Destination path needed]".to_string(),
                         operation: format!("{:?}", action.action_type),
@@ -96,7 +104,7 @@ pub async fn preview_action_plan(
                     affected_items.push(AffectedItem {
                         path: dst_path_str.to_string(),
                         operation: format!("{:?}", action.action_type),
-                        preview: None,
+                        preview: archive::preview_listing(action, &ctx),
                     });
                 }
             }
@@ -106,61 +114,190 @@ pub async fn preview_action_plan(
             if let Some(src_path_str) = src_path_value.as_str() {
                 if src_path_str == "__PROMPT_PATH__" {
                     needs_path = true;
-                    missing_paths.push(action.id.clone());
+                    missing_paths.push(missing_path(action, "source_path"));
                 }
             }
         }
     }
 
-    // Check if requires explicit confirmation (high risk or delete operation)
-    let requires_explicit_confirmation = plan.schema.risk_score > 0.7
-        || plan.schema.actions.iter().any(|a| matches!(a.action_type, ActionType::FsDeleteFile))
-        || !missing_paths.is_empty(); // Also require confirmation if paths missing
+    // Deleting without the trash must never happen silently
+    for action in actions {
+        let permanent = action.args.get("permanent").and_then(|v| v.as_bool()).unwrap_or(false);
+        if permanent && matches!(action.action_type, ActionType::FsDeleteFile | ActionType::FsDeleteDirectory) {
+            let path = action.args.get("path").and_then(|v| v.as_str()).unwrap_or("[Path needed]");
+            warnings.push(format!("{} will be permanently deleted instead of moved to the trash", path));
+        }
+    }
+
+    // Show what each use of an earlier step's output stands for as the disk is now
+    let references = dry_run::preview_references(&verified.plan, &ctx);
+
+    // Flag every destination that already exists, with what its step will do about it
+    let conflicts = dry_run::preview_conflicts(&verified.plan, &ctx);
+
+    // Paths outside the allowed folders the user may approve for this plan only
+    let mut escalations: Vec<String> = Vec::new();
+    for action in &references::resolve_statically(&verified.plan).schema.actions {
+        for (arg, access) in validator::path_accesses(&action.action_type) {
+            let Some(path_str) = action.args.get(*arg).and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let known = path_str != "__PROMPT_PATH__" && !references::has_references(action);
+            if known && validator::needs_escalation(&ctx, path_str, *access) && !escalations.iter().any(|p| p == path_str) {
+                escalations.push(path_str.to_string());
+            }
+        }
+    }
+
+    // Also require confirmation if paths missing
+    let requires_explicit_confirmation = needs_explicit_confirmation(&verified, &ctx) || !missing_paths.is_empty();
 
     Ok(PreviewResult {
-        plan,
         risk_score: verified.plan.schema.risk_score,
+        approval: verified.approval,
+        plan: verified.plan,
         affected_items,
-        warnings: verified.verification_notes,
+        warnings,
         requires_explicit_confirmation,
         missing_paths,
+        references,
+        conflicts,
+        escalations,
     })
 }
 
+/// Check if requires explicit confirmation (high risk or delete operation), or if any path needs escalation.
+/// Paths only known at run time cannot be checked yet, so they are judged by their folder.
+fn needs_explicit_confirmation(verified: &VerifiedPlan, ctx: &ExecutionContext) -> bool {
+    let resolved = references::resolve_statically(&verified.plan);
+
+    verified.approval >= Approval::ExplicitConfirmation
+        || !verified.plan.schema.escalated_paths.is_empty()
+        || verified.plan.schema.actions.iter().any(|a| matches!(a.action_type, ActionType::FsDeleteFile | ActionType::FsDeleteDirectory))
+        || resolved.schema.actions.iter().any(|a| validator::check_action_paths(a, ctx).is_err())
+}
+
+/// A placeholder path arg, with any candidates the planner matched for it
+fn missing_path(action: &Action, arg: &str) -> MissingPath {
+    let candidates = action
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.path_candidates.as_ref())
+        .and_then(|candidates| candidates.get(arg))
+        .cloned()
+        .unwrap_or_default();
+
+    MissingPath {
+        action_id: action.id.clone(),
+        arg: arg.to_string(),
+        candidates,
+    }
+}
+
 /// Execute an action plan
 #[tauri::command]
 pub async fn execute_action_plan(
     app: AppHandle,
     plan: ActionPlan,
     confirm_token: Option<String>,
+    confirmation: Option<String>,
 ) -> Result<ActionResult, String> {
-    let user_home = dirs::home_dir()
-        .ok_or("Failed to get user home directory".to_string())?;
+    let ctx = settings::execution_context(&app)?;
 
     // Verify plan before execution
-    verifier::verify_action_plan(&plan, &user_home)?;
+    let verified = verifier::verify_action_plan(&plan, &ctx)?;
+
+    // A supplied token is always checked, so a dry run cannot hide that it is revoked or too narrow
+    let token = match &confirm_token {
+        Some(token) => Some(policy::validate_plan_token(&store::open(&app)?, &verified.plan, token)?),
+        None => None,
+    };
+
+    // A dry run only simulates the plan, so it needs no approval and is not audited
+    if plan.schema.dry_run {
+        return Ok(dry_run::simulate_plan(&verified.plan, &ctx, token.as_ref()));
+    }
+
+    // Bulk actions run only as the file list the user saw in the preview
+    if selector::has_selectors(&plan) {
+        return Err("This plan selects files by pattern; preview it first so every file is listed before it runs".to_string());
+    }
+
+    // Critical plans not typed by the user need a valid capability token covering every action
+    if verified.approval == Approval::CapabilityToken && token.is_none() {
+        return Err(format!(
+            "This plan is critical risk ({:.2}) and came from {:?} input; it needs a capability token to run",
+            verified.plan.schema.risk_score, plan.schema.origin.source
+        ));
+    }
+
+    // Plans the preview asked the user to type out run only with the summary they typed
+    let confirmed = confirmation.is_some_and(|text| text.to_lowercase() == verified.plan.schema.summary.to_lowercase());
+    if needs_explicit_confirmation(&verified, &ctx) && !confirmed {
+        return Err("This plan needs explicit confirmation; type its summary to approve it".to_string());
+    }
+
+    // Paths outside the allowed folders are only usable once approved outside the webview
+    let escalated = &verified.plan.schema.escalated_paths;
+    if !escalated.is_empty() {
+        for path in escalated {
+            settings::check_allowed_root(&app, path)?;
+        }
+        let message = format!(
+            "Let this plan change files outside the allowed folders?\n\n{}",
+            escalated.join("\n")
+        );
+        if !user_confirms(&app, "Allow folders for this plan?", message).await {
+            return Err("The paths outside the allowed folders were not approved".to_string());
+        }
+    }
+    let ctx = ctx.with_escalations(escalated.iter().map(PathBuf::from).collect());
 
-    // Execute the plan
-    let result = worker::execute_action_plan(&plan, confirm_token, user_home.clone()).await?;
+    // Execute the plan in the verified order, so steps run after the steps whose output they use
+    let plan = verified.plan;
+    let mut outcome = worker::execute_action_plan(&app, &plan, confirm_token, ctx).await;
 
-    // Prepare audit log entry (frontend will save to database)
-    // The audit log entry is returned in the result for frontend to persist
-    let _audit_log = AuditLog::new(app.clone());
-    // Frontend will handle saving to database via database actions
+    // Record every execution attempt (including failures) in the audit chain
+    let audit_log = AuditLog::new(app.clone());
+    match &mut outcome {
+        Ok(result) => {
+            // The changes are on disk either way; undo finds the plan through its audit entry
+            if let Err(audit_err) = audit_log.append_entry(&plan, Some(result)).await {
+                eprintln!("Failed to record audit entry: {}", audit_err);
+                result.undo_available = false;
+                result.undo_ttl = None;
+                result.error = Some(format!("The plan ran but could not be recorded, so it cannot be undone: {}", audit_err));
+            }
+        }
+        Err(e) => {
+            let failed = ActionResult {
+                action_id: plan.schema.id.clone(),
+                success: false,
+                executed_at: chrono::Utc::now().timestamp(),
+                results: Vec::new(),
+                error: Some(e.clone()),
+                undo_available: false,
+                undo_ttl: None,
+            };
+            if let Err(audit_err) = audit_log.append_entry(&plan, Some(&failed)).await {
+                eprintln!("Failed to record audit entry: {}", audit_err);
+            }
+        }
+    }
 
-    Ok(result)
+    outcome
 }
 
 /// Undo an action
 #[tauri::command]
 pub async fn undo_action(
-    _app: AppHandle,
+    app: AppHandle,
     action_id: String,
 ) -> Result<(), String> {
     let user_home = dirs::home_dir()
         .ok_or("Failed to get user home directory".to_string())?;
 
-    worker::undo_action(&action_id, user_home).await
+    worker::undo_action(&app, &action_id, user_home).await
 }
 
 /// Get audit history
@@ -169,27 +306,118 @@ pub async fn get_audit_history(
     app: AppHandle,
     limit: i32,
 ) -> Result<Vec<AuditEntry>, String> {
-    // Audit history is fetched via frontend database actions
-    // This returns empty - frontend should use getAuditLogsFromDB() instead
-    Ok(vec![])
+    AuditLog::new(app).get_history(limit).await
+}
+
+/// Verify the audit log hash chain
+#[tauri::command]
+pub async fn verify_audit_integrity(
+    app: AppHandle,
+) -> Result<AuditIntegrityReport, String> {
+    AuditLog::new(app).verify_integrity().await
 }
 
-/// Mint a capability token
+/// Mint a capability token, once the user grants its scopes in a native dialog.
+/// The webview can only ask, so it cannot approve its own critical plans.
 #[tauri::command]
 pub async fn mint_capability_token(
-    _app: AppHandle,
+    app: AppHandle,
     scopes: Vec<String>,
     ttl_seconds: i32,
     session_id: String,
 ) -> Result<String, String> {
-    let token = policy::mint_capability_token(
+    if !user_grants_token(&app, &scopes, ttl_seconds).await {
+        return Err("The capability token was not granted".to_string());
+    }
+
+    let conn = store::open(&app)?;
+    let (token_string, _token) = policy::mint_capability_token(
+        &conn,
         scopes,
         ttl_seconds as i64,
         session_id,
     )?;
 
-    // Return token as JSON string (will be encoded in frontend)
-    serde_json::to_string(&token)
-        .map_err(|e| format!("Failed to serialize token: {}", e))
+    // Signed JWT, passed back unchanged as `confirm_token` on execution
+    Ok(token_string)
+}
+
+/// Ask the user to grant a token's scopes, outside the webview
+async fn user_grants_token(app: &AppHandle, scopes: &[String], ttl_seconds: i32) -> bool {
+    let message = format!(
+        "The assistant asks to run critical actions for {} seconds with these permissions:\n\n{}",
+        ttl_seconds,
+        scopes.join("\n")
+    );
+    user_confirms(app, "Grant assistant permissions?", message).await
+}
+
+/// Ask the user in a native dialog the webview cannot answer for them
+async fn user_confirms(app: &AppHandle, title: &str, message: String) -> bool {
+    let (granted_tx, granted_rx) = tokio::sync::oneshot::channel();
+
+    app.dialog()
+        .message(message)
+        .title(title)
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom("Grant".to_string(), "Deny".to_string()))
+        .show(move |granted| {
+            let _ = granted_tx.send(granted);
+        });
+
+    granted_rx.await.unwrap_or(false)
 }
 
+/// Revoke a capability token by nonce
+#[tauri::command]
+pub async fn revoke_capability_token(
+    app: AppHandle,
+    nonce: String,
+) -> Result<(), String> {
+    let conn = store::open(&app)?;
+    policy::revoke_token_nonce(&conn, &nonce)
+}
+
+/// JSON Schema of Action Schema v2 plans, for tools that produce plans
+#[tauri::command]
+pub async fn get_action_plan_schema() -> Result<serde_json::Value, String> {
+    Ok(schema::action_plan_schema())
+}
+
+/// Get assistant settings
+#[tauri::command]
+pub async fn get_assistant_settings(
+    app: AppHandle,
+) -> Result<AssistantSettings, String> {
+    settings::load(&app)
+}
+
+/// Update assistant settings
+#[tauri::command]
+pub async fn update_assistant_settings(
+    app: AppHandle,
+    settings: AssistantSettings,
+) -> Result<(), String> {
+    // Widening where the assistant may write is refused for risky folders and confirmed natively
+    let current = settings::load(&app)?;
+    let added: Vec<&String> = settings
+        .allowed_roots
+        .iter()
+        .filter(|root| !current.allowed_roots.contains(root))
+        .collect();
+
+    if !added.is_empty() {
+        for root in &added {
+            settings::check_allowed_root(&app, root)?;
+        }
+        let message = format!(
+            "Let the assistant change files in these folders?\n\n{}",
+            added.iter().map(|root| root.as_str()).collect::<Vec<_>>().join("\n")
+        );
+        if !user_confirms(&app, "Allow assistant folders?", message).await {
+            return Err("The new allowed folders were not confirmed".to_string());
+        }
+    }
+
+    settings::save(&app, &settings)
+}
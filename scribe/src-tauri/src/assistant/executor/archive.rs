@@ -0,0 +1,960 @@
+use crate::assistant::executor::fs_adapter;
+use crate::assistant::glob;
+use crate::assistant::schema::{self, ArchiveCreateArgs, ArchiveExtractArgs};
+use crate::assistant::types::*;
+use crate::assistant::validator::{check_access, check_no_protected_inside, PathAccess};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{self, Read};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+/// Entries named in the preview of an extraction
+const MAX_PREVIEW_ENTRIES: usize = 50;
+
+/// Entries smaller than this are not checked for their compression ratio;
+/// small runs of repeated bytes compress far beyond any sane ratio
+const RATIO_MIN_BYTES: u64 = 1024 * 1024;
+
+/// Tar headers and padding a decompressed stream may hold per entry, beyond the file data
+const TAR_OVERHEAD_PER_ENTRY: u64 = 4096;
+
+/// Archive format, named in the `format` arg
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum Format {
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz", alias = "tgz")]
+    TarGz,
+}
+
+impl Format {
+    /// The format named by `format`, or else by the archive's extension
+    pub fn of(format: Option<Format>, archive: &str) -> Result<Self, String> {
+        let lower = archive.to_lowercase();
+        match format {
+            Some(format) => Ok(format),
+            None if lower.ends_with(".zip") => Ok(Format::Zip),
+            None if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") => Ok(Format::TarGz),
+            None => Err(format!(
+                "Cannot tell the archive format of {}; use .zip, .tar.gz or .tgz, or set format",
+                archive
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::TarGz => "tar.gz",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    /// Symlinks, hard links and devices are never unpacked
+    Skipped,
+}
+
+/// One archive entry, with a path checked to stay inside the destination
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub size: u64,
+}
+
+/// Pack a file or directory into an archive (the executor snapshots an archive it replaces)
+pub fn create(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: ArchiveCreateArgs = schema::parse_args(action)?;
+    let source_str = args.source_path.as_str();
+    let destination_str = args.destination_path.as_str();
+
+    // Refuse protected paths (including inside the tree) and writes outside the allowed roots
+    check_access(ctx, source_str, PathAccess::Read)?;
+    check_no_protected_inside(ctx, source_str)?;
+    check_access(ctx, destination_str, PathAccess::Write)?;
+
+    let source = PathBuf::from(source_str);
+    let destination = PathBuf::from(destination_str);
+
+    if !source.exists() {
+        return Err(format!("Source does not exist: {}", source_str));
+    }
+
+    let target = fs_adapter::settle_conflict(&destination, OnConflict::of(action)?, |p| p.symlink_metadata().is_ok())?;
+    if target.skipped() {
+        return Ok(fs_adapter::skipped_transfer(action, source_str, destination_str));
+    }
+    let destination = target.path;
+    if destination.is_dir() {
+        return Err(format!("Destination is a directory: {}", destination_str));
+    }
+    if source.is_dir() && destination.starts_with(&source) {
+        return Err(format!("The archive cannot be written inside {}, which it packs", source_str));
+    }
+
+    let format = Format::of(args.format, destination_str)?;
+    let include = args
+        .include
+        .iter()
+        .flatten()
+        .map(|pattern| glob::compile(pattern))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (members, skipped) = collect_members(&source, &include, &ctx.directory_limits)?;
+    let bytes: u64 = members.iter().map(|member| member.size).sum();
+
+    let created_dirs = match destination.parent() {
+        Some(parent) => fs_adapter::create_dirs(parent)?,
+        None => Vec::new(),
+    };
+
+    let file = fs::File::create(&destination)
+        .map_err(|e| format!("Failed to create archive: {}", e))?;
+    match format {
+        Format::Zip => write_zip(file, &members),
+        Format::TarGz => write_tar_gz(file, &members),
+    }
+    .map_err(|e| format!("Failed to write archive: {}", e))?;
+
+    let size = fs::metadata(&destination)
+        .map_err(|e| format!("Failed to read archive metadata: {}", e))?
+        .len();
+
+    let written = destination.to_string_lossy();
+    Ok(ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(serde_json::json!({
+            "source": source_str,
+            "destination": written,
+            "path": written,
+            "conflict": target.conflict.map(OnConflict::name),
+            "format": format.name(),
+            "entries": members.len(),
+            "bytes": bytes,
+            "size": size,
+            "skipped": skipped,
+            "created_dirs": created_dirs,
+        })),
+        error: None,
+        snapshot_id: None,
+    })
+}
+
+/// Unpack an archive into a directory. The whole archive is checked before anything is written,
+/// and the bytes actually unpacked are counted again against the limits as they are written.
+/// Files it replaces were snapshotted by the executor.
+pub fn extract(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: ArchiveExtractArgs = schema::parse_args(action)?;
+    let source_str = args.source_path.as_str();
+    let destination_str = args.destination_path.as_str();
+
+    // Refuse protected paths and writes outside the allowed roots
+    check_access(ctx, source_str, PathAccess::Read)?;
+    check_access(ctx, destination_str, PathAccess::Write)?;
+    check_no_protected_inside(ctx, destination_str)?;
+
+    let archive = PathBuf::from(source_str);
+    let destination = PathBuf::from(destination_str);
+
+    if !archive.is_file() {
+        return Err(format!("Archive does not exist: {}", source_str));
+    }
+    if destination.is_file() {
+        return Err(format!("Destination is a file: {}", destination_str));
+    }
+
+    let format = Format::of(args.format, source_str)?;
+    let limits = &ctx.archive_limits;
+    let entries = list_entries(&archive, format, limits)?;
+    check_entry_targets(ctx, &destination, &entries)?;
+
+    let created_dirs = fs_adapter::create_dirs(&destination)?;
+    let mut unpacked = Unpacked::default();
+
+    let open = || fs::File::open(&archive).map_err(|e| format!("Failed to open archive: {}", e));
+    match format {
+        Format::Zip => {
+            let mut zip = zip::ZipArchive::new(open()?)
+                .map_err(|e| format!("Failed to read archive: {}", e))?;
+            for index in 0..zip.len() {
+                let mut entry = zip
+                    .by_index(index)
+                    .map_err(|e| format!("Failed to read archive: {}", e))?;
+                let name = entry.name().to_string();
+                let kind = zip_kind(&entry);
+                let mode = entry.unix_mode();
+                let compressed = entry.compressed_size();
+                let written = unpack_entry(&destination, &name, kind, &mut entry, mode, limits, &mut unpacked)?;
+                check_ratio(&name, written, compressed, limits)?;
+            }
+        }
+        Format::TarGz => {
+            let mut tar = tar::Archive::new(Bounded::new(GzDecoder::new(open()?), tar_budget(limits)));
+            for entry in tar.entries().map_err(|e| format!("Failed to read archive: {}", e))? {
+                let mut entry = entry.map_err(|e| format!("Failed to read archive: {}", e))?;
+                let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+                let kind = tar_kind(entry.header().entry_type());
+                let mode = entry.header().mode().ok();
+                unpack_entry(&destination, &name, kind, &mut entry, mode, limits, &mut unpacked)?;
+            }
+        }
+    }
+
+    Ok(ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(serde_json::json!({
+            "source": source_str,
+            "destination": destination_str,
+            "path": destination_str,
+            "format": format.name(),
+            "entries": entries.len(),
+            "bytes": unpacked.bytes,
+            "extracted": unpacked.files,
+            "overwritten": unpacked.overwritten,
+            "extracted_dirs": unpacked.dirs,
+            "skipped": unpacked.skipped,
+            "created_dirs": created_dirs,
+        })),
+        error: None,
+        snapshot_id: None,
+    })
+}
+
+/// What an extract action is expected to create and replace, in the shape of its output,
+/// so the executor can snapshot replaced files and clean up after a failed or interrupted run
+pub fn planned_extraction(action: &Action, ctx: &ExecutionContext) -> Result<serde_json::Value, String> {
+    let args: ArchiveExtractArgs = schema::parse_args(action)?;
+    let format = Format::of(args.format, &args.source_path)?;
+    let entries = list_entries(Path::new(&args.source_path), format, &ctx.archive_limits)?;
+    let destination = PathBuf::from(&args.destination_path);
+    check_entry_targets(ctx, &destination, &entries)?;
+
+    let mut files = Vec::new();
+    let mut overwritten = Vec::new();
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for entry in &entries {
+        let target = destination.join(&entry.path);
+        let parent = match entry.kind {
+            EntryKind::Directory => Some(target.as_path()),
+            EntryKind::File => target.parent(),
+            EntryKind::Skipped => continue,
+        };
+        for dir in parent.map(fs_adapter::missing_dirs).unwrap_or_default() {
+            if dir.starts_with(&destination) && dir != destination && !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        if entry.kind == EntryKind::File {
+            let target = target.to_string_lossy().to_string();
+            if Path::new(&target).is_file() {
+                overwritten.push(target);
+            } else {
+                files.push(target);
+            }
+        }
+    }
+
+    let created_dirs: Vec<String> = fs_adapter::missing_dirs(&destination)
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+
+    Ok(serde_json::json!({
+        "extracted": files,
+        "overwritten": overwritten,
+        "extracted_dirs": dirs,
+        "created_dirs": created_dirs,
+    }))
+}
+
+/// Contents of the archive an extract action would unpack, for the preview
+pub fn preview_listing(action: &Action, ctx: &ExecutionContext) -> Option<String> {
+    if action.action_type != ActionType::ArchiveExtract {
+        return None;
+    }
+    let args: ArchiveExtractArgs = schema::parse_args(action).ok()?;
+    check_access(ctx, &args.source_path, PathAccess::Read).ok()?;
+
+    let entries = Format::of(args.format, &args.source_path)
+        .and_then(|format| list_entries(Path::new(&args.source_path), format, &ctx.archive_limits));
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => return Some(format!("Archive cannot be extracted: {}", e)),
+    };
+
+    let bytes: u64 = entries.iter().map(|entry| entry.size).sum();
+    let mut lines = vec![format!("{} entries, {} bytes", entries.len(), bytes)];
+    for entry in entries.iter().take(MAX_PREVIEW_ENTRIES) {
+        let path = entry.path.to_string_lossy();
+        lines.push(match entry.kind {
+            EntryKind::File => format!("{} ({} bytes)", path, entry.size),
+            EntryKind::Directory => format!("{}/", path),
+            EntryKind::Skipped => format!("{} (link or special file, skipped)", path),
+        });
+    }
+    if entries.len() > MAX_PREVIEW_ENTRIES {
+        lines.push(format!("... and {} more", entries.len() - MAX_PREVIEW_ENTRIES));
+    }
+    Some(lines.join("\n"))
+}
+
+/// Entries of an archive, refusing unsafe archives: entries with absolute paths or `..`
+/// that would land outside the destination, more entries or bytes than the limits, and
+/// data compressed beyond the maximum ratio. Sizes are as the archive declares them.
+pub fn list_entries(archive: &Path, format: Format, limits: &ArchiveLimits) -> Result<Vec<Entry>, String> {
+    let file = fs::File::open(archive).map_err(|e| format!("Failed to open archive: {}", e))?;
+    let compressed_size = file
+        .metadata()
+        .map_err(|e| format!("Failed to read archive metadata: {}", e))?
+        .len();
+    let mut listing = Listing {
+        entries: Vec::new(),
+        bytes: 0,
+        limits,
+    };
+
+    match format {
+        Format::Zip => {
+            let mut zip = zip::ZipArchive::new(file).map_err(|e| format!("Failed to read archive: {}", e))?;
+            for index in 0..zip.len() {
+                let entry = zip
+                    .by_index_raw(index)
+                    .map_err(|e| format!("Failed to read archive: {}", e))?;
+                check_ratio(entry.name(), entry.size(), entry.compressed_size(), limits)?;
+                listing.add(entry.name(), zip_kind(&entry), entry.size())?;
+            }
+        }
+        Format::TarGz => {
+            // The entries are only reachable by decompressing everything before them
+            let mut tar = tar::Archive::new(Bounded::new(GzDecoder::new(file), tar_budget(limits)));
+            for entry in tar.entries().map_err(|e| format!("Failed to read archive: {}", e))? {
+                let entry = entry.map_err(|e| format!("Failed to read archive: {}", e))?;
+                let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+                listing.add(&name, tar_kind(entry.header().entry_type()), entry.size())?;
+            }
+            check_ratio(&archive.to_string_lossy(), listing.bytes, compressed_size, limits)?;
+        }
+    }
+
+    Ok(listing.entries)
+}
+
+/// Refuse entries that would be written to a protected location below the destination,
+/// like `.ssh/authorized_keys` unpacked into the home directory
+pub fn check_entry_targets(ctx: &ExecutionContext, destination: &Path, entries: &[Entry]) -> Result<(), String> {
+    for entry in entries.iter().filter(|entry| entry.kind != EntryKind::Skipped) {
+        let target = destination.join(&entry.path);
+        check_access(ctx, &target.to_string_lossy(), PathAccess::Write)
+            .map_err(|e| format!("Archive entry {} cannot be extracted: {}", entry.path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Entries listed so far, and their total size, checked against the limits as they are added
+struct Listing<'a> {
+    entries: Vec<Entry>,
+    bytes: u64,
+    limits: &'a ArchiveLimits,
+}
+
+impl Listing<'_> {
+    fn add(&mut self, name: &str, kind: EntryKind, size: u64) -> Result<(), String> {
+        let Some(path) = safe_path(name)? else {
+            return Ok(());
+        };
+
+        if self.entries.len() >= self.limits.max_entries {
+            return Err(format!(
+                "Archive has more than {} entries, which exceeds the archive limit",
+                self.limits.max_entries
+            ));
+        }
+        if kind == EntryKind::File {
+            self.bytes = self.bytes.saturating_add(size);
+            if self.bytes > self.limits.max_bytes {
+                return Err(format!(
+                    "Archive unpacks to more than {} bytes, which exceeds the archive limit",
+                    self.limits.max_bytes
+                ));
+            }
+        }
+
+        self.entries.push(Entry {
+            name: name.to_string(),
+            path,
+            kind,
+            size: if kind == EntryKind::File { size } else { 0 },
+        });
+        Ok(())
+    }
+}
+
+/// The relative path an entry unpacks to, or an error if it would land outside the destination.
+/// None for entries that name the destination itself, like "./".
+fn safe_path(name: &str) -> Result<Option<PathBuf>, String> {
+    // Archives made on Windows may use backslashes
+    let normalized = name.replace('\\', "/");
+    let absolute = || format!("Archive entry '{}' has an absolute path; not extracting", name);
+
+    if normalized.starts_with('/') || normalized.as_bytes().get(1) == Some(&b':') {
+        return Err(absolute());
+    }
+
+    let mut path = PathBuf::new();
+    for component in Path::new(&normalized).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                return Err(format!(
+                    "Archive entry '{}' points outside the destination; not extracting",
+                    name
+                ))
+            }
+            Component::RootDir | Component::Prefix(_) => return Err(absolute()),
+        }
+    }
+
+    Ok((!path.as_os_str().is_empty()).then_some(path))
+}
+
+/// Refuse data that expands more than the maximum ratio, once it is large enough to matter
+fn check_ratio(name: &str, size: u64, compressed: u64, limits: &ArchiveLimits) -> Result<(), String> {
+    if size >= RATIO_MIN_BYTES && size / compressed.max(1) > limits.max_ratio {
+        return Err(format!(
+            "{} expands {} times, more than the limit of {}; it may be a zip bomb",
+            name,
+            size / compressed.max(1),
+            limits.max_ratio
+        ));
+    }
+    Ok(())
+}
+
+/// Most bytes a tar stream may decompress to: the data limit plus room for headers
+fn tar_budget(limits: &ArchiveLimits) -> u64 {
+    limits
+        .max_bytes
+        .saturating_add((limits.max_entries as u64 + 1).saturating_mul(TAR_OVERHEAD_PER_ENTRY))
+}
+
+fn zip_kind(entry: &zip::read::ZipFile<'_>) -> EntryKind {
+    if entry.is_symlink() {
+        EntryKind::Skipped
+    } else if entry.is_dir() {
+        EntryKind::Directory
+    } else {
+        EntryKind::File
+    }
+}
+
+fn tar_kind(entry_type: tar::EntryType) -> EntryKind {
+    if entry_type.is_file() {
+        EntryKind::File
+    } else if entry_type.is_dir() {
+        EntryKind::Directory
+    } else {
+        EntryKind::Skipped
+    }
+}
+
+/// What an extraction has written so far
+#[derive(Default)]
+struct Unpacked {
+    files: Vec<String>,
+    overwritten: Vec<String>,
+    dirs: Vec<String>,
+    skipped: Vec<String>,
+    bytes: u64,
+}
+
+/// Write one entry below `root`, returning the bytes written.
+/// Links and special files are skipped; nothing is written through an existing symlink.
+fn unpack_entry(
+    root: &Path,
+    name: &str,
+    kind: EntryKind,
+    reader: &mut dyn Read,
+    mode: Option<u32>,
+    limits: &ArchiveLimits,
+    unpacked: &mut Unpacked,
+) -> Result<u64, String> {
+    let Some(relative) = safe_path(name)? else {
+        return Ok(0);
+    };
+    let target = root.join(&relative);
+
+    match kind {
+        EntryKind::Skipped => {
+            unpacked.skipped.push(name.to_string());
+            return Ok(0);
+        }
+        EntryKind::Directory => {
+            make_dirs(root, &relative, unpacked)?;
+            return Ok(0);
+        }
+        EntryKind::File => {}
+    }
+
+    if let Some(parent) = relative.parent() {
+        make_dirs(root, parent, unpacked)?;
+    }
+
+    let target_str = target.to_string_lossy().to_string();
+    match fs::symlink_metadata(&target) {
+        Ok(meta) if meta.is_file() => unpacked.overwritten.push(target_str),
+        Ok(_) => {
+            return Err(format!(
+                "{} already exists and is not a regular file; not extracting over it",
+                target_str
+            ))
+        }
+        Err(_) => unpacked.files.push(target_str),
+    }
+
+    let budget = limits.max_bytes.saturating_sub(unpacked.bytes);
+    let mut file = fs::File::create(&target)
+        .map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+    let written = io::copy(&mut reader.take(budget + 1), &mut file)
+        .map_err(|e| format!("Failed to extract {}: {}", name, e))?;
+
+    unpacked.bytes += written;
+    if unpacked.bytes > limits.max_bytes {
+        return Err(format!(
+            "Archive unpacks to more than {} bytes, which exceeds the archive limit; stopped extracting",
+            limits.max_bytes
+        ));
+    }
+
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        // Keep the executable bits, never setuid/setgid, and always let the owner read and write
+        let permissions = fs::Permissions::from_mode((mode & 0o777) | 0o600);
+        fs::set_permissions(&target, permissions)
+            .map_err(|e| format!("Failed to set permissions on {}: {}", target.display(), e))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(written)
+}
+
+/// Create the directories of `relative` below `root` one level at a time,
+/// refusing to pass through anything that is not a real directory
+fn make_dirs(root: &Path, relative: &Path, unpacked: &mut Unpacked) -> Result<(), String> {
+    let mut dir = root.to_path_buf();
+    for component in relative.components() {
+        dir.push(component);
+        match fs::symlink_metadata(&dir) {
+            Ok(meta) if meta.is_dir() => continue,
+            Ok(_) => {
+                return Err(format!(
+                    "{} is not a directory; not extracting through it",
+                    dir.display()
+                ))
+            }
+            Err(_) => {
+                fs::create_dir(&dir)
+                    .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+                unpacked.dirs.push(dir.to_string_lossy().to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A file or directory to pack, and its name inside the archive
+struct Member {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+    size: u64,
+    mode: u32,
+}
+
+/// What to pack from `source`: the file itself, or a directory's tree under the directory's name.
+/// With `include` globs only matching files are packed. Symlinks are skipped, never followed.
+fn collect_members(source: &Path, include: &[regex::Regex], limits: &DirectoryLimits) -> Result<(Vec<Member>, Vec<String>), String> {
+    let base = source
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| format!("Cannot archive {}", source.display()))?;
+
+    let meta = fs::symlink_metadata(source).map_err(|e| format!("Failed to read metadata: {}", e))?;
+    if !meta.is_dir() {
+        return Ok((vec![member(source, base, &meta)], Vec::new()));
+    }
+
+    fs_adapter::check_tree_limits(source, limits)?;
+
+    let mut members = Vec::new();
+    let mut skipped = Vec::new();
+    if include.is_empty() {
+        members.push(member(source, base.clone(), &meta));
+    }
+
+    let mut pending = vec![source.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut children: Vec<fs::DirEntry> = fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok())
+            .collect();
+        children.sort_by_key(|entry| entry.file_name());
+
+        for entry in children {
+            let path = entry.path();
+            let relative = path.strip_prefix(source).unwrap_or(&path);
+            let relative_name = glob::normalize_separators(&relative.to_string_lossy());
+            let meta = fs::symlink_metadata(&path).map_err(|e| format!("Failed to read metadata: {}", e))?;
+            let name = format!("{}/{}", base, relative_name);
+
+            if meta.is_dir() {
+                pending.push(path.clone());
+                if include.is_empty() {
+                    members.push(member(&path, name, &meta));
+                }
+            } else if meta.is_file() {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let included = include.is_empty()
+                    || include.iter().any(|pattern| pattern.is_match(&file_name) || pattern.is_match(&relative_name));
+                if included {
+                    members.push(member(&path, name, &meta));
+                }
+            } else {
+                skipped.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    if members.is_empty() {
+        return Err(format!("No files in {} match the include patterns", source.display()));
+    }
+    members.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((members, skipped))
+}
+
+fn member(path: &Path, name: String, meta: &fs::Metadata) -> Member {
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        meta.permissions().mode() & 0o777
+    };
+    #[cfg(not(unix))]
+    let mode = if meta.is_dir() { 0o755 } else { 0o644 };
+
+    Member {
+        path: path.to_path_buf(),
+        name,
+        is_dir: meta.is_dir(),
+        size: if meta.is_dir() { 0 } else { meta.len() },
+        mode,
+    }
+}
+
+fn write_zip(file: fs::File, members: &[Member]) -> Result<(), String> {
+    let mut zip = zip::ZipWriter::new(file);
+
+    for member in members {
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .unix_permissions(member.mode)
+            .large_file(member.size >= u32::MAX as u64);
+
+        if member.is_dir {
+            zip.add_directory(member.name.as_str(), options).map_err(|e| e.to_string())?;
+        } else {
+            zip.start_file(member.name.as_str(), options).map_err(|e| e.to_string())?;
+            let mut source = fs::File::open(&member.path).map_err(|e| e.to_string())?;
+            io::copy(&mut source, &mut zip).map_err(|e| e.to_string())?;
+        }
+    }
+
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn write_tar_gz(file: fs::File, members: &[Member]) -> Result<(), String> {
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    tar.follow_symlinks(false);
+
+    for member in members {
+        if member.is_dir {
+            tar.append_dir(&member.name, &member.path).map_err(|e| e.to_string())?;
+        } else {
+            tar.append_path_with_name(&member.path, &member.name).map_err(|e| e.to_string())?;
+        }
+    }
+
+    tar.into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Reader that fails once more than `limit` bytes have been read,
+/// so a decompression bomb stops early instead of filling the disk or memory
+struct Bounded<R> {
+    inner: R,
+    remaining: u64,
+    limit: u64,
+}
+
+impl<R: Read> Bounded<R> {
+    fn new(inner: R, limit: u64) -> Self {
+        Self {
+            inner,
+            remaining: limit,
+            limit,
+        }
+    }
+}
+
+impl<R: Read> Read for Bounded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read as u64 > self.remaining {
+            return Err(io::Error::other(format!(
+                "archive expands to more than {} bytes, which exceeds the archive limit",
+                self.limit
+            )));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// A zip holding `(name, content)` files, written with the names as given
+    fn zip_fixture(dir: &Path, files: &[(&str, &[u8])]) -> PathBuf {
+        let path = dir.join("fixture.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, content) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    /// A tar.gz holding `(name, content)` files. The name is written into the header
+    /// directly, since the tar builder itself refuses `..` and absolute paths.
+    fn tar_fixture(dir: &Path, files: &[(&str, &[u8])]) -> PathBuf {
+        let path = dir.join("fixture.tar.gz");
+        let mut tar = tar::Builder::new(GzEncoder::new(fs::File::create(&path).unwrap(), Compression::default()));
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append(&header, *content).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    fn extract_action(source: &Path, destination: &Path) -> Action {
+        Action {
+            id: "extract".to_string(),
+            action_type: ActionType::ArchiveExtract,
+            args: HashMap::from([
+                ("source_path".to_string(), serde_json::json!(source.to_string_lossy())),
+                ("destination_path".to_string(), serde_json::json!(destination.to_string_lossy())),
+            ]),
+            preconditions: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn safe_path_refuses_paths_outside_the_destination() {
+        for name in ["../x", "a/../../x", "a/..", "..\\x", "a\\..\\..\\x"] {
+            assert!(safe_path(name).unwrap_err().contains("points outside the destination"), "{}", name);
+        }
+        for name in ["/etc/passwd", "C:\\Windows\\x", "C:/x", "c:x", "\\\\server\\share\\x"] {
+            assert!(safe_path(name).unwrap_err().contains("absolute path"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn safe_path_keeps_relative_paths() {
+        assert_eq!(safe_path("a/b.txt").unwrap(), Some(PathBuf::from("a/b.txt")));
+        assert_eq!(safe_path("./a/./b.txt").unwrap(), Some(PathBuf::from("a/b.txt")));
+        assert_eq!(safe_path("a\\b.txt").unwrap(), Some(PathBuf::from("a/b.txt")));
+        assert_eq!(safe_path("./").unwrap(), None);
+    }
+
+    #[test]
+    fn zip_slip_entries_are_refused() {
+        let dir = TempDir::new().unwrap();
+        let limits = ArchiveLimits::default();
+
+        let archive = zip_fixture(dir.path(), &[("ok.txt", b"ok"), ("../evil.txt", b"x")]);
+        let err = list_entries(&archive, Format::Zip, &limits).unwrap_err();
+        assert!(err.contains("points outside the destination"), "{}", err);
+
+        let archive = zip_fixture(dir.path(), &[("/tmp/evil.txt", b"x")]);
+        let err = list_entries(&archive, Format::Zip, &limits).unwrap_err();
+        assert!(err.contains("absolute path"), "{}", err);
+    }
+
+    #[test]
+    fn tar_slip_entries_are_refused() {
+        let dir = TempDir::new().unwrap();
+        let limits = ArchiveLimits::default();
+
+        let archive = tar_fixture(dir.path(), &[("ok.txt", b"ok"), ("a/../../evil.txt", b"x")]);
+        let err = list_entries(&archive, Format::TarGz, &limits).unwrap_err();
+        assert!(err.contains("points outside the destination"), "{}", err);
+
+        let archive = tar_fixture(dir.path(), &[("/etc/evil", b"x")]);
+        let err = list_entries(&archive, Format::TarGz, &limits).unwrap_err();
+        assert!(err.contains("absolute path"), "{}", err);
+    }
+
+    #[test]
+    fn symlink_entries_are_skipped() {
+        let dir = TempDir::new().unwrap();
+        let destination = dir.path().join("out");
+
+        let zip_path = dir.path().join("links.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        zip.add_symlink("passwd", "/etc/passwd", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.finish().unwrap();
+
+        let tar_path = dir.path().join("links.tar.gz");
+        let mut tar = tar::Builder::new(GzEncoder::new(fs::File::create(&tar_path).unwrap(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        tar.append_link(&mut header, "passwd", "/etc/passwd").unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let ctx = ExecutionContext::new(dir.path().to_path_buf());
+        for archive in [&zip_path, &tar_path] {
+            let format = Format::of(None, &archive.to_string_lossy()).unwrap();
+            let entries = list_entries(archive, format, &ctx.archive_limits).unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].kind, EntryKind::Skipped);
+
+            let output = extract(&extract_action(archive, &destination), &ctx).unwrap().output.unwrap();
+            assert_eq!(output["skipped"], serde_json::json!(["passwd"]));
+            assert!(destination.join("passwd").symlink_metadata().is_err());
+        }
+    }
+
+    #[test]
+    fn ratio_bombs_are_refused() {
+        let dir = TempDir::new().unwrap();
+        let limits = ArchiveLimits::default();
+        let zeros = vec![0u8; 4 * RATIO_MIN_BYTES as usize];
+
+        let archive = zip_fixture(dir.path(), &[("zeros.bin", &zeros)]);
+        let err = list_entries(&archive, Format::Zip, &limits).unwrap_err();
+        assert!(err.contains("it may be a zip bomb"), "{}", err);
+
+        let archive = tar_fixture(dir.path(), &[("zeros.bin", &zeros)]);
+        let err = list_entries(&archive, Format::TarGz, &limits).unwrap_err();
+        assert!(err.contains("it may be a zip bomb"), "{}", err);
+    }
+
+    #[test]
+    fn ratio_is_only_checked_for_large_data() {
+        let limits = ArchiveLimits::default();
+        assert!(check_ratio("small", RATIO_MIN_BYTES - 1, 1, &limits).is_ok());
+        assert!(check_ratio("large", RATIO_MIN_BYTES, RATIO_MIN_BYTES / 50, &limits).is_ok());
+        assert!(check_ratio("bomb", RATIO_MIN_BYTES, RATIO_MIN_BYTES / 500, &limits).is_err());
+        assert!(check_ratio("empty", RATIO_MIN_BYTES, 0, &limits).is_err());
+    }
+
+    #[test]
+    fn byte_and_entry_limits_are_enforced() {
+        let dir = TempDir::new().unwrap();
+        let limits = ArchiveLimits { max_entries: 2, max_bytes: 100, max_ratio: 100 };
+
+        for (archive, format) in [
+            (zip_fixture(dir.path(), &[("big.bin", &[1u8; 101])]), Format::Zip),
+            (tar_fixture(dir.path(), &[("big.bin", &[1u8; 101])]), Format::TarGz),
+        ] {
+            let err = list_entries(&archive, format, &limits).unwrap_err();
+            assert!(err.contains("more than 100 bytes"), "{}", err);
+        }
+
+        let archive = zip_fixture(dir.path(), &[("a", b"a"), ("b", b"b"), ("c", b"c")]);
+        let err = list_entries(&archive, Format::Zip, &limits).unwrap_err();
+        assert!(err.contains("more than 2 entries"), "{}", err);
+    }
+
+    #[test]
+    fn unpacking_stops_at_the_byte_limit() {
+        let dir = TempDir::new().unwrap();
+        let limits = ArchiveLimits { max_entries: 10, max_bytes: 10, max_ratio: 100 };
+        let mut unpacked = Unpacked::default();
+
+        let err = unpack_entry(dir.path(), "a.bin", EntryKind::File, &mut &[7u8; 64][..], None, &limits, &mut unpacked)
+            .unwrap_err();
+        assert!(err.contains("stopped extracting"), "{}", err);
+        assert_eq!(fs::metadata(dir.path().join("a.bin")).unwrap().len(), 11);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unpacking_never_writes_through_symlinks() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("out");
+        let elsewhere = dir.path().join("elsewhere");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&elsewhere).unwrap();
+        std::os::unix::fs::symlink(&elsewhere, root.join("link")).unwrap();
+
+        let limits = ArchiveLimits::default();
+        let mut unpacked = Unpacked::default();
+        let through = unpack_entry(&root, "link/x.txt", EntryKind::File, &mut &b"x"[..], None, &limits, &mut unpacked);
+        assert!(through.unwrap_err().contains("not extracting through it"));
+        let over = unpack_entry(&root, "link", EntryKind::File, &mut &b"x"[..], None, &limits, &mut unpacked);
+        assert!(over.unwrap_err().contains("not a regular file"));
+        assert_eq!(fs::read_dir(&elsewhere).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn entries_landing_on_protected_paths_are_refused() {
+        let dir = TempDir::new().unwrap();
+        let home = dir.path().to_path_buf();
+        let ctx = ExecutionContext::new(home.clone());
+        let entry = |name: &str| Entry {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            kind: EntryKind::File,
+            size: 1,
+        };
+
+        assert!(check_entry_targets(&ctx, &home, &[entry("docs/a.txt")]).is_ok());
+        let err = check_entry_targets(&ctx, &home, &[entry("docs/a.txt"), entry(".ssh/authorized_keys")]).unwrap_err();
+        assert!(err.starts_with("Archive entry .ssh/authorized_keys cannot be extracted"), "{}", err);
+
+        // Nothing is written when one entry is refused
+        let archive = zip_fixture(dir.path(), &[("docs/a.txt", b"a"), (".ssh/authorized_keys", b"key")]);
+        assert!(extract(&extract_action(&archive, &home), &ctx).is_err());
+        assert!(!home.join("docs").exists());
+        assert!(!home.join(".ssh").exists());
+    }
+}
@@ -0,0 +1,136 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// How file bytes are carried in action args and outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Encoding {
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    #[serde(rename = "base64")]
+    Base64,
+    #[serde(rename = "latin-1", alias = "latin1", alias = "iso-8859-1")]
+    Latin1,
+}
+
+impl Encoding {
+    /// The encoding an `encoding` arg names, accepting the same names as the args schema
+    pub fn parse(name: &str) -> Result<Self, String> {
+        serde_json::from_value(serde_json::json!(name)).map_err(|_| format!("Unsupported encoding: {}", name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Base64 => "base64",
+            Encoding::Latin1 => "latin-1",
+        }
+    }
+}
+
+/// Encode bytes read from a file.
+/// With no explicit encoding, text is returned as UTF-8 and anything else as base64.
+/// Returns the encoding used and how many bytes the text covers, which is less than
+/// `bytes.len()` when a range ends inside a UTF-8 character.
+pub fn encode(bytes: &[u8], encoding: Option<Encoding>) -> Result<(String, Encoding, usize), String> {
+    match encoding {
+        Some(Encoding::Base64) => Ok((BASE64.encode(bytes), Encoding::Base64, bytes.len())),
+        Some(Encoding::Latin1) => Ok((bytes.iter().map(|&b| b as char).collect(), Encoding::Latin1, bytes.len())),
+        Some(Encoding::Utf8) => utf8_prefix(bytes)
+            .map(|(text, used)| (text, Encoding::Utf8, used))
+            .ok_or_else(|| "File is not valid UTF-8; read it with encoding \"base64\"".to_string()),
+        None => match utf8_prefix(bytes) {
+            Some((text, used)) if !text.contains('\0') => Ok((text, Encoding::Utf8, used)),
+            _ => Ok((BASE64.encode(bytes), Encoding::Base64, bytes.len())),
+        },
+    }
+}
+
+/// Decode `content` and write it out in chunks, returning the number of bytes written.
+/// Call with `io::sink()` first to validate without touching the file.
+pub fn decode_to<W: Write>(content: &str, encoding: Encoding, writer: &mut W) -> Result<u64, String> {
+    match encoding {
+        Encoding::Utf8 => {
+            writer
+                .write_all(content.as_bytes())
+                .map_err(|e| format!("Failed to write file: {}", e))?;
+            Ok(content.len() as u64)
+        }
+        Encoding::Base64 => {
+            // Ignore line breaks so wrapped base64 is accepted
+            let compact = CompactReader(content.as_bytes());
+            let mut decoder = base64::read::DecoderReader::new(compact, &BASE64);
+            io::copy(&mut decoder, writer).map_err(|e| match e.kind() {
+                io::ErrorKind::InvalidData => format!("Invalid base64 content: {}", e),
+                _ => format!("Failed to write file: {}", e),
+            })
+        }
+        Encoding::Latin1 => {
+            let mut chunk = Vec::with_capacity(8192);
+            let mut written = 0u64;
+            for c in content.chars() {
+                let byte = u8::try_from(u32::from(c))
+                    .map_err(|_| format!("Character '{}' cannot be written as latin-1", c))?;
+                chunk.push(byte);
+                if chunk.len() == chunk.capacity() {
+                    writer.write_all(&chunk).map_err(|e| format!("Failed to write file: {}", e))?;
+                    written += chunk.len() as u64;
+                    chunk.clear();
+                }
+            }
+            writer.write_all(&chunk).map_err(|e| format!("Failed to write file: {}", e))?;
+            Ok(written + chunk.len() as u64)
+        }
+    }
+}
+
+/// MIME type from the file's leading bytes, falling back to its extension
+pub fn sniff_mime(path: &Path, head: &[u8]) -> String {
+    if let Some(kind) = infer::get(head) {
+        return kind.mime_type().to_string();
+    }
+    if let Some(mime) = mime_guess::from_path(path).first() {
+        return mime.essence_str().to_string();
+    }
+    if utf8_prefix(head).is_some() {
+        "text/plain".to_string()
+    } else {
+        "application/octet-stream".to_string()
+    }
+}
+
+/// Longest valid UTF-8 prefix, allowing only an incomplete character at the end
+fn utf8_prefix(bytes: &[u8]) -> Option<(String, usize)> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some((text.to_string(), bytes.len())),
+        Err(e) if e.error_len().is_none() => {
+            let used = e.valid_up_to();
+            Some((String::from_utf8_lossy(&bytes[..used]).into_owned(), used))
+        }
+        Err(_) => None,
+    }
+}
+
+/// Reader that skips ASCII whitespace
+struct CompactReader<'a>(&'a [u8]);
+
+impl Read for CompactReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.0.split_first() {
+                Some((&byte, rest)) => {
+                    self.0 = rest;
+                    if !byte.is_ascii_whitespace() {
+                        buf[n] = byte;
+                        n += 1;
+                    }
+                }
+                None => break,
+            }
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,799 @@
+use crate::assistant::executor::{archive, content, edit, fs_adapter};
+use crate::assistant::policy;
+use crate::assistant::preconditions::{self, PathState};
+use crate::assistant::references;
+use crate::assistant::sandbox::ActionRunner;
+use crate::assistant::schema::{self, *};
+use crate::assistant::types::*;
+use crate::assistant::validator;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A path as the simulated plan would leave it
+#[derive(Debug, Clone)]
+enum Node {
+    Missing,
+    /// `content` is tracked when known so later edits can be checked against it;
+    /// `real` is the file on disk it still matches, if any
+    File { content: Option<String>, real: Option<PathBuf> },
+    /// Entries mirror `backing` on disk (an existing, copied or moved tree), or start empty
+    Dir { backing: Option<PathBuf> },
+}
+
+#[derive(Debug, Clone)]
+struct Change {
+    node: Node,
+    step: usize,
+}
+
+/// Copy-on-write view of the filesystem: paths changed by a simulated step shadow
+/// the disk, and everything else is read from the disk as it is now
+struct Overlay<'a> {
+    changes: HashMap<PathBuf, Change>,
+    /// Outputs of the simulated steps: real for lookups that were run, predicted from args otherwise
+    outputs: HashMap<String, serde_json::Value>,
+    /// Destinations that already existed when their step was simulated
+    conflicts: Vec<ConflictPreview>,
+    /// Where actions that rename on conflict would write instead, by action ID
+    renamed: HashMap<String, PathBuf>,
+    ctx: &'a ExecutionContext,
+}
+
+/// Outcome of simulating a plan
+struct Simulation {
+    results: Vec<ActionExecutionResult>,
+    error: Option<String>,
+    references: Vec<ReferencePreview>,
+    conflicts: Vec<ConflictPreview>,
+}
+
+/// Simulate a plan without touching disk.
+/// Each step sees the state earlier steps would leave behind, and the simulation stops
+/// at the first step that would fail, as a real execution would.
+/// With a capability token, steps whose paths come from earlier outputs are checked against it.
+pub fn simulate_plan(plan: &ActionPlan, ctx: &ExecutionContext, token: Option<&CapabilityToken>) -> ActionResult {
+    let Simulation { results, error, .. } = simulate(plan, ctx, token);
+
+    ActionResult {
+        action_id: plan.schema.id.clone(),
+        success: error.is_none(),
+        executed_at: chrono::Utc::now().timestamp(),
+        results,
+        error,
+        undo_available: false,
+        undo_ttl: None,
+    }
+}
+
+/// How each step reference in a plan resolves when the plan is simulated now, for the preview
+pub fn preview_references(plan: &ActionPlan, ctx: &ExecutionContext) -> Vec<ReferencePreview> {
+    simulate(plan, ctx, None).references
+}
+
+/// Every destination that already exists when its step would run, and what the step does about it.
+/// Steps past one that would fail are checked against the disk as it is now.
+pub fn preview_conflicts(plan: &ActionPlan, ctx: &ExecutionContext) -> Vec<ConflictPreview> {
+    let Simulation { results, mut conflicts, .. } = simulate(plan, ctx, None);
+
+    let reached = results.iter().filter(|result| result.success).count() + 1;
+    for action in plan.schema.actions.iter().skip(reached) {
+        let Some(destination) = action.args.get("destination_path").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let Ok(policy) = OnConflict::of(action) else {
+            continue;
+        };
+        let settles = matches!(
+            action.action_type,
+            ActionType::FsCopyFile
+                | ActionType::FsMoveFile
+                | ActionType::FsCopyDirectory
+                | ActionType::FsMoveDirectory
+                | ActionType::ArchiveCreate
+        );
+        if settles && !references::contains_reference(destination) && Path::new(destination).symlink_metadata().is_ok() {
+            let renamed_to = fs_adapter::settle_conflict(Path::new(destination), policy, |p| p.symlink_metadata().is_ok())
+                .ok()
+                .filter(|target| target.conflict == Some(OnConflict::Rename))
+                .map(|target| target.path.to_string_lossy().to_string());
+            conflicts.push(ConflictPreview {
+                action_id: action.id.clone(),
+                path: destination.to_string(),
+                on_conflict: policy.name().to_string(),
+                renamed_to,
+            });
+        }
+    }
+
+    conflicts
+}
+
+/// Simulate the steps in order. References are resolved from the outputs of earlier steps;
+/// a lookup whose output a later step uses is run against the disk as it is now,
+/// in the same sandbox a real execution uses.
+fn simulate(plan: &ActionPlan, ctx: &ExecutionContext, token: Option<&CapabilityToken>) -> Simulation {
+    let mut overlay = Overlay {
+        changes: HashMap::new(),
+        outputs: HashMap::new(),
+        conflicts: Vec::new(),
+        renamed: HashMap::new(),
+        ctx,
+    };
+    let referenced: HashSet<String> = plan
+        .schema
+        .actions
+        .iter()
+        .flat_map(|action| references::action_references(action).unwrap_or_default())
+        .map(|reference| reference.step_id)
+        .collect();
+    let mut runner = None;
+    let mut results = Vec::new();
+    let mut error = None;
+    let mut previews = Vec::new();
+
+    for (index, action) in plan.schema.actions.iter().enumerate() {
+        let step = index + 1;
+        previews.extend(reference_previews(plan, action, |reference| {
+            references::resolve(reference, &overlay.outputs).map(|value| references::display_value(&value))
+        }));
+
+        let outcome = references::substitute(action, &overlay.outputs).and_then(|action| {
+            validator::check_action_paths(&action, ctx)?;
+            if let Some(token) = token.filter(|_| references::has_references(&action)) {
+                policy::check_action_permission(token, &action)?;
+            }
+            overlay.check_preconditions(&action)?;
+            let effects = overlay.apply(&action, step)?;
+            let output = if is_read_only(&action.action_type) && referenced.contains(&action.id) {
+                run_lookup(&mut runner, plan, ctx, &action)?
+            } else {
+                overlay.predicted_output(&action)
+            };
+            overlay.outputs.insert(action.id.clone(), output);
+            Ok(effects)
+        });
+
+        match outcome {
+            Ok(effects) => results.push(ActionExecutionResult {
+                action_id: action.id.clone(),
+                success: true,
+                output: Some(serde_json::json!({
+                    "dry_run": true,
+                    "effects": effects,
+                })),
+                error: None,
+                snapshot_id: None,
+            }),
+            Err(e) => {
+                let e = format!("Step {} ({}) would fail: {}", step, action.action_type.name(), e);
+                results.push(ActionExecutionResult {
+                    action_id: action.id.clone(),
+                    success: false,
+                    output: None,
+                    error: Some(e.clone()),
+                    snapshot_id: None,
+                });
+                error = Some(e);
+
+                // Later references cannot be resolved once a step fails
+                for action in &plan.schema.actions[step..] {
+                    previews.extend(reference_previews(plan, action, |_| {
+                        Err(format!("Not resolved because step {} would fail", step))
+                    }));
+                }
+                break;
+            }
+        }
+    }
+
+    Simulation {
+        results,
+        error,
+        references: previews,
+        conflicts: overlay.conflicts,
+    }
+}
+
+/// Run a lookup for its output, starting the sandboxed runner on first use
+fn run_lookup(
+    runner: &mut Option<ActionRunner>,
+    plan: &ActionPlan,
+    ctx: &ExecutionContext,
+    action: &Action,
+) -> Result<serde_json::Value, String> {
+    let runner = match runner {
+        Some(runner) => runner,
+        None => runner.insert(ActionRunner::start_read_only(plan, ctx)?),
+    };
+    Ok(runner.run(action)?.output.unwrap_or_default())
+}
+
+/// A preview of each reference in an action's args, resolved by `resolve`
+fn reference_previews(
+    plan: &ActionPlan,
+    action: &Action,
+    resolve: impl Fn(&references::StepReference) -> Result<String, String>,
+) -> Vec<ReferencePreview> {
+    references::action_references(action)
+        .unwrap_or_default()
+        .iter()
+        .map(|reference| {
+            let resolved = resolve(reference);
+            ReferencePreview {
+                action_id: action.id.clone(),
+                arg: reference.arg.clone(),
+                reference: reference.text.clone(),
+                step: plan
+                    .schema
+                    .actions
+                    .iter()
+                    .position(|a| a.id == reference.step_id)
+                    .map_or(0, |position| position + 1),
+                resolved: resolved.as_ref().ok().cloned(),
+                note: resolved.err(),
+            }
+        })
+        .collect()
+}
+
+/// Steps that only read, so the dry run may run them for their output
+fn is_read_only(action_type: &ActionType) -> bool {
+    matches!(
+        action_type,
+        ActionType::FsReadFile | ActionType::FsListDirectory | ActionType::FsSearch | ActionType::FsStat
+    )
+}
+
+/// Check each action's preconditions against the state the earlier actions
+/// in the plan would leave behind, rather than against the disk as it is now
+pub fn check_plan_preconditions(plan: &ActionPlan, ctx: &ExecutionContext) -> Result<(), String> {
+    let mut overlay = Overlay {
+        changes: HashMap::new(),
+        outputs: HashMap::new(),
+        conflicts: Vec::new(),
+        renamed: HashMap::new(),
+        ctx,
+    };
+
+    for (index, action) in plan.schema.actions.iter().enumerate() {
+        let step = index + 1;
+        // A step using an output only known at run time is checked right before it runs
+        let Ok(action) = references::substitute(action, &overlay.outputs) else {
+            continue;
+        };
+        overlay
+            .check_preconditions(&action)
+            .map_err(|e| format!("Step {} ({}): {}", step, action.action_type.name(), e))?;
+
+        // Past a step that cannot run the simulated state means nothing; execution reports it
+        if overlay.apply(&action, step).is_err() {
+            break;
+        }
+        let output = overlay.predicted_output(&action);
+        overlay.outputs.insert(action.id.clone(), output);
+    }
+
+    Ok(())
+}
+
+impl Overlay<'_> {
+    /// Check an action's preconditions on every path argument
+    fn check_preconditions(&self, action: &Action) -> Result<(), String> {
+        let Some(action_preconditions) = &action.preconditions else {
+            return Ok(());
+        };
+
+        for (arg, _) in validator::path_accesses(&action.action_type) {
+            let Some(path_str) = action.args.get(*arg).and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let Some(precondition) = action_preconditions.for_arg(arg) else {
+                continue;
+            };
+            if path_str == "__PROMPT_PATH__" {
+                continue;
+            }
+
+            let path = Path::new(path_str);
+            let (state, cause) = self.state(path, precondition.sha256.is_some());
+            // Size and hash of untracked writes can only be checked at run time
+            let mut precondition = precondition.clone();
+            if matches!(self.lookup(path).0, Node::File { content: None, real: None }) {
+                precondition.max_size = None;
+                precondition.sha256 = None;
+            }
+            preconditions::check(&precondition, path_str, &state).map_err(|e| format!("{}{}", e, because(cause)))?;
+        }
+
+        Ok(())
+    }
+
+    /// What preconditions see at a path in the simulated state
+    fn state(&self, path: &Path, hash: bool) -> (PathState, Option<usize>) {
+        let (node, cause) = self.lookup(path);
+        let state = match node {
+            Node::Missing => PathState {
+                exists: false,
+                writable: self.creatable(path),
+                ..Default::default()
+            },
+            Node::Dir { backing: Some(real) } => preconditions::disk_state(&real, false),
+            // Created by the plan itself
+            Node::Dir { backing: None } => PathState {
+                exists: true,
+                directory: true,
+                readable: true,
+                writable: true,
+                ..Default::default()
+            },
+            Node::File { content: Some(content), real } => {
+                let (readable, writable) = real.as_deref().map(preconditions::permissions).unwrap_or((true, true));
+                PathState {
+                    exists: true,
+                    directory: false,
+                    readable,
+                    writable,
+                    size: Some(content.len() as u64),
+                    sha256: hash.then(|| preconditions::sha256_bytes(content.as_bytes())),
+                }
+            }
+            Node::File { content: None, real: Some(real) } => preconditions::disk_state(&real, hash),
+            // Written by the plan in a form that is not tracked; size and hash are checked at run time
+            Node::File { content: None, real: None } => PathState {
+                exists: true,
+                directory: false,
+                readable: true,
+                writable: true,
+                ..Default::default()
+            },
+        };
+        (state, cause)
+    }
+
+    /// Whether a missing path could be created in the simulated state
+    fn creatable(&self, path: &Path) -> bool {
+        for ancestor in path.ancestors().skip(1).filter(|p| !p.as_os_str().is_empty()) {
+            match self.lookup(ancestor).0 {
+                Node::Missing => continue,
+                Node::Dir { backing: Some(real) } => return preconditions::permissions(&real).1,
+                Node::Dir { backing: None } => return true,
+                Node::File { .. } => return false,
+            }
+        }
+        false
+    }
+
+    /// Apply one action to the overlay, returning what it would do to each path
+    fn apply(&mut self, action: &Action, step: usize) -> Result<Vec<serde_json::Value>, String> {
+        let mut effects = Vec::new();
+
+        match action.action_type {
+            ActionType::FsCreateFile => {
+                let args: CreateFileArgs = schema::parse_args(action)?;
+                let path = Path::new(&args.path);
+                let (node, cause) = self.lookup(path);
+                if let Node::Dir { .. } = node {
+                    return Err(format!("Path is a directory: {}{}", args.path, because(cause)));
+                }
+
+                let encoding = args.encoding.unwrap_or(content::Encoding::Utf8);
+                let text = args.content.as_deref().unwrap_or("");
+                content::decode_to(text, encoding, &mut std::io::sink())?;
+
+                self.create_parents(path, step, &mut effects)?;
+                effects.push(effect(replace_effect(&node), "file", &args.path));
+                self.set(path, Node::File {
+                    content: (encoding == content::Encoding::Utf8).then(|| text.to_string()),
+                    real: None,
+                }, step);
+            }
+            ActionType::FsReadFile => {
+                let args: ReadFileArgs = schema::parse_args(action)?;
+                self.expect_file(Path::new(&args.path), "File does not exist", "Path is not a file")?;
+                effects.push(effect("read", "file", &args.path));
+            }
+            ActionType::FsCopyFile | ActionType::FsMoveFile => {
+                let args: TransferArgs = schema::parse_args(action)?;
+                let source = Path::new(&args.source_path);
+                let destination = Path::new(&args.destination_path);
+                let moving = action.action_type == ActionType::FsMoveFile;
+
+                let (source_node, cause) = self.lookup(source);
+                match source_node {
+                    Node::Missing => {
+                        return Err(format!("Source file does not exist: {}{}", args.source_path, because(cause)))
+                    }
+                    // Only a symlink to a directory moves like a file
+                    Node::Dir { .. } if !moving || !source.is_symlink() => {
+                        return Err(format!("Source is not a file: {}{}", args.source_path, because(cause)))
+                    }
+                    _ => {}
+                }
+
+                let target = self.settle(action, destination)?;
+                if target.skipped() {
+                    effects.push(effect("skip", kind(&source_node), &args.destination_path));
+                    return Ok(effects);
+                }
+                let destination = target.path.as_path();
+                let destination_str = destination.to_string_lossy();
+                let (destination_node, cause) = self.lookup(destination);
+                if let Node::Dir { .. } = destination_node {
+                    return Err(format!("Destination is a directory: {}{}", destination_str, because(cause)));
+                }
+
+                self.create_parents(destination, step, &mut effects)?;
+                effects.push(effect(replace_effect(&destination_node), kind(&source_node), &destination_str));
+                self.copy_tree(source, destination, step);
+                if moving {
+                    effects.push(effect("remove", kind(&source_node), &args.source_path));
+                    self.set(source, Node::Missing, step);
+                }
+            }
+            ActionType::FsDeleteFile => {
+                let args: DeleteArgs = schema::parse_args(action)?;
+                let path = Path::new(&args.path);
+                self.expect_file(path, "File does not exist", "Path is a directory")?;
+                effects.push(remove_effect("file", &args));
+                self.set(path, Node::Missing, step);
+            }
+            ActionType::FsCreateDirectory => {
+                let args: CreateDirectoryArgs = schema::parse_args(action)?;
+                let path = Path::new(&args.path);
+                if let (Node::File { .. }, cause) = self.lookup(path) {
+                    return Err(format!("Path is a file: {}{}", args.path, because(cause)));
+                }
+                self.create_dirs(path, step, &mut effects)?;
+            }
+            ActionType::FsListDirectory => {
+                let args: ListDirectoryArgs = schema::parse_args(action)?;
+                self.expect_dir(Path::new(&args.path), "Path is not a directory")?;
+                effects.push(effect("list", "directory", &args.path));
+            }
+            ActionType::FsCopyDirectory | ActionType::FsMoveDirectory => {
+                let args: TransferArgs = schema::parse_args(action)?;
+                let source = Path::new(&args.source_path);
+                let destination = Path::new(&args.destination_path);
+
+                self.expect_dir(source, "Source is not a directory")?;
+                let target = self.settle(action, destination)?;
+                if target.skipped() {
+                    effects.push(effect("skip", "directory", &args.destination_path));
+                    return Ok(effects);
+                }
+                let destination = target.path.as_path();
+                let destination_str = destination.to_string_lossy();
+                let (destination_node, _) = self.lookup(destination);
+                if target.conflict == Some(OnConflict::Overwrite) && source.starts_with(destination) {
+                    return Err(format!("Cannot replace {} with a copy of something inside it", destination_str));
+                }
+
+                self.create_parents(destination, step, &mut effects)?;
+                effects.push(effect(replace_effect(&destination_node), "directory", &destination_str));
+                self.copy_tree(source, destination, step);
+                if action.action_type == ActionType::FsMoveDirectory {
+                    effects.push(effect("remove", "directory", &args.source_path));
+                    self.set(source, Node::Missing, step);
+                }
+            }
+            ActionType::FsDeleteDirectory => {
+                let args: DeleteArgs = schema::parse_args(action)?;
+                let path = Path::new(&args.path);
+                self.expect_dir(path, "Path is not a directory")?;
+                effects.push(remove_effect("directory", &args));
+                self.set(path, Node::Missing, step);
+            }
+            ActionType::FsAppendFile | ActionType::FsReplaceText | ActionType::FsApplyPatch => {
+                let path_str = action.args.get("path").and_then(|v| v.as_str()).unwrap_or("");
+                let path = Path::new(path_str);
+                let current = self.expect_file(path, "File does not exist", "File does not exist")?;
+
+                // Check the edit applies when the content is known; a binary or oversized
+                // file is left for the real run to report
+                let content = match current.and_then(|current| self.text_of(current)) {
+                    Some(current) => Some(edit::apply_edit(action, &current)?.content),
+                    None => None,
+                };
+
+                effects.push(effect("modify", "file", path_str));
+                self.set(path, Node::File { content, real: None }, step);
+            }
+            ActionType::FsRestoreFromTrash => {
+                let args: RestoreFromTrashArgs = schema::parse_args(action)?;
+                let path = Path::new(&args.path);
+                if let (Node::File { .. } | Node::Dir { .. }, cause) = self.lookup(path) {
+                    return Err(format!("{} already exists; not restoring over it{}", args.path, because(cause)));
+                }
+
+                // Without an explicit trash item the restored kind is only known at run time
+                let restored = match args.trash_path.as_deref() {
+                    Some(trash_path) => match disk(Path::new(trash_path)) {
+                        Node::Missing => return Err(format!("{} is not in the trash", trash_path)),
+                        node => node,
+                    },
+                    None => Node::File { content: None, real: None },
+                };
+
+                self.create_parents(path, step, &mut effects)?;
+                effects.push(effect("create", kind(&restored), &args.path));
+                self.set(path, restored, step);
+            }
+            ActionType::FsSearch => {
+                let args: SearchArgs = schema::parse_args(action)?;
+                self.expect_dir(Path::new(&args.path), "Path is not a directory")?;
+                effects.push(effect("search", "directory", &args.path));
+            }
+            ActionType::FsStat => {
+                let args: StatArgs = schema::parse_args(action)?;
+                let (node, cause) = self.lookup(Path::new(&args.path));
+                if let Node::Missing = node {
+                    return Err(format!("Path does not exist: {}{}", args.path, because(cause)));
+                }
+                effects.push(effect("stat", kind(&node), &args.path));
+            }
+            ActionType::ArchiveCreate => {
+                let args: ArchiveCreateArgs = schema::parse_args(action)?;
+                let destination = Path::new(&args.destination_path);
+                let (source_node, cause) = self.lookup(Path::new(&args.source_path));
+                if let Node::Missing = source_node {
+                    return Err(format!("Source does not exist: {}{}", args.source_path, because(cause)));
+                }
+                archive::Format::of(args.format, &args.destination_path)?;
+
+                let target = self.settle(action, destination)?;
+                if target.skipped() {
+                    effects.push(effect("skip", "file", &args.destination_path));
+                    return Ok(effects);
+                }
+                let destination = target.path.as_path();
+                let destination_str = destination.to_string_lossy();
+                let (destination_node, cause) = self.lookup(destination);
+                if let Node::Dir { .. } = destination_node {
+                    return Err(format!("Destination is a directory: {}{}", destination_str, because(cause)));
+                }
+
+                self.create_parents(destination, step, &mut effects)?;
+                effects.push(effect(replace_effect(&destination_node), "file", &destination_str));
+                self.set(destination, Node::File { content: None, real: None }, step);
+            }
+            ActionType::ArchiveExtract => {
+                let args: ArchiveExtractArgs = schema::parse_args(action)?;
+                let destination = Path::new(&args.destination_path);
+                validator::check_no_protected_inside(self.ctx, &args.destination_path)?;
+                let format = archive::Format::of(args.format, &args.source_path)?;
+                let source = self.expect_file(Path::new(&args.source_path), "Archive does not exist", "Archive is not a file")?;
+
+                // An archive an earlier step creates cannot be listed until it exists
+                let entries = match source {
+                    Some(Node::File { real: Some(real), .. }) => {
+                        archive::list_entries(&real, format, &self.ctx.archive_limits)?
+                    }
+                    _ => Vec::new(),
+                };
+                archive::check_entry_targets(self.ctx, destination, &entries)?;
+
+                self.create_dirs(destination, step, &mut effects)?;
+                for entry in entries {
+                    let target = destination.join(&entry.path);
+                    let target_str = target.to_string_lossy().to_string();
+                    match entry.kind {
+                        archive::EntryKind::Directory => self.create_dirs(&target, step, &mut effects)?,
+                        archive::EntryKind::File => {
+                            let (node, cause) = self.lookup(&target);
+                            if let Node::Dir { .. } = node {
+                                return Err(format!("{} is a directory; not extracting over it{}", target_str, because(cause)));
+                            }
+                            self.create_parents(&target, step, &mut effects)?;
+                            effects.push(effect(replace_effect(&node), "file", &target_str));
+                            self.set(&target, Node::File { content: None, real: None }, step);
+                        }
+                        archive::EntryKind::Skipped => effects.push(effect("skip", "link", &target_str)),
+                    }
+                }
+            }
+        }
+
+        Ok(effects)
+    }
+
+    /// Settle a destination that exists in the simulated state by the action's conflict policy,
+    /// recording the conflict for the preview
+    fn settle(&mut self, action: &Action, destination: &Path) -> Result<fs_adapter::Destination, String> {
+        let policy = OnConflict::of(action)?;
+        let (node, cause) = self.lookup(destination);
+        let settled = fs_adapter::settle_conflict(destination, policy, |p| !matches!(self.lookup(p).0, Node::Missing));
+
+        if !matches!(node, Node::Missing) {
+            self.conflicts.push(ConflictPreview {
+                action_id: action.id.clone(),
+                path: destination.to_string_lossy().to_string(),
+                on_conflict: policy.name().to_string(),
+                renamed_to: settled
+                    .as_ref()
+                    .ok()
+                    .filter(|target| target.conflict == Some(OnConflict::Rename))
+                    .map(|target| target.path.to_string_lossy().to_string()),
+            });
+        }
+
+        let target = settled.map_err(|e| format!("{}{}", e, because(cause)))?;
+        if target.conflict == Some(OnConflict::Rename) {
+            self.renamed.insert(action.id.clone(), target.path.clone());
+        }
+        Ok(target)
+    }
+
+    /// What an action is expected to output, at the name it would be renamed to on conflict
+    fn predicted_output(&self, action: &Action) -> serde_json::Value {
+        let mut output = references::predicted_output(action);
+        if let Some(renamed) = self.renamed.get(&action.id) {
+            let renamed = serde_json::json!(renamed.to_string_lossy());
+            output["path"] = renamed.clone();
+            output["destination"] = renamed;
+        }
+        output
+    }
+
+    /// Current state of a path and the step that last changed it, if any
+    fn lookup(&self, path: &Path) -> (Node, Option<usize>) {
+        if let Some(change) = self.changes.get(path) {
+            return (change.node.clone(), Some(change.step));
+        }
+
+        // The nearest changed ancestor decides what is below it
+        for ancestor in path.ancestors().skip(1) {
+            if let Some(change) = self.changes.get(ancestor) {
+                return match &change.node {
+                    Node::Dir { backing: Some(backing) } => {
+                        let rest = path.strip_prefix(ancestor).unwrap_or(path);
+                        (disk(&backing.join(rest)), None)
+                    }
+                    _ => (Node::Missing, Some(change.step)),
+                };
+            }
+        }
+
+        (disk(path), None)
+    }
+
+    /// Record the new state of a path, replacing whatever was recorded beneath it
+    fn set(&mut self, path: &Path, node: Node, step: usize) {
+        self.changes.retain(|p, _| !p.starts_with(path));
+        self.changes.insert(path.to_path_buf(), Change { node, step });
+    }
+
+    /// Make `destination` a copy of `source`, including changes recorded beneath it
+    fn copy_tree(&mut self, source: &Path, destination: &Path, step: usize) {
+        let (node, _) = self.lookup(source);
+        let children: Vec<(PathBuf, Change)> = self
+            .changes
+            .iter()
+            .filter(|(p, _)| p.as_path() != source && p.starts_with(source))
+            .filter_map(|(p, change)| {
+                let rest = p.strip_prefix(source).ok()?;
+                Some((destination.join(rest), change.clone()))
+            })
+            .collect();
+
+        self.set(destination, node, step);
+        self.changes.extend(children);
+    }
+
+    /// Parent directories an action creates before writing `path`
+    fn create_parents(&mut self, path: &Path, step: usize, effects: &mut Vec<serde_json::Value>) -> Result<(), String> {
+        match path.parent() {
+            Some(parent) => self.create_dirs(parent, step, effects),
+            None => Ok(()),
+        }
+    }
+
+    /// Create `dir` and any missing parents, outermost first
+    fn create_dirs(&mut self, dir: &Path, step: usize, effects: &mut Vec<serde_json::Value>) -> Result<(), String> {
+        let mut missing = Vec::new();
+        for ancestor in dir.ancestors().filter(|p| !p.as_os_str().is_empty()) {
+            match self.lookup(ancestor) {
+                (Node::Missing, _) => missing.push(ancestor.to_path_buf()),
+                (Node::Dir { .. }, _) => break,
+                (Node::File { .. }, cause) => {
+                    return Err(format!(
+                        "Failed to create directory: {} is a file{}",
+                        ancestor.display(),
+                        because(cause)
+                    ))
+                }
+            }
+        }
+
+        for dir in missing.iter().rev() {
+            effects.push(effect("create", "directory", &dir.to_string_lossy()));
+            self.set(dir, Node::Dir { backing: None }, step);
+        }
+        Ok(())
+    }
+
+    /// Require a file, returning it for edits
+    fn expect_file(&self, path: &Path, missing: &str, not_file: &str) -> Result<Option<Node>, String> {
+        match self.lookup(path) {
+            (node @ Node::File { .. }, _) => Ok(Some(node)),
+            (Node::Missing, cause) => Err(format!("{}: {}{}", missing, path.display(), because(cause))),
+            (Node::Dir { .. }, cause) => Err(format!("{}: {}{}", not_file, path.display(), because(cause))),
+        }
+    }
+
+    fn expect_dir(&self, path: &Path, not_dir: &str) -> Result<(), String> {
+        match self.lookup(path) {
+            (Node::Dir { .. }, _) => Ok(()),
+            (_, cause) => Err(format!("{}: {}{}", not_dir, path.display(), because(cause))),
+        }
+    }
+
+    /// Text of a file, from the overlay or from disk within the read limit
+    fn text_of(&self, node: Node) -> Option<String> {
+        match node {
+            Node::File { content: Some(content), .. } => Some(content),
+            Node::File { content: None, real: Some(real) } => {
+                let size = fs::metadata(&real).ok()?.len();
+                if size > self.ctx.file_limits.max_read_bytes {
+                    return None;
+                }
+                fs::read_to_string(&real).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// State of a path on disk
+fn disk(path: &Path) -> Node {
+    // Follow symlinks like the executor does, but still count dangling links as files
+    match fs::metadata(path).or_else(|_| path.symlink_metadata()) {
+        Ok(meta) if meta.is_dir() => Node::Dir {
+            backing: Some(path.to_path_buf()),
+        },
+        Ok(_) => Node::File {
+            content: None,
+            real: Some(path.to_path_buf()),
+        },
+        Err(_) => Node::Missing,
+    }
+}
+
+fn kind(node: &Node) -> &'static str {
+    match node {
+        Node::Dir { .. } => "directory",
+        _ => "file",
+    }
+}
+
+fn replace_effect(existing: &Node) -> &'static str {
+    match existing {
+        Node::Missing => "create",
+        _ => "overwrite",
+    }
+}
+
+fn effect(effect: &str, kind: &str, path: &str) -> serde_json::Value {
+    serde_json::json!({
+        "effect": effect,
+        "kind": kind,
+        "path": path,
+    })
+}
+
+fn remove_effect(kind: &str, args: &DeleteArgs) -> serde_json::Value {
+    serde_json::json!({
+        "effect": "remove",
+        "kind": kind,
+        "path": args.path,
+        "permanent": args.permanent.unwrap_or(false),
+    })
+}
+
+/// Point at the earlier step that put a path in its current state
+fn because(cause: Option<usize>) -> String {
+    match cause {
+        Some(step) => format!(" (because of step {})", step),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,136 @@
+use crate::assistant::executor::content::Encoding;
+use crate::assistant::schema::{self, AppendFileArgs, ApplyPatchArgs, ReplaceTextArgs};
+use crate::assistant::types::*;
+use crate::assistant::validator::{check_access, PathAccess};
+use regex::{NoExpand, Regex};
+use std::fs;
+
+/// New content of a file after an edit, plus what changed
+#[derive(Debug, Clone)]
+pub struct EditOutcome {
+    pub content: String,
+    pub matches: Option<usize>, // Number of replacements made by FsReplaceText
+}
+
+/// Compute the content an edit action would produce from the current content.
+/// Used both to execute the edit and to preview it, so both always agree.
+pub fn apply_edit(action: &Action, current: &str) -> Result<EditOutcome, String> {
+    match action.action_type {
+        ActionType::FsAppendFile => {
+            let args: AppendFileArgs = schema::parse_args(action)?;
+            Ok(EditOutcome {
+                content: format!("{}{}", current, args.content),
+                matches: None,
+            })
+        }
+        ActionType::FsReplaceText => {
+            let args: ReplaceTextArgs = schema::parse_args(action)?;
+            let pattern = args.pattern.as_str();
+
+            let (content, matches) =
+                replace_text(current, pattern, &args.replacement, args.regex.unwrap_or(false))?;
+
+            if matches == 0 {
+                return Err(format!("Pattern not found: {}", pattern));
+            }
+            if let Some(expected) = args.expected_matches {
+                if matches as u64 != expected {
+                    return Err(format!(
+                        "Expected {} match(es) for '{}' but found {}",
+                        expected, pattern, matches
+                    ));
+                }
+            }
+
+            Ok(EditOutcome {
+                content,
+                matches: Some(matches),
+            })
+        }
+        ActionType::FsApplyPatch => {
+            let args: ApplyPatchArgs = schema::parse_args(action)?;
+            let patch = diffy::Patch::from_str(&args.patch)
+                .map_err(|e| format!("Invalid patch: {}", e))?;
+            let content = diffy::apply(current, &patch)
+                .map_err(|e| format!("Patch does not apply: {}", e))?;
+
+            Ok(EditOutcome {
+                content,
+                matches: None,
+            })
+        }
+        _ => Err(format!("{:?} is not an edit action", action.action_type)),
+    }
+}
+
+/// Replace every occurrence of a literal string or regex.
+/// Regex replacements may refer to capture groups (`$1`, `${name}`).
+fn replace_text(
+    current: &str,
+    pattern: &str,
+    replacement: &str,
+    use_regex: bool,
+) -> Result<(String, usize), String> {
+    let re = if use_regex {
+        Regex::new(pattern).map_err(|e| format!("Invalid regex '{}': {}", pattern, e))?
+    } else {
+        Regex::new(&regex::escape(pattern)).map_err(|e| format!("Invalid pattern: {}", e))?
+    };
+
+    let matches = re.find_iter(current).count();
+    let content = if use_regex {
+        re.replace_all(current, replacement).into_owned()
+    } else {
+        re.replace_all(current, NoExpand(replacement)).into_owned()
+    };
+
+    Ok((content, matches))
+}
+
+/// Unified diff between two versions of a file
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    diffy::DiffOptions::new()
+        .set_original_filename(path.to_string())
+        .set_modified_filename(path.to_string())
+        .create_patch(old, new)
+        .to_string()
+}
+
+/// Diff an action would apply to its file, for the preview.
+/// Only computed for readable, unprotected text files within the read limit.
+pub fn preview_diff(action: &Action, ctx: &ExecutionContext) -> Option<String> {
+    let path_str = action.args.get("path").and_then(|v| v.as_str())?;
+    check_access(ctx, path_str, PathAccess::Read).ok()?;
+
+    let encoding = action.args.get("encoding").and_then(|v| v.as_str()).unwrap_or("utf-8");
+    if Encoding::parse(encoding).ok()? != Encoding::Utf8 {
+        return None;
+    }
+    if fs::metadata(path_str).is_ok_and(|meta| meta.len() > ctx.file_limits.max_read_bytes) {
+        return None;
+    }
+
+    let current = match fs::read_to_string(path_str) {
+        Ok(content) => content,
+        Err(_) if !std::path::Path::new(path_str).exists() => String::new(),
+        Err(_) => return None,
+    };
+
+    let new_content = match action.action_type {
+        ActionType::FsCreateFile => action
+            .args
+            .get("content")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        ActionType::FsAppendFile | ActionType::FsReplaceText | ActionType::FsApplyPatch => {
+            match apply_edit(action, &current) {
+                Ok(outcome) => outcome.content,
+                Err(e) => return Some(format!("Edit cannot be applied: {}", e)),
+            }
+        }
+        _ => return None,
+    };
+
+    Some(unified_diff(path_str, &current, &new_content))
+}
@@ -1,7 +1,10 @@
+use crate::assistant::executor::{archive, content, edit, search, trash};
+use crate::assistant::schema::{self, *};
 use crate::assistant::types::*;
+use crate::assistant::validator::{check_access, check_no_protected_inside, PathAccess};
 use std::fs;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 /// Execute file system actions safely
 pub fn execute_fs_action(
@@ -15,55 +18,61 @@ pub fn execute_fs_action(
         ActionType::FsMoveFile => move_file(action, ctx),
         ActionType::FsDeleteFile => delete_file(action, ctx),
         ActionType::FsCreateDirectory => create_directory(action, ctx),
+        ActionType::FsListDirectory => list_directory(action, ctx),
+        ActionType::FsCopyDirectory => copy_directory(action, ctx),
+        ActionType::FsMoveDirectory => move_directory(action, ctx),
+        ActionType::FsDeleteDirectory => delete_directory(action, ctx),
+        ActionType::FsAppendFile | ActionType::FsReplaceText | ActionType::FsApplyPatch => {
+            edit_file(action, ctx)
+        }
+        ActionType::FsRestoreFromTrash => restore_from_trash(action, ctx),
+        ActionType::FsSearch => search::search(action, ctx),
+        ActionType::FsStat => search::stat(action, ctx),
+        ActionType::ArchiveCreate => archive::create(action, ctx),
+        ActionType::ArchiveExtract => archive::extract(action, ctx),
     }
 }
 
 /// Create a new file
-fn create_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
-    let path_str = action.args
-        .get("path")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing 'path' argument")?;
+fn create_file(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: CreateFileArgs = schema::parse_args(action)?;
+    let path_str = args.path.as_str();
+
+    // Refuse protected paths and writes outside the allowed roots
+    check_access(ctx, path_str, PathAccess::Write)?;
 
     let path = PathBuf::from(path_str);
 
     // Create parent directory if needed
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create parent directory: {}", e))?;
-    }
+    let created_dirs = match path.parent() {
+        Some(parent) => create_dirs(parent)?,
+        None => Vec::new(),
+    };
 
     // Get content (default to empty string)
-    let content = action.args
-        .get("content")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
-
-    let encoding = action.args
-        .get("encoding")
-        .and_then(|v| v.as_str())
-        .unwrap_or("utf-8");
-
-    // Create and write file
-    let mut file = fs::File::create(&path)
+    let content = args.content.as_deref().unwrap_or("");
+
+    let encoding = args.encoding.unwrap_or(content::Encoding::Utf8);
+
+    // Validate before truncating anything, then decode straight into the file
+    content::decode_to(content, encoding, &mut std::io::sink())?;
+
+    let file = fs::File::create(&path)
         .map_err(|e| format!("Failed to create file: {}", e))?;
+    let mut writer = BufWriter::new(file);
 
-    match encoding {
-        "utf-8" | "utf8" => {
-            file.write_all(content.as_bytes())
-                .map_err(|e| format!("Failed to write file: {}", e))?;
-        }
-        _ => {
-            return Err(format!("Unsupported encoding: {}", encoding));
-        }
-    }
+    let size = content::decode_to(content, encoding, &mut writer)?;
+    writer.flush()
+        .map_err(|e| format!("Failed to write file: {}", e))?;
 
     Ok(ActionExecutionResult {
         action_id: action.id.clone(),
         success: true,
         output: Some(serde_json::json!({
             "path": path_str,
-            "size": content.len(),
+            "size": size,
+            "encoding": encoding.name(),
+            "created_dirs": created_dirs,
         })),
         error: None,
         snapshot_id: None, // Creation doesn't need snapshot
@@ -71,11 +80,12 @@ fn create_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecuti
 }
 
 /// Read a file
-fn read_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
-    let path_str = action.args
-        .get("path")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing 'path' argument")?;
+fn read_file(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: ReadFileArgs = schema::parse_args(action)?;
+    let path_str = args.path.as_str();
+
+    // Refuse protected paths
+    check_access(ctx, path_str, PathAccess::Read)?;
 
     let path = PathBuf::from(path_str);
 
@@ -89,21 +99,51 @@ fn read_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecution
         return Err(format!("Path is not a file: {}", path_str));
     }
 
-    // Read file content
+    let encoding = args.encoding;
+    let offset = args.offset.unwrap_or(0);
+    let requested = args.length;
+
+    // Read only the requested range, capped so large files never load whole
     let mut file = fs::File::open(&path)
         .map_err(|e| format!("Failed to open file: {}", e))?;
-
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
+    let size = file.metadata()
+        .map_err(|e| format!("Failed to read file metadata: {}", e))?
+        .len();
+
+    let available = size.saturating_sub(offset);
+    let wanted = requested.unwrap_or(available).min(available);
+    let limit = wanted.min(ctx.file_limits.max_read_bytes);
+
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("Failed to seek in file: {}", e))?;
+    let mut bytes = Vec::with_capacity(limit as usize);
+    file.take(limit).read_to_end(&mut bytes)
         .map_err(|e| format!("Failed to read file: {}", e))?;
 
+    let mime = if offset == 0 {
+        content::sniff_mime(&path, &bytes)
+    } else {
+        let mut head = Vec::new();
+        let _ = fs::File::open(&path).and_then(|f| f.take(8192).read_to_end(&mut head));
+        content::sniff_mime(&path, &head)
+    };
+
+    let (contents, encoding, used) = content::encode(&bytes, encoding)?;
+    let end = offset + used as u64;
+
     Ok(ActionExecutionResult {
         action_id: action.id.clone(),
         success: true,
         output: Some(serde_json::json!({
             "path": path_str,
             "content": contents,
-            "size": contents.len(),
+            "encoding": encoding.name(),
+            "mime": mime,
+            "size": size,
+            "offset": offset,
+            "length": used,
+            "truncated": (used as u64) < wanted,
+            "next_offset": if end < size { Some(end) } else { None },
         })),
         error: None,
         snapshot_id: None,
@@ -111,16 +151,14 @@ fn read_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecution
 }
 
 /// Copy a file
-fn copy_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
-    let source_str = action.args
-        .get("source_path")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing 'source_path' argument")?;
+fn copy_file(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: TransferArgs = schema::parse_args(action)?;
+    let source_str = args.source_path.as_str();
+    let destination_str = args.destination_path.as_str();
 
-    let destination_str = action.args
-        .get("destination_path")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing 'destination_path' argument")?;
+    // Refuse protected paths and writes outside the allowed roots
+    check_access(ctx, source_str, PathAccess::Read)?;
+    check_access(ctx, destination_str, PathAccess::Write)?;
 
     let source = PathBuf::from(source_str);
     let destination = PathBuf::from(destination_str);
@@ -134,22 +172,35 @@ fn copy_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecution
         return Err(format!("Source is not a file: {}", source_str));
     }
 
-    // Create parent directory if needed
-    if let Some(parent) = destination.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create parent directory: {}", e))?;
+    let target = settle_conflict(&destination, OnConflict::of(action)?, |p| p.symlink_metadata().is_ok())?;
+    if target.skipped() {
+        return Ok(skipped_transfer(action, source_str, destination_str));
     }
+    if target.path.is_dir() {
+        return Err(format!("Destination is a directory: {}", destination_str));
+    }
+    check_not_same(&source, &target.path)?;
+
+    // Create parent directory if needed
+    let created_dirs = match target.path.parent() {
+        Some(parent) => create_dirs(parent)?,
+        None => Vec::new(),
+    };
 
     // Copy file
-    fs::copy(&source, &destination)
+    fs::copy(&source, &target.path)
         .map_err(|e| format!("Failed to copy file: {}", e))?;
 
+    let written = target.path.to_string_lossy();
     Ok(ActionExecutionResult {
         action_id: action.id.clone(),
         success: true,
         output: Some(serde_json::json!({
             "source": source_str,
-            "destination": destination_str,
+            "destination": written,
+            "path": written,
+            "conflict": target.conflict.map(OnConflict::name),
+            "created_dirs": created_dirs,
         })),
         error: None,
         snapshot_id: None, // Copy doesn't modify source, so no snapshot needed
@@ -157,16 +208,14 @@ fn copy_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecution
 }
 
 /// Move a file
-fn move_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
-    let source_str = action.args
-        .get("source_path")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing 'source_path' argument")?;
+fn move_file(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: TransferArgs = schema::parse_args(action)?;
+    let source_str = args.source_path.as_str();
+    let destination_str = args.destination_path.as_str();
 
-    let destination_str = action.args
-        .get("destination_path")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing 'destination_path' argument")?;
+    // Refuse protected paths and writes outside the allowed roots
+    check_access(ctx, source_str, PathAccess::Remove)?;
+    check_access(ctx, destination_str, PathAccess::Write)?;
 
     let source = PathBuf::from(source_str);
     let destination = PathBuf::from(destination_str);
@@ -176,22 +225,40 @@ fn move_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecution
         return Err(format!("Source file does not exist: {}", source_str));
     }
 
-    // Create parent directory if needed
-    if let Some(parent) = destination.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create parent directory: {}", e))?;
+    // Directories go through fs_move_directory, which checks the tree limits; a symlink moves as the link
+    if !source.is_file() && !source.is_symlink() {
+        return Err(format!("Source is not a file: {}", source_str));
     }
 
+    let target = settle_conflict(&destination, OnConflict::of(action)?, |p| p.symlink_metadata().is_ok())?;
+    if target.skipped() {
+        return Ok(skipped_transfer(action, source_str, destination_str));
+    }
+    if target.path.is_dir() {
+        return Err(format!("Destination is a directory: {}", destination_str));
+    }
+    check_not_same(&source, &target.path)?;
+
+    // Create parent directory if needed
+    let created_dirs = match target.path.parent() {
+        Some(parent) => create_dirs(parent)?,
+        None => Vec::new(),
+    };
+
     // Move file
-    fs::rename(&source, &destination)
+    move_path(&source, &target.path)
         .map_err(|e| format!("Failed to move file: {}", e))?;
 
+    let written = target.path.to_string_lossy();
     Ok(ActionExecutionResult {
         action_id: action.id.clone(),
         success: true,
         output: Some(serde_json::json!({
             "source": source_str,
-            "destination": destination_str,
+            "destination": written,
+            "path": written,
+            "conflict": target.conflict.map(OnConflict::name),
+            "created_dirs": created_dirs,
         })),
         error: None,
         snapshot_id: None, // Snapshot should be created before move
@@ -199,72 +266,46 @@ fn move_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecution
 }
 
 /// Delete a file (move to trash)
-fn delete_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
-    let path_str = action.args
-        .get("path")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing 'path' argument")?;
+fn delete_file(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: DeleteArgs = schema::parse_args(action)?;
+    let path_str = args.path.as_str();
+
+    // Refuse protected paths and writes outside the allowed roots
+    check_access(ctx, path_str, PathAccess::Remove)?;
 
     let path = PathBuf::from(path_str);
 
     // Check if file exists
-    if !path.exists() {
+    if path.symlink_metadata().is_err() {
         return Err(format!("File does not exist: {}", path_str));
     }
 
-    // Try to move to trash (platform-specific)
-    #[cfg(target_os = "macos")]
-    {
-        use std::process::Command;
-        let output = Command::new("osascript")
-            .arg("-e")
-            .arg(format!("tell application \"Finder\" to move POSIX file \"{}\" to trash", path_str))
-            .output()
-            .map_err(|e| format!("Failed to move to trash: {}", e))?;
-        
-        if !output.status.success() {
-            // Fallback to regular delete if trash fails
-            fs::remove_file(&path)
-                .map_err(|e| format!("Failed to delete file: {}", e))?;
-        }
+    if path.is_dir() {
+        return Err(format!("Path is a directory: {}", path_str));
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        use std::process::Command;
-        let output = Command::new("powershell")
-            .arg("-Command")
-            .arg(format!("Remove-Item -Path '{}' -Force -ErrorAction Stop", path_str))
-            .output();
-        
-        if output.is_err() || !output.as_ref().unwrap().status.success() {
-            // Fallback to regular delete
-            fs::remove_file(&path)
-                .map_err(|e| format!("Failed to delete file: {}", e))?;
-        }
-    }
-
-    #[cfg(target_os = "linux")]
-    {
-        // On Linux, use gvfs-trash if available, otherwise regular delete
-        use std::process::Command;
-        let output = Command::new("gvfs-trash")
-            .arg(&path_str)
-            .output();
-        
-        if output.is_err() || !output.as_ref().unwrap().status.success() {
-            // Fallback to regular delete
-            fs::remove_file(&path)
-                .map_err(|e| format!("Failed to delete file: {}", e))?;
-        }
-    }
+    remove_to_trash(action, &args, &path, |p| fs::remove_file(p))
+}
 
-    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
-    {
-        // Fallback: regular delete
-        fs::remove_file(&path)
-            .map_err(|e| format!("Failed to delete file: {}", e))?;
-    }
+/// Move a file or tree to the trash, or delete it permanently only when the
+/// action explicitly asks for it with `"permanent": true`
+fn remove_to_trash(
+    action: &Action,
+    args: &DeleteArgs,
+    path: &Path,
+    remove: impl Fn(&Path) -> std::io::Result<()>,
+) -> Result<ActionExecutionResult, String> {
+    let path_str = args.path.as_str();
+    let permanent = args.permanent.unwrap_or(false);
+
+    let trashed_path = if permanent {
+        remove(path).map_err(|e| format!("Failed to delete {}: {}", path_str, e))?;
+        None
+    } else {
+        trash::move_to_trash(path)
+            .map_err(|e| format!("{}. Nothing was deleted; set \"permanent\" to delete it permanently instead", e))?
+            .trashed_path
+    };
 
     Ok(ActionExecutionResult {
         action_id: action.id.clone(),
@@ -272,33 +313,633 @@ fn delete_file(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecuti
         output: Some(serde_json::json!({
             "path": path_str,
             "deleted": true,
+            "trashed": !permanent,
+            "trashed_path": trashed_path.map(|p| p.to_string_lossy().to_string()),
         })),
         error: None,
         snapshot_id: None, // Snapshot should be created before delete
     })
 }
 
+/// Move a file or directory out of the trash back to `path`
+fn restore_from_trash(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: RestoreFromTrashArgs = schema::parse_args(action)?;
+    let path_str = args.path.as_str();
+    let trash_path_str = args.trash_path.as_deref();
+
+    // Refuse protected paths and writes outside the allowed roots
+    check_access(ctx, path_str, PathAccess::Write)?;
+    if let Some(trash_path_str) = trash_path_str {
+        check_access(ctx, trash_path_str, PathAccess::Remove)?;
+    }
+
+    let created_dirs = match Path::new(path_str).parent() {
+        Some(parent) => missing_dirs(parent),
+        None => Vec::new(),
+    };
+
+    let trashed_path = trash::restore_from_trash(trash_path_str.map(Path::new), Path::new(path_str))?;
+
+    Ok(ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(serde_json::json!({
+            "path": path_str,
+            "restored_from": trashed_path.to_string_lossy(),
+            "created_dirs": created_dirs
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect::<Vec<_>>(),
+        })),
+        error: None,
+        snapshot_id: None,
+    })
+}
+
 /// Create a directory
-fn create_directory(action: &Action, _ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
-    let path_str = action.args
-        .get("path")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing 'path' argument")?;
+fn create_directory(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: CreateDirectoryArgs = schema::parse_args(action)?;
+    let path_str = args.path.as_str();
+
+    // Refuse protected paths and writes outside the allowed roots
+    check_access(ctx, path_str, PathAccess::Write)?;
 
     let path = PathBuf::from(path_str);
 
+    // Remember whether the directory already existed so undo leaves it alone
+    let created = !path.exists();
+
     // Create directory (and parents)
-    fs::create_dir_all(&path)
-        .map_err(|e| format!("Failed to create directory: {}", e))?;
+    let created_dirs = create_dirs(&path)?;
 
     Ok(ActionExecutionResult {
         action_id: action.id.clone(),
         success: true,
         output: Some(serde_json::json!({
             "path": path_str,
+            "created": created,
+            "created_dirs": created_dirs,
         })),
         error: None,
         snapshot_id: None,
     })
 }
 
+
+/// List a directory, optionally recursing into subdirectories.
+/// Protected locations are listed but never descended into.
+fn list_directory(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: ListDirectoryArgs = schema::parse_args(action)?;
+    let path_str = args.path.as_str();
+    let recursive = args.recursive.unwrap_or(false);
+
+    // Refuse protected paths
+    check_access(ctx, path_str, PathAccess::Read)?;
+
+    let path = PathBuf::from(path_str);
+
+    if !path.is_dir() {
+        return Err(format!("Path is not a directory: {}", path_str));
+    }
+
+    let max_entries = ctx.directory_limits.max_entries;
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    let mut pending = vec![path];
+
+    'walk: while let Some(dir) = pending.pop() {
+        let mut children: Vec<fs::DirEntry> = fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok())
+            .collect();
+        children.sort_by_key(|entry| entry.file_name());
+
+        for entry in children {
+            if entries.len() >= max_entries {
+                truncated = true;
+                break 'walk;
+            }
+
+            let metadata = entry
+                .metadata()
+                .map_err(|e| format!("Failed to read metadata: {}", e))?;
+            let child = entry.path();
+            let child_str = child.to_string_lossy().to_string();
+
+            entries.push(serde_json::json!({
+                "name": entry.file_name().to_string_lossy(),
+                "path": child_str,
+                "is_dir": metadata.is_dir(),
+                "size": metadata.len(),
+            }));
+
+            if recursive && metadata.is_dir() && check_access(ctx, &child_str, PathAccess::Read).is_ok() {
+                pending.push(child);
+            }
+        }
+    }
+
+    Ok(ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(serde_json::json!({
+            "path": path_str,
+            "entries": entries,
+            "truncated": truncated,
+        })),
+        error: None,
+        snapshot_id: None,
+    })
+}
+
+/// Copy a directory tree. An existing destination is never merged into:
+/// it fails, is replaced (the executor snapshots it first), renamed around or skipped.
+fn copy_directory(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: TransferArgs = schema::parse_args(action)?;
+    let source_str = args.source_path.as_str();
+    let destination_str = args.destination_path.as_str();
+
+    // Refuse protected paths (including inside the tree) and writes outside the allowed roots
+    check_access(ctx, source_str, PathAccess::Read)?;
+    check_no_protected_inside(ctx, source_str)?;
+    check_access(ctx, destination_str, PathAccess::Write)?;
+
+    let source = PathBuf::from(source_str);
+    let destination = PathBuf::from(destination_str);
+
+    if !source.is_dir() {
+        return Err(format!("Source is not a directory: {}", source_str));
+    }
+
+    // Merging into an existing tree could not be undone cleanly
+    let target = settle_conflict(&destination, OnConflict::of(action)?, |p| p.symlink_metadata().is_ok())?;
+    if target.skipped() {
+        return Ok(skipped_transfer(action, source_str, destination_str));
+    }
+
+    let size = check_tree_limits(&source, &ctx.directory_limits)?;
+
+    if target.conflict == Some(OnConflict::Overwrite) {
+        check_not_inside(&source, &target.path)?;
+        remove_existing(&target.path)?;
+    }
+
+    let created_dirs = match target.path.parent() {
+        Some(parent) => create_dirs(parent)?,
+        None => Vec::new(),
+    };
+
+    copy_tree(&source, &target.path)
+        .map_err(|e| format!("Failed to copy directory: {}", e))?;
+
+    let written = target.path.to_string_lossy();
+    Ok(ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(serde_json::json!({
+            "source": source_str,
+            "destination": written,
+            "path": written,
+            "conflict": target.conflict.map(OnConflict::name),
+            "created": true,
+            "created_dirs": created_dirs,
+            "bytes": size.bytes,
+            "entries": size.entries,
+        })),
+        error: None,
+        snapshot_id: None,
+    })
+}
+
+/// Move a directory tree, settling an existing destination like `copy_directory`
+fn move_directory(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: TransferArgs = schema::parse_args(action)?;
+    let source_str = args.source_path.as_str();
+    let destination_str = args.destination_path.as_str();
+
+    // Refuse protected paths and writes outside the allowed roots
+    check_access(ctx, source_str, PathAccess::Remove)?;
+    check_access(ctx, destination_str, PathAccess::Write)?;
+
+    let source = PathBuf::from(source_str);
+    let destination = PathBuf::from(destination_str);
+
+    if !source.is_dir() {
+        return Err(format!("Source is not a directory: {}", source_str));
+    }
+
+    let target = settle_conflict(&destination, OnConflict::of(action)?, |p| p.symlink_metadata().is_ok())?;
+    if target.skipped() {
+        return Ok(skipped_transfer(action, source_str, destination_str));
+    }
+
+    // A move across filesystems copies the whole tree
+    let size = check_tree_limits(&source, &ctx.directory_limits)?;
+
+    if target.conflict == Some(OnConflict::Overwrite) {
+        check_not_inside(&source, &target.path)?;
+        remove_existing(&target.path)?;
+    }
+
+    let created_dirs = match target.path.parent() {
+        Some(parent) => create_dirs(parent)?,
+        None => Vec::new(),
+    };
+
+    move_path(&source, &target.path)
+        .map_err(|e| format!("Failed to move directory: {}", e))?;
+
+    let written = target.path.to_string_lossy();
+    Ok(ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(serde_json::json!({
+            "source": source_str,
+            "destination": written,
+            "path": written,
+            "conflict": target.conflict.map(OnConflict::name),
+            "created_dirs": created_dirs,
+            "bytes": size.bytes,
+            "entries": size.entries,
+        })),
+        error: None,
+        snapshot_id: None,
+    })
+}
+
+/// Delete a directory tree by moving it to the trash (the executor snapshots it first)
+fn delete_directory(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: DeleteArgs = schema::parse_args(action)?;
+    let path_str = args.path.as_str();
+
+    // Refuse protected paths, including any inside the tree
+    check_access(ctx, path_str, PathAccess::Remove)?;
+
+    let path = PathBuf::from(path_str);
+
+    if !path.is_dir() {
+        return Err(format!("Path is not a directory: {}", path_str));
+    }
+
+    let size = check_tree_limits(&path, &ctx.directory_limits)?;
+
+    let mut result = remove_to_trash(action, &args, &path, |p| fs::remove_dir_all(p))?;
+    if let Some(output) = result.output.as_mut() {
+        output["bytes"] = serde_json::json!(size.bytes);
+        output["entries"] = serde_json::json!(size.entries);
+    }
+
+    Ok(result)
+}
+
+/// Append to, replace text in, or patch an existing text file
+/// (the executor snapshots it first)
+fn edit_file(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let path_string = match action.action_type {
+        ActionType::FsAppendFile => schema::parse_args::<AppendFileArgs>(action)?.path,
+        ActionType::FsReplaceText => schema::parse_args::<ReplaceTextArgs>(action)?.path,
+        _ => schema::parse_args::<ApplyPatchArgs>(action)?.path,
+    };
+    let path_str = path_string.as_str();
+
+    // Refuse protected paths and writes outside the allowed roots
+    check_access(ctx, path_str, PathAccess::Write)?;
+
+    let path = PathBuf::from(path_str);
+
+    if !path.is_file() {
+        return Err(format!("File does not exist: {}", path_str));
+    }
+
+    // The whole file is edited in memory, so it must fit in the read limit
+    let limit = ctx.file_limits.max_read_bytes;
+    let file = fs::File::open(&path)
+        .map_err(|e| format!("Failed to open file: {}", e))?;
+    let mut bytes = Vec::new();
+    file.take(limit + 1).read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    if bytes.len() as u64 > limit {
+        return Err(format!("{} is larger than the {} byte limit for editing", path_str, limit));
+    }
+    let current = String::from_utf8(bytes)
+        .map_err(|_| format!("Failed to read file: {} is not valid UTF-8", path_str))?;
+
+    let outcome = edit::apply_edit(action, &current)?;
+
+    fs::write(&path, &outcome.content)
+        .map_err(|e| format!("Failed to write file: {}", e))?;
+
+    Ok(ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(serde_json::json!({
+            "path": path_str,
+            "size": outcome.content.len(),
+            "previous_size": current.len(),
+            "matches": outcome.matches,
+        })),
+        error: None,
+        snapshot_id: None,
+    })
+}
+
+/// Where an action writes once a conflict with an existing destination is settled
+#[derive(Debug, Clone)]
+pub struct Destination {
+    pub path: PathBuf,
+    /// The policy that settled a conflict, if there was one
+    pub conflict: Option<OnConflict>,
+}
+
+impl Destination {
+    pub fn skipped(&self) -> bool {
+        self.conflict == Some(OnConflict::Skip)
+    }
+}
+
+/// Most numbered names tried beside a taken destination
+const MAX_RENAME_ATTEMPTS: usize = 1_000;
+
+/// Settle a destination that may already exist by the action's conflict policy.
+/// `exists` tells whether a path is taken, so a dry run can ask its simulated state.
+pub fn settle_conflict(
+    destination: &Path,
+    policy: OnConflict,
+    exists: impl Fn(&Path) -> bool,
+) -> Result<Destination, String> {
+    if !exists(destination) {
+        return Ok(Destination {
+            path: destination.to_path_buf(),
+            conflict: None,
+        });
+    }
+
+    let path = match policy {
+        OnConflict::Fail => {
+            return Err(format!(
+                "Destination already exists: {}; set on_conflict to overwrite, rename or skip",
+                destination.display()
+            ))
+        }
+        OnConflict::Overwrite | OnConflict::Skip => destination.to_path_buf(),
+        OnConflict::Rename => free_name(destination, &exists)?,
+    };
+
+    Ok(Destination {
+        path,
+        conflict: Some(policy),
+    })
+}
+
+/// The first numbered name beside `path` that is not taken: `report (1).pdf`, `report (2).pdf`, ...
+/// The extension stays at the end, including a compound one like `.tar.gz`.
+fn free_name(path: &Path, exists: &impl Fn(&Path) -> bool) -> Result<PathBuf, String> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("Destination has no file name: {}", path.display()))?
+        .to_string_lossy()
+        .to_string();
+
+    // A leading dot is part of the name, not an extension
+    let mut split = name.rfind('.').filter(|&i| i > 0).unwrap_or(name.len());
+    if name[..split].to_lowercase().ends_with(".tar") && split > 4 {
+        split -= 4;
+    }
+    let (stem, extension) = name.split_at(split);
+
+    (1..=MAX_RENAME_ATTEMPTS)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !exists(candidate))
+        .ok_or_else(|| format!("No free name beside {} after {} tries", path.display(), MAX_RENAME_ATTEMPTS))
+}
+
+/// Output of a copy, move or archive that left an existing destination as it was
+pub fn skipped_transfer(action: &Action, source: &str, destination: &str) -> ActionExecutionResult {
+    ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(serde_json::json!({
+            "source": source,
+            "destination": destination,
+            "path": destination,
+            "conflict": OnConflict::Skip.name(),
+            "created_dirs": [],
+        })),
+        error: None,
+        snapshot_id: None,
+    }
+}
+
+/// Refuse to overwrite a file with itself, which would truncate it
+fn check_not_same(source: &Path, destination: &Path) -> Result<(), String> {
+    match (fs::canonicalize(source), fs::canonicalize(destination)) {
+        (Ok(a), Ok(b)) if a == b => Err(format!(
+            "Source and destination are the same file: {}",
+            destination.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Refuse to replace a tree that holds the source, which would remove the source with it
+fn check_not_inside(source: &Path, destination: &Path) -> Result<(), String> {
+    let source = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+    let destination = fs::canonicalize(destination).unwrap_or_else(|_| destination.to_path_buf());
+    if source.starts_with(&destination) {
+        return Err(format!(
+            "Cannot replace {} with a copy of something inside it",
+            destination.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Remove an existing destination before it is replaced (the executor snapshots it first)
+fn remove_existing(path: &Path) -> Result<(), String> {
+    let meta = path
+        .symlink_metadata()
+        .map_err(|e| format!("Failed to read metadata: {}", e))?;
+    if meta.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+/// Rename `source` to `destination`. Across filesystems, where a rename is not possible,
+/// copy it, flush the copy to disk and only then remove the source.
+pub fn move_path(source: &Path, destination: &Path) -> std::io::Result<()> {
+    match fs::rename(source, destination) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {}
+        result => return result,
+    }
+
+    let file_type = source.symlink_metadata()?.file_type();
+    let copied = if file_type.is_dir() {
+        copy_tree(source, destination)
+    } else if file_type.is_symlink() {
+        copy_symlink(source, destination)
+    } else {
+        fs::copy(source, destination).map(drop)
+    };
+
+    // The source stays until the copy is on disk; a partial copy is removed again
+    if let Err(e) = copied.and_then(|_| sync_tree(destination)) {
+        let _ = if destination.is_dir() && !destination.is_symlink() {
+            fs::remove_dir_all(destination)
+        } else {
+            fs::remove_file(destination)
+        };
+        return Err(e);
+    }
+    if let Some(parent) = destination.parent() {
+        sync_dir(parent)?;
+    }
+
+    if file_type.is_dir() {
+        fs::remove_dir_all(source)
+    } else {
+        fs::remove_file(source)
+    }
+}
+
+/// Flush a file, or every file and directory of a tree, to disk. Symlinks are not followed.
+fn sync_tree(path: &Path) -> std::io::Result<()> {
+    let file_type = path.symlink_metadata()?.file_type();
+    if file_type.is_dir() {
+        for entry in fs::read_dir(path)? {
+            sync_tree(&entry?.path())?;
+        }
+        sync_dir(path)
+    } else if file_type.is_file() {
+        fs::File::open(path)?.sync_all()
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    // Directories cannot be opened for syncing on Windows
+    Ok(())
+}
+
+/// Size of a directory tree
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TreeSize {
+    pub bytes: u64,
+    pub entries: usize,
+}
+
+/// Measure a directory tree, failing as soon as it exceeds the limits.
+/// Symlinks are counted but not followed.
+pub fn check_tree_limits(path: &Path, limits: &DirectoryLimits) -> Result<TreeSize, String> {
+    let mut size = TreeSize::default();
+    let mut pending = vec![path.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+            let metadata = fs::symlink_metadata(entry.path())
+                .map_err(|e| format!("Failed to read metadata: {}", e))?;
+
+            size.entries += 1;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                size.bytes += metadata.len();
+            }
+
+            if size.entries > limits.max_entries {
+                return Err(format!(
+                    "{} has more than {} entries, which exceeds the directory limit",
+                    path.display(),
+                    limits.max_entries
+                ));
+            }
+            if size.bytes > limits.max_bytes {
+                return Err(format!(
+                    "{} is larger than {} bytes, which exceeds the directory limit",
+                    path.display(),
+                    limits.max_bytes
+                ));
+            }
+        }
+    }
+
+    Ok(size)
+}
+
+/// Recursively copy a directory tree. Symlinks are recreated, never followed.
+pub fn copy_tree(source: &Path, destination: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(destination)?;
+
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = destination.join(entry.file_name());
+
+        if file_type.is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_symlink(link: &Path, target: &Path) -> std::io::Result<()> {
+    replace_symlink(&fs::read_link(link)?, target)
+}
+
+/// Create a symlink at `target` pointing to `points_to`, replacing whatever is there
+#[cfg(unix)]
+pub fn replace_symlink(points_to: &Path, target: &Path) -> std::io::Result<()> {
+    if target.symlink_metadata().is_ok() {
+        fs::remove_file(target)?;
+    }
+    std::os::unix::fs::symlink(points_to, target)
+}
+
+#[cfg(not(unix))]
+pub fn replace_symlink(_points_to: &Path, target: &Path) -> std::io::Result<()> {
+    // Creating symlinks needs extra privileges on Windows
+    eprintln!("Skipping symlink {}", target.display());
+    Ok(())
+}
+
+/// Directories that would have to be created for `dir` to exist, outermost first
+pub fn missing_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|p| !p.as_os_str().is_empty() && !p.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+/// Create a directory and its parents, returning the ones that were created
+pub fn create_dirs(dir: &Path) -> Result<Vec<String>, String> {
+    let missing = missing_dirs(dir);
+
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create directory: {}", e))?;
+
+    Ok(missing
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect())
+}
@@ -0,0 +1,197 @@
+use crate::assistant::store;
+use crate::assistant::types::*;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::AppHandle;
+
+/// Write-ahead journal for plan execution.
+/// A plan is recorded before its first action runs and each action's intent
+/// is recorded before it touches the filesystem, so a plan interrupted by a
+/// crash can be finished or reverted on the next start.
+pub struct Journal {
+    conn: Connection,
+}
+
+/// A journaled action of an interrupted plan
+#[derive(Debug, Clone)]
+pub struct JournalStep {
+    pub action_id: String,
+    pub pre_state: serde_json::Value, // What the action was expected to create, recorded before it ran
+    pub output: Option<serde_json::Value>,
+    pub completed: bool,
+}
+
+/// A plan that was still executing when the app stopped
+#[derive(Debug, Clone)]
+pub struct InterruptedPlan {
+    pub plan: ActionPlan,
+    pub started_at: i64,
+    pub steps: Vec<JournalStep>,
+}
+
+impl Journal {
+    pub fn new(app: &AppHandle) -> Result<Self, String> {
+        Ok(Self {
+            conn: store::open(app)?,
+        })
+    }
+
+    /// Record that a plan is about to run.
+    /// Fails if an earlier run of the same plan is still journaled because its rollback failed.
+    pub fn begin(&self, plan: &ActionPlan) -> Result<(), String> {
+        let leftover: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT started_at FROM plan_journal WHERE plan_id = ?1",
+                params![plan.schema.id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to query plan journal: {}", e))?;
+
+        if leftover.is_some() {
+            let steps: Vec<String> = self.steps(&plan.schema.id)?.into_iter().map(|step| step.action_id).collect();
+            return Err(format!(
+                "Plan {} needs attention: an earlier run could not be rolled back (steps: {}). It is retried on the next start.",
+                plan.schema.id,
+                steps.join(", ")
+            ));
+        }
+
+        let plan_json = serde_json::to_string(plan)
+            .map_err(|e| format!("Failed to serialize plan for journal: {}", e))?;
+
+        self.conn
+            .execute(
+                "INSERT INTO plan_journal (plan_id, plan_json, started_at) VALUES (?1, ?2, ?3)",
+                params![plan.schema.id, plan_json, chrono::Utc::now().timestamp()],
+            )
+            .map_err(|e| format!("Failed to start plan journal: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Record the intent to run an action, before it runs
+    pub fn record_intent(
+        &self,
+        plan_id: &str,
+        seq: usize,
+        action: &Action,
+        pre_state: &serde_json::Value,
+    ) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO journal_steps (plan_id, seq, action_id, pre_state_json, completed, recorded_at) VALUES (?1, ?2, ?3, ?4, 0, ?5)",
+                params![
+                    plan_id,
+                    seq as i64,
+                    action.id,
+                    pre_state.to_string(),
+                    chrono::Utc::now().timestamp(),
+                ],
+            )
+            .map_err(|e| format!("Failed to journal action {}: {}", action.id, e))?;
+
+        Ok(())
+    }
+
+    /// Record that an action finished
+    pub fn record_done(
+        &self,
+        plan_id: &str,
+        seq: usize,
+        output: Option<&serde_json::Value>,
+    ) -> Result<(), String> {
+        self.conn
+            .execute(
+                "UPDATE journal_steps SET completed = 1, output_json = ?3 WHERE plan_id = ?1 AND seq = ?2",
+                params![plan_id, seq as i64, output.map(|o| o.to_string())],
+            )
+            .map_err(|e| format!("Failed to journal action result: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Drop the journal of a plan once it is committed or fully rolled back
+    pub fn finish(&self, plan_id: &str) -> Result<(), String> {
+        self.conn
+            .execute("DELETE FROM journal_steps WHERE plan_id = ?1", params![plan_id])
+            .and_then(|_| {
+                self.conn
+                    .execute("DELETE FROM plan_journal WHERE plan_id = ?1", params![plan_id])
+            })
+            .map_err(|e| format!("Failed to clear plan journal: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Get every plan that started but never finished, oldest first
+    pub fn interrupted_plans(&self) -> Result<Vec<InterruptedPlan>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT plan_id, plan_json, started_at FROM plan_journal ORDER BY started_at ASC")
+            .map_err(|e| format!("Failed to query plan journal: {}", e))?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })
+            .map_err(|e| format!("Failed to query plan journal: {}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read plan journal: {}", e))?;
+
+        let mut plans = Vec::new();
+        for (plan_id, plan_json, started_at) in rows {
+            let plan: ActionPlan = serde_json::from_str(&plan_json)
+                .map_err(|e| format!("Failed to parse journaled plan {}: {}", plan_id, e))?;
+            plans.push(InterruptedPlan {
+                plan,
+                started_at,
+                steps: self.steps(&plan_id)?,
+            });
+        }
+
+        Ok(plans)
+    }
+
+    fn steps(&self, plan_id: &str) -> Result<Vec<JournalStep>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT action_id, pre_state_json, output_json, completed
+                 FROM journal_steps WHERE plan_id = ?1 ORDER BY seq ASC",
+            )
+            .map_err(|e| format!("Failed to query journal steps: {}", e))?;
+
+        let rows = stmt
+            .query_map(params![plan_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, bool>(3)?,
+                ))
+            })
+            .map_err(|e| format!("Failed to query journal steps: {}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read journal steps: {}", e))?;
+
+        rows.into_iter()
+            .map(|(action_id, pre_state, output, completed)| {
+                Ok(JournalStep {
+                    action_id,
+                    pre_state: serde_json::from_str(&pre_state)
+                        .map_err(|e| format!("Failed to parse journal step: {}", e))?,
+                    output: output
+                        .map(|o| serde_json::from_str(&o))
+                        .transpose()
+                        .map_err(|e| format!("Failed to parse journal step: {}", e))?,
+                    completed,
+                })
+            })
+            .collect()
+    }
+}
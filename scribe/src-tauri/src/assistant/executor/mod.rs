@@ -1,4 +1,11 @@
 pub mod worker;
 pub mod fs_adapter;
 pub mod snapshot;
+pub mod journal;
+pub mod edit;
+pub mod trash;
+pub mod content;
+pub mod search;
+pub mod archive;
 
+pub mod dry_run;
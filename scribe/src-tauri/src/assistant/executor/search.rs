@@ -0,0 +1,506 @@
+use crate::assistant::executor::content;
+use crate::assistant::glob;
+use crate::assistant::preconditions;
+use crate::assistant::schema::{self, SearchArgs, StatArgs};
+use crate::assistant::types::*;
+use crate::assistant::validator::{check_access, PathAccess};
+use chrono::{DateTime, Utc};
+use regex::bytes::RegexBuilder;
+use regex::Regex;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// Directories never searched, whatever the ignore files say
+const IGNORED_DIRS: &[&str] = &[".git", "node_modules"];
+
+/// Files read per directory for ignore rules
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+/// Most matching lines reported per file
+const MAX_LINE_MATCHES: usize = 5;
+
+/// Longest matching line reported, in characters
+const MAX_LINE_CHARS: usize = 200;
+
+/// Bytes checked for a NUL to tell binary files from text
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+/// How long to wait past the deadline for a walk stuck in a slow read
+const STUCK_GRACE: Duration = Duration::from_secs(2);
+
+/// Find files below a directory by name, glob and content.
+/// The walk runs on its own thread and stops at the search limits; results are
+/// ranked best first and the best one is output as `path` for later steps.
+pub fn search(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: SearchArgs = schema::parse_args(action)?;
+
+    // Refuse protected paths
+    check_access(ctx, &args.path, PathAccess::Read)?;
+
+    let root = PathBuf::from(&args.path);
+    if !root.is_dir() {
+        return Err(format!("Path is not a directory: {}", args.path));
+    }
+
+    let query = Query::new(&args, &ctx.search_limits)?;
+    let timeout = Duration::from_millis(ctx.search_limits.timeout_ms);
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+
+    let walk = {
+        let ctx = ctx.clone();
+        let cancelled = Arc::clone(&cancelled);
+        move || {
+            let outcome = query.walk(&root, &ctx, Instant::now() + timeout, &cancelled);
+            let _ = sender.send(outcome);
+        }
+    };
+    std::thread::Builder::new()
+        .name("assistant-search".to_string())
+        .spawn(walk)
+        .map_err(|e| format!("Failed to start search: {}", e))?;
+
+    let outcome = receiver.recv_timeout(timeout + STUCK_GRACE).map_err(|_| {
+        cancelled.store(true, Ordering::Relaxed);
+        format!("Search in {} did not finish within {} ms", args.path, ctx.search_limits.timeout_ms)
+    })?;
+
+    Ok(ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(serde_json::json!({
+            "path": outcome.hits.first().map(|hit| hit.path.clone()),
+            "name": outcome.hits.first().and_then(|hit| Path::new(&hit.path).file_name()).map(|name| name.to_string_lossy().to_string()),
+            "results": outcome.hits.iter().map(Hit::to_json).collect::<Vec<_>>(),
+            "total_matches": outcome.total,
+            "scanned": outcome.scanned,
+            "skipped_large_files": outcome.skipped_large,
+            "truncated": outcome.truncated,
+            "timed_out": outcome.timed_out,
+        })),
+        error: None,
+        snapshot_id: None,
+    })
+}
+
+/// Metadata of a file or directory; symlinks are described, not followed
+pub fn stat(action: &Action, ctx: &ExecutionContext) -> Result<ActionExecutionResult, String> {
+    let args: StatArgs = schema::parse_args(action)?;
+    let path_str = args.path.as_str();
+
+    // Refuse protected paths
+    check_access(ctx, path_str, PathAccess::Read)?;
+
+    let path = PathBuf::from(path_str);
+    let meta = fs::symlink_metadata(&path)
+        .map_err(|_| format!("Path does not exist: {}", path_str))?;
+
+    let kind = if meta.is_symlink() {
+        "symlink"
+    } else if meta.is_dir() {
+        "directory"
+    } else {
+        "file"
+    };
+    let (readable, writable) = preconditions::permissions(&path);
+
+    let mut output = serde_json::json!({
+        "path": path_str,
+        "kind": kind,
+        "size": meta.len(),
+        "modified_at": timestamp(meta.modified()),
+        "created_at": timestamp(meta.created()),
+        "accessed_at": timestamp(meta.accessed()),
+        "readable": readable,
+        "writable": writable,
+    });
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        output["mode"] = serde_json::json!(format!("{:o}", meta.mode() & 0o7777));
+    }
+
+    if meta.is_symlink() {
+        output["target"] = serde_json::json!(fs::read_link(&path).ok().map(|t| t.to_string_lossy().to_string()));
+    } else if meta.is_dir() {
+        let entries = fs::read_dir(&path)
+            .map_err(|e| format!("Failed to read directory {}: {}", path_str, e))?
+            .take(ctx.directory_limits.max_entries + 1)
+            .count();
+        output["entries"] = serde_json::json!(entries.min(ctx.directory_limits.max_entries));
+        output["entries_truncated"] = serde_json::json!(entries > ctx.directory_limits.max_entries);
+    } else {
+        let mut head = Vec::new();
+        fs::File::open(&path)
+            .and_then(|file| file.take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head))
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        output["mime"] = serde_json::json!(content::sniff_mime(&path, &head));
+
+        if args.hash.unwrap_or(false) {
+            let sha256 = preconditions::sha256_file(&path)
+                .map_err(|e| format!("Failed to hash file: {}", e))?;
+            output["sha256"] = serde_json::json!(sha256);
+        }
+    }
+
+    Ok(ActionExecutionResult {
+        action_id: action.id.clone(),
+        success: true,
+        output: Some(output),
+        error: None,
+        snapshot_id: None,
+    })
+}
+
+fn timestamp(time: std::io::Result<std::time::SystemTime>) -> Option<String> {
+    time.ok().map(|t| DateTime::<Utc>::from(t).to_rfc3339())
+}
+
+/// A search's criteria, compiled once; every given criterion must match
+struct Query {
+    words: Vec<String>,
+    glob: Option<Regex>,
+    glob_on_path: bool,
+    content: Option<regex::bytes::Regex>,
+    exclude: Vec<Regex>,
+    include_hidden: bool,
+    include_directories: bool,
+    max_depth: Option<usize>,
+    max_results: usize,
+    max_entries: usize,
+    max_file_bytes: u64,
+}
+
+/// What a walk found before it finished or hit a limit
+struct Outcome {
+    hits: Vec<Hit>,
+    total: usize,
+    scanned: usize,
+    skipped_large: usize,
+    truncated: bool,
+    timed_out: bool,
+}
+
+/// One matching file or directory
+struct Hit {
+    path: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<DateTime<Utc>>,
+    score: u32,
+    lines: Vec<(usize, String)>,
+}
+
+impl Hit {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "path": self.path,
+            "is_dir": self.is_dir,
+            "size": self.size,
+            "modified_at": self.modified.map(|m| m.to_rfc3339()),
+            "score": self.score,
+            "matches": self.lines.iter().map(|(line, text)| serde_json::json!({
+                "line": line,
+                "text": text,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// `.gitignore`-style patterns from one directory, applied to paths below it.
+/// Negated patterns are not supported and are skipped.
+struct IgnoreRule {
+    base: PathBuf,
+    pattern: Regex,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRule {
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let subject = if self.anchored {
+            relative.to_string_lossy().to_string()
+        } else {
+            relative.file_name().unwrap_or_default().to_string_lossy().to_string()
+        };
+        self.pattern.is_match(&glob::normalize_separators(&subject))
+    }
+}
+
+impl Query {
+    fn new(args: &SearchArgs, limits: &SearchLimits) -> Result<Self, String> {
+        let words = args
+            .name
+            .as_deref()
+            .unwrap_or("")
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect();
+
+        let content = args
+            .content
+            .as_deref()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(!args.case_sensitive.unwrap_or(false))
+                    .build()
+                    .map_err(|e| format!("Invalid regex '{}': {}", pattern, e))
+            })
+            .transpose()?;
+
+        let exclude = args
+            .exclude
+            .iter()
+            .flatten()
+            .map(|pattern| glob::compile(pattern.trim_end_matches('/')))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            words,
+            glob: args.glob.as_deref().map(glob::compile).transpose()?,
+            glob_on_path: args.glob.as_deref().is_some_and(|g| g.contains('/')),
+            content,
+            exclude,
+            include_hidden: args.include_hidden.unwrap_or(false),
+            include_directories: args.include_directories.unwrap_or(false),
+            max_depth: args.max_depth,
+            // A plan may ask for fewer results than the settings allow, never more
+            max_results: args.max_results.unwrap_or(limits.max_results).min(limits.max_results),
+            max_entries: limits.max_entries,
+            max_file_bytes: limits.max_file_bytes,
+        })
+    }
+
+    /// Walk breadth-first so shallow matches are found before a limit is hit.
+    /// Symlinks are neither followed nor matched; protected paths are skipped.
+    fn walk(&self, root: &Path, ctx: &ExecutionContext, deadline: Instant, cancelled: &AtomicBool) -> Outcome {
+        let mut outcome = Outcome {
+            hits: Vec::new(),
+            total: 0,
+            scanned: 0,
+            skipped_large: 0,
+            truncated: false,
+            timed_out: false,
+        };
+        let mut rules: Vec<IgnoreRule> = Vec::new();
+        let mut pending = VecDeque::from([(root.to_path_buf(), 0usize)]);
+
+        'walk: while let Some((dir, depth)) = pending.pop_front() {
+            rules.extend(read_ignore_rules(&dir));
+
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            let mut children: Vec<fs::DirEntry> = entries.filter_map(|entry| entry.ok()).collect();
+            children.sort_by_key(|entry| entry.file_name());
+
+            for entry in children {
+                if cancelled.load(Ordering::Relaxed) || Instant::now() >= deadline {
+                    outcome.timed_out = true;
+                    break 'walk;
+                }
+                outcome.scanned += 1;
+                if outcome.scanned > self.max_entries {
+                    outcome.truncated = true;
+                    break 'walk;
+                }
+
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                // Symlinks, FIFOs and devices could lead outside the root or block a read
+                if !file_type.is_file() && !file_type.is_dir() {
+                    continue;
+                }
+
+                let path = entry.path();
+                let name = entry.file_name().to_string_lossy().to_string();
+                let is_dir = file_type.is_dir();
+                let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+
+                if (name.starts_with('.') && !self.include_hidden)
+                    || (is_dir && IGNORED_DIRS.contains(&name.as_str()))
+                    || self.excluded(&relative, &name)
+                    || rules.iter().any(|rule| rule.matches(&path, is_dir))
+                    || check_access(ctx, &path.to_string_lossy(), PathAccess::Read).is_err()
+                {
+                    continue;
+                }
+
+                if is_dir && self.max_depth.is_none_or(|max| depth < max) {
+                    pending.push_back((path.clone(), depth + 1));
+                }
+                if is_dir && !self.include_directories {
+                    continue;
+                }
+
+                if let Some(hit) = self.check(&path, &relative, is_dir, &mut outcome) {
+                    outcome.total += 1;
+                    outcome.hits.push(hit);
+                }
+            }
+        }
+
+        // Best score first, then most recently modified, then by path
+        outcome.hits.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(b.modified.cmp(&a.modified))
+                .then(a.path.cmp(&b.path))
+        });
+        if outcome.hits.len() > self.max_results {
+            outcome.hits.truncate(self.max_results);
+            outcome.truncated = true;
+        }
+        outcome
+    }
+
+    fn excluded(&self, relative: &Path, name: &str) -> bool {
+        let relative = glob::normalize_separators(&relative.to_string_lossy());
+        self.exclude
+            .iter()
+            .any(|pattern| pattern.is_match(name) || pattern.is_match(&relative))
+    }
+
+    /// Score an entry against every criterion, or None if one does not match
+    fn check(&self, path: &Path, relative: &Path, is_dir: bool, outcome: &mut Outcome) -> Option<Hit> {
+        let name = relative.file_name()?.to_string_lossy().to_lowercase();
+        let mut score = 0;
+
+        if let Some(glob) = &self.glob {
+            let subject = if self.glob_on_path {
+                relative.to_string_lossy().to_string()
+            } else {
+                relative.file_name()?.to_string_lossy().to_string()
+            };
+            if !glob.is_match(&glob::normalize_separators(&subject)) {
+                return None;
+            }
+            score += 1;
+        }
+
+        if !self.words.is_empty() {
+            score += self.name_score(relative, &name)?;
+        }
+
+        let meta = fs::metadata(path).ok()?;
+        let mut lines = Vec::new();
+        if let Some(content) = &self.content {
+            if is_dir {
+                return None;
+            }
+            if meta.len() > self.max_file_bytes {
+                outcome.skipped_large += 1;
+                return None;
+            }
+            let (count, found) = matching_lines(path, content)?;
+            score += count.min(10) as u32 * 2;
+            lines = found;
+        }
+
+        Some(Hit {
+            path: path.to_string_lossy().to_string(),
+            is_dir,
+            size: meta.len(),
+            modified: meta.modified().ok().map(DateTime::<Utc>::from),
+            score,
+            lines,
+        })
+    }
+
+    /// Every word must appear in the path below the root; words in the name itself weigh more,
+    /// and a name made of exactly the words, in any order, scores highest
+    fn name_score(&self, relative: &Path, name: &str) -> Option<u32> {
+        let path = relative.to_string_lossy().to_lowercase();
+        let mut score = 0;
+
+        for word in &self.words {
+            if name.contains(word.as_str()) {
+                score += 10;
+            } else if path.contains(word.as_str()) {
+                score += 4;
+            } else {
+                return None;
+            }
+        }
+
+        let stem = Path::new(name).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let normalized: String = stem.chars().map(|c| if c.is_alphanumeric() { c } else { ' ' }).collect();
+        let mut stem_words: Vec<&str> = normalized.split_whitespace().collect();
+        let mut words: Vec<&str> = self.words.iter().map(String::as_str).collect();
+        stem_words.sort_unstable();
+        words.sort_unstable();
+        if stem_words == words {
+            score += 20;
+        }
+
+        Some(score)
+    }
+}
+
+/// Number of lines in a text file that match, and the first few of them.
+/// None if nothing matches or the file looks binary.
+fn matching_lines(path: &Path, content: &regex::bytes::Regex) -> Option<(usize, Vec<(usize, String)>)> {
+    let bytes = fs::read(path).ok()?;
+    if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+        return None;
+    }
+
+    let mut count = 0;
+    let mut found = Vec::new();
+    for (index, line) in bytes.split(|b| *b == b'\n').enumerate() {
+        if !content.is_match(line) {
+            continue;
+        }
+        count += 1;
+        if found.len() < MAX_LINE_MATCHES {
+            let text: String = String::from_utf8_lossy(line).trim().chars().take(MAX_LINE_CHARS).collect();
+            found.push((index + 1, text));
+        }
+    }
+
+    (count > 0).then_some((count, found))
+}
+
+/// Ignore rules from the `.gitignore` and `.ignore` files of one directory
+fn read_ignore_rules(dir: &Path) -> Vec<IgnoreRule> {
+    let mut rules = Vec::new();
+
+    for file in IGNORE_FILES {
+        let Ok(text) = fs::read_to_string(dir.join(file)) else {
+            continue;
+        };
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            let dir_only = line.ends_with('/');
+            let pattern = line.trim_end_matches('/');
+            // A slash anywhere but the end ties the pattern to this directory
+            let anchored = pattern.contains('/');
+            let Ok(compiled) = glob::compile(pattern.trim_start_matches('/')) else {
+                continue;
+            };
+            rules.push(IgnoreRule {
+                base: dir.to_path_buf(),
+                pattern: compiled,
+                anchored,
+                dir_only,
+            });
+        }
+    }
+
+    rules
+}
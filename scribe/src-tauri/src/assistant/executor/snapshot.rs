@@ -1,27 +1,104 @@
+use crate::assistant::executor::fs_adapter;
+use crate::assistant::settings;
+use crate::assistant::store;
 use crate::assistant::types::*;
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 use std::fs;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
-use tempfile::TempDir;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
 use uuid::Uuid;
 
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// How often the background sweeper evicts expired and excess snapshots
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// zstd level for compressed blobs; low, since a snapshot is taken before every change
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Held while blobs are written or removed, so a blob a new snapshot reuses
+/// is never collected before the snapshot is recorded
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
 /// Snapshot manager for undo functionality
+/// File contents are stored once per distinct content under the app data
+/// directory, named by their SHA-256 and optionally zstd-compressed. Each
+/// snapshot lists its files in `snapshot_entries` and is tracked in the
+/// `action_snapshots` table so it outlives the plan that created it.
 pub struct SnapshotManager {
-    temp_dir: TempDir,
-    user_home: PathBuf,
+    blob_dir: PathBuf,
+    limits: SnapshotLimits,
+    conn: Connection,
+}
+
+/// What a snapshot entry saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+impl EntryKind {
+    fn name(self) -> &'static str {
+        match self {
+            EntryKind::File => "file",
+            EntryKind::Directory => "directory",
+            EntryKind::Symlink => "symlink",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "file" => Some(EntryKind::File),
+            "directory" => Some(EntryKind::Directory),
+            "symlink" => Some(EntryKind::Symlink),
+            _ => None,
+        }
+    }
+}
+
+/// One file, directory or symlink saved in a snapshot
+struct Entry {
+    /// Relative to the snapshotted path; empty for the path itself
+    path: String,
+    kind: EntryKind,
+    blob_hash: Option<String>,
+    mode: Option<u32>,
+    link_target: Option<String>,
 }
 
 impl SnapshotManager {
-    pub fn new(user_home: PathBuf) -> Result<Self, String> {
-        let temp_dir = tempfile::tempdir()
-            .map_err(|e| format!("Failed to create temp directory: {}", e))?;
+    pub fn new(app: &AppHandle) -> Result<Self, String> {
+        let blob_dir = app
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to get app data directory: {}", e))?
+            .join("snapshots")
+            .join("blobs");
+
+        fs::create_dir_all(&blob_dir)
+            .map_err(|e| format!("Failed to create snapshot directory: {}", e))?;
 
         Ok(Self {
-            temp_dir,
-            user_home,
+            blob_dir,
+            limits: settings::load(app)?.snapshot_limits,
+            conn: store::open(app)?,
         })
     }
 
-    /// Create a snapshot of a file before modification
+    /// Until when something done at `at` can be undone, under the current retention setting
+    pub fn undo_until(&self, at: i64) -> i64 {
+        at + self.limits.max_age_days as i64 * SECS_PER_DAY
+    }
+
+    /// Create a snapshot of a file or directory tree before modification.
+    /// Callers are expected to have checked directory size limits first.
     pub fn create_snapshot(
         &self,
         action_id: &str,
@@ -30,42 +107,240 @@ impl SnapshotManager {
         if !original_path.exists() {
             return Err("File does not exist, cannot create snapshot".to_string());
         }
-
-        if !original_path.is_file() {
-            return Err("Path is not a file, cannot snapshot".to_string());
+        if !original_path.is_dir() && !original_path.is_file() {
+            return Err("Path is not a file or directory, cannot snapshot".to_string());
         }
 
-        // Generate snapshot ID
-        let snapshot_id = Uuid::new_v4().to_string();
-        
-        // Create snapshot path
-        let snapshot_path = self.temp_dir.path().join(&snapshot_id);
-
-        // Copy file to snapshot location
-        fs::copy(original_path, &snapshot_path)
-            .map_err(|e| format!("Failed to copy file to snapshot: {}", e))?;
-
-        // Calculate retention (7 days from now)
         let created_at = chrono::Utc::now().timestamp();
-        let retention_until = created_at + (7 * 24 * 60 * 60); // 7 days in seconds
-
-        Ok(ActionSnapshot {
-            id: snapshot_id,
+        let snapshot = ActionSnapshot {
+            id: Uuid::new_v4().to_string(),
             action_id: action_id.to_string(),
             original_path: original_path.to_string_lossy().to_string(),
-            snapshot_path: snapshot_path.to_string_lossy().to_string(),
+            snapshot_path: self.blob_dir.to_string_lossy().to_string(),
             created_at,
-            retention_until,
+            retention_until: self.undo_until(created_at),
+        };
+
+        let _lock = lock_store();
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to record snapshot: {}", e))?;
+
+        // Blobs written here are only referenced once the transaction commits
+        let mut written = Vec::new();
+        let saved = self
+            .save_tree(&tx, &snapshot.id, original_path, &mut written)
+            .and_then(|()| record_snapshot(&tx, &snapshot))
+            .and_then(|()| tx.commit().map_err(|e| format!("Failed to record snapshot: {}", e)));
+
+        if let Err(e) = saved {
+            for blob in written {
+                let _ = fs::remove_file(blob);
+            }
+            return Err(e);
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Record every file, directory and symlink under `root` as entries of a snapshot,
+    /// storing file contents as blobs. A symlinked root is followed, like a copy would.
+    fn save_tree(
+        &self,
+        conn: &Connection,
+        snapshot_id: &str,
+        root: &Path,
+        written: &mut Vec<PathBuf>,
+    ) -> Result<(), String> {
+        let mut pending = vec![root.to_path_buf()];
+
+        while let Some(path) = pending.pop() {
+            let metadata = if path == root { fs::metadata(&path) } else { fs::symlink_metadata(&path) }
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let relative = path
+                .strip_prefix(root)
+                .ok()
+                .and_then(|rest| rest.to_str())
+                .ok_or_else(|| format!("Cannot snapshot {}: its name is not valid UTF-8", path.display()))?
+                .to_string();
+
+            let entry = if metadata.is_dir() {
+                let children = fs::read_dir(&path)
+                    .map_err(|e| format!("Failed to read directory {}: {}", path.display(), e))?;
+                for child in children {
+                    let child = child.map_err(|e| format!("Failed to read directory {}: {}", path.display(), e))?;
+                    pending.push(child.path());
+                }
+                Entry {
+                    path: relative,
+                    kind: EntryKind::Directory,
+                    blob_hash: None,
+                    mode: mode_of(&metadata),
+                    link_target: None,
+                }
+            } else if metadata.file_type().is_symlink() {
+                let target = fs::read_link(&path)
+                    .map_err(|e| format!("Failed to read symlink {}: {}", path.display(), e))?;
+                let target = target
+                    .to_str()
+                    .ok_or_else(|| format!("Cannot snapshot {}: its target is not valid UTF-8", path.display()))?
+                    .to_string();
+                Entry {
+                    path: relative,
+                    kind: EntryKind::Symlink,
+                    blob_hash: None,
+                    mode: None,
+                    link_target: Some(target),
+                }
+            } else if metadata.is_file() {
+                Entry {
+                    path: relative,
+                    kind: EntryKind::File,
+                    blob_hash: Some(self.store_blob(conn, &path, written)?),
+                    mode: mode_of(&metadata),
+                    link_target: None,
+                }
+            } else {
+                // Sockets, FIFOs and devices have no content to restore
+                continue;
+            };
+
+            conn.execute(
+                "INSERT INTO snapshot_entries (snapshot_id, path, kind, blob_hash, mode, link_target) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![snapshot_id, entry.path, entry.kind.name(), entry.blob_hash, entry.mode, entry.link_target],
+            )
+            .map_err(|e| format!("Failed to record snapshot entry: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Store a file's content unless the same content is already stored. Returns its hash.
+    fn store_blob(&self, conn: &Connection, path: &Path, written: &mut Vec<PathBuf>) -> Result<String, String> {
+        let temp = self.blob_dir.join(format!("{}.tmp", Uuid::new_v4()));
+        let (hash, size) = match write_blob(path, &temp, self.limits.compress) {
+            Ok(stored) => stored,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(format!("Failed to copy {} to snapshot: {}", path.display(), e));
+            }
+        };
+
+        let known = conn
+            .query_row("SELECT 1 FROM snapshot_blobs WHERE hash = ?1", params![hash], |_| Ok(()))
+            .optional()
+            .map_err(|e| format!("Failed to query snapshot blobs: {}", e))?
+            .is_some();
+        if known {
+            let _ = fs::remove_file(&temp);
+            return Ok(hash);
+        }
+
+        let blob_path = self.blob_path(&hash, self.limits.compress);
+        let stored = blob_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::rename(&temp, &blob_path))
+            .and_then(|()| fs::metadata(&blob_path));
+        let stored_size = match stored {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(format!("Failed to store snapshot blob: {}", e));
+            }
+        };
+        written.push(blob_path);
+
+        conn.execute(
+            "INSERT INTO snapshot_blobs (hash, size, stored_size, compressed, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![hash, size as i64, stored_size as i64, self.limits.compress, chrono::Utc::now().timestamp()],
+        )
+        .map_err(|e| format!("Failed to record snapshot blob: {}", e))?;
+
+        Ok(hash)
+    }
+
+    fn blob_path(&self, hash: &str, compressed: bool) -> PathBuf {
+        let name = if compressed { format!("{}.zst", hash) } else { hash.to_string() };
+        self.blob_dir.join(&hash[..2]).join(name)
+    }
+
+    /// Get all snapshots recorded for an action, oldest first
+    pub fn snapshots_for_action(&self, action_id: &str) -> Result<Vec<ActionSnapshot>, String> {
+        self.query_snapshots(
+            "SELECT id, action_id, original_path, snapshot_path, created_at, retention_until
+             FROM action_snapshots WHERE action_id = ?1 ORDER BY created_at ASC, rowid ASC",
+            action_id,
+        )
+    }
+
+    /// Whether a snapshot is still kept (it may have been evicted)
+    pub fn snapshot_exists(&self, snapshot_id: &str) -> Result<bool, String> {
+        self.conn
+            .query_row("SELECT 1 FROM action_snapshots WHERE id = ?1", params![snapshot_id], |_| Ok(()))
+            .optional()
+            .map(|row| row.is_some())
+            .map_err(|e| format!("Failed to query snapshots: {}", e))
+    }
+
+    fn query_snapshots(&self, sql: &str, param: impl rusqlite::ToSql) -> Result<Vec<ActionSnapshot>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(sql)
+            .map_err(|e| format!("Failed to query snapshots: {}", e))?;
+
+        let rows = stmt
+            .query_map(params![param], |row| {
+                Ok(ActionSnapshot {
+                    id: row.get(0)?,
+                    action_id: row.get(1)?,
+                    original_path: row.get(2)?,
+                    snapshot_path: row.get(3)?,
+                    created_at: row.get(4)?,
+                    retention_until: row.get(5)?,
+                })
+            })
+            .map_err(|e| format!("Failed to query snapshots: {}", e))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read snapshot: {}", e))
+    }
+
+    /// The entries of a snapshot, parents before their children.
+    /// Empty for snapshots taken as a full copy, before the blob store.
+    fn entries(&self, snapshot_id: &str) -> Result<Vec<Entry>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT path, kind, blob_hash, mode, link_target
+                 FROM snapshot_entries WHERE snapshot_id = ?1 ORDER BY path ASC",
+            )
+            .map_err(|e| format!("Failed to query snapshot entries: {}", e))?;
+
+        let rows = stmt
+            .query_map(params![snapshot_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+            })
+            .map_err(|e| format!("Failed to query snapshot entries: {}", e))?;
+
+        rows.map(|row| {
+            let (path, kind, blob_hash, mode, link_target) =
+                row.map_err(|e| format!("Failed to read snapshot entry: {}", e))?;
+            let kind = EntryKind::parse(&kind)
+                .ok_or_else(|| format!("Unknown snapshot entry kind '{}'", kind))?;
+            Ok(Entry { path, kind, blob_hash, mode, link_target })
         })
+        .collect()
     }
 
     /// Restore a file from snapshot
     pub fn restore_from_snapshot(&self, snapshot: &ActionSnapshot) -> Result<(), String> {
-        let snapshot_path = Path::new(&snapshot.snapshot_path);
         let original_path = Path::new(&snapshot.original_path);
+        let entries = self.entries(&snapshot.id)?;
 
-        if !snapshot_path.exists() {
-            return Err("Snapshot file does not exist".to_string());
+        if entries.is_empty() {
+            return Err(format!("Snapshot {} has no saved entries", snapshot.id));
         }
 
         // Create parent directory if needed
@@ -74,40 +349,303 @@ impl SnapshotManager {
                 .map_err(|e| format!("Failed to create parent directory: {}", e))?;
         }
 
-        // Copy snapshot back to original location
-        fs::copy(snapshot_path, original_path)
-            .map_err(|e| format!("Failed to restore from snapshot: {}", e))?;
+        for entry in &entries {
+            let target = if entry.path.is_empty() { original_path.to_path_buf() } else { original_path.join(&entry.path) };
+            let restored = match (entry.kind, &entry.blob_hash, &entry.link_target) {
+                (EntryKind::Directory, _, _) => fs::create_dir_all(&target).map_err(|e| e.to_string()),
+                (EntryKind::File, Some(hash), _) => self.restore_blob(hash, &target).and_then(|()| {
+                    entry.mode.map_or(Ok(()), |mode| set_mode(&target, mode)).map_err(|e| e.to_string())
+                }),
+                (EntryKind::Symlink, _, Some(link_target)) => {
+                    fs_adapter::replace_symlink(Path::new(link_target), &target).map_err(|e| e.to_string())
+                }
+                _ => Err("the snapshot entry is incomplete".to_string()),
+            };
+            restored.map_err(|e| format!("Failed to restore {} from snapshot: {}", target.display(), e))?;
+        }
+
+        // Directory permissions last, so read-only directories could still be filled
+        for entry in entries.iter().rev().filter(|entry| entry.kind == EntryKind::Directory) {
+            let target = if entry.path.is_empty() { original_path.to_path_buf() } else { original_path.join(&entry.path) };
+            if let Some(mode) = entry.mode {
+                set_mode(&target, mode)
+                    .map_err(|e| format!("Failed to restore permissions of {}: {}", target.display(), e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a blob's content to `target`, checking it still matches its hash
+    fn restore_blob(&self, hash: &str, target: &Path) -> Result<(), String> {
+        let compressed: bool = self
+            .conn
+            .query_row("SELECT compressed FROM snapshot_blobs WHERE hash = ?1", params![hash], |row| row.get(0))
+            .optional()
+            .map_err(|e| format!("Failed to query snapshot blobs: {}", e))?
+            .ok_or_else(|| format!("snapshot data {} is missing", hash))?;
+
+        let blob = fs::File::open(self.blob_path(hash, compressed))
+            .map_err(|e| format!("Failed to open snapshot data {}: {}", hash, e))?;
+        let content: Box<dyn Read> = if compressed {
+            Box::new(zstd::stream::read::Decoder::new(blob).map_err(|e| format!("Failed to decompress snapshot data: {}", e))?)
+        } else {
+            Box::new(blob)
+        };
+
+        let mut reader = HashingReader::new(content);
+        let mut file = fs::File::create(target).map_err(|e| e.to_string())?;
+        io::copy(&mut reader, &mut file).map_err(|e| e.to_string())?;
+
+        if reader.finish().0 != hash {
+            return Err(format!("snapshot data {} is corrupt", hash));
+        }
 
         Ok(())
     }
 
-    /// Delete a snapshot (cleanup)
+    /// Delete a snapshot (cleanup), and any blob no other snapshot uses
     pub fn delete_snapshot(&self, snapshot: &ActionSnapshot) -> Result<(), String> {
-        let snapshot_path = Path::new(&snapshot.snapshot_path);
-        
-        if snapshot_path.exists() {
-            fs::remove_file(snapshot_path)
-                .map_err(|e| format!("Failed to delete snapshot: {}", e))?;
+        let _lock = lock_store();
+        self.delete_snapshot_locked(snapshot)
+    }
+
+    /// Delete a snapshot while the caller holds the store lock
+    fn delete_snapshot_locked(&self, snapshot: &ActionSnapshot) -> Result<(), String> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to delete snapshot record: {}", e))?;
+        tx.execute("DELETE FROM snapshot_entries WHERE snapshot_id = ?1", params![snapshot.id])
+            .and_then(|_| tx.execute("DELETE FROM action_snapshots WHERE id = ?1", params![snapshot.id]))
+            .and_then(|_| tx.commit())
+            .map_err(|e| format!("Failed to delete snapshot record: {}", e))?;
+
+        self.remove_unused_blobs()
+    }
+
+    /// Remove blobs no snapshot entry refers to. Callers hold the store lock.
+    fn remove_unused_blobs(&self) -> Result<(), String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT hash, compressed FROM snapshot_blobs
+                 WHERE NOT EXISTS (SELECT 1 FROM snapshot_entries WHERE blob_hash = snapshot_blobs.hash)",
+            )
+            .map_err(|e| format!("Failed to query snapshot blobs: {}", e))?;
+        let unused = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?)))
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to query snapshot blobs: {}", e))?;
+
+        for (hash, compressed) in unused {
+            match fs::remove_file(self.blob_path(&hash, compressed)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to delete snapshot data {}: {}", hash, e)),
+            }
+            self.conn
+                .execute("DELETE FROM snapshot_blobs WHERE hash = ?1", params![hash])
+                .map_err(|e| format!("Failed to delete snapshot blob record: {}", e))?;
         }
 
         Ok(())
     }
 
-    /// Clean up expired snapshots
-    pub fn cleanup_expired(&self, snapshots: Vec<ActionSnapshot>) -> Result<usize, String> {
+    /// Clean up snapshots older than the current retention, except those of the actions in `keep`
+    fn cleanup_expired(&self, keep: &HashSet<String>) -> Result<usize, String> {
         let now = chrono::Utc::now().timestamp();
+        let expired = self.query_snapshots(
+            "SELECT id, action_id, original_path, snapshot_path, created_at, retention_until
+             FROM action_snapshots WHERE created_at < ?1",
+            now - self.undo_until(0),
+        )?;
         let mut cleaned = 0;
 
-        for snapshot in snapshots {
-            if snapshot.retention_until < now {
-                if let Err(e) = self.delete_snapshot(&snapshot) {
-                    eprintln!("Failed to delete expired snapshot {}: {}", snapshot.id, e);
-                } else {
-                    cleaned += 1;
-                }
+        for snapshot in expired.iter().filter(|s| !keep.contains(&s.action_id)) {
+            if let Err(e) = self.delete_snapshot_locked(snapshot) {
+                eprintln!("Failed to delete expired snapshot {}: {}", snapshot.id, e);
+            } else {
+                cleaned += 1;
             }
         }
 
         Ok(cleaned)
     }
+
+    /// Evict the oldest snapshots, an action at a time, until the stored blobs
+    /// fit in the size limit. Snapshots of the actions in `keep` are never evicted.
+    fn evict_over_quota(&self, keep: &HashSet<String>) -> Result<usize, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT action_id FROM action_snapshots GROUP BY action_id ORDER BY MIN(created_at) ASC, MIN(rowid) ASC")
+            .map_err(|e| format!("Failed to query snapshots: {}", e))?;
+        let oldest_first = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to query snapshots: {}", e))?;
+        let mut evicted = 0;
+
+        for action_id in oldest_first.iter().filter(|id| !keep.contains(*id)) {
+            if self.stored_bytes()? <= self.limits.max_bytes {
+                break;
+            }
+            // An action's snapshots go together, so undo never restores only part of it
+            for snapshot in self.snapshots_for_action(action_id)? {
+                self.delete_snapshot_locked(&snapshot)?;
+                evicted += 1;
+            }
+        }
+
+        Ok(evicted)
+    }
+
+    /// Bytes the blob store takes on disk
+    fn stored_bytes(&self) -> Result<u64, String> {
+        self.conn
+            .query_row("SELECT COALESCE(SUM(stored_size), 0) FROM snapshot_blobs", [], |row| row.get::<_, i64>(0))
+            .map(|bytes| bytes as u64)
+            .map_err(|e| format!("Failed to measure snapshot storage: {}", e))
+    }
+
+    /// IDs of the actions of every plan that is running or waiting for recovery
+    fn journaled_actions(&self) -> Result<HashSet<String>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT plan_id, plan_json FROM plan_journal")
+            .map_err(|e| format!("Failed to query plan journal: {}", e))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to read plan journal: {}", e))?;
+
+        let mut actions = HashSet::new();
+        for (plan_id, plan_json) in rows {
+            match serde_json::from_str::<ActionPlan>(&plan_json) {
+                Ok(plan) => actions.extend(plan.schema.actions.into_iter().map(|a| a.id)),
+                Err(e) => eprintln!("Failed to parse journaled plan {}: {}", plan_id, e),
+            }
+        }
+        Ok(actions)
+    }
+
+    /// Evict expired snapshots, then the oldest while the store is over its size limit.
+    /// Snapshots of plans that are running or waiting for recovery are kept, since
+    /// rolling them back needs them. The store lock is held throughout, so a plan that
+    /// starts meanwhile cannot take a snapshot until the sweep is done.
+    /// Returns how many snapshots were removed.
+    pub fn sweep(&self) -> Result<usize, String> {
+        let _lock = lock_store();
+        let journaled = self.journaled_actions()?;
+        Ok(self.cleanup_expired(&journaled)? + self.evict_over_quota(&journaled)?)
+    }
+}
+
+/// Sweep the snapshot store now and every `SWEEP_INTERVAL` on a background thread.
+/// Settings are reloaded for each sweep, so changed limits apply without a restart.
+pub fn start_sweeper(app: AppHandle) {
+    let spawned = std::thread::Builder::new()
+        .name("snapshot-sweeper".to_string())
+        .spawn(move || loop {
+            match SnapshotManager::new(&app).and_then(|manager| manager.sweep()) {
+                Ok(0) => {}
+                Ok(count) => eprintln!("Evicted {} assistant snapshot(s)", count),
+                Err(e) => eprintln!("Failed to sweep assistant snapshots: {}", e),
+            }
+            std::thread::sleep(SWEEP_INTERVAL);
+        });
+
+    if let Err(e) = spawned {
+        eprintln!("Failed to start snapshot sweeper: {}", e);
+    }
+}
+
+/// Record a snapshot in the `action_snapshots` table
+fn record_snapshot(conn: &Connection, snapshot: &ActionSnapshot) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO action_snapshots (id, action_id, original_path, snapshot_path, created_at, retention_until) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            snapshot.id,
+            snapshot.action_id,
+            snapshot.original_path,
+            snapshot.snapshot_path,
+            snapshot.created_at,
+            snapshot.retention_until,
+        ],
+    )
+    .map_err(|e| format!("Failed to record snapshot: {}", e))?;
+
+    Ok(())
+}
+
+fn lock_store() -> MutexGuard<'static, ()> {
+    // The lock guards no data, so a panic while it was held leaves nothing inconsistent
+    STORE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Copy a file to `temp`, compressed if asked, and return the hash and size of its content
+fn write_blob(source: &Path, temp: &Path, compress: bool) -> io::Result<(String, u64)> {
+    let mut reader = HashingReader::new(fs::File::open(source)?);
+    let mut file = fs::File::create(temp)?;
+
+    if compress {
+        zstd::stream::copy_encode(&mut reader, &mut file, COMPRESSION_LEVEL)?;
+    } else {
+        io::copy(&mut reader, &mut file)?;
+    }
+
+    Ok(reader.finish())
+}
+
+/// Reader that hashes what passes through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Hex SHA-256 and length of everything read
+    fn finish(self) -> (String, u64) {
+        (format!("{:x}", self.hasher.finalize()), self.bytes)
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode_of(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
 }
@@ -0,0 +1,556 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// Where a trashed file or directory ended up
+#[derive(Debug, Clone)]
+pub struct TrashedItem {
+    pub trashed_path: Option<PathBuf>, // Unknown when the platform trash does not report it
+}
+
+/// Move a file or directory to the trash. Never falls back to deleting.
+pub fn move_to_trash(path: &Path) -> Result<TrashedItem, String> {
+    platform::move_to_trash(path)
+}
+
+/// Move a trashed item back to its original location.
+/// `trashed_path` is looked up from the trash metadata when not given.
+pub fn restore_from_trash(trashed_path: Option<&Path>, destination: &Path) -> Result<PathBuf, String> {
+    platform::restore_from_trash(trashed_path, destination)
+}
+
+/// Check that `path` names an item directly inside the `files/` folder of the
+/// trash it is on, so a restore cannot move arbitrary files. Returns it with the
+/// folder canonicalized.
+pub fn trashed_item(path: &Path) -> Result<PathBuf, String> {
+    let not_in_trash = || format!("{} is not an item in the trash", path.display());
+
+    let name = path.file_name().ok_or_else(not_in_trash)?;
+    let files = path
+        .parent()
+        .and_then(|p| p.canonicalize().ok())
+        .filter(|p| p.file_name() == Some(OsStr::new("files")))
+        .ok_or_else(not_in_trash)?;
+    let item = files.join(name);
+
+    let trash = trash_dir_for(&item)
+        .and_then(|t| t.canonicalize().ok())
+        .ok_or_else(not_in_trash)?;
+    if files.parent() != Some(trash.as_path()) {
+        return Err(not_in_trash());
+    }
+
+    Ok(item)
+}
+
+/// Trash directory that would receive `path`, created if missing.
+/// The sandbox grants the worker access to it for delete and restore actions.
+pub fn trash_dir_for(path: &Path) -> Option<PathBuf> {
+    platform::trash_dir_for(path)
+}
+
+/// freedesktop.org Trash specification 1.0
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::TrashedItem;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::io::Write;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    use std::path::{Path, PathBuf};
+
+    /// A trash directory and how its `.trashinfo` paths are written
+    struct TrashDir {
+        root: PathBuf,
+        topdir: Option<PathBuf>, // Set for per-mount trashes, whose paths are relative to the mount
+    }
+
+    pub fn move_to_trash(path: &Path) -> Result<TrashedItem, String> {
+        let path = absolute_parent(path)?;
+        let trash = select_trash(&path)?;
+
+        let name = path
+            .file_name()
+            .ok_or_else(|| format!("Cannot trash {}", path.display()))?;
+
+        let info_path_value = match &trash.topdir {
+            Some(topdir) => path.strip_prefix(topdir).unwrap_or(&path).to_path_buf(),
+            None => path.clone(),
+        };
+        let info = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(&info_path_value),
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
+        );
+
+        let (trashed_path, info_file) = reserve_name(&trash.root, name, &info)?;
+
+        if let Err(e) = fs::rename(&path, &trashed_path) {
+            let _ = fs::remove_file(&info_file);
+            return Err(format!("Failed to move {} to trash: {}", path.display(), e));
+        }
+
+        Ok(TrashedItem {
+            trashed_path: Some(trashed_path),
+        })
+    }
+
+    pub fn restore_from_trash(trashed_path: Option<&Path>, destination: &Path) -> Result<PathBuf, String> {
+        let destination = absolute_parent(destination)?;
+
+        let trashed_path = match trashed_path {
+            Some(path) => super::trashed_item(path)?,
+            None => find_in_trash(&destination)?,
+        };
+
+        if destination.symlink_metadata().is_ok() {
+            return Err(format!("{} already exists; not restoring over it", destination.display()));
+        }
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create parent directory: {}", e))?;
+        }
+
+        fs::rename(&trashed_path, &destination)
+            .map_err(|e| format!("Failed to restore {} from trash: {}", destination.display(), e))?;
+
+        if let Some(info_file) = info_file_for(&trashed_path) {
+            if let Err(e) = fs::remove_file(&info_file) {
+                eprintln!("Failed to remove {}: {}", info_file.display(), e);
+            }
+        }
+
+        Ok(trashed_path)
+    }
+
+    pub fn trash_dir_for(path: &Path) -> Option<PathBuf> {
+        let path = absolute_parent(path).ok()?;
+        select_trash(&path).ok().map(|trash| trash.root)
+    }
+
+    /// Canonicalize the parent but not the item itself, so symlinks are trashed as links
+    fn absolute_parent(path: &Path) -> Result<PathBuf, String> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| format!("Invalid path: {}", path.display()))?;
+        let parent = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        // The parent may not exist yet when restoring
+        let parent = parent
+            .ancestors()
+            .find_map(|ancestor| {
+                ancestor
+                    .canonicalize()
+                    .ok()
+                    .map(|canonical| canonical.join(parent.strip_prefix(ancestor).unwrap_or(Path::new(""))))
+            })
+            .unwrap_or_else(|| parent.to_path_buf());
+
+        Ok(parent.join(name))
+    }
+
+    /// Device of the deepest existing ancestor (or the item itself)
+    fn device_of(path: &Path) -> Option<u64> {
+        path.ancestors()
+            .find_map(|p| fs::symlink_metadata(p).ok())
+            .map(|m| m.dev())
+    }
+
+    /// Use the home trash when the item is on the same filesystem,
+    /// otherwise the trash at the top of the item's mount
+    fn select_trash(path: &Path) -> Result<TrashDir, String> {
+        let data_home = dirs::data_dir().ok_or("Failed to locate the XDG data directory")?;
+        let home_trash = data_home.join("Trash");
+
+        let device = device_of(path).ok_or_else(|| format!("Cannot stat {}", path.display()))?;
+
+        if device_of(&home_trash) == Some(device) {
+            ensure_trash_dirs(&home_trash)?;
+            return Ok(TrashDir {
+                root: home_trash,
+                topdir: None,
+            });
+        }
+
+        let topdir = mount_topdir(path, device);
+        Ok(TrashDir {
+            root: topdir_trash(&topdir)?,
+            topdir: Some(topdir),
+        })
+    }
+
+    /// Highest ancestor of `path` that is still on `device`
+    fn mount_topdir(path: &Path, device: u64) -> PathBuf {
+        let mut top = path.to_path_buf();
+        for ancestor in path.ancestors().skip(1) {
+            match fs::metadata(ancestor) {
+                Ok(meta) if meta.dev() == device => top = ancestor.to_path_buf(),
+                _ => break,
+            }
+        }
+        top
+    }
+
+    /// `$topdir/.Trash/$uid` if the administrator set up a shared trash
+    /// (a real directory with the sticky bit), otherwise `$topdir/.Trash-$uid`
+    fn topdir_trash(topdir: &Path) -> Result<PathBuf, String> {
+        let uid = unsafe { libc::getuid() };
+
+        let shared = topdir.join(".Trash");
+        if let Ok(meta) = fs::symlink_metadata(&shared) {
+            if meta.is_dir() && meta.mode() & libc::S_ISVTX != 0 {
+                let user_trash = shared.join(uid.to_string());
+                if ensure_trash_dirs(&user_trash).is_ok() {
+                    return Ok(user_trash);
+                }
+            }
+        }
+
+        let own = topdir.join(format!(".Trash-{}", uid));
+        ensure_trash_dirs(&own)?;
+
+        let meta = fs::symlink_metadata(&own)
+            .map_err(|e| format!("Failed to check {}: {}", own.display(), e))?;
+        if !meta.is_dir() || meta.uid() != uid {
+            return Err(format!("{} is not a usable trash directory", own.display()));
+        }
+
+        Ok(own)
+    }
+
+    fn ensure_trash_dirs(root: &Path) -> Result<(), String> {
+        for dir in [root.join("files"), root.join("info")] {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(&dir)
+                .map_err(|e| format!("Failed to create trash directory {}: {}", dir.display(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Claim a unique name by creating its `.trashinfo` exclusively
+    fn reserve_name(trash: &Path, name: &OsStr, info: &str) -> Result<(PathBuf, PathBuf), String> {
+        let name_path = Path::new(name);
+        let stem = name_path.file_stem().unwrap_or(name).to_string_lossy().to_string();
+        let extension = name_path.extension().map(|e| e.to_string_lossy().to_string());
+
+        for n in 1..10_000 {
+            let candidate = match (n, &extension) {
+                (1, _) => name.to_string_lossy().to_string(),
+                (_, Some(ext)) => format!("{}.{}.{}", stem, n, ext),
+                (_, None) => format!("{}.{}", stem, n),
+            };
+
+            let info_file = trash.join("info").join(format!("{}.trashinfo", candidate));
+            let trashed_path = trash.join("files").join(&candidate);
+
+            match fs::OpenOptions::new().write(true).create_new(true).open(&info_file) {
+                Ok(mut file) => {
+                    if trashed_path.symlink_metadata().is_ok() {
+                        // Orphaned file without metadata; leave it alone
+                        let _ = fs::remove_file(&info_file);
+                        continue;
+                    }
+                    file.write_all(info.as_bytes())
+                        .and_then(|_| file.sync_all())
+                        .map_err(|e| format!("Failed to write trash info: {}", e))?;
+                    return Ok((trashed_path, info_file));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(format!("Failed to write trash info: {}", e)),
+            }
+        }
+
+        Err(format!("Too many items named {} in trash", name.to_string_lossy()))
+    }
+
+    /// `<trash>/info/<name>.trashinfo` for `<trash>/files/<name>`
+    fn info_file_for(trashed_path: &Path) -> Option<PathBuf> {
+        let name = trashed_path.file_name()?;
+        let root = trashed_path.parent()?.parent()?;
+        let mut info_name = name.to_os_string();
+        info_name.push(".trashinfo");
+        Some(root.join("info").join(info_name))
+    }
+
+    /// Find the most recently trashed item that came from `original`
+    fn find_in_trash(original: &Path) -> Result<PathBuf, String> {
+        let root = trash_dir_for(original)
+            .ok_or_else(|| format!("No trash directory for {}", original.display()))?;
+        let topdir = root
+            .parent()
+            .map(|p| if p.file_name() == Some(OsStr::new(".Trash")) { p.parent().unwrap_or(p) } else { p })
+            .map(Path::to_path_buf);
+
+        let entries = fs::read_dir(root.join("info"))
+            .map_err(|e| format!("Failed to read trash: {}", e))?;
+
+        let mut best: Option<(String, PathBuf)> = None;
+        for entry in entries.flatten() {
+            let Ok(info) = fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            let field = |key: &str| {
+                info.lines()
+                    .find_map(|line| line.strip_prefix(key))
+                    .map(str::to_string)
+            };
+            let (Some(encoded), Some(date)) = (field("Path="), field("DeletionDate=")) else {
+                continue;
+            };
+
+            let mut recorded = decode_path(&encoded);
+            if recorded.is_relative() {
+                match &topdir {
+                    Some(topdir) => recorded = topdir.join(recorded),
+                    None => continue,
+                }
+            }
+            if recorded != original {
+                continue;
+            }
+
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_string_lossy().strip_suffix(".trashinfo").map(str::to_string) else {
+                continue;
+            };
+            if best.as_ref().is_none_or(|(best_date, _)| date > *best_date) {
+                best = Some((date, root.join("files").join(name)));
+            }
+        }
+
+        best.map(|(_, path)| path)
+            .ok_or_else(|| format!("{} was not found in the trash", original.display()))
+    }
+
+    /// Percent-encode a path as required for the `Path=` key
+    fn encode_path(path: &Path) -> String {
+        let mut encoded = String::new();
+        for &byte in path.as_os_str().as_bytes() {
+            if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        encoded
+    }
+
+    fn decode_path(encoded: &str) -> PathBuf {
+        let bytes = encoded.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' && i + 2 < bytes.len() {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+            }
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+        PathBuf::from(OsStr::from_bytes(&decoded))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::trashed_item;
+        use super::*;
+        use tempfile::TempDir;
+
+        #[test]
+        fn paths_are_percent_encoded() {
+            assert_eq!(encode_path(Path::new("/home/me/a-b_c.d~")), "/home/me/a-b_c.d~");
+            assert_eq!(encode_path(Path::new("/home/me/My File%.txt")), "/home/me/My%20File%25.txt");
+            assert_eq!(encode_path(Path::new("/tmp/é")), "/tmp/%C3%A9");
+            assert_eq!(encode_path(Path::new(OsStr::from_bytes(b"/tmp/\xff"))), "/tmp/%FF");
+        }
+
+        #[test]
+        fn encoded_paths_decode_to_the_original() {
+            for path in ["/home/me/My File%.txt", "/tmp/é", "relative/a+b=c"] {
+                assert_eq!(decode_path(&encode_path(Path::new(path))), Path::new(path));
+            }
+            assert_eq!(decode_path("/tmp/%FF"), Path::new(OsStr::from_bytes(b"/tmp/\xff")));
+            // Malformed escapes are kept as written
+            assert_eq!(decode_path("/tmp/100%"), Path::new("/tmp/100%"));
+            assert_eq!(decode_path("/tmp/%zz%4"), Path::new("/tmp/%zz%4"));
+        }
+
+        #[test]
+        fn info_files_sit_next_to_the_files_folder() {
+            assert_eq!(
+                info_file_for(Path::new("/t/Trash/files/a.txt")),
+                Some(PathBuf::from("/t/Trash/info/a.txt.trashinfo"))
+            );
+        }
+
+        #[test]
+        fn names_taken_in_the_trash_get_a_number() {
+            let trash = TempDir::new().unwrap();
+            ensure_trash_dirs(trash.path()).unwrap();
+
+            let (first, first_info) = reserve_name(trash.path(), OsStr::new("a.txt"), "info").unwrap();
+            assert_eq!(first, trash.path().join("files/a.txt"));
+            assert_eq!(fs::read_to_string(first_info).unwrap(), "info");
+
+            // An orphaned file without metadata is skipped, not overwritten
+            fs::write(trash.path().join("files/a.2.txt"), "orphan").unwrap();
+            let (third, _) = reserve_name(trash.path(), OsStr::new("a.txt"), "info").unwrap();
+            assert_eq!(third, trash.path().join("files/a.3.txt"));
+            assert!(!trash.path().join("info/a.2.txt.trashinfo").exists());
+        }
+
+        // The only test that sets XDG_DATA_HOME, so the home trash is a temporary directory
+        #[test]
+        fn trashed_items_must_be_in_the_trash() {
+            let dir = TempDir::new().unwrap();
+            std::env::set_var("XDG_DATA_HOME", dir.path().join("data"));
+            fs::create_dir_all(dir.path().join("data")).unwrap();
+
+            let original = dir.path().join("My File%.txt");
+            fs::write(&original, "x").unwrap();
+            let trashed = move_to_trash(&original).unwrap().trashed_path.unwrap();
+            let trash = dir.path().join("data/Trash").canonicalize().unwrap();
+            assert_eq!(trashed, trash.join("files/My File%.txt"));
+
+            let info = fs::read_to_string(trash.join("info/My File%.txt.trashinfo")).unwrap();
+            let recorded = info.lines().find_map(|line| line.strip_prefix("Path=")).unwrap();
+            assert_eq!(recorded, encode_path(&dir.path().canonicalize().unwrap().join("My File%.txt")));
+            assert!(info.starts_with("[Trash Info]\n"));
+            assert!(info.contains("\nDeletionDate="));
+
+            assert_eq!(trashed_item(&trashed).unwrap(), trashed);
+            assert_eq!(trashed_item(&trash.join("files/../files/My File%.txt")).unwrap(), trashed);
+
+            // Only items directly inside the trash's files/ folder are accepted
+            let outside = dir.path().join("files");
+            fs::create_dir_all(&outside).unwrap();
+            fs::write(outside.join("b.txt"), "x").unwrap();
+            fs::create_dir_all(trash.join("files/sub")).unwrap();
+            for path in [
+                outside.join("b.txt"),
+                trash.join("info/My File%.txt.trashinfo"),
+                trash.join("files/sub/c.txt"),
+                trash.join("files"),
+                PathBuf::from("/"),
+            ] {
+                let err = trashed_item(&path).unwrap_err();
+                assert!(err.ends_with("is not an item in the trash"), "{}", err);
+            }
+            let err = restore_from_trash(Some(&outside.join("b.txt")), &dir.path().join("b.txt")).unwrap_err();
+            assert!(err.ends_with("is not an item in the trash"), "{}", err);
+            assert!(outside.join("b.txt").exists());
+
+            // Without a trashed path the item is found from its recorded original path
+            restore_from_trash(None, &original).unwrap();
+            assert_eq!(fs::read_to_string(&original).unwrap(), "x");
+            assert!(!trash.join("info/My File%.txt.trashinfo").exists());
+        }
+    }
+}
+
+/// Finder on macOS; it does not report where the item went
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::TrashedItem;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    pub fn move_to_trash(path: &Path) -> Result<TrashedItem, String> {
+        let script = format!(
+            "tell application \"Finder\" to move POSIX file \"{}\" to trash",
+            path.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        let output = Command::new("osascript")
+            .arg("-e")
+            .arg(script)
+            .output()
+            .map_err(|e| format!("Failed to move to trash: {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "Failed to move {} to trash: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(TrashedItem { trashed_path: None })
+    }
+
+    pub fn restore_from_trash(_trashed_path: Option<&Path>, _destination: &Path) -> Result<PathBuf, String> {
+        Err("Restoring from the trash is only supported on Linux".to_string())
+    }
+
+    pub fn trash_dir_for(_path: &Path) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Recycle Bin on Windows; it does not report where the item went
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::TrashedItem;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    pub fn move_to_trash(path: &Path) -> Result<TrashedItem, String> {
+        let method = if path.is_dir() { "DeleteDirectory" } else { "DeleteFile" };
+        let script = format!(
+            "Add-Type -AssemblyName Microsoft.VisualBasic; [Microsoft.VisualBasic.FileIO.FileSystem]::{}('{}', 'OnlyErrorDialogs', 'SendToRecycleBin')",
+            method,
+            path.to_string_lossy().replace('\'', "''")
+        );
+        let output = Command::new("powershell")
+            .arg("-NoProfile")
+            .arg("-Command")
+            .arg(script)
+            .output()
+            .map_err(|e| format!("Failed to move to Recycle Bin: {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "Failed to move {} to Recycle Bin: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(TrashedItem { trashed_path: None })
+    }
+
+    pub fn restore_from_trash(_trashed_path: Option<&Path>, _destination: &Path) -> Result<PathBuf, String> {
+        Err("Restoring from the Recycle Bin is only supported on Linux".to_string())
+    }
+
+    pub fn trash_dir_for(_path: &Path) -> Option<PathBuf> {
+        None
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod platform {
+    use super::TrashedItem;
+    use std::path::{Path, PathBuf};
+
+    pub fn move_to_trash(path: &Path) -> Result<TrashedItem, String> {
+        Err(format!("No trash is available for {} on this platform", path.display()))
+    }
+
+    pub fn restore_from_trash(_trashed_path: Option<&Path>, _destination: &Path) -> Result<PathBuf, String> {
+        Err("Restoring from the trash is not supported on this platform".to_string())
+    }
+
+    pub fn trash_dir_for(_path: &Path) -> Option<PathBuf> {
+        None
+    }
+}
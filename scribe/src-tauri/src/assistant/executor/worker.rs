@@ -1,153 +1,606 @@
 use crate::assistant::types::*;
-use crate::assistant::executor::{fs_adapter, snapshot};
+use crate::assistant::executor::{archive, fs_adapter, trash};
+use crate::assistant::executor::journal::Journal;
+use crate::assistant::executor::snapshot;
 use crate::assistant::policy;
-use std::path::PathBuf;
+use crate::assistant::preconditions;
+use crate::assistant::references;
+use crate::assistant::sandbox::ActionRunner;
+use crate::assistant::audit::AuditLog;
+use crate::assistant::store;
+use crate::assistant::validator;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
 
 /// Execute an action plan with transactional safety
 pub async fn execute_action_plan(
+    app: &AppHandle,
     plan: &ActionPlan,
     capability_token: Option<String>,
-    user_home: PathBuf,
+    ctx: ExecutionContext,
 ) -> Result<ActionResult, String> {
-    let mut snapshot_manager = snapshot::SnapshotManager::new(user_home.clone())
+    let snapshot_manager = snapshot::SnapshotManager::new(app)
         .map_err(|e| format!("Failed to create snapshot manager: {}", e))?;
 
-    let ctx = ExecutionContext::new(user_home.clone());
+    // Refuse the whole plan up front if any path is protected or outside the
+    // allowed roots, before anything is snapshotted or changed. Paths taken from
+    // an earlier step's output are checked once they are known, before their step runs.
+    for action in plan.schema.actions.iter().filter(|a| !references::has_references(a)) {
+        validator::check_action_paths(action, &ctx)?;
+    }
 
     // Validate capability token if provided
-    if let Some(token_str) = capability_token {
-        let token = policy::validate_token(&token_str)
-            .map_err(|e| format!("Token validation failed: {}", e))?;
-        
-        // Check permissions for each action
-        for action in &plan.schema.actions {
-            let action_type_str = match action.action_type {
-                ActionType::FsCreateFile => "fs",
-                ActionType::FsReadFile => "fs",
-                ActionType::FsCopyFile => "fs",
-                ActionType::FsMoveFile => "fs",
-                ActionType::FsDeleteFile => "fs",
-                ActionType::FsCreateDirectory => "fs",
-            };
+    let token = match capability_token {
+        Some(token_str) => Some(policy::validate_plan_token(&store::open(app)?, plan, &token_str)?),
+        None => None,
+    };
 
-            if let Some(path_value) = action.args.get("path") {
-                if let Some(path_str) = path_value.as_str() {
-                    if !policy::check_permission(&token, action_type_str, path_str) {
-                        return Err(format!("Permission denied for action on {}", path_str));
-                    }
-                }
-            }
-        }
-    }
+    // File operations run in a confined worker process where supported
+    let mut runner = ActionRunner::start(plan, &ctx)?;
 
+    // Record the plan before anything changes so a crash can be recovered
+    let journal = Journal::new(app)?;
+    journal.begin(plan)?;
+
+    let plan_id = &plan.schema.id;
     let mut results = Vec::new();
-    let mut snapshots = Vec::new();
+    let mut applied: Vec<(Action, serde_json::Value)> = Vec::new();
+    let mut outputs: HashMap<String, serde_json::Value> = HashMap::new();
     let executed_at = chrono::Utc::now().timestamp();
 
-    // Execute each action with snapshot creation
-    for action in &plan.schema.actions {
-        // Create snapshot for destructive operations
-        let snapshot_id = if needs_snapshot(&action.action_type) {
-            if let Some(path_value) = action.args.get("path") {
-                if let Some(path_str) = path_value.as_str() {
-                    let path = PathBuf::from(path_str);
-                    match snapshot_manager.create_snapshot(&action.id, &path) {
-                        Ok(snapshot) => {
-                            snapshots.push(snapshot.clone());
-                            Some(snapshot.id.clone())
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to create snapshot: {}", e);
-                            None
-                        }
-                    }
-                } else {
-                    None
+    for (seq, planned) in plan.schema.actions.iter().enumerate() {
+        // Fill in the outputs of earlier steps, then check the paths that only now are known
+        let resolved = references::substitute(planned, &outputs).and_then(|action| {
+            if references::has_references(planned) {
+                validator::check_action_paths(&action, &ctx)?;
+                if let Some(token) = &token {
+                    policy::check_action_permission(token, &action)?;
                 }
-            } else if let Some(src_path_value) = action.args.get("source_path") {
-                if let Some(src_path_str) = src_path_value.as_str() {
-                    let path = PathBuf::from(src_path_str);
-                    match snapshot_manager.create_snapshot(&action.id, &path) {
-                        Ok(snapshot) => {
-                            snapshots.push(snapshot.clone());
-                            Some(snapshot.id.clone())
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to create snapshot: {}", e);
-                            None
-                        }
-                    }
-                } else {
-                    None
-                }
-            } else {
-                None
             }
-        } else {
-            None
+            Ok(action)
+        });
+        let action = match resolved {
+            Ok(action) => action,
+            Err(e) => {
+                let e = format!("Step {} ({}): {}", seq + 1, planned.action_type.name(), e);
+                return Err(abort_plan(plan_id, &applied, &journal, &snapshot_manager, e));
+            }
         };
+        let action = &action;
+        let expected = pre_state(action, &ctx);
+
+        // Re-check preconditions right before the action; the disk may have changed since verification
+        if let Err(e) = preconditions::check_on_disk(action) {
+            return Err(abort_plan(plan_id, &applied, &journal, &snapshot_manager, e));
+        }
 
-        // Execute the action
-        match fs_adapter::execute_fs_action(action, &ctx) {
-            Ok(mut result) => {
-                result.snapshot_id = snapshot_id;
+        // Snapshot and journal the action; nothing has changed yet if this fails
+        let snapshot_id = match prepare_step(plan_id, seq, action, &expected, &journal, &snapshot_manager, &ctx.directory_limits) {
+            Ok(snapshot_id) => snapshot_id,
+            Err(e) => {
+                discard_action_snapshots(action, &snapshot_manager);
+                return Err(abort_plan(plan_id, &applied, &journal, &snapshot_manager, e));
+            }
+        };
+
+        let outcome = runner.run(action).and_then(|mut result| {
+            journal.record_done(plan_id, seq, result.output.as_ref())?;
+            result.snapshot_id = snapshot_id;
+            Ok(result)
+        });
+
+        match outcome {
+            Ok(result) => {
+                let output = result.output.clone().unwrap_or(expected);
+                outputs.insert(action.id.clone(), output.clone());
+                applied.push((action.clone(), output));
                 results.push(result);
             }
             Err(e) => {
-                // Rollback: undo all previous actions
-                rollback_actions(&snapshots, &mut snapshot_manager)?;
-                return Err(format!("Action execution failed: {}. All actions rolled back.", e));
+                // The failed action may have partially applied, so revert it too
+                applied.push((action.clone(), expected));
+                return Err(abort_plan(plan_id, &applied, &journal, &snapshot_manager, e));
             }
         }
     }
 
+    journal.finish(plan_id)?;
+
     Ok(ActionResult {
         action_id: plan.schema.id.clone(),
         success: true,
         executed_at,
         results,
         error: None,
-        undo_available: !snapshots.is_empty(),
-        undo_ttl: Some(chrono::Utc::now().timestamp() + (7 * 24 * 60 * 60)), // 7 days
+        undo_available: plan.schema.actions.iter().any(|a| is_undoable(&a.action_type)),
+        undo_ttl: Some(snapshot_manager.undo_until(executed_at)),
     })
 }
 
-/// Check if an action type needs a snapshot
-fn needs_snapshot(action_type: &ActionType) -> bool {
-    matches!(
+/// Snapshot the files an action will overwrite or remove, then journal its intent.
+/// Returns the ID of the first snapshot taken.
+fn prepare_step(
+    plan_id: &str,
+    seq: usize,
+    action: &Action,
+    expected: &serde_json::Value,
+    journal: &Journal,
+    snapshot_manager: &snapshot::SnapshotManager,
+    limits: &DirectoryLimits,
+) -> Result<Option<String>, String> {
+    let mut snapshot_id = None;
+    for path in snapshot_targets(action, expected) {
+        if path.is_dir() {
+            fs_adapter::check_tree_limits(&path, limits)?;
+        }
+        // Never change a file that could not be saved first
+        let snapshot = snapshot_manager
+            .create_snapshot(&action.id, &path)
+            .map_err(|e| format!("Failed to create snapshot: {}", e))?;
+        snapshot_id.get_or_insert(snapshot.id);
+    }
+
+    journal.record_intent(plan_id, seq, action, expected)?;
+
+    Ok(snapshot_id)
+}
+
+/// Roll back the applied actions of a failed plan and build the error to return.
+/// The journal is kept if rollback fails so recovery retries it on next start.
+fn abort_plan(
+    plan_id: &str,
+    applied: &[(Action, serde_json::Value)],
+    journal: &Journal,
+    snapshot_manager: &snapshot::SnapshotManager,
+    error: String,
+) -> String {
+    let rollback = applied
+        .iter()
+        .rev()
+        .try_for_each(|(action, output)| revert_action(action, Some(output), snapshot_manager).map(|_| ()))
+        .and_then(|_| journal.finish(plan_id));
+
+    match rollback {
+        Ok(()) => format!("Action execution failed: {}. All actions rolled back.", error),
+        Err(e) => format!(
+            "Action execution failed: {}. Rollback failed: {}; it will be retried on next start.",
+            error, e
+        ),
+    }
+}
+
+/// Finish or revert plans that were left half-done by a crash or forced quit.
+/// A plan whose every action completed is committed; any other plan is
+/// rolled back. Each outcome is recorded in the audit log.
+pub async fn recover_interrupted_plans(app: &AppHandle) -> Result<usize, String> {
+    let journal = Journal::new(app)?;
+    let snapshot_manager = snapshot::SnapshotManager::new(app)?;
+    let audit_log = AuditLog::new(app.clone());
+
+    let interrupted = journal.interrupted_plans()?;
+
+    for entry in &interrupted {
+        let plan = &entry.plan;
+        let finished = entry.steps.len() == plan.schema.actions.len()
+            && entry.steps.iter().all(|step| step.completed);
+
+        let result = if finished {
+            let mut results = Vec::new();
+            for step in &entry.steps {
+                results.push(ActionExecutionResult {
+                    action_id: step.action_id.clone(),
+                    success: true,
+                    output: step.output.clone(),
+                    error: None,
+                    snapshot_id: snapshot_manager
+                        .snapshots_for_action(&step.action_id)?
+                        .first()
+                        .map(|s| s.id.clone()),
+                });
+            }
+
+            ActionResult {
+                action_id: plan.schema.id.clone(),
+                success: true,
+                executed_at: entry.started_at,
+                results,
+                error: None,
+                undo_available: plan.schema.actions.iter().any(|a| is_undoable(&a.action_type)),
+                undo_ttl: Some(snapshot_manager.undo_until(entry.started_at)),
+            }
+        } else {
+            let outputs: HashMap<String, serde_json::Value> = entry
+                .steps
+                .iter()
+                .map(|step| (step.action_id.clone(), step.output.clone().unwrap_or_else(|| step.pre_state.clone())))
+                .collect();
+
+            // A step that cannot be reverted keeps its snapshots and is listed; the rest are still reverted
+            let mut failed = Vec::new();
+            for action in plan.schema.actions.iter().rev() {
+                match entry.steps.iter().find(|step| step.action_id == action.id) {
+                    // Unfinished steps fall back to what they were expected to create
+                    Some(step) => {
+                        let output = step.output.as_ref().unwrap_or(&step.pre_state);
+                        if let Err(e) = revert_action(&as_run(action, &outputs), Some(output), &snapshot_manager) {
+                            eprintln!("Failed to roll back step {} of plan {}: {}", action.id, plan.schema.id, e);
+                            failed.push(ActionExecutionResult {
+                                action_id: action.id.clone(),
+                                success: false,
+                                output: step.output.clone(),
+                                error: Some(e),
+                                snapshot_id: snapshot_manager
+                                    .snapshots_for_action(&action.id)
+                                    .ok()
+                                    .and_then(|snapshots| snapshots.first().map(|s| s.id.clone())),
+                            });
+                        }
+                    }
+                    // Never started, but may have been snapshotted
+                    None => discard_action_snapshots(action, &snapshot_manager),
+                }
+            }
+
+            let error = if failed.is_empty() {
+                "Execution was interrupted; all actions were rolled back on restart".to_string()
+            } else {
+                format!(
+                    "Execution was interrupted and {} step(s) could not be rolled back on restart; they need attention",
+                    failed.len()
+                )
+            };
+
+            ActionResult {
+                action_id: plan.schema.id.clone(),
+                success: false,
+                executed_at: entry.started_at,
+                results: failed,
+                error: Some(error),
+                undo_available: false,
+                undo_ttl: None,
+            }
+        };
+
+        audit_log.append_entry(plan, Some(&result)).await?;
+        journal.finish(&plan.schema.id)?;
+    }
+
+    Ok(interrupted.len())
+}
+
+/// Existing paths whose current contents must be preserved before an action runs.
+/// Whole trees are snapshotted for directory deletes, and for directory copies and moves
+/// that overwrite their destination; a destination is only snapshotted when it is overwritten.
+/// Extractions snapshot the files their pre-state expects them to overwrite.
+fn snapshot_targets(action: &Action, expected: &serde_json::Value) -> Vec<PathBuf> {
+    if action.action_type == ActionType::ArchiveExtract {
+        return expected
+            .get("overwritten")
+            .and_then(|v| v.as_array())
+            .map(|paths| paths.iter().filter_map(|p| p.as_str()).map(PathBuf::from).collect())
+            .unwrap_or_default();
+    }
+
+    let overwrites = OnConflict::of(action).is_ok_and(|policy| policy == OnConflict::Overwrite);
+    let (keys, directories): (&[&str], bool) = match action.action_type {
+        ActionType::FsDeleteFile
+        | ActionType::FsCreateFile
+        | ActionType::FsAppendFile
+        | ActionType::FsReplaceText
+        | ActionType::FsApplyPatch => (&["path"], false),
+        ActionType::FsMoveFile if overwrites => (&["source_path", "destination_path"], false),
+        ActionType::FsMoveFile => (&["source_path"], false),
+        ActionType::FsCopyFile | ActionType::ArchiveCreate if overwrites => (&["destination_path"], false),
+        ActionType::FsCopyDirectory | ActionType::FsMoveDirectory if overwrites => (&["destination_path"], true),
+        ActionType::FsDeleteDirectory => (&["path"], true),
+        ActionType::FsReadFile
+        | ActionType::FsCopyFile
+        | ActionType::ArchiveCreate
+        | ActionType::FsCreateDirectory
+        | ActionType::FsListDirectory
+        | ActionType::FsCopyDirectory
+        | ActionType::FsMoveDirectory
+        | ActionType::FsRestoreFromTrash
+        | ActionType::FsSearch
+        | ActionType::FsStat
+        | ActionType::ArchiveExtract => (&[], false),
+    };
+
+    keys.iter()
+        .filter_map(|key| action.args.get(*key).and_then(|v| v.as_str()))
+        .map(PathBuf::from)
+        .filter(|path| path.is_file() || (directories && path.is_dir()))
+        .collect()
+}
+
+/// Check if an action type changes anything that undo would have to reverse
+fn is_undoable(action_type: &ActionType) -> bool {
+    !matches!(
         action_type,
-        ActionType::FsDeleteFile | ActionType::FsMoveFile
+        ActionType::FsReadFile | ActionType::FsListDirectory | ActionType::FsSearch | ActionType::FsStat
     )
 }
 
-/// Rollback actions using snapshots
-fn rollback_actions(
-    snapshots: &[ActionSnapshot],
-    snapshot_manager: &mut snapshot::SnapshotManager,
-) -> Result<(), String> {
-    // Restore snapshots in reverse order
+/// What an action is expected to create, recorded in the journal before it
+/// runs. Has the same shape as the action's output so either can drive a revert.
+fn pre_state(action: &Action, ctx: &ExecutionContext) -> serde_json::Value {
+    let arg = |key: &str| action.args.get(key).and_then(|v| v.as_str()).map(PathBuf::from);
+    let missing = |dir: Option<&Path>| -> Vec<String> {
+        dir.map(fs_adapter::missing_dirs)
+            .unwrap_or_default()
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect()
+    };
+
+    match action.action_type {
+        ActionType::FsCreateFile | ActionType::FsRestoreFromTrash => {
+            let path = arg("path");
+            serde_json::json!({ "created_dirs": missing(path.as_deref().and_then(Path::parent)) })
+        }
+        // Where the action will write once a conflict is settled; nothing if it will fail or skip
+        ActionType::FsCopyFile
+        | ActionType::FsMoveFile
+        | ActionType::FsCopyDirectory
+        | ActionType::FsMoveDirectory
+        | ActionType::ArchiveCreate => {
+            let target = OnConflict::of(action).ok().zip(arg("destination_path")).and_then(|(policy, destination)| {
+                fs_adapter::settle_conflict(&destination, policy, |p| p.symlink_metadata().is_ok()).ok()
+            });
+            match target.filter(|target| !target.skipped()) {
+                Some(target) => serde_json::json!({
+                    "destination": target.path.to_string_lossy(),
+                    "conflict": target.conflict.map(OnConflict::name),
+                    "created": action.action_type == ActionType::FsCopyDirectory,
+                    "created_dirs": missing(target.path.parent()),
+                }),
+                None => serde_json::json!({}),
+            }
+        }
+        ActionType::FsCreateDirectory => {
+            let path = arg("path");
+            serde_json::json!({
+                "created": path.as_ref().is_some_and(|p| !p.exists()),
+                "created_dirs": missing(path.as_deref()),
+            })
+        }
+        // An archive that cannot be listed fails before anything is unpacked
+        ActionType::ArchiveExtract => {
+            archive::planned_extraction(action, ctx).unwrap_or_else(|_| serde_json::json!({}))
+        }
+        ActionType::FsReadFile
+        | ActionType::FsDeleteFile
+        | ActionType::FsListDirectory
+        | ActionType::FsDeleteDirectory
+        | ActionType::FsAppendFile
+        | ActionType::FsReplaceText
+        | ActionType::FsApplyPatch
+        | ActionType::FsSearch
+        | ActionType::FsStat => serde_json::json!({}),
+    }
+}
+
+/// Revert one action: reverse its effect, then restore every file it
+/// overwrote or removed from its snapshots. Returns the restored paths.
+fn revert_action(
+    action: &Action,
+    output: Option<&serde_json::Value>,
+    snapshot_manager: &snapshot::SnapshotManager,
+) -> Result<Vec<String>, String> {
+    reverse_action(action, output)?;
+
+    let snapshots = snapshot_manager.snapshots_for_action(&action.id)?;
+    let mut restored_paths = Vec::new();
     for snapshot in snapshots.iter().rev() {
         snapshot_manager
             .restore_from_snapshot(snapshot)
             .map_err(|e| format!("Failed to restore snapshot: {}", e))?;
+        restored_paths.push(snapshot.original_path.clone());
+    }
+    discard_snapshots(&snapshots, snapshot_manager);
+
+    Ok(restored_paths)
+}
+
+/// Remove snapshots that are no longer needed (plan rolled back or undone)
+fn discard_snapshots(snapshots: &[ActionSnapshot], snapshot_manager: &snapshot::SnapshotManager) {
+    for snapshot in snapshots {
+        if let Err(e) = snapshot_manager.delete_snapshot(snapshot) {
+            eprintln!("Failed to delete snapshot {}: {}", snapshot.id, e);
+        }
     }
-    Ok(())
 }
 
-/// Undo an action using its snapshot
+/// Remove the snapshots of an action that never ran
+fn discard_action_snapshots(action: &Action, snapshot_manager: &snapshot::SnapshotManager) {
+    match snapshot_manager.snapshots_for_action(&action.id) {
+        Ok(snapshots) => discard_snapshots(&snapshots, snapshot_manager),
+        Err(e) => eprintln!("Failed to look up snapshots for {}: {}", action.id, e),
+    }
+}
+
+/// Undo a previously executed plan.
+/// Actions are reversed newest first: created files and copies are removed,
+/// moves are moved back, and every snapshot taken for the plan is restored.
 pub async fn undo_action(
-    _action_id: &str,
+    app: &AppHandle,
+    action_id: &str,
     _user_home: PathBuf,
 ) -> Result<(), String> {
-    // This would typically query the database for snapshots
-    // For now, we'll create a placeholder implementation
-    // Full implementation would:
-    // 1. Query database for snapshots with this action_id
-    // 2. Restore each snapshot
-    // 3. Mark action as undone in audit log
-
-    // Placeholder: return success for now
-    // Full implementation would be integrated with database
+    let audit_log = AuditLog::new(app.clone());
+    let (plan, result) = audit_log
+        .find_execution(action_id)
+        .await?
+        .ok_or_else(|| format!("No undoable execution found for action {}", action_id))?;
+
+    if !result.undo_available {
+        return Err("This action cannot be undone".to_string());
+    }
+
+    let snapshot_manager = snapshot::SnapshotManager::new(app)?;
+
+    // The window follows the current retention setting, not the one at execution time
+    let now = chrono::Utc::now().timestamp();
+    if snapshot_manager.undo_until(result.executed_at) < now {
+        return Err("Undo window for this action has expired".to_string());
+    }
+
+    // Snapshots are evicted once expired or when the store is over its size limit
+    for step in &result.results {
+        if let Some(snapshot_id) = &step.snapshot_id {
+            if !snapshot_manager.snapshot_exists(snapshot_id)? {
+                return Err("The saved copies needed to undo this action were removed to free snapshot storage".to_string());
+            }
+        }
+    }
+
+    let mut restored_paths = Vec::new();
+    let outputs: HashMap<String, serde_json::Value> = result
+        .results
+        .iter()
+        .filter_map(|r| Some((r.action_id.clone(), r.output.clone()?)))
+        .collect();
+
+    for action in plan.schema.actions.iter().rev() {
+        let output = outputs.get(&action.id);
+
+        restored_paths.extend(revert_action(&as_run(action, &outputs), output, &snapshot_manager)?);
+    }
+
+    audit_log.append_undo(&plan, &restored_paths).await?;
+
     Ok(())
 }
 
+/// An action with the outputs of the steps it used filled in, as it ran.
+/// Only an action that never ran can miss them, and then there is nothing to reverse.
+fn as_run(action: &Action, outputs: &HashMap<String, serde_json::Value>) -> Action {
+    references::substitute(action, outputs).unwrap_or_else(|_| action.clone())
+}
+
+/// Reverse the effect of a single action that snapshots cannot restore on their own
+fn reverse_action(action: &Action, output: Option<&serde_json::Value>) -> Result<(), String> {
+    let arg = |key: &str| action.args.get(key).and_then(|v| v.as_str()).map(PathBuf::from);
+    let created = output
+        .and_then(|o| o.get("created"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    // Where a copy, move or archive wrote, which a renaming conflict policy may have changed;
+    // nothing if it skipped an existing destination
+    let written = output
+        .filter(|o| o.get("conflict").and_then(|v| v.as_str()) != Some(OnConflict::Skip.name()))
+        .and_then(|o| o.get("destination"))
+        .and_then(|v| v.as_str())
+        .map(PathBuf::from);
+
+    match action.action_type {
+        ActionType::FsCreateFile => {
+            if let Some(path) = arg("path") {
+                remove_if_file(&path)?;
+            }
+        }
+        ActionType::FsCopyFile | ActionType::ArchiveCreate => {
+            if let Some(destination) = written {
+                remove_if_file(&destination)?;
+            }
+        }
+        ActionType::ArchiveExtract => {
+            // Remove the new files, then the directories the archive added, innermost first;
+            // overwritten files come back from their snapshots
+            let paths = |key: &str| -> Vec<PathBuf> {
+                output
+                    .and_then(|o| o.get(key))
+                    .and_then(|v| v.as_array())
+                    .map(|paths| paths.iter().filter_map(|p| p.as_str()).map(PathBuf::from).collect())
+                    .unwrap_or_default()
+            };
+            for file in paths("extracted") {
+                remove_if_file(&file)?;
+            }
+            for dir in paths("extracted_dirs").iter().rev() {
+                remove_if_empty_dir(dir);
+            }
+        }
+        ActionType::FsCopyDirectory => {
+            // The destination did not exist before or was replaced, so the whole tree is the copy
+            if let Some(destination) = written.filter(|_| created) {
+                if destination.is_dir() {
+                    fs::remove_dir_all(&destination)
+                        .map_err(|e| format!("Failed to remove {}: {}", destination.display(), e))?;
+                }
+            }
+        }
+        ActionType::FsMoveFile | ActionType::FsMoveDirectory => {
+            if let (Some(source), Some(destination)) = (arg("source_path"), written) {
+                if destination.exists() && !source.exists() {
+                    fs_adapter::move_path(&destination, &source)
+                        .map_err(|e| format!("Failed to move {} back: {}", destination.display(), e))?;
+                }
+            }
+        }
+        ActionType::FsCreateDirectory => {
+            // Entries recorded before "created_dirs" existed only report the leaf
+            let has_created_dirs = output.is_some_and(|o| o.get("created_dirs").is_some());
+            if let Some(path) = arg("path").filter(|_| created && !has_created_dirs) {
+                remove_if_empty_dir(&path);
+            }
+        }
+        ActionType::FsDeleteFile | ActionType::FsDeleteDirectory => {
+            // Take the item back out of the trash; the snapshot covers it if that fails
+            let trashed_path = output
+                .and_then(|o| o.get("trashed_path"))
+                .and_then(|v| v.as_str())
+                .map(PathBuf::from);
+            if let (Some(trashed_path), Some(path)) = (trashed_path, arg("path")) {
+                if trashed_path.symlink_metadata().is_ok() && path.symlink_metadata().is_err() {
+                    if let Err(e) = trash::restore_from_trash(Some(&trashed_path), &path) {
+                        eprintln!("Falling back to snapshot for {}: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+        ActionType::FsRestoreFromTrash => {
+            if let Some(path) = arg("path").filter(|p| p.symlink_metadata().is_ok()) {
+                trash::move_to_trash(&path)?;
+            }
+        }
+        // Edited files come back from their snapshots
+        ActionType::FsReadFile
+        | ActionType::FsListDirectory
+        | ActionType::FsSearch
+        | ActionType::FsStat
+        | ActionType::FsAppendFile
+        | ActionType::FsReplaceText
+        | ActionType::FsApplyPatch => {}
+    }
+
+    // Only remove directories this plan created, innermost first, and only if still empty
+    let created_dirs = output
+        .and_then(|o| o.get("created_dirs"))
+        .and_then(|v| v.as_array())
+        .map(|dirs| dirs.iter().filter_map(|d| d.as_str()).map(PathBuf::from).collect::<Vec<_>>())
+        .unwrap_or_default();
+    for dir in created_dirs.iter().rev() {
+        remove_if_empty_dir(dir);
+    }
+
+    Ok(())
+}
+
+fn remove_if_empty_dir(path: &Path) {
+    if path.is_dir() {
+        if let Err(e) = fs::remove_dir(path) {
+            eprintln!("Leaving directory {} in place: {}", path.display(), e);
+        }
+    }
+}
+
+fn remove_if_file(path: &Path) -> Result<(), String> {
+    if path.is_file() {
+        fs::remove_file(path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use regex::Regex;
+
+/// Compile a path glob into an anchored regex.
+///
+/// - `*` matches within a single path segment
+/// - `**` as a whole segment matches zero or more segments
+/// - `?` matches one character other than `/`
+/// - `[abc]`, `[a-z]`, `[!abc]` match character classes
+///
+/// Everything else (including `.`) is matched literally.
+pub fn compile(pattern: &str) -> Result<Regex, String> {
+    let pattern = normalize_separators(pattern);
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut re = String::from("^");
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let starts_segment = i == 0 || chars[i - 1] == '/';
+                let next = chars.get(i + 2);
+                if starts_segment && next == Some(&'/') {
+                    // "**/" matches any number of leading segments, including none
+                    re.push_str("(?:[^/]+/)*");
+                    i += 3;
+                } else if starts_segment && next.is_none() {
+                    re.push_str(".*");
+                    i += 2;
+                } else {
+                    // "**" inside a segment behaves like "*"
+                    re.push_str("[^/]*");
+                    i += 2;
+                }
+            }
+            '*' => {
+                re.push_str("[^/]*");
+                i += 1;
+            }
+            '?' => {
+                re.push_str("[^/]");
+                i += 1;
+            }
+            '[' => match parse_class(&chars, i) {
+                Some((class, end)) => {
+                    re.push_str(&class);
+                    i = end + 1;
+                }
+                None => {
+                    re.push_str(&regex::escape("["));
+                    i += 1;
+                }
+            },
+            #[cfg(not(windows))]
+            '\\' if i + 1 < chars.len() => {
+                re.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+            }
+            c => {
+                re.push_str(&regex::escape(&c.to_string()));
+                i += 1;
+            }
+        }
+    }
+
+    re.push('$');
+    Regex::new(&re).map_err(|e| format!("Invalid glob pattern '{}': {}", pattern, e))
+}
+
+/// Check whether a path matches a glob pattern
+pub fn matches(pattern: &str, path: &str) -> bool {
+    compile(pattern)
+        .map(|re| re.is_match(&normalize_separators(path)))
+        .unwrap_or(false)
+}
+
+/// Translate a `[...]` class starting at `start` into a regex class.
+/// Returns the class and the index of the closing bracket.
+fn parse_class(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    // A leading ']' is part of the class, not its end
+    let body_start = i;
+    if chars.get(i) == Some(&']') {
+        i += 1;
+    }
+    while i < chars.len() && chars[i] != ']' {
+        i += 1;
+    }
+    if i >= chars.len() {
+        return None;
+    }
+
+    let body = &chars[body_start..i];
+    let mut class = String::from(if negated { "[^/" } else { "[" });
+    for (idx, c) in body.iter().enumerate() {
+        let is_range = *c == '-' && idx > 0 && idx + 1 < body.len();
+        if is_range {
+            class.push('-');
+        } else {
+            class.push_str(&regex::escape(&c.to_string()));
+        }
+    }
+    // A class never matches the separator, even when it lists `/` or a range spans it
+    if !negated {
+        class.push_str("&&[^/]");
+    }
+    class.push(']');
+
+    Some((class, i))
+}
+
+/// Use `/` as the only separator so patterns behave the same on every platform
+#[cfg(windows)]
+pub fn normalize_separators(s: &str) -> String {
+    s.replace('\\', "/")
+}
+
+#[cfg(not(windows))]
+pub fn normalize_separators(s: &str) -> String {
+    s.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn star_stays_within_a_segment() {
+        assert!(matches("/home/me/Docs/*", "/home/me/Docs/a.txt"));
+        assert!(matches("/home/me/Docs/*.txt", "/home/me/Docs/notes.txt"));
+        assert!(!matches("/home/me/Docs/*", "/home/me/Docs/sub/a.txt"));
+        assert!(!matches("/home/me/Docs/*.txt", "/home/me/Docs/notes.md"));
+    }
+
+    #[test]
+    fn double_star_spans_segments() {
+        assert!(matches("/home/me/**", "/home/me/a/b/c.txt"));
+        assert!(matches("/home/me/**/c.txt", "/home/me/c.txt"));
+        assert!(matches("/home/me/**/c.txt", "/home/me/a/b/c.txt"));
+        assert!(!matches("/home/me/**/c.txt", "/home/you/c.txt"));
+        assert!(!matches("/home/me/**", "/home/meat/a.txt"));
+        // Inside a segment it is a single star
+        assert!(matches("/home/me/x**", "/home/me/xyz"));
+        assert!(!matches("/home/me/x**", "/home/me/x/y"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches("/tmp/?.txt", "/tmp/a.txt"));
+        assert!(!matches("/tmp/?.txt", "/tmp/ab.txt"));
+        assert!(!matches("/tmp?a.txt", "/tmp/a.txt"));
+    }
+
+    #[test]
+    fn classes_match_listed_characters() {
+        assert!(matches("/tmp/[ab].txt", "/tmp/b.txt"));
+        assert!(!matches("/tmp/[ab].txt", "/tmp/c.txt"));
+        assert!(matches("/tmp/[a-c].txt", "/tmp/c.txt"));
+        assert!(!matches("/tmp/[a-c].txt", "/tmp/d.txt"));
+        assert!(matches("/tmp/[!a].txt", "/tmp/b.txt"));
+        assert!(!matches("/tmp/[!a].txt", "/tmp/a.txt"));
+        assert!(matches("/tmp/[]x].txt", "/tmp/].txt"));
+        // An unclosed bracket is literal
+        assert!(matches("/tmp/[a", "/tmp/[a"));
+    }
+
+    #[test]
+    fn classes_never_match_the_separator() {
+        assert!(!matches("/tmp[/]a.txt", "/tmp/a.txt"));
+        assert!(!matches("/tmp[.-0]a.txt", "/tmp/a.txt"));
+        assert!(!matches("/tmp[!a]a.txt", "/tmp/a.txt"));
+    }
+
+    #[test]
+    fn dots_are_literal() {
+        assert!(matches("/home/me/notes.v2/*", "/home/me/notes.v2/a.txt"));
+        assert!(!matches("/home/me/notes.v2/*", "/home/me/notesXv2/a.txt"));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn backslash_escapes_special_characters() {
+        assert!(matches(r"/tmp/\*", "/tmp/*"));
+        assert!(!matches(r"/tmp/\*", "/tmp/a"));
+        assert!(matches(r"/tmp/\[a\]", "/tmp/[a]"));
+        assert!(!matches(r"/tmp/\[a\]", "/tmp/a"));
+    }
+}
@@ -4,8 +4,14 @@ pub mod executor;
 pub mod policy;
 pub mod audit;
 pub mod validator;
+pub mod preconditions;
 pub mod commands;
+pub mod glob;
 pub mod sandbox;
+pub mod settings;
+pub mod store;
+pub mod schema;
+pub mod references;
 
 pub use types::*;
 pub use commands::*;
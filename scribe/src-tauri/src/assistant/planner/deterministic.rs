@@ -1,522 +1,1570 @@
+use crate::assistant::planner::resolver::{PathResolver, Resolution};
+use crate::assistant::references;
 use crate::assistant::types::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-/// Parse user intent into an action plan using rule-based matching
-/// Returns ActionPlan with placeholder paths if path is missing (frontend will prompt)
+/// Words that join two clauses ("create folder a and then move b into it")
+const CONJUNCTIONS: &[&str] = &["and", "then", "also", "afterwards", "&&"];
+
+/// Words that refer to the path of the previous step
+const PRONOUNS: &[&str] = &["it", "there", "that", "this"];
+
+/// Words that end a source path and start its destination
+const DESTINATION_WORDS: &[&str] = &["to", "into", "in", "inside", "as"];
+
+const DIRECTORY_NOUNS: &[&str] = &["directory", "dir", "folder"];
+
+/// Words that end a search query and start the directory to search
+const SEARCH_DIR_WORDS: &[&str] = &["in", "under", "inside", "within"];
+
+/// Words in a search query that say nothing about the file's name
+const SEARCH_FILLER: &[&str] = &["the", "a", "an", "my", "all", "file", "files", "from", "called", "named", "for", "of"];
+
+/// Words that end an archive's source and start where it is written or unpacked
+const ARCHIVE_DESTINATION_WORDS: &[&str] = &["to", "into", "as", "in", "inside"];
+
+/// Words after a destination that say what to do if it already exists
+const CONFLICT_WORDS: &[&str] = &["overwriting", "replacing", "keeping", "renaming", "skipping", "unless"];
+
+/// Words that may follow a conflict word: "keeping both", "replacing existing files", "unless it exists"
+const CONFLICT_FILLER: &[&str] = &["both", "it", "them", "they", "existing", "any", "file", "files", "one", "ones", "exists", "exist"];
+
+/// Words that ask for a path's metadata after "show" or "get"
+const STAT_NOUNS: &[&str] = &["info", "information", "details", "metadata", "properties"];
+
+/// Parse user intent into an action plan using a small grammar.
+/// Clauses joined by "and", "then", commas or semicolons become steps in order, and
+/// "it" or "there" refers to the path of the previous step, or to a search's best match
+/// through a reference to its output. Paths keep their original
+/// casing and can be quoted to contain spaces or keywords.
+/// Returns ActionPlan with placeholder paths if a path is missing or ambiguous (frontend will prompt)
 pub fn parse_intent(
     user_input: &str,
-    user_home: &Path,
+    resolver: &PathResolver,
 ) -> Result<ActionPlan, String> {
-    let input_lower = user_input.to_lowercase().trim().to_string();
-    
-    // Generate unique IDs
-    let plan_id = Uuid::new_v4().to_string();
-    let request_id = Uuid::new_v4().to_string();
-
-    // Try to match common patterns
-    if let Some(action) = parse_create_file(&input_lower, user_home) {
-        return Ok(ActionPlan {
-            schema: ActionSchema {
-                id: plan_id,
-                origin: ActionOrigin {
-                    user_input: user_input.to_string(),
-                    source: ActionSource::Ui,
-                    request_id,
-                },
-                actions: vec![action],
-                summary: format!("Create file: {}", extract_file_path(&input_lower)),
-                risk_score: RiskScore::Low.value(),
-                dry_run: true,
-            },
-        });
+    let mut parser = Parser {
+        input: user_input,
+        tokens: tokenize(user_input),
+        pos: 0,
+        resolver,
+        last_path: None,
+        last_dir: None,
+        plan_dirs: HashSet::new(),
+        plan_files: HashSet::new(),
+        found: HashMap::new(),
+        ambiguous: Vec::new(),
+    };
+
+    let mut steps = Vec::new();
+    loop {
+        parser.skip_conjunctions();
+        if parser.pos >= parser.tokens.len() {
+            break;
+        }
+
+        let clause_start = parser.pos;
+        match parser.parse_clause() {
+            Some(step) if parser.at_clause_end() => steps.push(step),
+            _ => {
+                return Err(format!(
+                    "Could not parse intent: \"{}\". Please use LLM planner for complex requests.",
+                    parser.clause_text(clause_start)
+                ))
+            }
+        }
     }
 
-    if let Some(action) = parse_read_file(&input_lower, user_home) {
-        return Ok(ActionPlan {
-            schema: ActionSchema {
-                id: plan_id,
-                origin: ActionOrigin {
-                    user_input: user_input.to_string(),
-                    source: ActionSource::Ui,
-                    request_id,
-                },
-                actions: vec![action],
-                summary: format!("Read file: {}", extract_file_path(&input_lower)),
-                risk_score: RiskScore::Low.value(),
-                dry_run: true,
-            },
-        });
+    if steps.is_empty() {
+        return Err("Could not parse intent. Please use LLM planner for complex requests.".to_string());
     }
 
-    if let Some((src_action, dst_action)) = parse_copy_file(&input_lower, user_home) {
-        return Ok(ActionPlan {
-            schema: ActionSchema {
-                id: plan_id,
-                origin: ActionOrigin {
-                    user_input: user_input.to_string(),
-                    source: ActionSource::Ui,
-                    request_id,
-                },
-                actions: vec![src_action, dst_action],
-                summary: format!("Copy file"),
-                risk_score: RiskScore::Medium.value(),
-                dry_run: true,
+    let risk_score = steps
+        .iter()
+        .map(|step| step.risk.value())
+        .fold(0.0, f64::max);
+    let summary = steps
+        .iter()
+        .map(|step| step.summary.as_str())
+        .collect::<Vec<_>>()
+        .join(", then ");
+    let mut actions: Vec<Action> = steps.into_iter().flat_map(|step| step.actions).collect();
+    let summary = parser.prompt_for_ambiguous(&mut actions, summary);
+
+    Ok(ActionPlan {
+        schema: ActionSchema {
+            id: Uuid::new_v4().to_string(),
+            origin: ActionOrigin {
+                user_input: user_input.to_string(),
+                source: ActionSource::Ui,
+                request_id: Uuid::new_v4().to_string(),
             },
+            actions,
+            summary,
+            risk_score,
+            dry_run: true,
+            escalated_paths: Vec::new(),
+        },
+    })
+}
+
+/// Actions produced by one clause
+struct Step {
+    actions: Vec<Action>,
+    summary: String,
+    risk: RiskScore,
+}
+
+impl Step {
+    /// Let the step's copy, move or archive handle an existing destination by `policy`
+    fn on_conflict(mut self, policy: Option<OnConflict>) -> Self {
+        let Some(policy) = policy else {
+            return self;
+        };
+        if let Some(action) = self.actions.last_mut() {
+            set_on_conflict(action, policy);
+        }
+        self.summary.push_str(match policy {
+            OnConflict::Fail => "",
+            OnConflict::Overwrite => ", replacing it if it exists",
+            OnConflict::Rename => ", under a new name if it exists",
+            OnConflict::Skip => ", unless it exists",
         });
+        self
     }
+}
 
-    if let Some(action) = parse_move_file(&input_lower, user_home) {
-        return Ok(ActionPlan {
-            schema: ActionSchema {
-                id: plan_id,
-                origin: ActionOrigin {
-                    user_input: user_input.to_string(),
-                    source: ActionSource::Ui,
-                    request_id,
-                },
-                actions: vec![action],
-                summary: format!("Move file"),
-                risk_score: RiskScore::Medium.value(),
-                dry_run: true,
-            },
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word,
+    Quoted,
+    Separator,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+/// A path as written: literal text, or a pronoun for the previous step's path
+enum PathArg {
+    Literal(String),
+    Previous,
+}
+
+/// Split input into words, quoted strings and separators, keeping byte offsets
+/// so multi-word paths can be sliced from the original text
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if c == ',' || c == ';' {
+            chars.next();
+            tokens.push(Token {
+                kind: TokenKind::Separator,
+                text: c.to_string(),
+                start,
+                end: start + 1,
+            });
+            continue;
+        }
+
+        // Quotes only open at the start of a word, so "what's" stays one word
+        if let Some(close) = matches!(c, '"' | '\'' | '`')
+            .then(|| input[start + 1..].find(c))
+            .flatten()
+        {
+            let end = start + 1 + close + 1;
+            tokens.push(Token {
+                kind: TokenKind::Quoted,
+                text: input[start + 1..end - 1].to_string(),
+                start,
+                end,
+            });
+            while chars.peek().is_some_and(|&(i, _)| i < end) {
+                chars.next();
+            }
+            continue;
+        }
+
+        let mut end = start;
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_whitespace() || c == ',' || c == ';' {
+                break;
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+        tokens.push(Token {
+            kind: TokenKind::Word,
+            text: input[start..end].to_string(),
+            start,
+            end,
         });
     }
 
-    if let Some(action) = parse_delete_file(&input_lower, user_home) {
-        return Ok(ActionPlan {
-            schema: ActionSchema {
-                id: plan_id,
-                origin: ActionOrigin {
-                    user_input: user_input.to_string(),
-                    source: ActionSource::Ui,
-                    request_id,
-                },
-                actions: vec![action],
-                summary: format!("Delete file: {}", extract_file_path(&input_lower)),
-                risk_score: RiskScore::High.value(),
-                dry_run: true,
+    tokens
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    resolver: &'a PathResolver,
+    /// Path the previous step produced or acted on, for "it" and "there"
+    last_path: Option<PathBuf>,
+    /// Directory the previous step worked in, for "it" where a directory is expected
+    last_dir: Option<PathBuf>,
+    /// Directories earlier steps create or move into place
+    plan_dirs: HashSet<PathBuf>,
+    /// Files earlier steps create or move into place
+    plan_files: HashSet<PathBuf>,
+    /// References to the best match of earlier searches, and to its file name
+    found: HashMap<PathBuf, String>,
+    /// Paths as typed that matched several existing paths, and those paths.
+    /// Stood in for by `ambiguous_marker` until the plan is built.
+    ambiguous: Vec<(String, Vec<PathBuf>)>,
+}
+
+impl Parser<'_> {
+    /// Parse one clause starting at a verb
+    fn parse_clause(&mut self) -> Option<Step> {
+        let verb = self.next_word()?;
+
+        match verb.as_str() {
+            "create" | "make" | "new" | "touch" | "mkdir" => self.parse_create(&verb),
+            "find" | "search" | "locate" | "look" => {
+                if verb == "look" && !self.eat(&["for"]) {
+                    return None;
+                }
+                self.parse_search()
+            }
+            "stat" | "inspect" => self.parse_stat(),
+            "get" => {
+                if !self.eat(STAT_NOUNS) {
+                    return None;
+                }
+                self.parse_stat()
+            }
+            "read" | "open" | "show" | "view" | "display" | "cat" => {
+                if matches!(verb.as_str(), "show" | "view") && self.eat(DIRECTORY_NOUNS) {
+                    return self.parse_list();
+                }
+                if verb == "show" && self.eat(STAT_NOUNS) {
+                    return self.parse_stat();
+                }
+                if !self.eat_phrase(&["the", "file"]) {
+                    self.eat(&["file"]);
+                }
+                self.parse_read()
+            }
+            "list" | "ls" => {
+                let _ = self.eat_phrase(&["files", "in"])
+                    || self.eat_phrase(&["contents", "of"])
+                    || self.eat(DIRECTORY_NOUNS)
+                    || self.eat(&["files"]);
+                self.parse_list()
+            }
+            "what's" | "whats" => {
+                if !self.eat(&["in"]) {
+                    return None;
+                }
+                self.parse_list()
+            }
+            "what" => {
+                if !self.eat_phrase(&["is", "in"]) {
+                    return None;
+                }
+                self.parse_list()
+            }
+            "zip" | "compress" | "archive" | "tar" => self.parse_archive(&verb),
+            "unzip" | "extract" | "unpack" | "untar" => self.parse_extract(),
+            "copy" | "cp" => self.parse_transfer(false),
+            "move" | "mv" | "rename" => self.parse_transfer(true),
+            "delete" | "remove" | "rm" | "rmdir" | "trash" | "erase" => self.parse_delete(&verb),
+            _ => None,
+        }
+    }
+
+    /// "create file X [in DIR] [with content Y]", "create folder X", "mkdir X"
+    fn parse_create(&mut self, verb: &str) -> Option<Step> {
+        let directory = match verb {
+            "mkdir" => true,
+            "touch" => false,
+            _ => {
+                self.eat(&["a", "an", "the"]);
+                self.eat(&["new", "empty"]);
+                if self.eat(DIRECTORY_NOUNS) {
+                    true
+                } else if self.eat(&["file"]) {
+                    false
+                } else {
+                    return None;
+                }
+            }
+        };
+
+        let name = self.parse_path(&["with", "containing", "in", "into", "inside", "under"]);
+        let mut base = None;
+        let mut content = None;
+
+        loop {
+            if self.eat(&["in", "into", "inside", "under"]) {
+                let dir = self.parse_path(&["with", "containing"])?;
+                base = Some(self.resolve_dir(dir)?);
+            } else if !directory && (self.eat_phrase(&["with", "content"]) || self.eat_phrase(&["with", "text"]) || self.eat(&["containing"])) {
+                content = Some(self.parse_content()?);
+            } else {
+                break;
+            }
+        }
+
+        let path = match (name, base) {
+            (Some(PathArg::Literal(name)), Some(base)) if Path::new(&name).is_relative() => base.join(name),
+            (Some(name), _) => self.resolve_new(name)?,
+            (None, _) if directory => return None,
+            // Path missing - frontend will prompt for it
+            (None, _) => return Some(self.step(create_file_action(None, ""), "Create file", None, RiskScore::Low)),
+        };
+
+        if directory {
+            self.plan_dirs.insert(path.clone());
+            let action = create_directory_action(&path);
+            Some(self.step(action, "Create directory", Some(path), RiskScore::Low))
+        } else {
+            self.plan_files.insert(path.clone());
+            let action = create_file_action(Some(&path), content.as_deref().unwrap_or(""));
+            Some(self.step(action, "Create file", Some(path), RiskScore::Low))
+        }
+    }
+
+    /// "read X", "open file X"
+    fn parse_read(&mut self) -> Option<Step> {
+        let path = match self.parse_path(&[]) {
+            Some(arg) => Some(self.resolve(arg)?),
+            None => None,
+        };
+        let action = read_file_action(path.as_deref(), if path.is_some() { 0.9 } else { 0.7 });
+        Some(self.step(action, "Read file", path, RiskScore::Low))
+    }
+
+    /// "list X [recursively]"
+    fn parse_list(&mut self) -> Option<Step> {
+        let path = match self.parse_path(&["recursively", "recursive"]) {
+            Some(arg) => Some(self.resolve_dir(arg)?),
+            None => None,
+        };
+        let recursive = self.eat(&["recursively", "recursive"]);
+        let action = list_directory_action(path.as_deref(), recursive);
+        Some(self.step(action, "List directory", path, RiskScore::Low))
+    }
+
+    /// "find the invoice from ACME [in DIR]", "search for *.pdf in X", "find files containing "total" in X".
+    /// Searches the home directory when no directory is named.
+    fn parse_search(&mut self) -> Option<Step> {
+        self.eat(&["for"]);
+        let query = match self.parse_path(&[SEARCH_DIR_WORDS, &["containing", "mentioning"]].concat()) {
+            Some(PathArg::Literal(query)) => query,
+            Some(PathArg::Previous) => return None,
+            None => String::new(),
+        };
+
+        let content = if self.eat(&["containing", "mentioning"]) {
+            match self.parse_path(SEARCH_DIR_WORDS)? {
+                PathArg::Literal(text) => Some(text),
+                PathArg::Previous => return None,
+            }
+        } else {
+            None
+        };
+
+        let directory = if self.eat(SEARCH_DIR_WORDS) {
+            let dir = self.parse_path(&[])?;
+            self.resolve_dir(dir)?
+        } else {
+            self.resolver.home().to_path_buf()
+        };
+
+        let words: Vec<&str> = query
+            .split_whitespace()
+            .filter(|word| !SEARCH_FILLER.contains(&word.to_lowercase().as_str()))
+            .collect();
+        let (globs, words): (Vec<&str>, Vec<&str>) = words.into_iter().partition(|word| is_glob(word));
+        if globs.len() > 1 || (globs.is_empty() && words.is_empty() && content.is_none()) {
+            return None;
+        }
+
+        let name = (!words.is_empty()).then(|| words.join(" "));
+        let mut described = match (&name, globs.first()) {
+            (Some(name), _) => format!("\"{}\"", name),
+            (None, Some(glob)) => glob.to_string(),
+            (None, None) => "files".to_string(),
+        };
+        if let Some(text) = &content {
+            described.push_str(&format!(" containing \"{}\"", text));
+        }
+        let summary = format!("Search for {} in {}", described, directory.display());
+        let action = search_action(&directory, name.as_deref(), globs.first().copied(), content.as_deref());
+
+        // The match is only known at run time, so "it" refers to it through the search's output
+        let best_match = PathBuf::from(references::output_reference(&action.id, "path"));
+        self.found.insert(best_match.clone(), references::output_reference(&action.id, "name"));
+        self.last_path = Some(best_match);
+        self.last_dir = None;
+        Some(Step {
+            actions: vec![action],
+            summary,
+            risk: RiskScore::Low,
+        })
+    }
+
+    /// "stat X", "inspect X", "show info for X", "get details of X"
+    fn parse_stat(&mut self) -> Option<Step> {
+        self.eat(&["of", "for", "about", "on"]);
+        if !self.eat_phrase(&["the", "file"]) && !self.eat(&["file"]) {
+            self.eat(&["the"]);
+            self.eat(DIRECTORY_NOUNS);
+        }
+        let path = match self.parse_path(&[])? {
+            PathArg::Previous => self.last_path.clone()?,
+            arg => self.resolve(arg)?,
+        };
+        let action = stat_action(&path);
+        Some(self.step(action, "Show details", Some(path), RiskScore::Low))
+    }
+
+    /// "zip X [to Y]", "compress folder X into Y", "tar X".
+    /// Without a destination the archive goes next to X as X.zip (X.tar.gz for "tar").
+    fn parse_archive(&mut self, verb: &str) -> Option<Step> {
+        if !self.eat_phrase(&["the", "file"]) && !self.eat(&["file"]) {
+            self.eat(&["the"]);
+            self.eat(DIRECTORY_NOUNS);
+        }
+        let extension = if verb == "tar" { "tar.gz" } else { "zip" };
+
+        let source = match self.parse_path(ARCHIVE_DESTINATION_WORDS)? {
+            PathArg::Previous => self.last_path.clone()?,
+            arg => self.resolve(arg)?,
+        };
+        let name = self.name_of(&source)?;
+
+        let destination = match self.next_if(ARCHIVE_DESTINATION_WORDS) {
+            Some(word) => {
+                let arg = self.parse_path(CONFLICT_WORDS)?;
+                let into = word != "to" && word != "as";
+                let destination = if into { self.resolve_dir(arg)? } else { self.resolve_new(arg)? };
+                if into || self.is_dir(&destination) {
+                    destination.join(format!("{}.{}", name, extension))
+                } else if has_archive_extension(&destination) {
+                    destination
+                } else {
+                    let mut with_extension = destination.into_os_string();
+                    with_extension.push(format!(".{}", extension));
+                    PathBuf::from(with_extension)
+                }
+            }
+            // A search result's folder is not known until it runs
+            None if self.found.contains_key(&source) => return None,
+            None => source.with_file_name(format!("{}.{}", name, extension)),
+        };
+
+        let on_conflict = self.parse_on_conflict();
+        let summary = format!("Archive: {} to {}", self.display(&source), self.display(&destination));
+        let action = archive_create_action(&source, &destination);
+        self.plan_files.insert(destination.clone());
+        self.remember(destination);
+        let step = Step {
+            actions: vec![action],
+            summary,
+            risk: RiskScore::Medium,
+        };
+        Some(step.on_conflict(on_conflict))
+    }
+
+    /// "unzip X [to Y]", "extract X into Y".
+    /// Without a destination the archive is unpacked into a folder named after it, next to it.
+    fn parse_extract(&mut self) -> Option<Step> {
+        if !self.eat_phrase(&["the", "archive"]) && !self.eat(&["archive"]) {
+            self.eat(&["the"]);
+        }
+
+        let source = match self.parse_path(ARCHIVE_DESTINATION_WORDS)? {
+            PathArg::Previous => self.last_path.clone()?,
+            arg => self.resolve(arg)?,
+        };
+
+        let destination = if self.eat(ARCHIVE_DESTINATION_WORDS) {
+            let arg = self.parse_path(&[])?;
+            self.resolve_new_dir(arg)?
+        } else if self.found.contains_key(&source) {
+            return None;
+        } else {
+            let name = source.file_name()?.to_string_lossy().to_string();
+            let lower = name.to_lowercase();
+            let stem_len = [".tar.gz", ".tgz", ".zip"]
+                .iter()
+                .find(|extension| lower.ends_with(*extension))
+                .map_or(name.len(), |extension| name.len() - extension.len());
+            source.with_file_name(&name[..stem_len])
+        };
+
+        let summary = format!("Extract archive: {} to {}", self.display(&source), self.display(&destination));
+        let action = archive_extract_action(&source, &destination);
+        self.plan_dirs.insert(destination.clone());
+        self.remember(destination);
+        Some(Step {
+            actions: vec![action],
+            summary,
+            risk: RiskScore::Medium,
+        })
+    }
+
+    /// "copy X to Y", "move X into Y", "rename X Y", "cp -r X Y",
+    /// and for many files "move all *.png from X to Y".
+    /// "cp -f", "mv -n" and a trailing "overwriting", "keeping both" or "skipping existing"
+    /// say what to do if the destination exists; otherwise the step fails.
+    fn parse_transfer(&mut self, moving: bool) -> Option<Step> {
+        if self.at_selection() {
+            return self.parse_bulk_transfer(moving);
+        }
+
+        let mut directory = self.eat(&["-r", "-rf"]).then_some(true);
+        let flag = self.parse_conflict_flag();
+        if self.eat(DIRECTORY_NOUNS) {
+            directory = Some(true);
+        } else if self.eat(&["file"]) {
+            directory = Some(false);
+        }
+
+        let source_start = self.pos;
+        let source_arg = self.parse_path(DESTINATION_WORDS)?;
+        let (source, destination_word, destination_arg) = match self.next_if(DESTINATION_WORDS) {
+            Some(word) => (self.resolve(source_arg)?, word, self.parse_path(CONFLICT_WORDS)?),
+            None => {
+                // "rename a.txt b.txt": the first word is the source, the rest the destination
+                self.pos = source_start;
+                let source = self.next_token()?;
+                if source.kind == TokenKind::Separator {
+                    return None;
+                }
+                (self.resolve(PathArg::Literal(source.text))?, "to".to_string(), self.parse_path(CONFLICT_WORDS)?)
+            }
+        };
+        let on_conflict = flag.or_else(|| self.parse_on_conflict());
+
+        let directory = directory.unwrap_or_else(|| self.is_dir(&source));
+        // Like mv and cp, moving into a directory keeps the source's name
+        let into = destination_word != "to" && destination_word != "as";
+        let destination = match destination_arg {
+            arg if into => self.resolve_dir(arg)?,
+            // A search result's folder is not known until it runs, so it cannot be renamed in place
+            PathArg::Literal(name) if self.is_rename(moving, &name) && !self.found.contains_key(&source) => {
+                source.with_file_name(name)
+            }
+            PathArg::Literal(name) if self.is_rename(moving, &name) => return None,
+            arg => self.resolve_new(arg)?,
+        };
+        let destination = if into || (destination_word == "to" && self.is_dir(&destination)) {
+            destination.join(self.name_of(&source)?)
+        } else {
+            destination
+        };
+
+        let name = |path: &Path| self.display(path);
+        let step = match (moving, directory) {
+            (false, false) => {
+                // The copy takes the file the read step read
+                let read = read_file_action(Some(&source), 0.85);
+                let read_path = PathBuf::from(references::output_reference(&read.id, "path"));
+                Step {
+                    summary: format!("Copy file: {} to {}", name(&source), name(&destination)),
+                    actions: vec![read, copy_file_action(&read_path, &destination)],
+                    risk: RiskScore::Medium,
+                }
+            }
+            (true, false) => Step {
+                actions: vec![transfer_action(ActionType::FsMoveFile, &source, &destination)],
+                summary: format!("Move file: {} to {}", name(&source), name(&destination)),
+                risk: RiskScore::Medium,
             },
-        });
+            (false, true) => Step {
+                actions: vec![transfer_action(ActionType::FsCopyDirectory, &source, &destination)],
+                summary: format!("Copy directory: {} to {}", name(&source), name(&destination)),
+                risk: RiskScore::Medium,
+            },
+            (true, true) => Step {
+                actions: vec![transfer_action(ActionType::FsMoveDirectory, &source, &destination)],
+                summary: format!("Move directory: {} to {}", name(&source), name(&destination)),
+                risk: RiskScore::High,
+            },
+        };
+
+        if directory {
+            if moving {
+                self.plan_dirs.remove(&source);
+            }
+            self.plan_dirs.insert(destination.clone());
+        } else {
+            if moving {
+                self.plan_files.remove(&source);
+            }
+            self.plan_files.insert(destination.clone());
+        }
+        self.remember(destination);
+        Some(step.on_conflict(on_conflict))
     }
 
-    if let Some(action) = parse_create_directory(&input_lower, user_home) {
-        return Ok(ActionPlan {
-            schema: ActionSchema {
-                id: plan_id,
-                origin: ActionOrigin {
-                    user_input: user_input.to_string(),
-                    source: ActionSource::Ui,
-                    request_id,
-                },
-                actions: vec![action],
-                summary: format!("Create directory: {}", extract_file_path(&input_lower)),
-                risk_score: RiskScore::Low.value(),
-                dry_run: true,
+    /// "move all png files from X to Y", "copy *.txt in X into Y"
+    fn parse_bulk_transfer(&mut self, moving: bool) -> Option<Step> {
+        let select = self.parse_selection(&["to", "into"])?;
+        if !self.eat(&["to", "into"]) {
+            return None;
+        }
+        let destination = self.parse_path(CONFLICT_WORDS)?;
+        let destination = self.resolve_new_dir(destination)?;
+        let on_conflict = self.parse_on_conflict();
+
+        let (action_type, verb) = if moving {
+            (ActionType::FsMoveFile, "Move")
+        } else {
+            (ActionType::FsCopyFile, "Copy")
+        };
+        let summary = format!(
+            "{} files matching {} to {}",
+            verb,
+            describe_selection(&select),
+            destination.display()
+        );
+
+        self.plan_dirs.insert(destination.clone());
+        self.remember(destination.clone());
+        let step = Step {
+            actions: vec![bulk_transfer_action(action_type, select, &destination)],
+            summary,
+            risk: RiskScore::Medium,
+        };
+        Some(step.on_conflict(on_conflict))
+    }
+
+    /// "delete X [permanently]", "delete folder X", "rm -r X", "rmdir X",
+    /// and for many files "delete *.tmp in X"
+    fn parse_delete(&mut self, verb: &str) -> Option<Step> {
+        if self.at_selection() {
+            let select = self.parse_selection(&["permanently", "forever"])?;
+            let permanent = self.eat(&["permanently", "forever"]);
+            let summary = format!("Delete files matching {}", describe_selection(&select));
+            if let Some(directory) = select.get("directory").and_then(|v| v.as_str()) {
+                self.remember(PathBuf::from(directory));
+            }
+            return Some(Step {
+                actions: vec![bulk_delete_action(select, permanent)],
+                summary,
+                risk: RiskScore::High,
+            });
+        }
+
+        let mut directory = (verb == "rmdir").then_some(true);
+        if self.eat(&["-r", "-rf", "-fr"]) || self.eat(DIRECTORY_NOUNS) {
+            directory = Some(true);
+        } else if self.eat(&["file"]) {
+            directory = Some(false);
+        }
+
+        let path = match self.parse_path(&["permanently", "forever"]) {
+            Some(arg) => Some(self.resolve(arg)?),
+            None => None,
+        };
+        let permanent = self.eat(&["permanently", "forever"]);
+
+        let directory = directory.unwrap_or_else(|| path.as_deref().is_some_and(|p| self.is_dir(p)));
+        if let Some(path) = &path {
+            self.plan_dirs.remove(path);
+            self.plan_files.remove(path);
+        }
+
+        let action = delete_action(directory, path.as_deref(), permanent);
+        if directory {
+            Some(self.step(action, "Delete directory", path, RiskScore::Critical))
+        } else {
+            Some(self.step(action, "Delete file", path, RiskScore::High))
+        }
+    }
+
+    /// Build a single-action step and remember its path for later pronouns
+    fn step(&mut self, action: Action, summary: &str, path: Option<PathBuf>, risk: RiskScore) -> Step {
+        let summary = match &path {
+            Some(path) => format!("{}: {}", summary, self.display(path)),
+            None => summary.to_string(),
+        };
+        if let Some(path) = path {
+            self.remember(path);
+        }
+        Step {
+            actions: vec![action],
+            summary,
+            risk,
+        }
+    }
+
+    /// Whether the next words pick files by pattern: "*.tmp", "all *.png", "all pdf files", "all files"
+    fn at_selection(&self) -> bool {
+        let word = |i: usize| {
+            self.tokens
+                .get(self.pos + i)
+                .filter(|t| t.kind == TokenKind::Word)
+                .map(|t| t.text.to_lowercase())
+        };
+
+        match word(0).as_deref() {
+            Some("all") => match word(1) {
+                Some(next) if is_glob(&next) || next == "files" => true,
+                Some(_) => word(2).as_deref() == Some("files"),
+                None => false,
             },
-        });
+            Some(first) => is_glob(first),
+            None => false,
+        }
     }
 
-    Err("Could not parse intent. Please use LLM planner for complex requests.".to_string())
-}
+    /// A selector for "[all] PATTERN [files] in|from DIR", "all EXT files in DIR", "all files in DIR"
+    /// or "DIR/PATTERN". The directory ends at one of `stops`.
+    fn parse_selection(&mut self, stops: &[&str]) -> Option<serde_json::Value> {
+        self.eat(&["all"]);
+        let pattern = if self.eat(&["files"]) {
+            None
+        } else {
+            let pattern = self.next_token()?.text;
+            self.eat(&["files"]);
+            Some(pattern)
+        };
 
-/// Parse "create file X" or "create file X with content Y"
-/// Returns action with "__PROMPT_PATH__" placeholder if path is missing
-fn parse_create_file(input: &str, user_home: &Path) -> Option<Action> {
-    // Check for missing path patterns
-    if regex::Regex::new(r"create\s+(?:a\s+)?file\s*$|new\s+file\s*$")
-        .ok()
-        .and_then(|re| if re.is_match(input) { Some(()) } else { None })
-        .is_some()
-    {
-        // Path missing - return action with placeholder
-        let mut args = HashMap::new();
-        args.insert("path".to_string(), serde_json::Value::String("__PROMPT_PATH__".to_string()));
-        args.insert("content".to_string(), serde_json::Value::String("".to_string()));
-        args.insert("encoding".to_string(), serde_json::Value::String("utf-8".to_string()));
-        
-        return Some(Action {
-            id: Uuid::new_v4().to_string(),
-            action_type: ActionType::FsCreateFile,
-            args,
-            preconditions: Some(Precondition {
-                writable: Some(true),
-                exists: Some(false),
-                readable: None,
-                directory: None,
-            }),
-            metadata: Some(ActionMetadata {
-                confidence: Some(0.7), // Lower confidence when path missing
-            }),
-        });
+        let (directory, pattern) = if self.eat(&["in", "from", "inside"]) {
+            let dir = self.parse_path(stops)?;
+            (self.resolve_dir(dir)?, pattern)
+        } else {
+            // "Downloads/*.tmp": the pattern only applies to the last segment
+            let (dir, name) = pattern.as_deref().filter(|p| is_glob(p))?.rsplit_once('/')?;
+            if is_glob(dir) {
+                return None;
+            }
+            let dir = PathArg::Literal(if dir.is_empty() { "/" } else { dir }.to_string());
+            (self.resolve_dir(dir)?, Some(name.to_string()))
+        };
+        // A selector's directory cannot be prompted for, so it must resolve to one path
+        if is_ambiguous_marker(&directory) {
+            return None;
+        }
+
+        let mut select = serde_json::Map::new();
+        select.insert("directory".to_string(), path_value(Some(&directory)));
+        match pattern {
+            Some(glob) if is_glob(&glob) => {
+                select.insert("glob".to_string(), serde_json::Value::String(glob));
+            }
+            // "all PDF files" or "all .pdf files"
+            Some(extension) => {
+                let extension = extension.trim_start_matches('.').to_lowercase();
+                select.insert("extensions".to_string(), serde_json::json!([extension]));
+            }
+            None => {}
+        }
+        Some(serde_json::Value::Object(select))
     }
 
-    let patterns = vec![
-        r"create\s+file\s+(.+?)(?:\s+with\s+content\s+(.+))?$",
-        r"create\s+a\s+file\s+(.+?)(?:\s+with\s+content\s+(.+))?$",
-        r"new\s+file\s+(.+?)(?:\s+with\s+content\s+(.+))?$",
-    ];
+    /// A path up to the end of the clause or one of `stops`
+    fn parse_path(&mut self, stops: &[&str]) -> Option<PathArg> {
+        let first = self.tokens.get(self.pos)?.clone();
+        match first.kind {
+            TokenKind::Separator => return None,
+            TokenKind::Quoted => {
+                self.pos += 1;
+                return Some(PathArg::Literal(first.text));
+            }
+            TokenKind::Word => {}
+        }
 
-    for pattern in patterns {
-        if let Some(captures) = regex::Regex::new(pattern)
-            .ok()
-            .and_then(|re| re.captures(input))
-        {
-            let file_path = captures.get(1)?.as_str().trim();
-            if file_path.is_empty() {
-                continue; // Skip empty captures
+        let mut end = self.pos;
+        while let Some(token) = self.tokens.get(end) {
+            let lower = token.text.to_lowercase();
+            if token.kind != TokenKind::Word || CONJUNCTIONS.contains(&lower.as_str()) || stops.contains(&lower.as_str()) {
+                break;
             }
-            let content = captures.get(2).map(|m| m.as_str().trim().to_string());
-            
-            let full_path = match resolve_path(file_path, user_home) {
-                Some(p) => p,
-                None => continue, // Try next pattern
-            };
-            
-            let mut args = HashMap::new();
-            args.insert("path".to_string(), serde_json::Value::String(full_path.to_string_lossy().to_string()));
-            if let Some(content) = content {
-                args.insert("content".to_string(), serde_json::Value::String(content));
-            } else {
-                args.insert("content".to_string(), serde_json::Value::String("".to_string()));
+            end += 1;
+        }
+        if end == self.pos {
+            return None;
+        }
+
+        let start = self.pos;
+        self.pos = end;
+        if end == start + 1 && PRONOUNS.contains(&first.text.to_lowercase().as_str()) {
+            return Some(PathArg::Previous);
+        }
+        Some(PathArg::Literal(
+            self.input[self.tokens[start].start..self.tokens[end - 1].end].to_string(),
+        ))
+    }
+
+    /// File content: a quoted string, or everything to the end of the input
+    fn parse_content(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos)?.clone();
+        if token.kind == TokenKind::Quoted {
+            self.pos += 1;
+            return Some(token.text);
+        }
+        self.pos = self.tokens.len();
+        Some(self.input[token.start..].trim().to_string())
+    }
+
+    /// A path that should already exist, or exist by the time this step runs
+    fn resolve(&mut self, arg: PathArg) -> Option<PathBuf> {
+        match arg {
+            PathArg::Literal(path) => {
+                let resolution = self.resolver.existing(&path, &|p| self.exists(p));
+                Some(self.resolved(path, resolution))
             }
-            args.insert("encoding".to_string(), serde_json::Value::String("utf-8".to_string()));
-
-            return Some(Action {
-                id: Uuid::new_v4().to_string(),
-                action_type: ActionType::FsCreateFile,
-                args,
-                preconditions: Some(Precondition {
-                    writable: Some(true),
-                    exists: Some(false),
-                    readable: None,
-                    directory: None,
-                }),
-                metadata: Some(ActionMetadata {
-                    confidence: Some(0.9),
-                }),
-            });
+            PathArg::Previous => self.last_path.clone(),
         }
     }
 
-    None
-}
+    /// A path this step creates; only its directories are matched against existing names
+    fn resolve_new(&mut self, arg: PathArg) -> Option<PathBuf> {
+        match arg {
+            PathArg::Literal(path) => {
+                let resolution = self.resolver.new_path(&path, &|p| self.exists(p));
+                Some(self.resolved(path, resolution))
+            }
+            PathArg::Previous => self.last_path.clone(),
+        }
+    }
 
-/// Parse "read file X" or "open file X" or "show file X"
-/// Returns action with "__PROMPT_PATH__" placeholder if path is missing
-fn parse_read_file(input: &str, user_home: &Path) -> Option<Action> {
-    // Check for missing path patterns
-    if regex::Regex::new(r"(?:read|open|show|view|display)\s+file\s*$")
-        .ok()
-        .and_then(|re| if re.is_match(input) { Some(()) } else { None })
-        .is_some()
-    {
-        let mut args = HashMap::new();
-        args.insert("path".to_string(), serde_json::Value::String("__PROMPT_PATH__".to_string()));
-        
-        return Some(Action {
-            id: Uuid::new_v4().to_string(),
-            action_type: ActionType::FsReadFile,
-            args,
-            preconditions: Some(Precondition {
-                readable: Some(true),
-                exists: Some(true),
-                writable: None,
-                directory: None,
-            }),
-            metadata: Some(ActionMetadata {
-                confidence: Some(0.7),
-            }),
-        });
+    /// Like `resolve`, but "it" means the last directory rather than the last file
+    fn resolve_dir(&mut self, arg: PathArg) -> Option<PathBuf> {
+        match arg {
+            PathArg::Previous => self.last_dir.clone(),
+            arg => self.resolve(arg),
+        }
     }
 
-    let patterns = vec![
-        r"(?:read|open|show|view|display)\s+file\s+(.+?)$",
-        r"(?:read|open|show|view|display)\s+(.+?)$",
-    ];
+    /// Like `resolve_new`, but "it" means the last directory rather than the last file
+    fn resolve_new_dir(&mut self, arg: PathArg) -> Option<PathBuf> {
+        match arg {
+            PathArg::Previous => self.last_dir.clone(),
+            arg => self.resolve_new(arg),
+        }
+    }
 
-    for pattern in patterns {
-        if let Some(captures) = regex::Regex::new(pattern)
-            .ok()
-            .and_then(|re| re.captures(input))
-        {
-            let file_path = captures.get(1)?.as_str().trim();
-            if file_path.is_empty() {
-                continue;
+    /// The resolved path, or a marker to prompt for it later if it is ambiguous
+    fn resolved(&mut self, typed: String, resolution: Resolution) -> PathBuf {
+        match resolution {
+            Resolution::Path(path) => path,
+            Resolution::Ambiguous(candidates) => {
+                self.ambiguous.push((typed, candidates));
+                PathBuf::from(ambiguous_marker(self.ambiguous.len() - 1))
             }
-            let full_path = match resolve_path(file_path, user_home) {
-                Some(p) => p,
-                None => continue,
-            };
-
-            let mut args = HashMap::new();
-            args.insert("path".to_string(), serde_json::Value::String(full_path.to_string_lossy().to_string()));
-
-            return Some(Action {
-                id: Uuid::new_v4().to_string(),
-                action_type: ActionType::FsReadFile,
-                args,
-                preconditions: Some(Precondition {
-                    readable: Some(true),
-                    exists: Some(true),
-                    writable: None,
-                    directory: None,
-                }),
-                metadata: Some(ActionMetadata {
-                    confidence: Some(0.9),
-                }),
-            });
         }
     }
 
-    None
-}
+    /// Replace ambiguous path markers with prompts that offer the candidates,
+    /// and with the path as typed in the summary
+    fn prompt_for_ambiguous(&self, actions: &mut [Action], mut summary: String) -> String {
+        for (index, (typed, candidates)) in self.ambiguous.iter().enumerate() {
+            let marker = ambiguous_marker(index);
+            // After a directory the marker stands for a file name ("move X into Y")
+            let typed_name = typed.rsplit('/').next().unwrap_or(typed);
+            summary = summary
+                .replace(&format!("/{}", marker), &format!("/{}", typed_name))
+                .replace(&marker, typed);
 
-/// Parse "copy X to Y" or "copy file X to Y"
-fn parse_copy_file(input: &str, user_home: &Path) -> Option<(Action, Action)> {
-    let patterns = vec![
-        r"copy\s+(?:file\s+)?(.+?)\s+to\s+(.+?)$",
-        r"copy\s+(.+?)\s+(?:to\s+)?(.+?)$",
-    ];
-
-    for pattern in patterns {
-        if let Some(captures) = regex::Regex::new(pattern)
-            .ok()
-            .and_then(|re| re.captures(input))
-        {
-            let src_path = captures.get(1)?.as_str().trim();
-            let dst_path = captures.get(2)?.as_str().trim();
-            
-            let full_src_path = resolve_path(src_path, user_home)?;
-            let full_dst_path = resolve_path(dst_path, user_home)?;
-
-            let mut src_args = HashMap::new();
-            src_args.insert("path".to_string(), serde_json::Value::String(full_src_path.to_string_lossy().to_string()));
-
-            let mut dst_args = HashMap::new();
-            dst_args.insert("source_path".to_string(), serde_json::Value::String(full_src_path.to_string_lossy().to_string()));
-            dst_args.insert("destination_path".to_string(), serde_json::Value::String(full_dst_path.to_string_lossy().to_string()));
-
-            let read_action = Action {
-                id: Uuid::new_v4().to_string(),
-                action_type: ActionType::FsReadFile,
-                args: src_args,
-                preconditions: Some(Precondition {
-                    readable: Some(true),
-                    exists: Some(true),
-                    writable: None,
-                    directory: None,
-                }),
-                metadata: Some(ActionMetadata {
-                    confidence: Some(0.85),
-                }),
-            };
-
-            let copy_action = Action {
-                id: Uuid::new_v4().to_string(),
-                action_type: ActionType::FsCopyFile,
-                args: dst_args,
-                preconditions: Some(Precondition {
-                    writable: Some(true),
-                    exists: Some(false), // Destination shouldn't exist
-                    readable: None,
-                    directory: None,
-                }),
-                metadata: Some(ActionMetadata {
-                    confidence: Some(0.85),
-                }),
-            };
-
-            return Some((read_action, copy_action));
-        }
-    }
-
-    None
+            for action in actions.iter_mut() {
+                let mut prompts = BTreeMap::new();
+                for (arg, value) in action.args.iter_mut() {
+                    let Some((before, after)) = value.as_str().and_then(|v| v.split_once(&marker)) else {
+                        continue;
+                    };
+                    let after = after.trim_start_matches('/');
+                    let options: Vec<String> = candidates
+                        .iter()
+                        .map(|candidate| {
+                            let path = if before.is_empty() {
+                                candidate.join(after)
+                            } else {
+                                Path::new(before).join(candidate.file_name().unwrap_or_default()).join(after)
+                            };
+                            path.to_string_lossy().trim_end_matches('/').to_string()
+                        })
+                        .collect();
+                    prompts.insert(arg.clone(), options);
+                    *value = path_value(None);
+                }
+
+                if !prompts.is_empty() {
+                    let metadata = action.metadata.get_or_insert_with(Default::default);
+                    metadata
+                        .path_candidates
+                        .get_or_insert_with(BTreeMap::new)
+                        .extend(prompts);
+                }
+            }
+        }
+        summary
+    }
+
+    /// Remember a step's path, and the directory it is or is in, for later pronouns
+    fn remember(&mut self, path: PathBuf) {
+        self.last_dir = if self.is_dir(&path) {
+            Some(path.clone())
+        } else if self.found.contains_key(&path) {
+            None
+        } else {
+            path.parent().map(Path::to_path_buf)
+        };
+        self.last_path = Some(path);
+    }
+
+    /// The file name a path keeps when it is moved or copied into a folder,
+    /// or a reference to it for a search's best match
+    fn name_of(&self, path: &Path) -> Option<String> {
+        match self.found.get(path) {
+            Some(name) => Some(name.clone()),
+            None => Some(path.file_name()?.to_string_lossy().to_string()),
+        }
+    }
+
+    /// A path for a step summary, with references to a search's best match in words
+    fn display(&self, path: &Path) -> String {
+        let mut text = path.to_string_lossy().to_string();
+        for (best_match, name) in &self.found {
+            text = text
+                .replace(best_match.to_string_lossy().as_ref(), "the found file")
+                .replace(name.as_str(), "[found file name]");
+        }
+        text
+    }
+
+    /// A directory created earlier in the plan or already on disk
+    fn is_dir(&self, path: &Path) -> bool {
+        self.plan_dirs.contains(path) || path.is_dir()
+    }
+
+    /// A path created earlier in the plan or already on disk
+    fn exists(&self, path: &Path) -> bool {
+        self.plan_dirs.contains(path) || self.plan_files.contains(path) || path.exists()
+    }
+
+    /// "rename a.txt to b.txt" renames in place when the new name has no directory part
+    /// and is not the name of a folder
+    fn is_rename(&self, moving: bool, name: &str) -> bool {
+        let is_folder = match self.resolver.existing(name, &|p| self.exists(p)) {
+            Resolution::Path(path) => self.is_dir(&path),
+            Resolution::Ambiguous(_) => false,
+        };
+        moving && !name.contains('/') && !name.starts_with('~') && !is_folder
+    }
+
+    /// "-f" or "--force" overwrites an existing destination, "-n" or "--no-clobber" leaves it
+    fn parse_conflict_flag(&mut self) -> Option<OnConflict> {
+        match self.next_if(&["-f", "--force", "-n", "--no-clobber"])?.as_str() {
+            "-f" | "--force" => Some(OnConflict::Overwrite),
+            _ => Some(OnConflict::Skip),
+        }
+    }
+
+    /// "overwriting", "replacing existing", "keeping both", "skipping existing" or
+    /// "unless it exists" after a destination
+    fn parse_on_conflict(&mut self) -> Option<OnConflict> {
+        let policy = match self.next_if(CONFLICT_WORDS)?.as_str() {
+            "overwriting" | "replacing" => OnConflict::Overwrite,
+            "keeping" | "renaming" => OnConflict::Rename,
+            _ => OnConflict::Skip,
+        };
+        while self.eat(CONFLICT_FILLER) {}
+        Some(policy)
+    }
+
+    fn skip_conjunctions(&mut self) {
+        loop {
+            if self.tokens.get(self.pos).is_some_and(|t| t.kind == TokenKind::Separator) {
+                self.pos += 1;
+            } else if !self.eat(CONJUNCTIONS) && !self.eat_phrase(&["after", "that"]) {
+                break;
+            }
+        }
+    }
+
+    fn at_clause_end(&self) -> bool {
+        match self.tokens.get(self.pos) {
+            None => true,
+            Some(token) if token.kind == TokenKind::Separator => true,
+            Some(token) => {
+                token.kind == TokenKind::Word && CONJUNCTIONS.contains(&token.text.to_lowercase().as_str())
+            }
+        }
+    }
+
+    /// Text of a clause, for error messages
+    fn clause_text(&self, start: usize) -> String {
+        let mut end = start + 1;
+        while end < self.tokens.len() {
+            let token = &self.tokens[end];
+            if token.kind == TokenKind::Separator
+                || (token.kind == TokenKind::Word && CONJUNCTIONS.contains(&token.text.to_lowercase().as_str()))
+            {
+                break;
+            }
+            end += 1;
+        }
+        self.input[self.tokens[start].start..self.tokens[end - 1].end].to_string()
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// Next unquoted word, lowercased
+    fn next_word(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos)?;
+        if token.kind != TokenKind::Word {
+            return None;
+        }
+        self.pos += 1;
+        Some(token.text.to_lowercase())
+    }
+
+    /// Consume the next word if it is one of `words`, returning it
+    fn next_if(&mut self, words: &[&str]) -> Option<String> {
+        let token = self.tokens.get(self.pos)?;
+        let lower = token.text.to_lowercase();
+        if token.kind == TokenKind::Word && words.contains(&lower.as_str()) {
+            self.pos += 1;
+            return Some(lower);
+        }
+        None
+    }
+
+    fn eat(&mut self, words: &[&str]) -> bool {
+        self.next_if(words).is_some()
+    }
+
+    /// Consume a sequence of words, or nothing if they do not all match
+    fn eat_phrase(&mut self, phrase: &[&str]) -> bool {
+        let matches = phrase.iter().enumerate().all(|(i, word)| {
+            self.tokens
+                .get(self.pos + i)
+                .is_some_and(|t| t.kind == TokenKind::Word && t.text.eq_ignore_ascii_case(word))
+        });
+        if matches {
+            self.pos += phrase.len();
+        }
+        matches
+    }
 }
 
-/// Parse "move X to Y" or "rename X to Y"
-fn parse_move_file(input: &str, user_home: &Path) -> Option<Action> {
-    let patterns = vec![
-        r"(?:move|rename)\s+(?:file\s+)?(.+?)\s+to\s+(.+?)$",
-        r"rename\s+(.+?)\s+(?:to\s+)?(.+?)$",
-    ];
-
-    for pattern in patterns {
-        if let Some(captures) = regex::Regex::new(pattern)
-            .ok()
-            .and_then(|re| re.captures(input))
-        {
-            let src_path = captures.get(1)?.as_str().trim();
-            let dst_path = captures.get(2)?.as_str().trim();
-            
-            let full_src_path = resolve_path(src_path, user_home)?;
-            let full_dst_path = resolve_path(dst_path, user_home)?;
-
-            let mut args = HashMap::new();
-            args.insert("source_path".to_string(), serde_json::Value::String(full_src_path.to_string_lossy().to_string()));
-            args.insert("destination_path".to_string(), serde_json::Value::String(full_dst_path.to_string_lossy().to_string()));
-
-            return Some(Action {
-                id: Uuid::new_v4().to_string(),
-                action_type: ActionType::FsMoveFile,
-                args,
-                preconditions: Some(Precondition {
-                    writable: Some(true),
-                    exists: Some(true), // Source must exist
-                    readable: None,
-                    directory: None,
-                }),
-                metadata: Some(ActionMetadata {
-                    confidence: Some(0.85),
-                }),
-            });
+/// Handle an existing destination by `policy` instead of requiring it to be free
+fn set_on_conflict(action: &mut Action, policy: OnConflict) {
+    action
+        .args
+        .insert("on_conflict".to_string(), serde_json::Value::String(policy.name().to_string()));
+    if let Some(Preconditions::PerArg(preconditions)) = &mut action.preconditions {
+        if let Some(destination) = preconditions.get_mut("destination_path") {
+            destination.exists = None;
         }
     }
+}
 
-    None
+fn path_value(path: Option<&Path>) -> serde_json::Value {
+    match path {
+        Some(path) => serde_json::Value::String(path.to_string_lossy().to_string()),
+        None => serde_json::Value::String("__PROMPT_PATH__".to_string()),
+    }
 }
 
-/// Parse "delete X" or "remove X"
-/// Returns action with "__PROMPT_PATH__" placeholder if path is missing
-fn parse_delete_file(input: &str, user_home: &Path) -> Option<Action> {
-    // Check for missing path
-    if regex::Regex::new(r"(?:delete|remove|rm)\s+(?:file\s+)?$")
-        .ok()
-        .and_then(|re| if re.is_match(input) { Some(()) } else { None })
-        .is_some()
-    {
-        let mut args = HashMap::new();
-        args.insert("path".to_string(), serde_json::Value::String("__PROMPT_PATH__".to_string()));
-        
-        return Some(Action {
-            id: Uuid::new_v4().to_string(),
-            action_type: ActionType::FsDeleteFile,
-            args,
-            preconditions: Some(Precondition {
+fn create_file_action(path: Option<&Path>, content: &str) -> Action {
+    let mut args = HashMap::new();
+    args.insert("path".to_string(), path_value(path));
+    args.insert("content".to_string(), serde_json::Value::String(content.to_string()));
+    args.insert("encoding".to_string(), serde_json::Value::String("utf-8".to_string()));
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: ActionType::FsCreateFile,
+        args,
+        preconditions: Some(Preconditions::on("path", Precondition {
+            writable: Some(true),
+            exists: Some(false),
+            ..Default::default()
+        })),
+        metadata: Some(ActionMetadata {
+            confidence: Some(if path.is_some() { 0.9 } else { 0.7 }), // Lower confidence when path missing
+            ..Default::default()
+        }),
+    }
+}
+
+fn read_file_action(path: Option<&Path>, confidence: f64) -> Action {
+    let mut args = HashMap::new();
+    args.insert("path".to_string(), path_value(path));
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: ActionType::FsReadFile,
+        args,
+        preconditions: Some(Preconditions::on("path", Precondition {
+            readable: Some(true),
+            exists: Some(true),
+            ..Default::default()
+        })),
+        metadata: Some(ActionMetadata {
+            confidence: Some(confidence),
+            ..Default::default()
+        }),
+    }
+}
+
+fn copy_file_action(source: &Path, destination: &Path) -> Action {
+    let mut action = transfer_action(ActionType::FsCopyFile, source, destination);
+    action.preconditions = Some(
+        Preconditions::on("source_path", Precondition {
+            readable: Some(true),
+            exists: Some(true),
+            ..Default::default()
+        })
+        .and("destination_path", Precondition {
+            writable: Some(true),
+            exists: Some(false),
+            ..Default::default()
+        }),
+    );
+    action
+}
+
+/// Move a file, or copy or move a directory
+fn transfer_action(action_type: ActionType, source: &Path, destination: &Path) -> Action {
+    let mut args = HashMap::new();
+    args.insert("source_path".to_string(), path_value(Some(source)));
+    args.insert("destination_path".to_string(), path_value(Some(destination)));
+
+    let directory = matches!(action_type, ActionType::FsCopyDirectory | ActionType::FsMoveDirectory);
+    let moving = matches!(action_type, ActionType::FsMoveFile | ActionType::FsMoveDirectory);
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type,
+        args,
+        preconditions: Some(
+            Preconditions::on("source_path", Precondition {
+                readable: (!moving).then_some(true),
+                writable: moving.then_some(true),
                 exists: Some(true),
-                writable: None,
-                readable: None,
-                directory: None,
-            }),
-            metadata: Some(ActionMetadata {
-                confidence: Some(0.7),
+                directory: directory.then_some(true),
+                ..Default::default()
+            })
+            .and("destination_path", Precondition {
+                writable: Some(true),
+                exists: directory.then_some(false),
+                ..Default::default()
             }),
-        });
+        ),
+        metadata: Some(ActionMetadata {
+            confidence: Some(0.85),
+            ..Default::default()
+        }),
     }
+}
 
-    let patterns = vec![
-        r"(?:delete|remove|rm)\s+(?:file\s+)?(.+?)$",
-    ];
+/// Copy or move the files a selector picks into a directory
+fn bulk_transfer_action(action_type: ActionType, select: serde_json::Value, destination: &Path) -> Action {
+    let mut args = HashMap::new();
+    args.insert("select".to_string(), select);
+    args.insert("destination_path".to_string(), path_value(Some(destination)));
 
-    for pattern in patterns {
-        if let Some(captures) = regex::Regex::new(pattern)
-            .ok()
-            .and_then(|re| re.captures(input))
-        {
-            let file_path = captures.get(1)?.as_str().trim();
-            if file_path.is_empty() {
-                continue;
-            }
-            let full_path = match resolve_path(file_path, user_home) {
-                Some(p) => p,
-                None => continue,
-            };
-
-            let mut args = HashMap::new();
-            args.insert("path".to_string(), serde_json::Value::String(full_path.to_string_lossy().to_string()));
-
-            return Some(Action {
-                id: Uuid::new_v4().to_string(),
-                action_type: ActionType::FsDeleteFile,
-                args,
-                preconditions: Some(Precondition {
-                    exists: Some(true),
-                    writable: None,
-                    readable: None,
-                    directory: None,
-                }),
-                metadata: Some(ActionMetadata {
-                    confidence: Some(0.9),
-                }),
-            });
-        }
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type,
+        args,
+        preconditions: Some(Preconditions::on("destination_path", Precondition {
+            writable: Some(true),
+            exists: Some(false),
+            ..Default::default()
+        })),
+        metadata: Some(ActionMetadata {
+            confidence: Some(0.8),
+            ..Default::default()
+        }),
     }
+}
 
-    None
+/// Delete the files a selector picks
+fn bulk_delete_action(select: serde_json::Value, permanent: bool) -> Action {
+    let mut args = HashMap::new();
+    args.insert("select".to_string(), select);
+    if permanent {
+        args.insert("permanent".to_string(), serde_json::Value::Bool(true));
+    }
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: ActionType::FsDeleteFile,
+        args,
+        preconditions: None,
+        metadata: Some(ActionMetadata {
+            confidence: Some(0.8),
+            ..Default::default()
+        }),
+    }
 }
 
-/// Parse "create directory X" or "mkdir X"
-fn parse_create_directory(input: &str, user_home: &Path) -> Option<Action> {
-    let patterns = vec![
-        r"create\s+(?:directory|dir|folder)\s+(.+?)$",
-        r"mkdir\s+(.+?)$",
-        r"new\s+(?:directory|dir|folder)\s+(.+?)$",
-    ];
-
-    for pattern in patterns {
-        if let Some(captures) = regex::Regex::new(pattern)
-            .ok()
-            .and_then(|re| re.captures(input))
-        {
-            let dir_path = captures.get(1)?.as_str().trim();
-            let full_path = resolve_path(dir_path, user_home)?;
-
-            let mut args = HashMap::new();
-            args.insert("path".to_string(), serde_json::Value::String(full_path.to_string_lossy().to_string()));
-
-            return Some(Action {
-                id: Uuid::new_v4().to_string(),
-                action_type: ActionType::FsCreateDirectory,
-                args,
-                preconditions: Some(Precondition {
-                    writable: Some(true),
-                    exists: Some(false),
-                    readable: None,
-                    directory: Some(false), // Parent should be a directory
-                }),
-                metadata: Some(ActionMetadata {
-                    confidence: Some(0.9),
-                }),
-            });
-        }
+fn delete_action(directory: bool, path: Option<&Path>, permanent: bool) -> Action {
+    let mut args = HashMap::new();
+    args.insert("path".to_string(), path_value(path));
+    if permanent {
+        args.insert("permanent".to_string(), serde_json::Value::Bool(true));
+    }
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: if directory { ActionType::FsDeleteDirectory } else { ActionType::FsDeleteFile },
+        args,
+        preconditions: Some(Preconditions::on("path", Precondition {
+            exists: Some(true),
+            directory: Some(directory),
+            ..Default::default()
+        })),
+        metadata: Some(ActionMetadata {
+            confidence: Some(if path.is_some() { 0.9 } else { 0.7 }),
+            ..Default::default()
+        }),
+    }
+}
+
+fn create_directory_action(path: &Path) -> Action {
+    let mut args = HashMap::new();
+    args.insert("path".to_string(), path_value(Some(path)));
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: ActionType::FsCreateDirectory,
+        args,
+        preconditions: Some(Preconditions::on("path", Precondition {
+            writable: Some(true),
+            exists: Some(false),
+            ..Default::default()
+        })),
+        metadata: Some(ActionMetadata {
+            confidence: Some(0.9),
+            ..Default::default()
+        }),
+    }
+}
+
+fn list_directory_action(path: Option<&Path>, recursive: bool) -> Action {
+    let mut args = HashMap::new();
+    args.insert("path".to_string(), path_value(path));
+    args.insert("recursive".to_string(), serde_json::Value::Bool(recursive));
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: ActionType::FsListDirectory,
+        args,
+        preconditions: Some(Preconditions::on("path", Precondition {
+            readable: Some(true),
+            exists: Some(true),
+            directory: Some(true),
+            ..Default::default()
+        })),
+        metadata: Some(ActionMetadata {
+            confidence: Some(if path.is_some() { 0.9 } else { 0.7 }),
+            ..Default::default()
+        }),
+    }
+}
+
+/// Search a directory by name words, a glob and content; the content is matched literally
+fn search_action(directory: &Path, name: Option<&str>, glob: Option<&str>, content: Option<&str>) -> Action {
+    let mut args = HashMap::new();
+    args.insert("path".to_string(), path_value(Some(directory)));
+    if let Some(name) = name {
+        args.insert("name".to_string(), serde_json::Value::String(name.to_string()));
+    }
+    if let Some(glob) = glob {
+        args.insert("glob".to_string(), serde_json::Value::String(glob.to_string()));
+    }
+    if let Some(content) = content {
+        args.insert("content".to_string(), serde_json::Value::String(regex::escape(content)));
     }
 
-    None
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: ActionType::FsSearch,
+        args,
+        preconditions: Some(Preconditions::on("path", Precondition {
+            readable: Some(true),
+            exists: Some(true),
+            directory: Some(true),
+            ..Default::default()
+        })),
+        metadata: Some(ActionMetadata {
+            confidence: Some(0.8),
+            ..Default::default()
+        }),
+    }
 }
 
-/// Extract file path from user input (heuristic)
-fn extract_file_path(input: &str) -> String {
-    // Try to find quoted strings first
-    if let Some(captures) = regex::Regex::new(r#""([^"]+)""#)
-        .ok()
-        .and_then(|re| re.captures(input))
-    {
-        return captures.get(1).map(|m| m.as_str().to_string()).unwrap_or_default();
-    }
-    
-    // Otherwise, try to extract last path-like segment
-    if let Some(captures) = regex::Regex::new(r"(\S+(?:/[^/\s]+)+)")
-        .ok()
-        .and_then(|re| re.captures(input))
-    {
-        return captures.get(1).map(|m| m.as_str().to_string()).unwrap_or_default();
-    }
-
-    input.to_string()
+fn stat_action(path: &Path) -> Action {
+    let mut args = HashMap::new();
+    args.insert("path".to_string(), path_value(Some(path)));
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: ActionType::FsStat,
+        args,
+        preconditions: Some(Preconditions::on("path", Precondition {
+            exists: Some(true),
+            ..Default::default()
+        })),
+        metadata: Some(ActionMetadata {
+            confidence: Some(0.9),
+            ..Default::default()
+        }),
+    }
 }
 
-/// Resolve relative path to absolute path (anywhere on system)
-fn resolve_path(path: &str, _user_home: &Path) -> Option<PathBuf> {
-    let path = Path::new(path);
-    
-    if path.is_absolute() {
-        // Absolute path - use as is
-        Some(path.to_path_buf())
-    } else {
-        // Relative path - resolve from current working directory
-        let cwd = std::env::current_dir().ok()?;
-        Some(cwd.join(path))
+fn archive_create_action(source: &Path, destination: &Path) -> Action {
+    let mut args = HashMap::new();
+    args.insert("source_path".to_string(), path_value(Some(source)));
+    args.insert("destination_path".to_string(), path_value(Some(destination)));
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: ActionType::ArchiveCreate,
+        args,
+        preconditions: Some(
+            Preconditions::on("source_path", Precondition {
+                readable: Some(true),
+                exists: Some(true),
+                ..Default::default()
+            })
+            .and("destination_path", Precondition {
+                writable: Some(true),
+                exists: Some(false),
+                ..Default::default()
+            }),
+        ),
+        metadata: Some(ActionMetadata {
+            confidence: Some(0.85),
+            ..Default::default()
+        }),
+    }
+}
+
+fn archive_extract_action(source: &Path, destination: &Path) -> Action {
+    let mut args = HashMap::new();
+    args.insert("source_path".to_string(), path_value(Some(source)));
+    args.insert("destination_path".to_string(), path_value(Some(destination)));
+
+    Action {
+        id: Uuid::new_v4().to_string(),
+        action_type: ActionType::ArchiveExtract,
+        args,
+        preconditions: Some(
+            Preconditions::on("source_path", Precondition {
+                readable: Some(true),
+                exists: Some(true),
+                directory: Some(false),
+                ..Default::default()
+            })
+            .and("destination_path", Precondition {
+                writable: Some(true),
+                ..Default::default()
+            }),
+        ),
+        metadata: Some(ActionMetadata {
+            confidence: Some(0.85),
+            ..Default::default()
+        }),
     }
 }
 
+fn has_archive_extension(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    [".zip", ".tar.gz", ".tgz"].iter().any(|extension| name.ends_with(extension))
+}
+
+fn is_glob(word: &str) -> bool {
+    word.contains(['*', '?', '['])
+}
+
+/// "*.png in /home/me/Desktop", for summaries
+fn describe_selection(select: &serde_json::Value) -> String {
+    let pattern = match (select.get("glob"), select.get("extensions")) {
+        (Some(glob), _) => glob.as_str().unwrap_or_default().to_string(),
+        (None, Some(extensions)) => format!("*.{}", extensions[0].as_str().unwrap_or_default()),
+        (None, None) => "*".to_string(),
+    };
+    format!("{} in {}", pattern, select["directory"].as_str().unwrap_or_default())
+}
+
+/// Stand-in path for the ambiguous path at `index`
+fn ambiguous_marker(index: usize) -> String {
+    format!("__AMBIGUOUS_PATH_{}__", index)
+}
+
+fn is_ambiguous_marker(path: &Path) -> bool {
+    path.to_string_lossy().starts_with("__AMBIGUOUS_PATH_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn home() -> TempDir {
+        let home = TempDir::new().unwrap();
+        std::fs::create_dir_all(home.path().join("Projects/Old Notes")).unwrap();
+        std::fs::write(home.path().join("Projects/Report.PDF"), "x").unwrap();
+        std::fs::write(home.path().join("a.txt"), "x").unwrap();
+        home
+    }
+
+    fn parse(home: &TempDir, input: &str) -> Result<Vec<Action>, String> {
+        let resolver = PathResolver::new(home.path().to_path_buf());
+        parse_intent(input, &resolver).map(|plan| plan.schema.actions)
+    }
+
+    fn arg<'a>(action: &'a Action, name: &str) -> &'a serde_json::Value {
+        &action.args[name]
+    }
+
+    fn path(home: &TempDir, rest: &str) -> serde_json::Value {
+        serde_json::Value::String(home.path().join(rest).to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn tokens_keep_quotes_separators_and_offsets() {
+        let tokens = tokenize("open \"My File.txt\", then what's next;");
+        let kinds: Vec<(TokenKind, &str)> = tokens.iter().map(|t| (t.kind.clone(), t.text.as_str())).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (TokenKind::Word, "open"),
+                (TokenKind::Quoted, "My File.txt"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Word, "then"),
+                (TokenKind::Word, "what's"),
+                (TokenKind::Word, "next"),
+                (TokenKind::Separator, ";"),
+            ]
+        );
+        assert_eq!((tokens[1].start, tokens[1].end), (5, 18));
+    }
+
+    #[test]
+    fn pronouns_refer_to_the_previous_step() {
+        let home = home();
+        let actions = parse(&home, "create folder Projects/New and move a.txt into it").unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].action_type, ActionType::FsCreateDirectory);
+        assert_eq!(arg(&actions[0], "path"), &path(&home, "Projects/New"));
+        assert_eq!(actions[1].action_type, ActionType::FsMoveFile);
+        assert_eq!(arg(&actions[1], "source_path"), &path(&home, "a.txt"));
+        assert_eq!(arg(&actions[1], "destination_path"), &path(&home, "Projects/New/a.txt"));
+    }
+
+    #[test]
+    fn quoted_paths_may_contain_spaces() {
+        let home = home();
+        let actions = parse(&home, "copy \"Projects/Old Notes\" to Backup").unwrap();
+        assert_eq!(actions[0].action_type, ActionType::FsCopyDirectory);
+        assert_eq!(arg(&actions[0], "source_path"), &path(&home, "Projects/Old Notes"));
+        assert_eq!(arg(&actions[0], "destination_path"), &path(&home, "Backup"));
+    }
+
+    #[test]
+    fn paths_and_content_keep_their_casing() {
+        let home = home();
+        let actions = parse(&home, "create file Notes/Todo.md with content Buy Milk").unwrap();
+        assert_eq!(actions[0].action_type, ActionType::FsCreateFile);
+        assert_eq!(arg(&actions[0], "path"), &path(&home, "Notes/Todo.md"));
+        assert_eq!(arg(&actions[0], "content"), "Buy Milk");
+    }
+
+    #[test]
+    fn a_found_file_is_referenced_by_the_search_output() {
+        let home = home();
+        let actions = parse(&home, "find report in Projects, then open it").unwrap();
+        assert_eq!(actions[0].action_type, ActionType::FsSearch);
+        assert_eq!(arg(&actions[0], "name"), "report");
+        assert_eq!(arg(&actions[0], "path"), &path(&home, "Projects"));
+        assert_eq!(actions[1].action_type, ActionType::FsReadFile);
+        assert_eq!(
+            arg(&actions[1], "path"),
+            &serde_json::Value::String(format!("{{{{steps.{}.output.path}}}}", actions[0].id))
+        );
+    }
+
+    #[test]
+    fn separators_split_steps() {
+        let home = home();
+        let actions = parse(&home, "delete a.txt; delete Projects/Report.PDF").unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(arg(&actions[0], "path"), &path(&home, "a.txt"));
+        assert_eq!(arg(&actions[1], "path"), &path(&home, "Projects/Report.PDF"));
+    }
+
+    #[test]
+    fn trailing_words_set_options() {
+        let home = home();
+        let actions = parse(&home, "copy a.txt to b.txt overwriting").unwrap();
+        let copy = actions.last().unwrap();
+        assert_eq!(copy.action_type, ActionType::FsCopyFile);
+        assert_eq!(arg(copy, "on_conflict"), "overwrite");
+
+        let actions = parse(&home, "delete a.txt permanently").unwrap();
+        assert_eq!(arg(&actions[0], "permanent"), true);
+    }
+
+    #[test]
+    fn misspelled_paths_are_corrected() {
+        let home = home();
+        let actions = parse(&home, "delete projects/reprot.pdf").unwrap();
+        assert_eq!(arg(&actions[0], "path"), &path(&home, "Projects/Report.PDF"));
+    }
+
+    #[test]
+    fn ambiguous_paths_ask_the_user() {
+        let home = home();
+        std::fs::write(home.path().join("notes.md"), "x").unwrap();
+        std::fs::write(home.path().join("notes.txt"), "x").unwrap();
+        let actions = parse(&home, "delete notes").unwrap();
+        assert_eq!(arg(&actions[0], "path"), "__PROMPT_PATH__");
+        let candidates = actions[0].metadata.as_ref().unwrap().path_candidates.as_ref().unwrap();
+        assert_eq!(
+            candidates["path"],
+            vec![
+                home.path().join("notes.md").to_string_lossy().to_string(),
+                home.path().join("notes.txt").to_string_lossy().to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_clauses_are_named_in_the_error() {
+        let home = home();
+        let err = parse(&home, "create folder x and frobnicate y").unwrap_err();
+        assert!(err.contains("\"frobnicate y\""), "{}", err);
+        assert!(parse(&home, "fly to the moon").is_err());
+        assert!(parse(&home, "").is_err());
+    }
+}
@@ -1,11 +1,284 @@
+use crate::assistant::planner::resolver::PathResolver;
+use crate::assistant::references;
+use crate::assistant::schema;
+use crate::assistant::settings;
 use crate::assistant::types::*;
+use std::collections::{HashMap, HashSet};
+use tauri::AppHandle;
+use uuid::Uuid;
 
-/// LLM planner placeholder
-/// The actual LLM planning happens in frontend using existing AI provider system
-/// This module would validate and parse LLM responses if needed
+/// Plan a request with the configured chat model.
+/// The reply is parsed and checked against the action schema; the caller still verifies it.
+pub async fn plan(
+    app: &AppHandle,
+    user_input: &str,
+    source: ActionSource,
+    history: Option<String>,
+) -> Result<ActionPlan, String> {
+    let resolver = settings::path_resolver(app)?;
 
-pub fn parse_llm_response(_response: &str) -> Result<ActionPlan, String> {
-    // Placeholder: LLM planning is done in frontend
-    Err("LLM planning should be done in frontend".to_string())
+    let response = crate::api::chat_completion(
+        app,
+        format!("Convert this request into an action plan:\n\n{}", user_input),
+        Some(planning_prompt(&resolver)),
+        history,
+    )
+    .await?;
+
+    parse_llm_response(&response, user_input, source)
+}
+
+/// System prompt describing every action type and its arguments, and the user's named folders
+pub fn planning_prompt(resolver: &PathResolver) -> String {
+    let mut actions = String::new();
+    for action_type in ActionType::ALL {
+        actions.push_str(&format!("- {}: {}\n", action_type.name(), action_type.description()));
+        for arg in schema::describe_args(action_type) {
+            actions.push_str(&format!(
+                "    {} ({}, {}): {}\n",
+                arg.name,
+                arg.kind,
+                if arg.required { "required" } else { "optional" },
+                arg.description
+            ));
+        }
+    }
+
+    let mut folders = String::new();
+    for (name, path) in resolver.named_folders() {
+        folders.push_str(&format!("- {}: {}\n", name, path.display()));
+    }
+
+    format!(
+        r#"You are an action planner. Convert the user's request into a JSON action plan.
+
+Action types and their args:
+{actions}
+Folders the user may name:
+{folders}
+Output format:
+{{
+  "actions": [
+    {{
+      "id": "step-1",
+      "type": "<action type>",
+      "args": {{ ... }},
+      "preconditions": {{ "<path arg>": {{ "exists": true|false, "readable": true|false, "writable": true|false, "directory": true|false, "sha256": "<hex>", "max_size": <bytes> }} }},
+      "metadata": {{ "confidence": 0.0-1.0 }}
+    }}
+  ],
+  "summary": "Brief description of the plan",
+  "risk_score": 0.0-1.0
+}}
+
+Rules:
+1. Only use the action types and args listed above
+2. Always use absolute paths; resolve relative paths against {home}, or against a folder above when the path starts with its name
+3. Set risk_score: 0.1-0.3 for read/create/list/search/stat, 0.5-0.7 for copy/move, 0.7-0.9 for delete, 0.9-1.0 for deleting a directory
+4. To change an existing file use fs_append_file, fs_replace_text or fs_apply_patch, never fs_create_file
+5. Deletes move items to the trash; set permanent only if the user explicitly asks for permanent deletion
+6. Preconditions are optional and keyed by path arg (e.g. "source_path"); they are checked after the earlier steps
+7. For many files ("all screenshots", "*.tmp"), give fs_copy_file, fs_move_file or fs_delete_file a "select" object instead of source_path or path: {{ "directory": "<absolute dir>", "glob": "*.png", "extensions": ["png"], "modified_before": "YYYY-MM-DD", "modified_after": "YYYY-MM-DD", "min_size": <bytes>, "max_size": <bytes>, "recursive": true|false }} with only the fields you need; destination_path is then the directory the files go into
+8. When the user describes a file without its path ("the invoice from ACME"), use fs_search below the likeliest folder with name words, a glob or a content regex; use fs_stat for a file's size, dates or permissions
+9. archive_create packs source_path into a new .zip or .tar.gz at destination_path; archive_extract unpacks an archive into the destination_path directory; both are 0.4-0.6 risk
+10. To use an earlier step's result, give that step a short id and put {{{{steps.<id>.output.<field>}}}} in a later string arg, e.g. "source_path": "{{{{steps.find.output.path}}}}" after an fs_search with id "find"; outputs have path (and source, destination for copies and moves), and fs_search also has name and results
+11. Copies, moves and archive_create stop the plan if the destination exists; set on_conflict to "overwrite", "rename" or "skip" only when the user says what to do with existing files
+12. Only output valid JSON, no markdown or explanations"#,
+        actions = actions,
+        folders = folders,
+        home = resolver.home().display(),
+    )
 }
 
+/// Parse a model reply into an action plan.
+/// Tolerates code fences, surrounding prose, comments and trailing commas, but
+/// rejects action types and args that are not in the schema.
+/// Ids, provenance, dry_run and escalations are always set here, never taken from the model.
+pub fn parse_llm_response(
+    response: &str,
+    user_input: &str,
+    source: ActionSource,
+) -> Result<ActionPlan, String> {
+    let json = extract_json(response).ok_or("LLM response does not contain a JSON object")?;
+    let value: serde_json::Value = serde_json::from_str(&repair_json(json))
+        .map_err(|e| format!("Failed to parse LLM plan: {}", e))?;
+
+    let actions = value
+        .get("actions")
+        .and_then(|v| v.as_array())
+        .ok_or("LLM plan has no actions")?;
+    if actions.is_empty() {
+        return Err("LLM plan has no actions".to_string());
+    }
+
+    let mut seen_ids = HashSet::new();
+    let mut actions = actions
+        .iter()
+        .enumerate()
+        .map(|(index, action)| parse_action(action, index, &mut seen_ids))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // References name steps by the model's ids; point them at the generated ones
+    let renamed: HashMap<String, String> = actions
+        .iter()
+        .filter_map(|action| Some((action.metadata.as_ref()?.step_alias.clone()?, action.id.clone())))
+        .collect();
+    for action in &mut actions {
+        references::rename_steps(action, &renamed);
+    }
+
+    let risk_score = value
+        .get("risk_score")
+        .and_then(|v| v.as_f64())
+        .unwrap_or(RiskScore::Medium.value())
+        .clamp(0.0, 1.0);
+
+    Ok(ActionPlan {
+        schema: ActionSchema {
+            id: Uuid::new_v4().to_string(),
+            origin: ActionOrigin {
+                user_input: user_input.to_string(),
+                source,
+                request_id: Uuid::new_v4().to_string(),
+            },
+            actions,
+            summary: value
+                .get("summary")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string(),
+            risk_score,
+            dry_run: true,
+            escalated_paths: Vec::new(),
+        },
+    })
+}
+
+/// Check one action against the schema of its type
+fn parse_action(
+    value: &serde_json::Value,
+    index: usize,
+    seen_ids: &mut HashSet<String>,
+) -> Result<Action, String> {
+    let step = index + 1;
+
+    let type_name = value
+        .get("type")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| format!("Step {} has no action type", step))?;
+    let action_type = ActionType::from_name(type_name)
+        .ok_or_else(|| format!("Step {} uses unknown action type '{}'", step, type_name))?;
+
+    let mut args = HashMap::new();
+    match value.get("args") {
+        Some(serde_json::Value::Object(map)) => {
+            for (name, arg) in map {
+                // Models often emit null for args they mean to leave out
+                if !arg.is_null() {
+                    args.insert(name.clone(), arg.clone());
+                }
+            }
+        }
+        None | Some(serde_json::Value::Null) => {}
+        Some(_) => return Err(format!("Step {} args must be an object", step)),
+    }
+
+    schema::validate_args(&action_type, &args)
+        .map_err(|e| format!("Step {} ({}) {}", step, type_name, e))?;
+
+    let preconditions = match value.get("preconditions") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(v) => Some(
+            serde_json::from_value(v.clone())
+                .map_err(|e| format!("Step {} has invalid preconditions: {}", step, e))?,
+        ),
+    };
+
+    let mut metadata = value
+        .get("metadata")
+        .and_then(|v| serde_json::from_value::<ActionMetadata>(v.clone()).ok());
+
+    // Snapshots and undo are keyed by action id, so every step gets a fresh one.
+    // The model's id is kept as an alias, if unique, so later steps can refer to it.
+    let step_alias = value
+        .get("id")
+        .and_then(|v| v.as_str())
+        .filter(|id| !id.is_empty() && seen_ids.insert(id.to_string()))
+        .map(str::to_string);
+    if step_alias.is_some() || metadata.is_some() {
+        metadata.get_or_insert_with(ActionMetadata::default).step_alias = step_alias;
+    }
+
+    Ok(Action {
+        id: Uuid::new_v4().to_string(),
+        action_type,
+        args,
+        preconditions,
+        metadata,
+    })
+}
+
+/// Outermost JSON object in a reply, skipping code fences and prose around it
+fn extract_json(response: &str) -> Option<&str> {
+    let start = response.find('{')?;
+    let end = response.rfind('}')?;
+    (start < end).then(|| &response[start..=end])
+}
+
+/// Remove comments and trailing commas outside of strings
+fn repair_json(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut chars = json.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        out.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for skipped in chars.by_ref() {
+                    if skipped == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for skipped in chars.by_ref() {
+                    if previous == '*' && skipped == '/' {
+                        break;
+                    }
+                    previous = skipped;
+                }
+            }
+            '}' | ']' => {
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
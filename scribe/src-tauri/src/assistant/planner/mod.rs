@@ -1,4 +1,7 @@
 pub mod deterministic;
 pub mod llm;
+pub mod resolver;
+pub mod risk;
+pub mod selector;
 pub mod verifier;
 
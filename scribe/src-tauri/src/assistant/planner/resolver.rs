@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Most near-miss candidates offered for one ambiguous path
+const MAX_CANDIDATES: usize = 10;
+
+/// Most entries read from one directory while looking for a near miss
+const MAX_SCAN: usize = 5_000;
+
+/// A folder users name by its role, found via the XDG user dirs (or the platform's equivalent)
+struct KnownFolder {
+    name: &'static str,
+    lookup: fn() -> Option<PathBuf>,
+    /// Used below home when the platform does not define the folder
+    fallback: &'static str,
+}
+
+const KNOWN_FOLDERS: &[KnownFolder] = &[
+    KnownFolder { name: "desktop", lookup: dirs::desktop_dir, fallback: "Desktop" },
+    KnownFolder { name: "documents", lookup: dirs::document_dir, fallback: "Documents" },
+    KnownFolder { name: "downloads", lookup: dirs::download_dir, fallback: "Downloads" },
+    KnownFolder { name: "music", lookup: dirs::audio_dir, fallback: "Music" },
+    KnownFolder { name: "pictures", lookup: dirs::picture_dir, fallback: "Pictures" },
+    KnownFolder { name: "videos", lookup: dirs::video_dir, fallback: "Videos" },
+    KnownFolder { name: "public", lookup: dirs::public_dir, fallback: "Public" },
+    KnownFolder { name: "templates", lookup: dirs::template_dir, fallback: "Templates" },
+];
+
+/// Outcome of resolving a typed path
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    Path(PathBuf),
+    /// Several existing paths fit equally well; the user has to pick
+    Ambiguous(Vec<PathBuf>),
+}
+
+/// Turns the paths users type into absolute paths.
+/// Relative paths start from the home directory, or from a known folder ("Downloads")
+/// or user alias named by their first segment. Segments that do not exist are
+/// matched against existing names: same name in another case, same name without the
+/// extension, then a one- or two-letter typo.
+#[derive(Debug, Clone)]
+pub struct PathResolver {
+    home: PathBuf,
+    aliases: Vec<(String, PathBuf)>,
+}
+
+impl PathResolver {
+    pub fn new(home: PathBuf) -> Self {
+        Self {
+            home,
+            aliases: Vec::new(),
+        }
+    }
+
+    /// Named folders from the settings; values may start with `~`
+    pub fn with_aliases(mut self, aliases: &BTreeMap<String, String>) -> Self {
+        self.aliases = aliases
+            .iter()
+            .map(|(name, path)| (name.to_lowercase(), self.expand_home(path)))
+            .collect();
+        self
+    }
+
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    /// Aliases, then known folders, as shown to the LLM planner
+    pub fn named_folders(&self) -> Vec<(String, PathBuf)> {
+        let mut folders = self.aliases.clone();
+        for folder in KNOWN_FOLDERS {
+            if !folders.iter().any(|(alias, _)| alias == folder.name) {
+                folders.push((folder.fallback.to_string(), self.known_folder(folder.name).unwrap_or_default()));
+            }
+        }
+        folders
+    }
+
+    /// Resolve a path that should already exist.
+    /// `exists` decides which paths count as existing, so paths created earlier in a plan are not "corrected".
+    pub fn existing(&self, input: &str, exists: &dyn Fn(&Path) -> bool) -> Resolution {
+        self.resolve(input, exists, true)
+    }
+
+    /// Resolve a path that may not exist yet: its directories are matched, its last segment is kept as typed
+    pub fn new_path(&self, input: &str, exists: &dyn Fn(&Path) -> bool) -> Resolution {
+        self.resolve(input, exists, false)
+    }
+
+    fn resolve(&self, input: &str, exists: &dyn Fn(&Path) -> bool, match_last: bool) -> Resolution {
+        let input = input.trim();
+        let path = Path::new(input);
+
+        // Absolute paths are taken as typed
+        if path.is_absolute() {
+            return Resolution::Path(path.to_path_buf());
+        }
+
+        let (root, rest) = self.root_of(input);
+        let segments: Vec<&str> = rest
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => s.to_str(),
+                Component::ParentDir => Some(".."),
+                _ => None,
+            })
+            .collect();
+
+        let mut candidates = vec![root];
+        for (i, segment) in segments.iter().enumerate() {
+            let last = i + 1 == segments.len();
+            let mut next = Vec::new();
+            for dir in &candidates {
+                let exact = dir.join(segment);
+                if exists(&exact) || (last && !match_last) {
+                    next.push(exact);
+                    continue;
+                }
+                let matches = near_misses(dir, segment);
+                if matches.is_empty() {
+                    next.push(exact);
+                } else {
+                    next.extend(matches);
+                }
+            }
+            next.truncate(MAX_CANDIDATES);
+            candidates = next;
+        }
+
+        if candidates.len() == 1 {
+            Resolution::Path(candidates.remove(0))
+        } else {
+            Resolution::Ambiguous(candidates)
+        }
+    }
+
+    /// The directory a relative path starts from, and the rest of the path
+    fn root_of<'a>(&self, input: &'a str) -> (PathBuf, &'a Path) {
+        if input == "~" {
+            return (self.home.clone(), Path::new(""));
+        }
+        if let Some(rest) = input.strip_prefix("~/") {
+            return (self.home.clone(), Path::new(rest));
+        }
+
+        let (first, rest) = input.split_once('/').unwrap_or((input, ""));
+        let name = first.to_lowercase();
+        if let Some((_, path)) = self.aliases.iter().find(|(alias, _)| *alias == name) {
+            return (path.clone(), Path::new(rest));
+        }
+        if let Some(folder) = self.known_folder(&name) {
+            return (folder, Path::new(rest));
+        }
+        if name == "home" {
+            return (self.home.clone(), Path::new(rest));
+        }
+
+        (self.home.clone(), Path::new(input))
+    }
+
+    /// A known folder by name, singular or plural ("download", "Downloads")
+    fn known_folder(&self, name: &str) -> Option<PathBuf> {
+        let folder = KNOWN_FOLDERS
+            .iter()
+            .find(|folder| folder.name == name || folder.name.strip_suffix('s') == Some(name))?;
+        Some((folder.lookup)().unwrap_or_else(|| self.home.join(folder.fallback)))
+    }
+
+    fn expand_home(&self, path: &str) -> PathBuf {
+        match path.strip_prefix('~') {
+            Some(rest) => self.home.join(rest.trim_start_matches('/')),
+            None => PathBuf::from(path),
+        }
+    }
+}
+
+/// Existing entries of `dir` that `segment` most likely meant, best tier only
+fn near_misses(dir: &Path, segment: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let wanted = segment.to_lowercase();
+    let max_distance = match wanted.chars().count() {
+        0..=3 => 0,
+        4..=9 => 1,
+        _ => 2,
+    };
+
+    // Tiers: other case, missing extension, typo
+    let mut tiers: [Vec<PathBuf>; 3] = Default::default();
+    for entry in entries.flatten().take(MAX_SCAN) {
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        if name.starts_with('.') && !wanted.starts_with('.') {
+            continue;
+        }
+
+        let stem = Path::new(&name)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if name == wanted {
+            tiers[0].push(entry.path());
+        } else if stem == wanted {
+            tiers[1].push(entry.path());
+        } else if max_distance > 0 && edit_distance(&name, &wanted) <= max_distance {
+            tiers[2].push(entry.path());
+        }
+    }
+
+    let mut best = tiers.into_iter().find(|tier| !tier.is_empty()).unwrap_or_default();
+    best.sort();
+    best
+}
+
+/// Edit distance between two strings, by character, counting a swap of neighbours as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn home() -> TempDir {
+        let home = TempDir::new().unwrap();
+        fs::create_dir_all(home.path().join("Projects/Scribe")).unwrap();
+        fs::write(home.path().join("Projects/Report.PDF"), "x").unwrap();
+        fs::write(home.path().join("Projects/notes.md"), "x").unwrap();
+        fs::write(home.path().join("Projects/notes.txt"), "x").unwrap();
+        fs::write(home.path().join("Projects/.secret"), "x").unwrap();
+        home
+    }
+
+    fn on_disk(path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn existing(home: &TempDir, input: &str) -> Resolution {
+        PathResolver::new(home.path().to_path_buf()).existing(input, &on_disk)
+    }
+
+    fn at(home: &TempDir, rest: &str) -> Resolution {
+        Resolution::Path(home.path().join(rest))
+    }
+
+    #[test]
+    fn existing_paths_are_kept() {
+        let home = home();
+        assert_eq!(existing(&home, "Projects/Report.PDF"), at(&home, "Projects/Report.PDF"));
+        assert_eq!(existing(&home, " Projects "), at(&home, "Projects"));
+    }
+
+    #[test]
+    fn near_misses_are_found_by_tier() {
+        let home = home();
+        // Other case
+        assert_eq!(existing(&home, "projects/report.pdf"), at(&home, "Projects/Report.PDF"));
+        // Missing extension
+        assert_eq!(existing(&home, "Projects/report"), at(&home, "Projects/Report.PDF"));
+        // Typo, including swapped letters
+        assert_eq!(existing(&home, "Projcets/Scirbe"), at(&home, "Projects/Scribe"));
+        // A missing name with nothing close is kept as typed
+        assert_eq!(existing(&home, "Projects/Budget.xlsx"), at(&home, "Projects/Budget.xlsx"));
+    }
+
+    #[test]
+    fn short_names_and_hidden_files_are_not_guessed() {
+        let home = home();
+        fs::write(home.path().join("abc"), "x").unwrap();
+        assert_eq!(existing(&home, "abd"), at(&home, "abd"));
+        assert_eq!(existing(&home, "Projects/secret"), at(&home, "Projects/secret"));
+        assert_eq!(existing(&home, "Projects/.SECRET"), at(&home, "Projects/.secret"));
+    }
+
+    #[test]
+    fn equally_good_matches_are_ambiguous() {
+        let home = home();
+        assert_eq!(
+            existing(&home, "Projects/notes"),
+            Resolution::Ambiguous(vec![
+                home.path().join("Projects/notes.md"),
+                home.path().join("Projects/notes.txt"),
+            ])
+        );
+    }
+
+    #[test]
+    fn new_paths_keep_their_last_segment() {
+        let home = home();
+        let resolver = PathResolver::new(home.path().to_path_buf());
+        assert_eq!(resolver.new_path("projects/Report", &on_disk), at(&home, "Projects/Report"));
+        assert_eq!(resolver.new_path("Projects/New/a.txt", &on_disk), at(&home, "Projects/New/a.txt"));
+    }
+
+    #[test]
+    fn the_exists_check_decides_what_is_corrected() {
+        let home = home();
+        let resolver = PathResolver::new(home.path().to_path_buf());
+        // A path an earlier step creates is not "corrected" to one on disk
+        let planned = home.path().join("Projects/report");
+        let exists = |path: &Path| path == planned || path.exists();
+        assert_eq!(resolver.existing("Projects/report", &exists), at(&home, "Projects/report"));
+    }
+
+    #[test]
+    fn roots_come_from_home_aliases_and_known_folders() {
+        let home = home();
+        let aliases = BTreeMap::from([
+            ("Work".to_string(), "~/Projects".to_string()),
+            ("tmp".to_string(), "/var/tmp".to_string()),
+        ]);
+        let resolver = PathResolver::new(home.path().to_path_buf()).with_aliases(&aliases);
+
+        assert_eq!(resolver.existing("~", &on_disk), at(&home, ""));
+        assert_eq!(resolver.existing("~/Projects/Scribe", &on_disk), at(&home, "Projects/Scribe"));
+        assert_eq!(resolver.existing("home/Projects", &on_disk), at(&home, "Projects"));
+        assert_eq!(resolver.existing("work/Scribe", &on_disk), at(&home, "Projects/Scribe"));
+        assert_eq!(resolver.existing("tmp/x", &on_disk), Resolution::Path(PathBuf::from("/var/tmp/x")));
+
+        let downloads = resolver.known_folder("downloads").unwrap();
+        assert_eq!(resolver.existing("Download/file.zip", &on_disk), Resolution::Path(downloads.join("file.zip")));
+    }
+
+    #[test]
+    fn absolute_paths_are_taken_as_typed() {
+        let home = home();
+        let typed = home.path().join("projects/report");
+        assert_eq!(
+            existing(&home, &typed.to_string_lossy()),
+            Resolution::Path(typed)
+        );
+    }
+
+    #[test]
+    fn named_folders_list_aliases_first() {
+        let home = home();
+        let aliases = BTreeMap::from([("downloads".to_string(), "~/Inbox".to_string())]);
+        let folders = PathResolver::new(home.path().to_path_buf()).with_aliases(&aliases).named_folders();
+        assert_eq!(folders[0], ("downloads".to_string(), home.path().join("Inbox")));
+        assert_eq!(folders.len(), KNOWN_FOLDERS.len());
+        assert!(!folders.iter().any(|(name, _)| name == "Downloads"));
+    }
+
+    #[test]
+    fn edit_distance_counts_swaps_as_one() {
+        assert_eq!(edit_distance("report", "report"), 0);
+        assert_eq!(edit_distance("report", "reprot"), 1);
+        assert_eq!(edit_distance("report", "repot"), 1);
+        assert_eq!(edit_distance("report", "reports"), 1);
+        assert_eq!(edit_distance("report", "rapurt"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("größe", "grösse"), 2);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::assistant::executor::fs_adapter;
+use crate::assistant::references;
+use crate::assistant::types::*;
+use crate::assistant::validator::{self, PathAccess};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+const MANY_FILES: usize = 100;
+const VERY_MANY_FILES: usize = 1_000;
+const MANY_BYTES: u64 = 100 * 1024 * 1024; // 100 MiB
+const VERY_MANY_BYTES: u64 = 1024 * 1024 * 1024; // 1 GiB
+
+/// One contribution to a plan's risk score
+#[derive(Debug, Clone)]
+pub struct RiskFactor {
+    pub name: &'static str,
+    pub detail: String,
+    pub weight: f64,
+}
+
+/// Computed risk of a plan and the factors behind it
+#[derive(Debug, Clone)]
+pub struct RiskAssessment {
+    pub score: f64,
+    pub level: RiskScore,
+    pub factors: Vec<RiskFactor>,
+}
+
+impl RiskAssessment {
+    /// One note per factor, for `verification_notes`
+    pub fn notes(&self) -> Vec<String> {
+        let mut notes: Vec<String> = self
+            .factors
+            .iter()
+            .map(|f| format!("Risk ({}): {} ({:+.2})", f.name, f.detail, f.weight))
+            .collect();
+        notes.push(format!("Risk score {:.2} ({:?})", self.score, self.level));
+        notes
+    }
+}
+
+/// Score a plan from its riskiest operation, adjusted for path sensitivity,
+/// how many files and bytes it touches, overwrites, and where it came from.
+/// The planner's own risk_score is ignored.
+pub fn assess_plan(plan: &ActionPlan, ctx: &ExecutionContext) -> RiskAssessment {
+    let mut factors = Vec::new();
+
+    // Operation: the riskiest action sets the base score
+    if let Some((action, base)) = plan
+        .schema
+        .actions
+        .iter()
+        .map(|action| (action, operation_risk(action)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+    {
+        factors.push(RiskFactor {
+            name: "operation",
+            detail: operation_detail(action),
+            weight: base,
+        });
+    }
+
+    let mut outside_roots = BTreeSet::new();
+    let mut hidden = BTreeSet::new();
+    let mut overwritten = BTreeSet::new();
+    let mut over_limit = BTreeSet::new();
+    let mut chosen_at_run_time = BTreeSet::new();
+    let mut files = 0usize;
+    let mut bytes = 0u64;
+
+    for action in &plan.schema.actions {
+        for (arg, access) in validator::path_accesses(&action.action_type) {
+            let Some(path_str) = action.args.get(*arg).and_then(|v| v.as_str()) else {
+                continue;
+            };
+            if path_str == "__PROMPT_PATH__" {
+                continue;
+            }
+
+            // A path from an earlier step's output is only known when it runs; judge it by its folder
+            if references::contains_reference(path_str) {
+                let Some(folder) = references::static_path(&plan.schema.actions, action, arg) else {
+                    continue;
+                };
+                if *access != PathAccess::Read {
+                    chosen_at_run_time.insert(path_str.to_string());
+                    if validator::check_access(ctx, &folder, *access).is_err() {
+                        outside_roots.insert(folder.clone());
+                    }
+                }
+                if is_hidden(Path::new(&folder), &ctx.user_home) {
+                    hidden.insert(folder);
+                }
+                continue;
+            }
+            let path = Path::new(path_str);
+
+            if *access != PathAccess::Read && validator::check_access(ctx, path_str, *access).is_err() {
+                outside_roots.insert(path_str.to_string());
+            }
+            if is_hidden(path, &ctx.user_home) {
+                hidden.insert(path_str.to_string());
+            }
+            if *access == PathAccess::Write && overwrites(action) && path.exists() {
+                overwritten.insert(path_str.to_string());
+            }
+
+            // Trees are measured where they are read or removed; writes count the file they replace.
+            // Listings, searches and stats are capped by their own limits and change nothing.
+            if path.is_dir() {
+                if *access != PathAccess::Write && !is_lookup(&action.action_type) {
+                    match fs_adapter::check_tree_limits(path, &ctx.directory_limits) {
+                        Ok(size) => {
+                            files += size.entries;
+                            bytes += size.bytes;
+                        }
+                        Err(_) => {
+                            over_limit.insert(path_str.to_string());
+                        }
+                    }
+                }
+            } else if let Ok(meta) = path.symlink_metadata() {
+                if meta.is_file() {
+                    files += 1;
+                    bytes += meta.len();
+                }
+            }
+        }
+    }
+
+    if !outside_roots.is_empty() {
+        factors.push(RiskFactor {
+            name: "path",
+            detail: format!("outside the allowed folders or protected: {}", join(&outside_roots)),
+            weight: 0.2,
+        });
+    }
+    if !hidden.is_empty() {
+        factors.push(RiskFactor {
+            name: "path",
+            detail: format!("hidden or configuration location: {}", join(&hidden)),
+            weight: 0.1,
+        });
+    }
+    if !overwritten.is_empty() {
+        factors.push(RiskFactor {
+            name: "overwrite",
+            detail: format!("replaces existing {}", join(&overwritten)),
+            weight: 0.15,
+        });
+    }
+
+    if !chosen_at_run_time.is_empty() {
+        factors.push(RiskFactor {
+            name: "reference",
+            detail: format!("changes a path taken from an earlier step's output: {}", join(&chosen_at_run_time)),
+            weight: 0.1,
+        });
+    }
+
+    if !over_limit.is_empty() {
+        factors.push(RiskFactor {
+            name: "files",
+            detail: format!("exceeds the directory limit: {}", join(&over_limit)),
+            weight: 0.2,
+        });
+    } else if files > MANY_FILES {
+        factors.push(RiskFactor {
+            name: "files",
+            detail: format!("touches {} files", files),
+            weight: if files > VERY_MANY_FILES { 0.2 } else { 0.1 },
+        });
+    }
+
+    if bytes > MANY_BYTES {
+        factors.push(RiskFactor {
+            name: "bytes",
+            detail: format!("touches {:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+            weight: if bytes > VERY_MANY_BYTES { 0.2 } else { 0.1 },
+        });
+    }
+
+    // Voice input can be misheard and plugins act without the user typing the request
+    match plan.schema.origin.source {
+        ActionSource::Voice => factors.push(RiskFactor {
+            name: "source",
+            detail: "planned from voice input".to_string(),
+            weight: 0.1,
+        }),
+        ActionSource::Plugin => factors.push(RiskFactor {
+            name: "source",
+            detail: "planned by a plugin".to_string(),
+            weight: 0.15,
+        }),
+        ActionSource::Ui | ActionSource::Automation => {}
+    }
+
+    let score = factors.iter().map(|f| f.weight).sum::<f64>().clamp(0.0, 1.0);
+
+    RiskAssessment {
+        score,
+        level: RiskScore::from_value(score),
+        factors,
+    }
+}
+
+/// Base risk of an action by operation
+pub fn operation_risk(action: &Action) -> f64 {
+    let permanent = action.args.get("permanent").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    match action.action_type {
+        ActionType::FsReadFile
+        | ActionType::FsCreateDirectory
+        | ActionType::FsCreateFile
+        | ActionType::FsListDirectory
+        | ActionType::FsSearch
+        | ActionType::FsStat
+        | ActionType::FsRestoreFromTrash => RiskScore::Low.value(),
+        ActionType::FsCopyFile | ActionType::FsCopyDirectory => RiskScore::Medium.value(),
+        ActionType::ArchiveCreate | ActionType::ArchiveExtract => RiskScore::Medium.value(),
+        ActionType::FsAppendFile | ActionType::FsReplaceText | ActionType::FsApplyPatch => {
+            RiskScore::Medium.value()
+        }
+        ActionType::FsMoveFile | ActionType::FsMoveDirectory => RiskScore::High.value(),
+        ActionType::FsDeleteFile if permanent => RiskScore::Critical.value(),
+        ActionType::FsDeleteFile => RiskScore::High.value(),
+        ActionType::FsDeleteDirectory => RiskScore::Critical.value(),
+    }
+}
+
+fn operation_detail(action: &Action) -> String {
+    let permanent = action.args.get("permanent").and_then(|v| v.as_bool()).unwrap_or(false);
+    let target = action
+        .args
+        .get("path")
+        .or_else(|| action.args.get("source_path"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    if permanent {
+        format!("{} permanently deletes {}", action.action_type.name(), target)
+    } else {
+        format!("{} on {}", action.action_type.name(), target)
+    }
+}
+
+/// Action types that only look at a tree, within their own limits
+fn is_lookup(action_type: &ActionType) -> bool {
+    matches!(
+        action_type,
+        ActionType::FsListDirectory | ActionType::FsSearch | ActionType::FsStat
+    )
+}
+
+/// Actions whose write target replaces an existing file or tree if there is one:
+/// file creation always, copies, moves and archives only when set to overwrite on conflict
+fn overwrites(action: &Action) -> bool {
+    match action.action_type {
+        ActionType::FsCreateFile => true,
+        ActionType::FsCopyFile
+        | ActionType::FsMoveFile
+        | ActionType::FsCopyDirectory
+        | ActionType::FsMoveDirectory
+        | ActionType::ArchiveCreate => OnConflict::of(action).is_ok_and(|policy| policy == OnConflict::Overwrite),
+        _ => false,
+    }
+}
+
+/// Dotfiles and dot-directories under the home directory (shell, app and tool configuration)
+fn is_hidden(path: &Path, user_home: &Path) -> bool {
+    path.strip_prefix(user_home)
+        .map(|rest| {
+            rest.components()
+                .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+        })
+        .unwrap_or(false)
+}
+
+fn join(paths: &BTreeSet<String>) -> String {
+    paths.iter().cloned().collect::<Vec<_>>().join(", ")
+}
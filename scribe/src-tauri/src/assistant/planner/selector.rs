@@ -0,0 +1,465 @@
+use crate::assistant::glob;
+use crate::assistant::schema::{self, BulkDeleteArgs, BulkTransferArgs, Selector};
+use crate::assistant::types::*;
+use crate::assistant::validator::{check_access, PathAccess};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Whether any action still selects files instead of naming one
+pub fn has_selectors(plan: &ActionPlan) -> bool {
+    plan.schema.actions.iter().any(|action| action.args.contains_key("select"))
+}
+
+/// Replace each action that has a `select` arg by one action per selected file, in path order.
+/// Returns the expanded plan and a note per selector saying how many files it selected.
+pub fn expand_plan(plan: &ActionPlan, ctx: &ExecutionContext) -> Result<(ActionPlan, Vec<String>), String> {
+    let mut expanded = plan.clone();
+    expanded.schema.actions.clear();
+    let mut notes = Vec::new();
+
+    for (index, action) in plan.schema.actions.iter().enumerate() {
+        if !action.args.contains_key("select") {
+            expanded.schema.actions.push(action.clone());
+            continue;
+        }
+
+        let (selector, actions) = expand_action(action, ctx).map_err(|e| format!("actions[{}].{}", index, e))?;
+        notes.push(format!(
+            "Step {} ({}) selects {} file(s) in {}",
+            index + 1,
+            action.action_type.name(),
+            actions.len(),
+            selector.directory
+        ));
+        expanded.schema.actions.extend(actions);
+    }
+
+    Ok((expanded, notes))
+}
+
+/// One concrete action per file the action's selector matches
+fn expand_action(action: &Action, ctx: &ExecutionContext) -> Result<(Selector, Vec<Action>), String> {
+    if !schema::accepts_selector(&action.action_type) {
+        return Err(format!("args.select: {} does not accept a selector", action.action_type.name()));
+    }
+    schema::validate_args(&action.action_type, &action.args)?;
+
+    let (selector, destination) = match action.action_type {
+        ActionType::FsDeleteFile => (schema::parse_args::<BulkDeleteArgs>(action)?.select, None),
+        _ => {
+            let args: BulkTransferArgs = schema::parse_args(action)?;
+            (args.select, Some(PathBuf::from(args.destination_path)))
+        }
+    };
+
+    let files = select_files(&selector, ctx).map_err(|e| format!("args.select: {}", e))?;
+    if files.is_empty() {
+        return Err(format!("args.select: no files match in {}", selector.directory));
+    }
+    let max_fan_out = ctx.selector_limits.max_fan_out;
+    if files.len() > max_fan_out {
+        return Err(format!(
+            "args.select: {} files match in {}, more than the maximum of {}; narrow the selector",
+            files.len(),
+            selector.directory,
+            max_fan_out
+        ));
+    }
+
+    let directory = Path::new(&selector.directory);
+    let actions = files
+        .iter()
+        .enumerate()
+        .map(|(n, file)| {
+            // Files from subdirectories keep their relative location under the destination
+            let destination = destination
+                .as_ref()
+                .map(|dir| dir.join(file.strip_prefix(directory).unwrap_or(file)));
+            concrete_action(action, n, file, destination.as_deref())
+        })
+        .collect();
+
+    Ok((selector, actions))
+}
+
+/// Copy of a bulk action that names a single file
+fn concrete_action(action: &Action, n: usize, file: &Path, destination: Option<&Path>) -> Action {
+    let source_arg = match action.action_type {
+        ActionType::FsDeleteFile => "path",
+        _ => "source_path",
+    };
+
+    let mut args = action.args.clone();
+    args.remove("select");
+    args.insert(source_arg.to_string(), path_value(file));
+    if let Some(destination) = destination {
+        args.insert("destination_path".to_string(), path_value(destination));
+    }
+
+    // The selected file must still be there, and still a file, when the step runs
+    let selected = Precondition {
+        exists: Some(true),
+        directory: Some(false),
+        ..Default::default()
+    };
+    let preconditions = match &action.preconditions {
+        Some(Preconditions::PerArg(map)) => {
+            let mut map = map.clone();
+            map.remove("select");
+            map.entry(source_arg.to_string()).or_insert(selected);
+            Preconditions::PerArg(map)
+        }
+        Some(all) => all.clone(),
+        None => Preconditions::on(source_arg, selected),
+    };
+
+    Action {
+        id: format!("{}-{}", action.id, n + 1),
+        action_type: action.action_type.clone(),
+        args,
+        preconditions: Some(preconditions),
+        metadata: action.metadata.clone(),
+    }
+}
+
+fn path_value(path: &Path) -> serde_json::Value {
+    serde_json::Value::String(path.to_string_lossy().to_string())
+}
+
+/// Files below the selector's directory that match all of its filters, sorted by path.
+/// Hidden entries are skipped unless the glob asks for them, and protected ones always are.
+pub fn select_files(selector: &Selector, ctx: &ExecutionContext) -> Result<Vec<PathBuf>, String> {
+    let directory = Path::new(&selector.directory);
+    if !directory.is_absolute() {
+        return Err(format!("directory must be absolute: {}", selector.directory));
+    }
+    check_access(ctx, &selector.directory, PathAccess::Read)?;
+    if !directory.is_dir() {
+        return Err(format!("directory does not exist: {}", selector.directory));
+    }
+
+    let filter = Filter::new(selector)?;
+    let recursive = selector.recursive.unwrap_or(false);
+    let max_entries = ctx.directory_limits.max_entries;
+
+    let mut selected = Vec::new();
+    let mut pending = vec![directory.to_path_buf()];
+    let mut scanned = 0;
+
+    while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+            scanned += 1;
+            if scanned > max_entries {
+                return Err(format!(
+                    "{} has more than {} entries to search; narrow the selector",
+                    selector.directory, max_entries
+                ));
+            }
+
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if check_access(ctx, &path.to_string_lossy(), PathAccess::Read).is_err() {
+                continue;
+            }
+
+            // Symlinks are neither followed nor selected
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                if recursive && !hidden {
+                    pending.push(path);
+                }
+                continue;
+            }
+            if !meta.is_file() || (hidden && !filter.glob_hidden) {
+                continue;
+            }
+
+            let relative = path.strip_prefix(directory).unwrap_or(&path);
+            if filter.matches(relative, &meta) {
+                selected.push(path);
+            }
+        }
+    }
+
+    selected.sort();
+    Ok(selected)
+}
+
+/// A selector's filters, parsed once
+struct Filter {
+    glob: Option<Regex>,
+    glob_on_path: bool,
+    glob_hidden: bool,
+    extensions: Option<Vec<String>>,
+    modified_before: Option<DateTime<Utc>>,
+    modified_after: Option<DateTime<Utc>>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+}
+
+impl Filter {
+    fn new(selector: &Selector) -> Result<Self, String> {
+        let glob_pattern = selector.glob.as_deref();
+
+        Ok(Self {
+            glob: glob_pattern.map(glob::compile).transpose()?,
+            glob_on_path: glob_pattern.is_some_and(|g| g.contains('/')),
+            glob_hidden: glob_pattern.is_some_and(|g| g.starts_with('.') || g.contains("/.")),
+            extensions: selector.extensions.as_ref().map(|extensions| {
+                extensions
+                    .iter()
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .collect()
+            }),
+            modified_before: selector.modified_before.as_deref().map(parse_date).transpose()?,
+            modified_after: selector.modified_after.as_deref().map(parse_date).transpose()?,
+            min_size: selector.min_size,
+            max_size: selector.max_size,
+        })
+    }
+
+    fn matches(&self, relative: &Path, meta: &fs::Metadata) -> bool {
+        if let Some(glob) = &self.glob {
+            let subject = if self.glob_on_path {
+                relative.to_string_lossy().to_string()
+            } else {
+                relative.file_name().unwrap_or_default().to_string_lossy().to_string()
+            };
+            if !glob.is_match(&glob::normalize_separators(&subject)) {
+                return false;
+            }
+        }
+
+        if let Some(extensions) = &self.extensions {
+            let extension = relative
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if !extensions.contains(&extension) {
+                return false;
+            }
+        }
+
+        if self.modified_before.is_some() || self.modified_after.is_some() {
+            let Ok(modified) = meta.modified().map(DateTime::<Utc>::from) else {
+                return false;
+            };
+            if self.modified_before.is_some_and(|before| modified >= before)
+                || self.modified_after.is_some_and(|after| modified <= after)
+            {
+                return false;
+            }
+        }
+
+        let size = meta.len();
+        self.min_size.is_none_or(|min| size >= min) && self.max_size.is_none_or(|max| size <= max)
+    }
+}
+
+/// An RFC 3339 timestamp, or a YYYY-MM-DD date meaning local midnight
+fn parse_date(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|midnight| midnight.with_timezone(&Utc))
+        .ok_or_else(|| format!("Invalid date '{}': expected RFC 3339 or YYYY-MM-DD", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn home() -> TempDir {
+        let home = TempDir::new().unwrap();
+        let desktop = home.path().join("Desktop");
+        fs::create_dir_all(desktop.join("shots")).unwrap();
+        fs::write(desktop.join("b.png"), "bb").unwrap();
+        fs::write(desktop.join("a.PNG"), "a").unwrap();
+        fs::write(desktop.join("notes.txt"), "notes").unwrap();
+        fs::write(desktop.join(".hidden.png"), "h").unwrap();
+        fs::write(desktop.join("shots/c.png"), "ccc").unwrap();
+        home
+    }
+
+    fn plan(action_type: &str, args: serde_json::Value) -> ActionPlan {
+        serde_json::from_value(serde_json::json!({
+            "id": "p",
+            "origin": { "user_input": "test", "source": "ui", "request_id": "r" },
+            "actions": [{ "id": "s", "type": action_type, "args": args }],
+            "summary": "test",
+            "risk_score": 0.5,
+            "dry_run": true
+        }))
+        .unwrap()
+    }
+
+    fn desktop(home: &TempDir) -> String {
+        home.path().join("Desktop").to_string_lossy().to_string()
+    }
+
+    fn arg(action: &Action, name: &str) -> String {
+        action.args[name].as_str().unwrap().to_string()
+    }
+
+    fn selected(home: &TempDir, select: serde_json::Value) -> Result<Vec<String>, String> {
+        let selector: Selector = serde_json::from_value(select).unwrap();
+        let ctx = ExecutionContext::new(home.path().to_path_buf());
+        let files = select_files(&selector, &ctx)?;
+        let names = files
+            .iter()
+            .map(|file| file.strip_prefix(home.path()).unwrap().to_string_lossy().to_string())
+            .collect();
+        Ok(names)
+    }
+
+    #[test]
+    fn one_action_per_file_in_path_order() {
+        let home = home();
+        let ctx = ExecutionContext::new(home.path().to_path_buf());
+        let out = home.path().join("Out");
+        let bulk = plan(
+            "fs_copy_file",
+            serde_json::json!({
+                "select": { "directory": desktop(&home), "glob": "*.png", "recursive": true },
+                "destination_path": out.to_string_lossy()
+            }),
+        );
+        assert!(has_selectors(&bulk));
+
+        let (expanded, notes) = expand_plan(&bulk, &ctx).unwrap();
+        assert!(!has_selectors(&expanded));
+        let actions = &expanded.schema.actions;
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].id, "s-1");
+        assert_eq!(arg(&actions[0], "source_path"), format!("{}/b.png", desktop(&home)));
+        assert_eq!(arg(&actions[0], "destination_path"), out.join("b.png").to_string_lossy());
+        // Files from subdirectories keep their place under the destination
+        assert_eq!(actions[1].id, "s-2");
+        assert_eq!(arg(&actions[1], "destination_path"), out.join("shots/c.png").to_string_lossy());
+        assert_eq!(notes, vec![format!("Step 1 (fs_copy_file) selects 2 file(s) in {}", desktop(&home))]);
+    }
+
+    #[test]
+    fn selected_files_must_still_exist() {
+        let home = home();
+        let ctx = ExecutionContext::new(home.path().to_path_buf());
+        let bulk = plan(
+            "fs_delete_file",
+            serde_json::json!({ "select": { "directory": desktop(&home), "glob": "notes.*" }, "permanent": true }),
+        );
+
+        let (expanded, _) = expand_plan(&bulk, &ctx).unwrap();
+        let action = &expanded.schema.actions[0];
+        assert_eq!(arg(action, "path"), format!("{}/notes.txt", desktop(&home)));
+        assert_eq!(action.args["permanent"], true);
+        let Some(Preconditions::PerArg(preconditions)) = &action.preconditions else {
+            panic!("expected preconditions on path, got {:?}", action.preconditions);
+        };
+        assert_eq!(preconditions["path"].exists, Some(true));
+        assert_eq!(preconditions["path"].directory, Some(false));
+    }
+
+    #[test]
+    fn more_files_than_the_fan_out_limit_are_refused() {
+        let home = home();
+        let mut ctx = ExecutionContext::new(home.path().to_path_buf());
+        ctx.selector_limits.max_fan_out = 2;
+        let bulk = plan(
+            "fs_delete_file",
+            serde_json::json!({ "select": { "directory": desktop(&home), "recursive": true } }),
+        );
+
+        let err = expand_plan(&bulk, &ctx).unwrap_err();
+        assert!(err.starts_with("actions[0].args.select: 4 files match"), "{}", err);
+        assert!(err.contains("more than the maximum of 2"), "{}", err);
+
+        ctx.selector_limits.max_fan_out = 4;
+        assert_eq!(expand_plan(&bulk, &ctx).unwrap().0.schema.actions.len(), 4);
+    }
+
+    #[test]
+    fn empty_selections_and_other_actions_are_refused() {
+        let home = home();
+        let ctx = ExecutionContext::new(home.path().to_path_buf());
+
+        let none = plan("fs_delete_file", serde_json::json!({ "select": { "directory": desktop(&home), "glob": "*.zip" } }));
+        let err = expand_plan(&none, &ctx).unwrap_err();
+        assert!(err.contains("no files match"), "{}", err);
+
+        let listing = plan("fs_list_directory", serde_json::json!({ "select": { "directory": desktop(&home) } }));
+        let err = expand_plan(&listing, &ctx).unwrap_err();
+        assert!(err.contains("fs_list_directory does not accept a selector"), "{}", err);
+    }
+
+    #[test]
+    fn hidden_files_need_a_glob_that_names_them() {
+        let home = home();
+        assert_eq!(
+            selected(&home, serde_json::json!({ "directory": desktop(&home), "glob": "*.png" })).unwrap(),
+            vec!["Desktop/b.png"]
+        );
+        assert_eq!(
+            selected(&home, serde_json::json!({ "directory": desktop(&home), "glob": ".*" })).unwrap(),
+            vec!["Desktop/.hidden.png"]
+        );
+    }
+
+    #[test]
+    fn filters_combine() {
+        let home = home();
+        // Extensions ignore case and a leading dot
+        assert_eq!(
+            selected(&home, serde_json::json!({ "directory": desktop(&home), "extensions": [".png"] })).unwrap(),
+            vec!["Desktop/a.PNG", "Desktop/b.png"]
+        );
+        assert_eq!(
+            selected(&home, serde_json::json!({ "directory": desktop(&home), "extensions": ["png"], "min_size": 2 })).unwrap(),
+            vec!["Desktop/b.png"]
+        );
+        assert_eq!(
+            selected(&home, serde_json::json!({ "directory": desktop(&home), "glob": "shots/*", "recursive": true })).unwrap(),
+            vec!["Desktop/shots/c.png"]
+        );
+        assert!(selected(&home, serde_json::json!({ "directory": desktop(&home), "modified_before": "2000-01-01" }))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            selected(&home, serde_json::json!({ "directory": desktop(&home), "modified_after": "2000-01-01T00:00:00Z", "max_size": 1 }))
+                .unwrap(),
+            vec!["Desktop/a.PNG"]
+        );
+    }
+
+    #[test]
+    fn directories_must_be_absolute_and_allowed() {
+        let home = home();
+        let err = selected(&home, serde_json::json!({ "directory": "Desktop" })).unwrap_err();
+        assert!(err.contains("must be absolute"), "{}", err);
+        assert!(selected(&home, serde_json::json!({ "directory": "/etc" })).is_err());
+    }
+
+    #[test]
+    fn dates_are_rfc3339_or_plain_days() {
+        assert_eq!(
+            parse_date("2026-01-02T03:04:05+01:00").unwrap().to_rfc3339(),
+            "2026-01-02T02:04:05+00:00"
+        );
+        let midnight = parse_date("2026-01-02").unwrap().with_timezone(&Local);
+        assert_eq!(midnight.format("%Y-%m-%d %H:%M").to_string(), "2026-01-02 00:00");
+        assert!(parse_date("yesterday").unwrap_err().contains("expected RFC 3339 or YYYY-MM-DD"));
+    }
+}
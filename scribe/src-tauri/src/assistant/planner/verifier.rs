@@ -1,23 +1,50 @@
+use crate::assistant::executor::dry_run;
+use crate::assistant::planner::{risk, selector};
+use crate::assistant::references;
+use crate::assistant::schema;
 use crate::assistant::types::*;
-use crate::assistant::validator::validate_path;
-use std::path::PathBuf;
+use crate::assistant::validator::{self, validate_path};
 
 /// Verify and validate an action plan
-pub fn verify_action_plan(plan: &ActionPlan, user_home: &PathBuf) -> Result<VerifiedPlan, String> {
-    let mut notes = Vec::new();
-    let mut risk_score = plan.schema.risk_score;
+/// Selectors are expanded first, so the verified plan names every file it touches,
+/// and steps are ordered after the steps whose output they use.
+/// Paths outside the allowed roots are reported in the notes rather than
+/// rejected here; the executor refuses them unless the plan escalates them.
+pub fn verify_action_plan(plan: &ActionPlan, ctx: &ExecutionContext) -> Result<VerifiedPlan, String> {
+    let user_home = &ctx.user_home;
+    let (plan, mut notes) = selector::expand_plan(plan, ctx)?;
+    let (plan, order_notes) = references::order_plan(&plan)?;
+    notes.extend(order_notes);
+    let plan = &plan;
+
+    // Checks below see the paths earlier steps' args already tell; paths only known
+    // at run time are checked by the folder they are in, and again when they run
+    let resolved = references::resolve_statically(plan);
+    let actions = &resolved.schema.actions;
 
     // Validate each action
-    for action in &plan.schema.actions {
+    for (index, (original, action)) in plan.schema.actions.iter().zip(actions).enumerate() {
         // Type check
         validate_action_type(&action.action_type)?;
 
+        // Args must match the typed args of the action type exactly
+        schema::validate_args(&original.action_type, &original.args)
+            .map_err(|e| format!("actions[{}].{}", index, e))?;
+        let static_path = |arg: &str, path_str: &str| {
+            references::static_path(actions, action, arg).ok_or_else(|| {
+                format!(
+                    "actions[{}].args.{}: cannot tell which folder {} is in before the plan runs",
+                    index, arg, path_str
+                )
+            })
+        };
+
         // Path validation (skip if path is placeholder)
         if let Some(path_value) = action.args.get("path") {
             if let Some(path_str) = path_value.as_str() {
                 // Skip validation for placeholder paths
                 if path_str != "__PROMPT_PATH__" {
-                    validate_path(path_str, user_home)?;
+                    validate_path(&static_path("path", path_str)?, user_home)?;
                 }
             }
         }
@@ -26,7 +53,7 @@ pub fn verify_action_plan(plan: &ActionPlan, user_home: &PathBuf) -> Result<Veri
             if let Some(src_path_str) = src_path_value.as_str() {
                 // Skip validation for placeholder paths
                 if src_path_str != "__PROMPT_PATH__" {
-                    validate_path(src_path_str, user_home)?;
+                    validate_path(&static_path("source_path", src_path_str)?, user_home)?;
                 }
             }
         }
@@ -35,23 +62,37 @@ pub fn verify_action_plan(plan: &ActionPlan, user_home: &PathBuf) -> Result<Veri
             if let Some(dst_path_str) = dst_path_value.as_str() {
                 // Skip validation for placeholder paths
                 if dst_path_str != "__PROMPT_PATH__" {
-                    validate_path(dst_path_str, user_home)?;
+                    validate_path(&static_path("destination_path", dst_path_str)?, user_home)?;
                 }
             }
         }
 
-        // Check preconditions
-        if let Some(preconditions) = &action.preconditions {
-            validate_preconditions(action, preconditions)?;
+        // Confinement: denylist and allowed roots
+        for (arg, access) in validator::path_accesses(&action.action_type) {
+            if let Some(path_str) = action.args.get(*arg).and_then(|v| v.as_str()) {
+                if path_str != "__PROMPT_PATH__" {
+                    if let Err(reason) = validator::check_access(ctx, &static_path(arg, path_str)?, *access) {
+                        notes.push(reason);
+                    }
+                }
+            }
         }
-
-        // Recalculate risk based on action type
-        let action_risk = calculate_action_risk(&action.action_type);
-        risk_score = (risk_score * 0.7 + action_risk * 0.3).min(1.0);
     }
 
+    // Check preconditions in plan order, against the state earlier actions leave behind
+    dry_run::check_plan_preconditions(plan, ctx)?;
+
+    // Score the plan ourselves and explain every factor
+    let assessment = risk::assess_plan(&resolved, ctx);
+    notes.extend(assessment.notes());
+
+    let mut verified = plan.clone();
+    verified.schema.risk_score = assessment.score;
+
     Ok(VerifiedPlan {
-        plan: plan.clone(),
+        approval: assessment.level.approval(&plan.schema.origin.source),
+        risk_level: assessment.level,
+        plan: verified,
         verified_at: chrono::Utc::now().timestamp(),
         verification_notes: notes,
     })
@@ -65,82 +106,18 @@ fn validate_action_type(action_type: &ActionType) -> Result<(), String> {
         | ActionType::FsCopyFile
         | ActionType::FsMoveFile
         | ActionType::FsDeleteFile
-        | ActionType::FsCreateDirectory => Ok(()),
-    }
-}
-
-/// Validate preconditions
-fn validate_preconditions(action: &Action, preconditions: &Precondition) -> Result<(), String> {
-    // Check if file exists (skip if path is placeholder)
-    if let Some(should_exist) = preconditions.exists {
-        if let Some(path_value) = action.args.get("path") {
-            if let Some(path_str) = path_value.as_str() {
-                // Skip precondition check for placeholder paths
-                if path_str == "__PROMPT_PATH__" {
-                    return Ok(()); // Will be validated after user provides path
-                }
-                let path = std::path::Path::new(path_str);
-                let exists = path.exists();
-                if should_exist && !exists {
-                    return Err(format!("Precondition failed: file should exist but doesn't: {}", path_str));
-                }
-                if !should_exist && exists {
-                    return Err(format!("Precondition failed: file should not exist but does: {}", path_str));
-                }
-            }
-        }
-    }
-
-    // Check if writable (skip if path is placeholder)
-    if let Some(should_be_writable) = preconditions.writable {
-        if let Some(path_value) = action.args.get("path") {
-            if let Some(path_str) = path_value.as_str() {
-                // Skip precondition check for placeholder paths
-                if path_str == "__PROMPT_PATH__" {
-                    return Ok(()); // Will be validated after user provides path
-                }
-                let path = std::path::Path::new(path_str);
-                if let Some(parent) = path.parent() {
-                    let metadata_result = std::fs::metadata(parent);
-                    if let Ok(_metadata) = metadata_result {
-                        #[cfg(unix)]
-                        {
-                            use std::os::unix::fs::PermissionsExt;
-                            let permissions = _metadata.permissions();
-                            let writable = permissions.mode() & 0o200 != 0;
-                            if should_be_writable && !writable {
-                                return Err(format!("Precondition failed: directory not writable: {}", path_str));
-                            }
-                        }
-                        #[cfg(not(unix))]
-                        {
-                            // On Windows, assume writable if we can write
-                            if should_be_writable {
-                                // Try to create a test file
-                                if let Err(_) = std::fs::File::create(parent.join(".test_write")) {
-                                    return Err(format!("Precondition failed: directory not writable: {}", path_str));
-                                }
-                                let _ = std::fs::remove_file(parent.join(".test_write"));
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        | ActionType::FsCreateDirectory
+        | ActionType::FsListDirectory
+        | ActionType::FsCopyDirectory
+        | ActionType::FsMoveDirectory
+        | ActionType::FsDeleteDirectory
+        | ActionType::FsAppendFile
+        | ActionType::FsReplaceText
+        | ActionType::FsApplyPatch
+        | ActionType::FsRestoreFromTrash
+        | ActionType::FsSearch
+        | ActionType::FsStat
+        | ActionType::ArchiveCreate
+        | ActionType::ArchiveExtract => Ok(()),
     }
-
-    Ok(())
 }
-
-/// Calculate risk score for action type
-fn calculate_action_risk(action_type: &ActionType) -> f64 {
-    match action_type {
-        ActionType::FsReadFile | ActionType::FsCreateDirectory | ActionType::FsCreateFile => {
-            RiskScore::Low.value()
-        }
-        ActionType::FsCopyFile => RiskScore::Medium.value(),
-        ActionType::FsMoveFile => RiskScore::High.value(),
-        ActionType::FsDeleteFile => RiskScore::High.value(),
-    }
-}
-
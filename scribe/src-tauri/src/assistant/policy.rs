@@ -1,26 +1,67 @@
+use crate::assistant::references;
 use crate::assistant::types::*;
-use jsonwebtoken::{encode, decode, Header, EncodingKey, DecodingKey, Validation};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use jsonwebtoken::{encode, decode, Algorithm, Header, EncodingKey, DecodingKey, Validation};
+use once_cell::sync::OnceCell;
+use rand::RngCore;
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-/// Secret key for signing tokens (in production, should be stored securely)
-static TOKEN_SECRET: &[u8] = b"actionable-assistant-secret-key-change-in-production";
+/// Keychain entry holding the per-install token signing secret
+const KEYCHAIN_SERVICE: &str = "ghost-assistant";
+const KEYCHAIN_ACCOUNT: &str = "capability-token-secret";
+
+/// Signing secret, loaded from (or created in) the keychain on first use
+static TOKEN_SECRET: OnceCell<Vec<u8>> = OnceCell::new();
 
 #[derive(Debug, Serialize, Deserialize)]
 struct TokenClaims {
     nonce: String,
     scopes: Vec<String>,
     session_id: String,
-    expires_at: i64,
-    issued_at: i64,
+    exp: i64,
+    iat: i64,
+}
+
+/// Get the HMAC secret for this install, generating it on first run
+fn token_secret() -> Result<&'static [u8], String> {
+    TOKEN_SECRET
+        .get_or_try_init(|| {
+            let entry = keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)
+                .map_err(|e| format!("Failed to access keychain: {}", e))?;
+
+            match entry.get_password() {
+                Ok(encoded) => BASE64
+                    .decode(encoded)
+                    .map_err(|e| format!("Stored token secret is corrupt: {}", e)),
+                Err(keyring::Error::NoEntry) => {
+                    let mut secret = vec![0u8; 32];
+                    rand::thread_rng().fill_bytes(&mut secret);
+                    entry
+                        .set_password(&BASE64.encode(&secret))
+                        .map_err(|e| format!("Failed to store token secret in keychain: {}", e))?;
+                    Ok(secret)
+                }
+                Err(e) => Err(format!("Failed to read token secret from keychain: {}", e)),
+            }
+        })
+        .map(|secret| secret.as_slice())
 }
 
-/// Mint a new capability token
+/// Mint a new capability token.
+/// Returns the signed token string and the decoded token; the nonce is
+/// recorded in `capability_tokens` so it can be revoked later.
 pub fn mint_capability_token(
+    conn: &Connection,
     scopes: Vec<String>,
     ttl_seconds: i64,
     session_id: String,
-) -> Result<CapabilityToken, String> {
+) -> Result<(String, CapabilityToken), String> {
+    if ttl_seconds <= 0 {
+        return Err("Token TTL must be positive".to_string());
+    }
+
     let now = chrono::Utc::now().timestamp();
     let expires_at = now + ttl_seconds;
     let nonce = Uuid::new_v4().to_string();
@@ -29,34 +70,47 @@ pub fn mint_capability_token(
         nonce: nonce.clone(),
         scopes: scopes.clone(),
         session_id: session_id.clone(),
-        expires_at,
-        issued_at: now,
+        exp: expires_at,
+        iat: now,
     };
 
-    let _token_string = encode(
-        &Header::default(),
+    let token_string = encode(
+        &Header::new(Algorithm::HS256),
         &claims,
-        &EncodingKey::from_secret(TOKEN_SECRET),
+        &EncodingKey::from_secret(token_secret()?),
     )
     .map_err(|e| format!("Failed to encode token: {}", e))?;
 
-    Ok(CapabilityToken {
-        nonce,
-        scopes,
-        ttl_seconds,
-        session_id,
-        expires_at,
-        issued_at: now,
-    })
+    let scopes_json = serde_json::to_string(&scopes)
+        .map_err(|e| format!("Failed to serialize scopes: {}", e))?;
+
+    conn.execute(
+        "INSERT INTO capability_tokens (nonce, scopes_json, expires_at, revoked, created_at) VALUES (?1, ?2, ?3, 0, ?4)",
+        params![nonce, scopes_json, expires_at, now],
+    )
+    .map_err(|e| format!("Failed to record token: {}", e))?;
+
+    Ok((
+        token_string,
+        CapabilityToken {
+            nonce,
+            scopes,
+            ttl_seconds,
+            session_id,
+            expires_at,
+            issued_at: now,
+        },
+    ))
 }
 
 /// Validate and decode a capability token
-pub fn validate_token(token_string: &str) -> Result<CapabilityToken, String> {
-    let validation = Validation::default();
-    
+pub fn validate_token(conn: &Connection, token_string: &str) -> Result<CapabilityToken, String> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = 0;
+
     let token_data = decode::<TokenClaims>(
         token_string,
-        &DecodingKey::from_secret(TOKEN_SECRET),
+        &DecodingKey::from_secret(token_secret()?),
         &validation,
     )
     .map_err(|e| format!("Failed to decode token: {}", e))?;
@@ -65,29 +119,46 @@ pub fn validate_token(token_string: &str) -> Result<CapabilityToken, String> {
 
     // Check expiration
     let now = chrono::Utc::now().timestamp();
-    if claims.expires_at < now {
+    if claims.exp < now {
         return Err("Token has expired".to_string());
     }
 
+    // Only tokens minted by this install and not revoked are accepted
+    let revoked: Option<bool> = conn
+        .query_row(
+            "SELECT revoked FROM capability_tokens WHERE nonce = ?1",
+            params![claims.nonce],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to look up token: {}", e))?;
+
+    match revoked {
+        None => return Err("Token is not known to this install".to_string()),
+        Some(true) => return Err("Token has been revoked".to_string()),
+        Some(false) => {}
+    }
+
     Ok(CapabilityToken {
         nonce: claims.nonce,
         scopes: claims.scopes,
-        ttl_seconds: claims.expires_at - claims.issued_at,
+        ttl_seconds: claims.exp - claims.iat,
         session_id: claims.session_id,
-        expires_at: claims.expires_at,
-        issued_at: claims.issued_at,
+        expires_at: claims.exp,
+        issued_at: claims.iat,
     })
 }
 
-/// Check if a token has permission for an action
+/// Check if a token has permission for an operation on a resource
 pub fn check_permission(
     token: &CapabilityToken,
     action_type: &str,
+    operation: &str,
     resource: &str,
 ) -> bool {
     for scope_str in &token.scopes {
         if let Ok(scope) = Scope::parse(scope_str) {
-            if scope.matches(action_type, resource) {
+            if scope.matches(action_type, operation, resource) {
                 return true;
             }
         }
@@ -95,10 +166,215 @@ pub fn check_permission(
     false
 }
 
+/// Scope operation required for each path-bearing argument of an action
+/// e.g. copying needs `fs:read` on the source and `fs:create` on the destination
+pub fn required_scopes(action_type: &ActionType) -> &'static [(&'static str, &'static str)] {
+    match action_type {
+        ActionType::FsReadFile => &[("path", "read")],
+        ActionType::FsCreateFile | ActionType::FsCreateDirectory => &[("path", "create")],
+        ActionType::FsCopyFile => &[("source_path", "read"), ("destination_path", "create")],
+        ActionType::FsMoveFile => &[("source_path", "move"), ("destination_path", "move")],
+        ActionType::FsDeleteFile => &[("path", "delete")],
+        ActionType::FsListDirectory | ActionType::FsSearch | ActionType::FsStat => &[("path", "read")],
+        ActionType::FsCopyDirectory => &[("source_path", "read"), ("destination_path", "create")],
+        ActionType::FsMoveDirectory => &[("source_path", "move"), ("destination_path", "move")],
+        ActionType::FsDeleteDirectory => &[("path", "delete")],
+        ActionType::FsAppendFile | ActionType::FsReplaceText | ActionType::FsApplyPatch => &[("path", "write")],
+        ActionType::FsRestoreFromTrash => &[("path", "create"), ("trash_path", "delete")],
+        ActionType::ArchiveCreate | ActionType::ArchiveExtract => &[("source_path", "read"), ("destination_path", "create")],
+    }
+}
+
+/// Check every path argument of an action against the token's scopes
+pub fn check_action_permission(token: &CapabilityToken, action: &Action) -> Result<(), String> {
+    for (arg, operation) in required_scopes(&action.action_type) {
+        let Some(path_str) = action.args.get(*arg).and_then(|v| v.as_str()) else {
+            // Without a trash_path the item is looked up in the trash by its original path
+            if *arg == "trash_path" {
+                continue;
+            }
+            return Err(format!("Missing '{}' argument for permission check", arg));
+        };
+
+        if !check_permission(token, "fs", operation, path_str) {
+            return Err(format!(
+                "Permission denied: fs:{} not granted for {}",
+                operation, path_str
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Validate a token and check it grants every action of a plan.
+/// Actions with paths from an earlier step's output are checked once those are known.
+pub fn validate_plan_token(conn: &Connection, plan: &ActionPlan, token_string: &str) -> Result<CapabilityToken, String> {
+    let token = validate_token(conn, token_string)
+        .map_err(|e| format!("Token validation failed: {}", e))?;
+
+    for action in plan.schema.actions.iter().filter(|a| !references::has_references(a)) {
+        check_action_permission(&token, action)?;
+    }
+
+    Ok(token)
+}
+
 /// Revoke a token (mark nonce as revoked in database)
-pub fn revoke_token_nonce(_nonce: &str) -> Result<(), String> {
-    // This should update the database to mark the nonce as revoked
-    // For now, we'll just return Ok (database update will be done via IPC)
+pub fn revoke_token_nonce(conn: &Connection, nonce: &str) -> Result<(), String> {
+    let updated = conn
+        .execute(
+            "UPDATE capability_tokens SET revoked = 1 WHERE nonce = ?1",
+            params![nonce],
+        )
+        .map_err(|e| format!("Failed to revoke token: {}", e))?;
+
+    if updated == 0 {
+        return Err(format!("Unknown token nonce: {}", nonce));
+    }
+
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use std::collections::HashMap;
+
+    /// A database with the token table, and a fixed secret instead of the keychain
+    fn test_conn() -> Connection {
+        TOKEN_SECRET.get_or_init(|| vec![7u8; 32]);
+        let conn = Connection::open_in_memory().expect("in-memory database opens");
+        conn.execute_batch(include_str!("../db/migrations/assistant.sql"))
+            .expect("assistant tables are created");
+        conn
+    }
+
+    fn mint(conn: &Connection, scope: &str) -> (String, CapabilityToken) {
+        mint_capability_token(conn, vec![scope.to_string()], 300, "test-session".to_string())
+            .expect("token is minted")
+    }
+
+    fn create_file(path: &str) -> Action {
+        Action {
+            id: "create".to_string(),
+            action_type: ActionType::FsCreateFile,
+            args: HashMap::from([("path".to_string(), serde_json::json!(path))]),
+            preconditions: None,
+            metadata: None,
+        }
+    }
+
+    fn allows(token: &CapabilityToken, path: &str) -> bool {
+        match check_action_permission(token, &create_file(path)) {
+            Ok(()) => true,
+            Err(e) => {
+                assert_eq!(e, format!("Permission denied: fs:create not granted for {}", path));
+                false
+            }
+        }
+    }
+
+    #[test]
+    fn minted_token_is_a_signed_jwt_with_nonce_and_scopes() {
+        let conn = test_conn();
+        let (token_string, token) = mint(&conn, "fs:create:/home/me/Docs/*");
+
+        let parts: Vec<&str> = token_string.split('.').collect();
+        assert_eq!(parts.len(), 3);
+        let payload: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+        assert_eq!(payload["nonce"], token.nonce.as_str());
+        assert_eq!(payload["scopes"], serde_json::json!(["fs:create:/home/me/Docs/*"]));
+
+        let validated = validate_token(&conn, &token_string).unwrap();
+        assert_eq!(validated.nonce, token.nonce);
+        assert_eq!(validated.scopes, token.scopes);
+        assert_eq!(validated.ttl_seconds, 300);
+    }
+
+    #[test]
+    fn non_positive_ttl_is_refused() {
+        let conn = test_conn();
+        let minted = mint_capability_token(&conn, vec!["fs:read:/tmp/*".to_string()], 0, "s".to_string());
+        assert_eq!(minted.unwrap_err(), "Token TTL must be positive");
+    }
+
+    #[test]
+    fn revoked_token_is_rejected() {
+        let conn = test_conn();
+        let (token_string, token) = mint(&conn, "fs:create:/home/me/Docs/*");
+
+        revoke_token_nonce(&conn, &token.nonce).unwrap();
+        assert_eq!(validate_token(&conn, &token_string).unwrap_err(), "Token has been revoked");
+        assert!(revoke_token_nonce(&conn, "no-such-nonce").is_err());
+    }
+
+    #[test]
+    fn token_from_another_install_is_rejected() {
+        let (token_string, _) = mint(&test_conn(), "fs:create:/home/me/Docs/*");
+        assert_eq!(
+            validate_token(&test_conn(), &token_string).unwrap_err(),
+            "Token is not known to this install"
+        );
+    }
+
+    #[test]
+    fn tampered_token_is_rejected() {
+        let conn = test_conn();
+        let (token_string, _) = mint(&conn, "fs:create:/home/me/Docs/*");
+
+        let mut parts: Vec<String> = token_string.split('.').map(str::to_string).collect();
+        let mut payload: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(&parts[1]).unwrap()).unwrap();
+        payload["scopes"] = serde_json::json!(["fs:*:/**"]);
+        parts[1] = URL_SAFE_NO_PAD.encode(payload.to_string());
+
+        assert!(validate_token(&conn, &parts.join(".")).is_err());
+    }
+
+    #[test]
+    fn dot_dot_cannot_escape_a_scoped_directory() {
+        let (_, token) = mint(&test_conn(), "fs:create:/home/me/Docs/*");
+        assert!(allows(&token, "/home/me/Docs/a.txt"));
+        assert!(!allows(&token, "/home/me/Docs/../.ssh/authorized_keys"));
+    }
+
+    #[test]
+    fn dot_in_a_scope_is_literal() {
+        let (_, token) = mint(&test_conn(), "fs:create:/home/me/notes.v2/*");
+        assert!(allows(&token, "/home/me/notes.v2/a.txt"));
+        assert!(!allows(&token, "/home/me/notesXv2/a.txt"));
+    }
+
+    #[test]
+    fn star_does_not_cross_directories() {
+        let (_, token) = mint(&test_conn(), "fs:create:/home/me/Docs/*");
+        assert!(allows(&token, "/home/me/Docs/a.txt"));
+        assert!(!allows(&token, "/home/me/Docs/sub/a.txt"));
+    }
+
+    #[test]
+    fn double_star_matches_nested_directories() {
+        let (_, token) = mint(&test_conn(), "fs:create:/home/me/Docs/**");
+        assert!(allows(&token, "/home/me/Docs/sub/deeper/a.txt"));
+        assert!(!allows(&token, "/home/me/Documents/a.txt"));
+    }
+
+    #[test]
+    fn character_classes_match_within_one_segment() {
+        let (_, token) = mint(&test_conn(), "fs:create:/home/me/[a-c]*.txt");
+        assert!(allows(&token, "/home/me/b.txt"));
+        assert!(!allows(&token, "/home/me/d.txt"));
+    }
+
+    #[test]
+    fn scope_checks_action_type_and_operation() {
+        let scope = Scope::parse("fs:read:/tmp/*").unwrap();
+        assert!(scope.matches("fs", "read", "/tmp/a"));
+        assert!(!scope.matches("fs", "delete", "/tmp/a"));
+        assert!(!scope.matches("net", "read", "/tmp/a"));
+        assert!(Scope::parse("fs:*:/tmp/*").unwrap().matches("fs", "delete", "/tmp/a"));
+        assert!(Scope::parse("fs:/tmp").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::assistant::types::*;
+use crate::assistant::validator;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What preconditions can observe about a path.
+/// `None` means the value is not known yet (e.g. content written by an earlier step
+/// in a simulation) and the check is left to execution time.
+#[derive(Debug, Clone, Default)]
+pub struct PathState {
+    pub exists: bool,
+    pub directory: bool,
+    pub readable: bool,
+    /// For a missing path, whether it could be created
+    pub writable: bool,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+}
+
+/// Check one path against its preconditions
+pub fn check(precondition: &Precondition, path_str: &str, state: &PathState) -> Result<(), String> {
+    if let Some(should_exist) = precondition.exists {
+        if should_exist && !state.exists {
+            return Err(format!("Precondition failed: file should exist but doesn't: {}", path_str));
+        }
+        if !should_exist && state.exists {
+            return Err(format!("Precondition failed: file should not exist but does: {}", path_str));
+        }
+    }
+
+    if let Some(should_be_directory) = precondition.directory {
+        if should_be_directory && !state.directory {
+            return Err(format!("Precondition failed: path should be a directory: {}", path_str));
+        }
+        if !should_be_directory && state.directory {
+            return Err(format!("Precondition failed: path should not be a directory: {}", path_str));
+        }
+    }
+
+    if let Some(should_be_readable) = precondition.readable {
+        if should_be_readable != state.readable {
+            return Err(format!(
+                "Precondition failed: path {} readable: {}",
+                if should_be_readable { "not" } else { "is" },
+                path_str
+            ));
+        }
+    }
+
+    if let Some(should_be_writable) = precondition.writable {
+        if should_be_writable != state.writable {
+            return Err(format!(
+                "Precondition failed: path {} writable: {}",
+                if should_be_writable { "not" } else { "is" },
+                path_str
+            ));
+        }
+    }
+
+    if let Some(max_size) = precondition.max_size {
+        let size = state
+            .size
+            .ok_or_else(|| format!("Precondition could not be verified: size of {} is unknown", path_str))?;
+        if size > max_size {
+            return Err(format!(
+                "Precondition failed: {} is {} bytes, more than the expected {}",
+                path_str, size, max_size
+            ));
+        }
+    }
+
+    if let Some(expected) = &precondition.sha256 {
+        let actual = state
+            .sha256
+            .as_ref()
+            .ok_or_else(|| format!("Precondition could not be verified: could not hash {}", path_str))?;
+        if !expected.eq_ignore_ascii_case(actual) {
+            return Err(format!("Precondition failed: {} changed since the plan was made (sha256 mismatch)", path_str));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check an action's preconditions against the disk as it is now.
+/// Run right before the action so the content hash and size guard against races.
+pub fn check_on_disk(action: &Action) -> Result<(), String> {
+    let Some(preconditions) = &action.preconditions else {
+        return Ok(());
+    };
+
+    for (arg, _) in validator::path_accesses(&action.action_type) {
+        let Some(path_str) = action.args.get(*arg).and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let Some(precondition) = preconditions.for_arg(arg) else {
+            continue;
+        };
+        if path_str == "__PROMPT_PATH__" {
+            continue;
+        }
+        check(precondition, path_str, &disk_state(Path::new(path_str), precondition.sha256.is_some()))?;
+    }
+
+    Ok(())
+}
+
+/// State of a path on disk, hashing file content only when asked
+pub fn disk_state(path: &Path, hash: bool) -> PathState {
+    match fs::metadata(path) {
+        Ok(meta) => {
+            let (readable, writable) = permissions(path);
+            PathState {
+                exists: true,
+                directory: meta.is_dir(),
+                readable,
+                writable,
+                size: meta.is_file().then_some(meta.len()),
+                sha256: if hash && meta.is_file() { sha256_file(path).ok() } else { None },
+            }
+        }
+        Err(_) => PathState {
+            exists: false,
+            writable: creatable(path),
+            ..Default::default()
+        },
+    }
+}
+
+/// Whether a missing path could be created: its nearest existing ancestor is a writable directory
+pub fn creatable(path: &Path) -> bool {
+    path.ancestors()
+        .skip(1)
+        .find(|p| p.exists())
+        .is_some_and(|p| p.is_dir() && permissions(p).1)
+}
+
+/// Read and write permission of an existing path
+pub fn permissions(path: &Path) -> (bool, bool) {
+    #[cfg(target_os = "linux")]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        // access(2) accounts for ownership and groups, unlike the mode bits alone
+        match CString::new(path.as_os_str().as_bytes()) {
+            Ok(c_path) => unsafe {
+                (
+                    libc::access(c_path.as_ptr(), libc::R_OK) == 0,
+                    libc::access(c_path.as_ptr(), libc::W_OK) == 0,
+                )
+            },
+            Err(_) => (false, false),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        match fs::metadata(path) {
+            Ok(meta) => (true, !meta.permissions().readonly()),
+            Err(_) => (false, false),
+        }
+    }
+}
+
+/// Hex SHA-256 of a file, streamed
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hex SHA-256 of in-memory content
+pub fn sha256_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
@@ -0,0 +1,661 @@
+use crate::assistant::types::*;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// `{{steps.<id>.output.<field>}}`, where the field may go deeper, like `results.0.path`
+static REFERENCE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\{\{\s*steps\.([A-Za-z0-9_-]+)\.output((?:\.[A-Za-z0-9_-]+)*)\s*\}\}")
+        .expect("step reference pattern is valid")
+});
+
+/// The start of a step reference, to catch malformed ones
+static REFERENCE_START: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{\{\s*steps\.").expect("step reference pattern is valid"));
+
+/// A use of an earlier step's output in one of an action's args
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepReference {
+    /// Top-level arg the reference is written in
+    pub arg: String,
+    /// The reference as written
+    pub text: String,
+    pub step_id: String,
+    /// Keys into the step's output, e.g. `["results", "0", "path"]`
+    pub field: Vec<String>,
+}
+
+impl StepReference {
+    fn from_captures(arg: &str, captures: &Captures) -> Self {
+        StepReference {
+            arg: arg.to_string(),
+            text: captures[0].to_string(),
+            step_id: captures[1].to_string(),
+            field: captures[2]
+                .split('.')
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// "output.path", for messages
+    pub fn field_name(&self) -> String {
+        std::iter::once("output")
+            .chain(self.field.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// A reference to a field of a step's output, as written in args
+pub fn output_reference(step_id: &str, field: &str) -> String {
+    format!("{{{{steps.{}.output.{}}}}}", step_id, field)
+}
+
+/// Whether a string uses an earlier step's output
+pub fn contains_reference(value: &str) -> bool {
+    REFERENCE_START.is_match(value)
+}
+
+/// Whether any arg of an action uses an earlier step's output
+pub fn has_references(action: &Action) -> bool {
+    action.args.values().any(value_has_reference)
+}
+
+fn value_has_reference(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(s) => contains_reference(s),
+        serde_json::Value::Array(items) => items.iter().any(value_has_reference),
+        serde_json::Value::Object(map) => map.values().any(value_has_reference),
+        _ => false,
+    }
+}
+
+/// Every step reference in an action's args, in arg order
+pub fn action_references(action: &Action) -> Result<Vec<StepReference>, String> {
+    let mut args: Vec<(&String, &serde_json::Value)> = action.args.iter().collect();
+    args.sort_by_key(|(arg, _)| *arg);
+
+    let mut found = Vec::new();
+    for (arg, value) in args {
+        collect_references(arg, value, &mut found)?;
+    }
+    Ok(found)
+}
+
+fn collect_references(arg: &str, value: &serde_json::Value, found: &mut Vec<StepReference>) -> Result<(), String> {
+    match value {
+        serde_json::Value::String(s) => {
+            found.extend(REFERENCE.captures_iter(s).map(|captures| StepReference::from_captures(arg, &captures)));
+            if contains_reference(&REFERENCE.replace_all(s, "")) {
+                return Err(format!(
+                    "args.{}: malformed step reference in '{}'; write {{{{steps.<id>.output.<field>}}}}",
+                    arg, s
+                ));
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_references(arg, item, found)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values() {
+                collect_references(arg, item, found)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Point references at steps by new ids, given as old id -> new id
+pub fn rename_steps(action: &mut Action, renamed: &HashMap<String, String>) {
+    for value in action.args.values_mut() {
+        rename_in_value(value, renamed);
+    }
+}
+
+fn rename_in_value(value: &mut serde_json::Value, renamed: &HashMap<String, String>) {
+    match value {
+        serde_json::Value::String(s) if contains_reference(s) => {
+            let replaced = REFERENCE.replace_all(s, |captures: &Captures| match renamed.get(&captures[1]) {
+                Some(id) => format!("{{{{steps.{}.output{}}}}}", id, &captures[2]),
+                None => captures[0].to_string(),
+            });
+            *s = replaced.into_owned();
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                rename_in_value(item, renamed);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values_mut() {
+                rename_in_value(item, renamed);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The reference a string consists of, if it is exactly one reference
+fn whole_reference(arg: &str, value: &str) -> Option<StepReference> {
+    let captures = REFERENCE.captures(value.trim())?;
+    (captures[0].len() == value.trim().len()).then(|| StepReference::from_captures(arg, &captures))
+}
+
+/// Order a plan so every step runs after the steps whose output it uses, keeping
+/// the written order otherwise. Fails on references to unknown steps, to the step
+/// itself, or in a cycle. Returns the ordered plan and a note per step that waits.
+pub fn order_plan(plan: &ActionPlan) -> Result<(ActionPlan, Vec<String>), String> {
+    let actions = &plan.schema.actions;
+    let mut dependencies: Vec<Vec<usize>> = Vec::with_capacity(actions.len());
+
+    for (index, action) in actions.iter().enumerate() {
+        let mut step_dependencies = Vec::new();
+        for reference in action_references(action).map_err(|e| format!("actions[{}].{}", index, e))? {
+            let matching: Vec<usize> = actions
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| a.id == reference.step_id)
+                .map(|(i, _)| i)
+                .collect();
+            let at = || format!("actions[{}].args.{}: {}", index, reference.arg, reference.text);
+
+            match matching.as_slice() {
+                [] => {
+                    return Err(format!(
+                        "{} refers to step '{}', which is not in the plan",
+                        at(),
+                        reference.step_id
+                    ))
+                }
+                [dependency] if *dependency == index => {
+                    return Err(format!("{} refers to the output of its own step", at()))
+                }
+                [dependency] => {
+                    if !step_dependencies.contains(dependency) {
+                        step_dependencies.push(*dependency);
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "{} is ambiguous: more than one step has the id '{}'",
+                        at(),
+                        reference.step_id
+                    ))
+                }
+            }
+        }
+        dependencies.push(step_dependencies);
+    }
+
+    if let Some(cycle) = find_cycle(&dependencies) {
+        let steps: Vec<String> = cycle.iter().map(|&i| format!("'{}'", actions[i].id)).collect();
+        return Err(format!(
+            "Steps {} use each other's output in a cycle, so they cannot be ordered",
+            steps.join(" -> ")
+        ));
+    }
+
+    // Always run the earliest written step whose dependencies have run
+    let mut order = Vec::with_capacity(actions.len());
+    let mut placed = vec![false; actions.len()];
+    while order.len() < actions.len() {
+        let next = (0..actions.len())
+            .find(|&i| !placed[i] && dependencies[i].iter().all(|&d| placed[d]))
+            .ok_or("Failed to order the plan's steps")?;
+        placed[next] = true;
+        order.push(next);
+    }
+
+    // Only steps that wait for a later step's output move back
+    let notes = order
+        .iter()
+        .enumerate()
+        .filter(|&(position, &index)| position > index)
+        .map(|(position, &index)| {
+            format!(
+                "Step {} ({}) runs as step {}, after the steps whose output it uses",
+                index + 1,
+                actions[index].action_type.name(),
+                position + 1
+            )
+        })
+        .collect();
+
+    let mut ordered = plan.clone();
+    ordered.schema.actions = order.iter().map(|&i| actions[i].clone()).collect();
+    Ok((ordered, notes))
+}
+
+/// A cycle of steps that each use the next one's output, ending where it starts
+fn find_cycle(dependencies: &[Vec<usize>]) -> Option<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        New,
+        OnStack,
+        Done,
+    }
+
+    fn visit(step: usize, dependencies: &[Vec<usize>], marks: &mut [Mark], stack: &mut Vec<usize>) -> Option<Vec<usize>> {
+        marks[step] = Mark::OnStack;
+        stack.push(step);
+        for &dependency in &dependencies[step] {
+            match marks[dependency] {
+                // Reached again before finishing: everything on the stack from there is a cycle
+                Mark::OnStack => {
+                    let start = stack.iter().position(|&s| s == dependency).unwrap_or(0);
+                    let mut cycle = stack[start..].to_vec();
+                    cycle.push(dependency);
+                    return Some(cycle);
+                }
+                Mark::New => {
+                    if let Some(cycle) = visit(dependency, dependencies, marks, stack) {
+                        return Some(cycle);
+                    }
+                }
+                Mark::Done => {}
+            }
+        }
+        stack.pop();
+        marks[step] = Mark::Done;
+        None
+    }
+
+    let mut marks = vec![Mark::New; dependencies.len()];
+    let mut stack = Vec::new();
+    (0..dependencies.len()).find_map(|step| {
+        if marks[step] == Mark::New {
+            visit(step, dependencies, &mut marks, &mut stack)
+        } else {
+            None
+        }
+    })
+}
+
+/// The value a reference stands for, given the outputs of the steps that ran
+pub fn resolve(reference: &StepReference, outputs: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value, String> {
+    let mut value = outputs.get(&reference.step_id).ok_or_else(|| {
+        format!("{} refers to step '{}', which has no output yet", reference.text, reference.step_id)
+    })?;
+
+    for key in &reference.field {
+        let next = match value {
+            serde_json::Value::Object(map) => map.get(key),
+            serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        };
+        value = next.ok_or_else(|| {
+            format!(
+                "{}: step '{}' has no {}",
+                reference.text,
+                reference.step_id,
+                reference.field_name()
+            )
+        })?;
+    }
+
+    if value.is_null() {
+        return Err(format!(
+            "{}: {} of step '{}' is empty",
+            reference.text,
+            reference.field_name(),
+            reference.step_id
+        ));
+    }
+    Ok(value.clone())
+}
+
+/// Text a resolved value takes inside a longer string
+fn resolved_text(reference: &StepReference, value: serde_json::Value) -> Result<String, String> {
+    match value {
+        serde_json::Value::String(s) => Ok(s),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        serde_json::Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(format!(
+            "{}: {} of step '{}' is not text, so it cannot be part of a string",
+            reference.text,
+            reference.field_name(),
+            reference.step_id
+        )),
+    }
+}
+
+/// How a resolved reference reads in the preview
+pub fn display_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// An action with every reference in its args replaced by what it resolves to.
+/// An arg that is a single reference takes the output value as it is; references
+/// inside a longer string are replaced by their text.
+pub fn substitute(action: &Action, outputs: &HashMap<String, serde_json::Value>) -> Result<Action, String> {
+    let mut resolved = action.clone();
+    for (arg, value) in resolved.args.iter_mut() {
+        substitute_value(arg, value, outputs).map_err(|e| format!("args.{}: {}", arg, e))?;
+    }
+    Ok(resolved)
+}
+
+fn substitute_value(arg: &str, value: &mut serde_json::Value, outputs: &HashMap<String, serde_json::Value>) -> Result<(), String> {
+    match value {
+        serde_json::Value::String(s) if contains_reference(s) => {
+            if let Some(reference) = whole_reference(arg, s) {
+                *value = resolve(&reference, outputs)?;
+                return Ok(());
+            }
+
+            let mut error = None;
+            let replaced = REFERENCE.replace_all(s, |captures: &Captures| {
+                let reference = StepReference::from_captures(arg, captures);
+                match resolve(&reference, outputs).and_then(|v| resolved_text(&reference, v)) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        error.get_or_insert(e);
+                        String::new()
+                    }
+                }
+            });
+            if let Some(e) = error {
+                return Err(e);
+            }
+            *s = replaced.into_owned();
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                substitute_value(arg, item, outputs)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values_mut() {
+                substitute_value(arg, item, outputs)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The part of a step's output that its args tell before it runs:
+/// `path` (what it works on or writes), `source` and `destination`.
+/// A search's `path` is its best match, which is only known once it has run.
+pub fn predicted_output(action: &Action) -> serde_json::Value {
+    let arg = |key: &str| action.args.get(key).filter(|v| v.is_string()).cloned();
+    let mut output = serde_json::Map::new();
+
+    if action.action_type != ActionType::FsSearch {
+        if let Some(path) = arg("path").or_else(|| arg("destination_path")) {
+            output.insert("path".to_string(), path);
+        }
+    }
+    if let Some(source) = arg("source_path") {
+        output.insert("source".to_string(), source);
+    }
+    if let Some(destination) = arg("destination_path") {
+        output.insert("destination".to_string(), destination);
+    }
+    serde_json::Value::Object(output)
+}
+
+/// The plan with every reference that the earlier steps' args already tell replaced,
+/// for checks made before it runs. References to outputs only known at run time stay.
+pub fn resolve_statically(plan: &ActionPlan) -> ActionPlan {
+    let mut resolved = plan.clone();
+    let mut outputs = HashMap::new();
+
+    for action in resolved.schema.actions.iter_mut() {
+        if let Ok(substituted) = substitute(action, &outputs) {
+            *action = substituted;
+        }
+        outputs.insert(action.id.clone(), predicted_output(action));
+    }
+    resolved
+}
+
+/// A path known before the plan runs that is, or contains, whatever a path arg
+/// resolves to: the arg itself without references, the folder written before the
+/// first reference, or for a whole reference the path the referenced step works on.
+/// None if that cannot be told.
+pub fn static_path(actions: &[Action], action: &Action, arg: &str) -> Option<String> {
+    static_location(actions, action, arg).map(|(path, _)| path)
+}
+
+/// Like `static_path`, and whether that is the path the arg resolves to itself
+/// rather than a folder it lies in (e.g. the root of a search it is a result of)
+pub fn static_location(actions: &[Action], action: &Action, arg: &str) -> Option<(String, bool)> {
+    static_location_within(actions, action, arg, actions.len())
+}
+
+fn static_location_within(actions: &[Action], action: &Action, arg: &str, depth: usize) -> Option<(String, bool)> {
+    let value = action.args.get(arg)?.as_str()?;
+    let Some(start) = REFERENCE_START.find(value).map(|m| m.start()) else {
+        return Some((value.to_string(), true));
+    };
+
+    if start > 0 {
+        // "/home/me/Tax/{{...}}" lies in /home/me/Tax
+        let prefix = &value[..start];
+        let dir = match prefix.strip_suffix('/') {
+            Some(dir) => dir.to_string(),
+            None => Path::new(prefix).parent()?.to_string_lossy().to_string(),
+        };
+        return (!dir.is_empty()).then_some((dir, false));
+    }
+
+    let reference = whole_reference(arg, value)?;
+    let step = actions.iter().find(|a| a.id == reference.step_id)?;
+    let step_arg = output_arg(step, &reference.field)?;
+    if depth == 0 {
+        return None;
+    }
+    // A path in a lookup's results or entries lies below the path it looked at
+    let (path, exact) = static_location_within(actions, step, step_arg, depth - 1)?;
+    Some((path, exact && reference.field.len() == 1))
+}
+
+/// The arg of a step that a path in its output equals or lies in
+fn output_arg(step: &Action, field: &[String]) -> Option<&'static str> {
+    let lookup = matches!(
+        step.action_type,
+        ActionType::FsSearch | ActionType::FsListDirectory | ActionType::FsStat | ActionType::FsReadFile
+    );
+    let field: Vec<&str> = field.iter().map(String::as_str).collect();
+
+    match field.as_slice() {
+        // Lookups only report paths at or below the path they look at
+        ["results" | "entries", _, "path"] if lookup => Some("path"),
+        ["path"] if step.args.contains_key("path") => Some("path"),
+        ["path" | "destination"] => Some("destination_path"),
+        ["source"] => Some("source_path"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(id: &str, action_type: ActionType, args: serde_json::Value) -> Action {
+        Action {
+            id: id.to_string(),
+            action_type,
+            args: serde_json::from_value(args).unwrap(),
+            preconditions: None,
+            metadata: None,
+        }
+    }
+
+    fn plan(actions: Vec<Action>) -> ActionPlan {
+        ActionPlan {
+            schema: ActionSchema {
+                id: "plan".to_string(),
+                origin: ActionOrigin {
+                    user_input: String::new(),
+                    source: ActionSource::Ui,
+                    request_id: String::new(),
+                },
+                actions,
+                summary: String::new(),
+                risk_score: 0.1,
+                dry_run: true,
+                escalated_paths: Vec::new(),
+            },
+        }
+    }
+
+    fn ids(plan: &ActionPlan) -> Vec<&str> {
+        plan.schema.actions.iter().map(|a| a.id.as_str()).collect()
+    }
+
+    #[test]
+    fn steps_run_after_the_steps_they_use() {
+        let plan = plan(vec![
+            action("copy", ActionType::FsCopyFile, serde_json::json!({
+                "source_path": "{{steps.find.output.results.0.path}}",
+                "destination_path": "/tmp/out/{{steps.dir.output.path}}",
+            })),
+            action("other", ActionType::FsCreateFile, serde_json::json!({"path": "/tmp/other.txt"})),
+            action("find", ActionType::FsSearch, serde_json::json!({"path": "/tmp", "name": "a"})),
+            action("dir", ActionType::FsCreateDirectory, serde_json::json!({"path": "/tmp/out"})),
+        ]);
+
+        let (ordered, notes) = order_plan(&plan).unwrap();
+        assert_eq!(ids(&ordered), ["other", "find", "dir", "copy"]);
+        assert_eq!(notes, ["Step 1 (fs_copy_file) runs as step 4, after the steps whose output it uses"]);
+    }
+
+    #[test]
+    fn written_order_is_kept_without_forward_references() {
+        let plan = plan(vec![
+            action("find", ActionType::FsSearch, serde_json::json!({"path": "/tmp", "name": "a"})),
+            action("read", ActionType::FsReadFile, serde_json::json!({"path": "{{steps.find.output.path}}"})),
+            action("other", ActionType::FsCreateFile, serde_json::json!({"path": "/tmp/other.txt"})),
+        ]);
+
+        let (ordered, notes) = order_plan(&plan).unwrap();
+        assert_eq!(ids(&ordered), ["find", "read", "other"]);
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_steps() {
+        let plan = plan(vec![
+            action("a", ActionType::FsCreateFile, serde_json::json!({"path": "{{steps.c.output.path}}"})),
+            action("b", ActionType::FsCreateFile, serde_json::json!({"path": "{{steps.a.output.path}}"})),
+            action("c", ActionType::FsCreateFile, serde_json::json!({"path": "{{steps.b.output.path}}"})),
+        ]);
+
+        assert_eq!(
+            order_plan(&plan).unwrap_err(),
+            "Steps 'a' -> 'c' -> 'b' -> 'a' use each other's output in a cycle, so they cannot be ordered"
+        );
+    }
+
+    #[test]
+    fn find_cycle_ignores_shared_dependencies() {
+        // Two steps using the same earlier step is a diamond, not a cycle
+        assert_eq!(find_cycle(&[vec![], vec![0], vec![0], vec![1, 2]]), None);
+        assert_eq!(find_cycle(&[vec![1], vec![0]]), Some(vec![0, 1, 0]));
+        assert_eq!(find_cycle(&[vec![], vec![2], vec![1]]), Some(vec![1, 2, 1]));
+    }
+
+    #[test]
+    fn bad_references_are_refused() {
+        let unknown = plan(vec![action("a", ActionType::FsReadFile, serde_json::json!({"path": "{{steps.x.output.path}}"}))]);
+        assert!(order_plan(&unknown).unwrap_err().contains("refers to step 'x', which is not in the plan"));
+
+        let own = plan(vec![action("a", ActionType::FsReadFile, serde_json::json!({"path": "{{steps.a.output.path}}"}))]);
+        assert!(order_plan(&own).unwrap_err().contains("refers to the output of its own step"));
+
+        let malformed = plan(vec![action("a", ActionType::FsReadFile, serde_json::json!({"path": "{{steps.b.path"}))]);
+        assert!(order_plan(&malformed).unwrap_err().contains("malformed step reference"));
+    }
+
+    #[test]
+    fn whole_references_keep_the_output_value() {
+        let outputs = HashMap::from([(
+            "list".to_string(),
+            serde_json::json!({"entries": [{"path": "/tmp/a.txt", "size": 3}], "count": 1}),
+        )]);
+        let read = action("read", ActionType::FsReadFile, serde_json::json!({
+            "path": "{{ steps.list.output.entries.0.path }}",
+            "length": "{{steps.list.output.entries.0.size}}",
+        }));
+
+        let resolved = substitute(&read, &outputs).unwrap();
+        assert_eq!(resolved.args["path"], "/tmp/a.txt");
+        assert_eq!(resolved.args["length"], 3);
+    }
+
+    #[test]
+    fn embedded_references_are_replaced_by_their_text() {
+        let outputs = HashMap::from([
+            ("dir".to_string(), serde_json::json!({"path": "/tmp/out"})),
+            ("list".to_string(), serde_json::json!({"count": 2, "entries": []})),
+        ]);
+        let create = action("create", ActionType::FsCreateFile, serde_json::json!({
+            "path": "{{steps.dir.output.path}}/report-{{steps.list.output.count}}.txt",
+        }));
+        assert_eq!(substitute(&create, &outputs).unwrap().args["path"], "/tmp/out/report-2.txt");
+
+        let embedded_list = action("create", ActionType::FsCreateFile, serde_json::json!({
+            "path": "/tmp/{{steps.list.output.entries}}.txt",
+        }));
+        assert!(substitute(&embedded_list, &outputs).unwrap_err().contains("is not text"));
+    }
+
+    #[test]
+    fn missing_outputs_are_errors() {
+        let outputs = HashMap::from([("dir".to_string(), serde_json::json!({"path": null}))]);
+        let on = |path: &str| action("a", ActionType::FsReadFile, serde_json::json!({"path": path}));
+
+        assert!(substitute(&on("{{steps.other.output.path}}"), &outputs).unwrap_err().contains("has no output yet"));
+        assert!(substitute(&on("{{steps.dir.output.size}}"), &outputs).unwrap_err().contains("has no output.size"));
+        assert!(substitute(&on("{{steps.dir.output.path}}"), &outputs).unwrap_err().contains("is empty"));
+    }
+
+    #[test]
+    fn static_resolution_fills_in_what_earlier_args_tell() {
+        let plan = plan(vec![
+            action("dir", ActionType::FsCreateDirectory, serde_json::json!({"path": "/tmp/out"})),
+            action("copy", ActionType::FsCopyFile, serde_json::json!({
+                "source_path": "/tmp/a.txt",
+                "destination_path": "{{steps.dir.output.path}}/a.txt",
+            })),
+            action("find", ActionType::FsSearch, serde_json::json!({"path": "/tmp", "name": "b"})),
+            action("read", ActionType::FsReadFile, serde_json::json!({"path": "{{steps.copy.output.destination}}"})),
+            action("open", ActionType::FsReadFile, serde_json::json!({"path": "{{steps.find.output.path}}"})),
+        ]);
+
+        let resolved = resolve_statically(&plan);
+        let arg = |step: usize, key: &str| resolved.schema.actions[step].args[key].clone();
+        assert_eq!(arg(1, "destination_path"), "/tmp/out/a.txt");
+        assert_eq!(arg(3, "path"), "/tmp/out/a.txt");
+        // A search's best match is only known once it has run
+        assert_eq!(arg(4, "path"), "{{steps.find.output.path}}");
+    }
+
+    #[test]
+    fn static_location_tells_exact_paths_from_folders() {
+        let actions = vec![
+            action("find", ActionType::FsSearch, serde_json::json!({"path": "/tmp/docs", "name": "a"})),
+            action("create", ActionType::FsCreateFile, serde_json::json!({"path": "/tmp/new.txt"})),
+            action("delete", ActionType::FsDeleteFile, serde_json::json!({"path": "{{steps.find.output.results.0.path}}"})),
+            action("undo", ActionType::FsDeleteFile, serde_json::json!({"path": "{{steps.create.output.path}}"})),
+            action("into", ActionType::FsCreateFile, serde_json::json!({"path": "/tmp/out/{{steps.find.output.count}}.txt"})),
+        ];
+        let location = |step: usize| static_location(&actions, &actions[step], "path");
+
+        assert_eq!(location(1), Some(("/tmp/new.txt".to_string(), true)));
+        assert_eq!(location(2), Some(("/tmp/docs".to_string(), false)));
+        assert_eq!(location(3), Some(("/tmp/new.txt".to_string(), true)));
+        assert_eq!(location(4), Some(("/tmp/out".to_string(), false)));
+    }
+}
@@ -1,8 +1,459 @@
-// Placeholder for sandboxing implementation
-// Full implementation would add OS-specific sandboxing
+use crate::assistant::executor::fs_adapter;
+use crate::assistant::types::*;
 
-pub fn setup_sandbox() -> Result<(), String> {
-    // Placeholder: no sandboxing in MVP
-    Ok(())
+/// Command-line flag that starts the app binary as a sandboxed action worker
+pub const WORKER_ARG: &str = "--assistant-sandbox-worker";
+
+/// Runs the actions of one plan.
+/// On Linux every action is executed by a child process confined with
+/// Landlock (only the plan's paths) and seccomp (no network, no exec);
+/// other platforms execute in-process.
+pub enum ActionRunner {
+    #[allow(dead_code)]
+    InProcess(ExecutionContext),
+    #[cfg(target_os = "linux")]
+    Sandboxed(linux::SandboxChild),
+}
+
+impl ActionRunner {
+    pub fn start(plan: &ActionPlan, ctx: &ExecutionContext) -> Result<Self, String> {
+        #[cfg(target_os = "linux")]
+        {
+            linux::SandboxChild::spawn(plan, ctx, false).map(ActionRunner::Sandboxed)
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = plan;
+            Ok(ActionRunner::InProcess(ctx.clone()))
+        }
+    }
+
+    /// Like `start`, but the worker may only read the plan's paths.
+    /// Used to run lookups during a dry run.
+    pub fn start_read_only(plan: &ActionPlan, ctx: &ExecutionContext) -> Result<Self, String> {
+        #[cfg(target_os = "linux")]
+        {
+            linux::SandboxChild::spawn(plan, ctx, true).map(ActionRunner::Sandboxed)
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = plan;
+            Ok(ActionRunner::InProcess(ctx.clone()))
+        }
+    }
+
+    pub fn run(&mut self, action: &Action) -> Result<ActionExecutionResult, String> {
+        match self {
+            ActionRunner::InProcess(ctx) => fs_adapter::execute_fs_action(action, ctx),
+            #[cfg(target_os = "linux")]
+            ActionRunner::Sandboxed(child) => child.run(action),
+        }
+    }
+}
+
+/// A note for the preview when this system cannot fully confine file actions
+pub fn confinement_warning() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::confinement_warning()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Check whether this process was started as a sandbox worker and, if so,
+/// serve requests until the parent closes the pipe.
+/// Returns `true` if the process ran as a worker and should exit.
+pub fn run_worker_if_requested() -> bool {
+    if std::env::args().nth(1).as_deref() != Some(WORKER_ARG) {
+        return false;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if let Err(e) = linux::serve() {
+            eprintln!("Assistant sandbox worker failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        eprintln!("Assistant sandbox worker is only supported on Linux");
+        std::process::exit(1);
+    }
+
+    true
 }
 
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+    use crate::assistant::executor::trash;
+    use crate::assistant::references;
+    use crate::assistant::validator::{self, PathAccess};
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, BitFlags, CompatLevel, Compatible, Ruleset, RulesetAttr,
+        RulesetCreatedAttr, RulesetStatus, ABI,
+    };
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::path::{Path, PathBuf};
+    use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+    /// Landlock ABI the rules are written against; older kernels enforce what they support
+    const LANDLOCK_ABI: ABI = ABI::V3;
+
+    /// Landlock ABI version of the running kernel, None if Landlock is unavailable
+    fn kernel_landlock_abi() -> Option<i64> {
+        // SAFETY: with a null attr, zero size and the version flag the call only reports the ABI
+        let version = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<libc::c_void>(),
+                0usize,
+                1u32, // LANDLOCK_CREATE_RULESET_VERSION
+            )
+        };
+        (version > 0).then_some(version)
+    }
+
+    pub fn confinement_warning() -> Option<String> {
+        match kernel_landlock_abi() {
+            None => Some("Landlock is not available on this system, so file actions will be refused".to_string()),
+            Some(version) if version < LANDLOCK_ABI as i64 => Some(format!(
+                "This kernel only supports Landlock ABI {}, so file actions are only partially confined",
+                version
+            )),
+            Some(_) => None,
+        }
+    }
+
+    /// First message sent to the worker: what it may touch
+    #[derive(Serialize, Deserialize)]
+    struct SandboxSetup {
+        ctx: ExecutionContext,
+        actions: Vec<Action>,
+        /// Only grant the read rules
+        read_only: bool,
+    }
+
+    /// Reply to a single action request
+    #[derive(Serialize, Deserialize)]
+    struct SandboxReply {
+        result: Result<ActionExecutionResult, String>,
+    }
+
+    /// Parent-side handle to a running worker
+    pub struct SandboxChild {
+        child: Child,
+        stdin: ChildStdin,
+        stdout: BufReader<ChildStdout>,
+        /// Parent directories created for each action before the worker was confined,
+        /// until the action reports them as its own
+        created_dirs: Vec<(String, Vec<String>)>,
+    }
+
+    impl SandboxChild {
+        pub fn spawn(plan: &ActionPlan, ctx: &ExecutionContext, read_only: bool) -> Result<Self, String> {
+            let exe = std::env::current_exe()
+                .map_err(|e| format!("Failed to locate app binary for sandbox: {}", e))?;
+
+            let mut child = Command::new(exe)
+                .arg(WORKER_ARG)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::inherit())
+                .spawn()
+                .map_err(|e| format!("Failed to start sandbox worker: {}", e))?;
+
+            let stdin = child.stdin.take().ok_or("Sandbox worker has no stdin")?;
+            let stdout = child.stdout.take().ok_or("Sandbox worker has no stdout")?;
+
+            let mut sandbox = Self {
+                child,
+                stdin,
+                stdout: BufReader::new(stdout),
+                created_dirs: Vec::new(),
+            };
+
+            // Landlock rules attach to existing directories, so create the ones writes go into first
+            if !read_only {
+                sandbox.create_parents(&plan.schema.actions, ctx)?;
+            }
+
+            sandbox.send(&SandboxSetup {
+                ctx: ctx.clone(),
+                actions: plan.schema.actions.clone(),
+                read_only,
+            })?;
+
+            Ok(sandbox)
+        }
+
+        /// Create the missing parent directory of every path the plan writes
+        fn create_parents(&mut self, actions: &[Action], ctx: &ExecutionContext) -> Result<(), String> {
+            for action in actions {
+                let mut created = Vec::new();
+                for (arg, access) in validator::path_accesses(&action.action_type) {
+                    if *access != PathAccess::Write {
+                        continue;
+                    }
+                    let Some(path_str) = references::static_path(actions, action, arg) else {
+                        continue;
+                    };
+                    let Some(parent) = Path::new(&path_str).parent().filter(|p| !p.exists()) else {
+                        continue;
+                    };
+                    validator::check_access(ctx, &parent.to_string_lossy(), PathAccess::Write)?;
+                    created.extend(fs_adapter::create_dirs(parent)?);
+                }
+                if !created.is_empty() {
+                    self.created_dirs.push((action.id.clone(), created));
+                }
+            }
+            Ok(())
+        }
+
+        pub fn run(&mut self, action: &Action) -> Result<ActionExecutionResult, String> {
+            self.send(action)?;
+
+            let mut line = String::new();
+            let read = self
+                .stdout
+                .read_line(&mut line)
+                .map_err(|e| format!("Failed to read from sandbox worker: {}", e))?;
+            if read == 0 {
+                return Err("Sandbox worker exited unexpectedly".to_string());
+            }
+
+            let reply: SandboxReply = serde_json::from_str(&line)
+                .map_err(|e| format!("Invalid reply from sandbox worker: {}", e))?;
+            let mut result = reply.result?;
+
+            // Hand the directories created for this action to it, so undoing it removes them
+            let index = self.created_dirs.iter().position(|(id, _)| *id == action.id);
+            let recorded = result
+                .output
+                .as_mut()
+                .and_then(|o| o.get_mut("created_dirs"))
+                .and_then(|v| v.as_array_mut());
+            if let (Some(index), Some(recorded)) = (index, recorded) {
+                let (_, dirs) = self.created_dirs.remove(index);
+                recorded.splice(0..0, dirs.into_iter().map(serde_json::Value::String));
+            }
+
+            Ok(result)
+        }
+
+        fn send<T: Serialize>(&mut self, message: &T) -> Result<(), String> {
+            let line = serde_json::to_string(message)
+                .map_err(|e| format!("Failed to serialize sandbox request: {}", e))?;
+            writeln!(self.stdin, "{}", line)
+                .and_then(|_| self.stdin.flush())
+                .map_err(|e| format!("Failed to write to sandbox worker: {}", e))
+        }
+    }
+
+    impl Drop for SandboxChild {
+        fn drop(&mut self) {
+            // Closing stdin ends the worker loop; kill in case it is stuck
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+
+            // Directories no action took over are removed again if still empty
+            for dir in self.created_dirs.iter().flat_map(|(_, dirs)| dirs).rev() {
+                let _ = fs::remove_dir(dir);
+            }
+        }
+    }
+
+    /// Worker side: read the setup, lock the process down, then execute
+    /// one action per line until stdin is closed.
+    pub fn serve() -> Result<(), String> {
+        let stdin = std::io::stdin();
+        let mut lines = stdin.lock().lines();
+        let mut stdout = std::io::stdout();
+
+        let setup_line = lines
+            .next()
+            .ok_or("Missing sandbox setup")?
+            .map_err(|e| format!("Failed to read sandbox setup: {}", e))?;
+        let setup: SandboxSetup = serde_json::from_str(&setup_line)
+            .map_err(|e| format!("Invalid sandbox setup: {}", e))?;
+
+        apply_landlock(&setup.actions, setup.read_only)?;
+        apply_seccomp()?;
+
+        for line in lines {
+            let line = line.map_err(|e| format!("Failed to read sandbox request: {}", e))?;
+            let result = serde_json::from_str::<Action>(&line)
+                .map_err(|e| format!("Invalid sandbox request: {}", e))
+                .and_then(|action| {
+                    // Only actions from the approved plan may run here
+                    if !setup.actions.iter().any(|a| a.id == action.id) {
+                        return Err(format!("Action {} is not part of the sandboxed plan", action.id));
+                    }
+                    fs_adapter::execute_fs_action(&action, &setup.ctx)
+                });
+
+            let reply = serde_json::to_string(&SandboxReply { result })
+                .map_err(|e| format!("Failed to serialize sandbox reply: {}", e))?;
+            writeln!(stdout, "{}", reply)
+                .and_then(|_| stdout.flush())
+                .map_err(|e| format!("Failed to write sandbox reply: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Restrict the filesystem to the paths named by the plan.
+    /// Reads get read access to the path itself. Writes get full access to the
+    /// target if it exists, and create rights in its parent unless an existing
+    /// file is written in place; replacing the target also needs remove rights there.
+    /// Removals get the target and remove rights in its parent.
+    /// A path taken from an earlier step's output is allowed by the folder it will be in,
+    /// without rights on that folder's parent.
+    fn apply_landlock(actions: &[Action], read_only: bool) -> Result<(), String> {
+        let read = AccessFs::from_read(LANDLOCK_ABI);
+        let create = read
+            | AccessFs::WriteFile
+            | AccessFs::Truncate
+            | AccessFs::MakeReg
+            | AccessFs::MakeDir
+            | AccessFs::MakeSym
+            | AccessFs::Refer;
+        let remove = read | AccessFs::RemoveFile | AccessFs::RemoveDir | AccessFs::Refer;
+        let mut rules: Vec<(PathBuf, BitFlags<AccessFs>)> = Vec::new();
+
+        for action in actions {
+            for (arg, access) in validator::path_accesses(&action.action_type) {
+                // A folder the target lies in covers everything below it; only the target itself needs its parent
+                let Some((path_str, exact)) = references::static_location(actions, action, arg) else {
+                    continue;
+                };
+                let path = validator::resolve_real_path(Path::new(&path_str));
+                let parent = path.parent().unwrap_or(&path).to_path_buf();
+
+                match access {
+                    PathAccess::Read => rules.push((path, read)),
+                    _ if read_only => {}
+                    PathAccess::Write => {
+                        let in_place = path.is_file() && writes_in_place(action);
+                        let replaces = OnConflict::of(action).is_ok_and(|p| p == OnConflict::Overwrite);
+                        rules.push((path, AccessFs::from_all(LANDLOCK_ABI)));
+                        if exact && !in_place {
+                            rules.push((parent, if replaces { create | remove } else { create }));
+                        }
+                    }
+                    PathAccess::Remove => {
+                        rules.push((path, AccessFs::from_all(LANDLOCK_ABI)));
+                        if exact {
+                            rules.push((parent, remove));
+                        }
+                    }
+                }
+            }
+
+            if !read_only {
+                rules.extend(trash_paths(actions, action).into_iter().map(|p| (p, AccessFs::from_all(LANDLOCK_ABI))));
+            }
+        }
+
+        // Missing paths get no rule: nothing can be read there, and writes go through the parent.
+        // Older kernels drop the rights they do not know; the preview warns about that.
+        let status = Ruleset::default()
+            .set_compatibility(CompatLevel::BestEffort)
+            .handle_access(AccessFs::from_all(LANDLOCK_ABI))
+            .and_then(|ruleset| ruleset.create())
+            .and_then(|ruleset| {
+                ruleset.add_rules(rules.iter().flat_map(|(path, access)| path_beneath_rules([path], *access)))
+            })
+            .and_then(|ruleset| ruleset.restrict_self())
+            .map_err(|e| format!("Failed to apply Landlock rules: {}", e))?;
+
+        if status.ruleset == RulesetStatus::NotEnforced {
+            return Err("Landlock is not supported by this kernel; refusing to run unconfined".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Whether an action writes an existing file in place, so access to that file is enough.
+    /// Renaming on conflict writes beside it, and a directory copy or move replaces it.
+    fn writes_in_place(action: &Action) -> bool {
+        let renames = OnConflict::of(action).is_ok_and(|policy| policy == OnConflict::Rename);
+        let replaces = matches!(action.action_type, ActionType::FsCopyDirectory | ActionType::FsMoveDirectory);
+        !renames && !replaces
+    }
+
+    /// Trash directories a delete or restore needs (files/ and info/ both live under it)
+    fn trash_paths(actions: &[Action], action: &Action) -> Vec<PathBuf> {
+        let arg = |key: &str| references::static_path(actions, action, key).map(PathBuf::from);
+
+        match action.action_type {
+            ActionType::FsDeleteFile | ActionType::FsDeleteDirectory | ActionType::FsRestoreFromTrash => {
+                let mut paths: Vec<PathBuf> = arg("path")
+                    .and_then(|path| trash::trash_dir_for(&path))
+                    .into_iter()
+                    .collect();
+                // <trash>/files/<name> -> <trash>, only for an item that really is in a trash
+                let item = arg("trash_path").and_then(|p| trash::trashed_item(&p).ok());
+                if let Some(root) = item.and_then(|p| p.parent()?.parent().map(Path::to_path_buf)) {
+                    paths.push(root);
+                }
+                paths
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Deny networking, exec and process tampering; everything else is allowed
+    fn apply_seccomp() -> Result<(), String> {
+        let denied = [
+            libc::SYS_socket,
+            libc::SYS_connect,
+            libc::SYS_bind,
+            libc::SYS_listen,
+            libc::SYS_accept,
+            libc::SYS_accept4,
+            libc::SYS_execve,
+            libc::SYS_execveat,
+            libc::SYS_ptrace,
+            libc::SYS_process_vm_writev,
+            libc::SYS_mount,
+            libc::SYS_umount2,
+            libc::SYS_bpf,
+            libc::SYS_kexec_load,
+        ];
+        let rules: BTreeMap<i64, Vec<seccompiler::SeccompRule>> =
+            denied.iter().map(|syscall| (*syscall, Vec::new())).collect();
+
+        let arch = std::env::consts::ARCH
+            .try_into()
+            .map_err(|e| format!("Unsupported architecture for seccomp: {}", e))?;
+
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::EPERM as u32),
+            arch,
+        )
+        .map_err(|e| format!("Failed to build seccomp filter: {}", e))?;
+
+        let program: BpfProgram = filter
+            .try_into()
+            .map_err(|e| format!("Failed to compile seccomp filter: {}", e))?;
+
+        seccompiler::apply_filter(&program)
+            .map_err(|e| format!("Failed to apply seccomp filter: {}", e))
+    }
+}
@@ -0,0 +1,474 @@
+use crate::assistant::executor::{archive, content};
+use crate::assistant::types::*;
+use schemars::{json_schema, generate::SchemaSettings, JsonSchema, Schema, SchemaGenerator};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Args of fs_create_file
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateFileArgs {
+    /// Absolute path of the file
+    pub path: String,
+    /// File content, encoded per `encoding`
+    pub content: Option<String>,
+    /// utf-8 (default), base64 or latin-1
+    pub encoding: Option<content::Encoding>,
+}
+
+/// Args of fs_read_file
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReadFileArgs {
+    /// Absolute path of the file
+    pub path: String,
+    /// Byte offset to start reading at
+    pub offset: Option<u64>,
+    /// Number of bytes to read
+    pub length: Option<u64>,
+    /// utf-8, base64 or latin-1; omit to get text as utf-8 and binaries as base64
+    pub encoding: Option<content::Encoding>,
+}
+
+/// Args of the copy and move actions
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TransferArgs {
+    /// Absolute source path
+    pub source_path: String,
+    /// Absolute destination path
+    pub destination_path: String,
+    /// What to do if the destination exists: fail (default), overwrite (snapshotted first so undo restores it), rename (numbered suffix, e.g. "report (1).pdf") or skip
+    pub on_conflict: Option<OnConflict>,
+}
+
+/// Args of fs_delete_file and fs_delete_directory
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DeleteArgs {
+    /// Absolute path to delete
+    pub path: String,
+    /// Skip the trash; only when the user explicitly asks
+    pub permanent: Option<bool>,
+}
+
+/// Args of fs_create_directory
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateDirectoryArgs {
+    /// Absolute path of the directory
+    pub path: String,
+}
+
+/// Args of fs_list_directory
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ListDirectoryArgs {
+    /// Absolute path of the directory
+    pub path: String,
+    /// Include subdirectories
+    pub recursive: Option<bool>,
+}
+
+/// Args of fs_append_file
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AppendFileArgs {
+    /// Absolute path of an existing file
+    pub path: String,
+    /// Text to append
+    pub content: String,
+}
+
+/// Args of fs_replace_text
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReplaceTextArgs {
+    /// Absolute path of an existing file
+    pub path: String,
+    /// Text or regex to find
+    pub pattern: String,
+    /// Replacement text; a regex replacement may use `$1`
+    pub replacement: String,
+    /// Treat `pattern` as a regex
+    pub regex: Option<bool>,
+    /// Fail unless exactly this many matches are found
+    pub expected_matches: Option<u64>,
+}
+
+/// Args of fs_apply_patch
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ApplyPatchArgs {
+    /// Absolute path of an existing file
+    pub path: String,
+    /// Unified diff
+    pub patch: String,
+}
+
+/// Args of fs_restore_from_trash
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RestoreFromTrashArgs {
+    /// Original absolute path to restore to
+    pub path: String,
+    /// Item inside the trash; looked up from the trash metadata when omitted
+    pub trash_path: Option<String>,
+}
+
+/// Args of fs_search; at least one of `name`, `glob` and `content` is required
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SearchArgs {
+    /// Absolute directory to search below
+    pub path: String,
+    /// Words the file name should contain, in any case, e.g. "acme invoice"
+    pub name: Option<String>,
+    /// Glob on the file name, e.g. "*.pdf"; with a `/` it matches the path below `path`
+    pub glob: Option<String>,
+    /// Regex the file contents must match
+    pub content: Option<String>,
+    /// Match `content` case-sensitively (default false)
+    pub case_sensitive: Option<bool>,
+    /// Most results to return, best first
+    pub max_results: Option<usize>,
+    /// How many directory levels below `path` to search; 0 searches only `path` itself
+    pub max_depth: Option<usize>,
+    /// Also search hidden files and directories
+    pub include_hidden: Option<bool>,
+    /// Globs of files and directories to skip, in addition to .gitignore rules
+    pub exclude: Option<Vec<String>>,
+    /// Include directories whose name matches, not only files
+    pub include_directories: Option<bool>,
+}
+
+/// Args of fs_stat
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StatArgs {
+    /// Absolute path of a file or directory
+    pub path: String,
+    /// Also compute the SHA-256 of a file's contents
+    pub hash: Option<bool>,
+}
+
+/// Args of archive_create
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveCreateArgs {
+    /// Absolute path of the file or directory to pack; a directory is stored under its own name
+    pub source_path: String,
+    /// Absolute path of the archive to write
+    pub destination_path: String,
+    /// What to do if the destination exists: fail (default), overwrite (snapshotted first so undo restores it), rename (numbered suffix, e.g. "report (1).pdf") or skip
+    pub on_conflict: Option<OnConflict>,
+    /// zip or tar.gz; taken from the destination's extension when omitted
+    pub format: Option<archive::Format>,
+    /// Globs of the files to pack from a directory, e.g. ["*.pdf"]; with a `/` they match the path below it
+    pub include: Option<Vec<String>>,
+}
+
+/// Args of archive_extract
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveExtractArgs {
+    /// Absolute path of the archive
+    pub source_path: String,
+    /// Absolute directory to unpack into; created if missing
+    pub destination_path: String,
+    /// zip or tar.gz; taken from the archive's extension when omitted
+    pub format: Option<archive::Format>,
+}
+
+/// Files in a directory, selected by name, date and size.
+/// Expanded into one action per file before the plan is previewed.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Selector {
+    /// Absolute directory to select files from
+    pub directory: String,
+    /// Glob on the file name, e.g. "*.png"; with a `/` it matches the path below `directory`
+    pub glob: Option<String>,
+    /// File extensions without the dot, e.g. ["jpg", "png"]
+    pub extensions: Option<Vec<String>>,
+    /// Only files last modified before this date (RFC 3339 or YYYY-MM-DD)
+    pub modified_before: Option<String>,
+    /// Only files last modified after this date (RFC 3339 or YYYY-MM-DD)
+    pub modified_after: Option<String>,
+    /// Smallest file size in bytes
+    pub min_size: Option<u64>,
+    /// Largest file size in bytes
+    pub max_size: Option<u64>,
+    /// Also select files in subdirectories
+    pub recursive: Option<bool>,
+}
+
+/// Args of fs_copy_file and fs_move_file for many files at once
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BulkTransferArgs {
+    /// Files to copy or move, instead of `source_path`
+    pub select: Selector,
+    /// Absolute directory the selected files go into
+    pub destination_path: String,
+    /// What to do with a file that already exists in the destination: fail (default), overwrite (snapshotted first so undo restores it), rename (numbered suffix, e.g. "report (1).pdf") or skip
+    pub on_conflict: Option<OnConflict>,
+}
+
+/// Args of fs_delete_file for many files at once
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BulkDeleteArgs {
+    /// Files to delete, instead of `path`
+    pub select: Selector,
+    /// Skip the trash; only when the user explicitly asks
+    pub permanent: Option<bool>,
+}
+
+/// Action types that accept a `select` arg in place of a single path
+pub fn accepts_selector(action_type: &ActionType) -> bool {
+    matches!(action_type, ActionType::FsCopyFile | ActionType::FsMoveFile | ActionType::FsDeleteFile)
+}
+
+/// Deserialize an action's args into its typed struct.
+/// Errors name the offending field, e.g. `args.offset: invalid type: ...`.
+pub fn parse_args<T: DeserializeOwned>(action: &Action) -> Result<T, String> {
+    parse_arg_map(&action.args)
+}
+
+fn parse_arg_map<T: DeserializeOwned>(args: &HashMap<String, serde_json::Value>) -> Result<T, String> {
+    let value = serde_json::Value::Object(args.clone().into_iter().collect());
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        let path = e.path().to_string();
+        if path == "." {
+            format!("args: {}", e.inner())
+        } else {
+            format!("args.{}: {}", path, e.inner())
+        }
+    })
+}
+
+/// Check args against the typed struct of the action type:
+/// unknown keys, wrong types, unknown enum values and missing required args are all rejected
+pub fn validate_args(action_type: &ActionType, args: &HashMap<String, serde_json::Value>) -> Result<(), String> {
+    match action_type {
+        ActionType::FsCreateFile => parse_arg_map::<CreateFileArgs>(args).map(drop),
+        ActionType::FsReadFile => parse_arg_map::<ReadFileArgs>(args).map(drop),
+        ActionType::FsCopyFile | ActionType::FsMoveFile if args.contains_key("select") => {
+            parse_arg_map::<BulkTransferArgs>(args).map(drop)
+        }
+        ActionType::FsCopyFile
+        | ActionType::FsMoveFile
+        | ActionType::FsCopyDirectory
+        | ActionType::FsMoveDirectory => parse_arg_map::<TransferArgs>(args).map(drop),
+        ActionType::FsDeleteFile if args.contains_key("select") => parse_arg_map::<BulkDeleteArgs>(args).map(drop),
+        ActionType::FsDeleteFile | ActionType::FsDeleteDirectory => parse_arg_map::<DeleteArgs>(args).map(drop),
+        ActionType::FsCreateDirectory => parse_arg_map::<CreateDirectoryArgs>(args).map(drop),
+        ActionType::FsListDirectory => parse_arg_map::<ListDirectoryArgs>(args).map(drop),
+        ActionType::FsAppendFile => parse_arg_map::<AppendFileArgs>(args).map(drop),
+        ActionType::FsReplaceText => parse_arg_map::<ReplaceTextArgs>(args).map(drop),
+        ActionType::FsApplyPatch => parse_arg_map::<ApplyPatchArgs>(args).map(drop),
+        ActionType::FsRestoreFromTrash => parse_arg_map::<RestoreFromTrashArgs>(args).map(drop),
+        ActionType::FsSearch => {
+            let search: SearchArgs = parse_arg_map(args)?;
+            if search.name.is_none() && search.glob.is_none() && search.content.is_none() {
+                return Err("args: fs_search needs at least one of `name`, `glob` or `content`".to_string());
+            }
+            Ok(())
+        }
+        ActionType::FsStat => parse_arg_map::<StatArgs>(args).map(drop),
+        ActionType::ArchiveCreate => parse_arg_map::<ArchiveCreateArgs>(args).map(drop),
+        ActionType::ArchiveExtract => parse_arg_map::<ArchiveExtractArgs>(args).map(drop),
+    }
+}
+
+/// Schema of the typed args of an action type
+fn args_schema(generator: &mut SchemaGenerator, action_type: &ActionType) -> Schema {
+    match action_type {
+        ActionType::FsCreateFile => generator.subschema_for::<CreateFileArgs>(),
+        ActionType::FsReadFile => generator.subschema_for::<ReadFileArgs>(),
+        ActionType::FsCopyFile | ActionType::FsMoveFile => {
+            let single = generator.subschema_for::<TransferArgs>();
+            let bulk = generator.subschema_for::<BulkTransferArgs>();
+            json_schema!({ "oneOf": [single, bulk] })
+        }
+        ActionType::FsCopyDirectory | ActionType::FsMoveDirectory => generator.subschema_for::<TransferArgs>(),
+        ActionType::FsDeleteFile => {
+            let single = generator.subschema_for::<DeleteArgs>();
+            let bulk = generator.subschema_for::<BulkDeleteArgs>();
+            json_schema!({ "oneOf": [single, bulk] })
+        }
+        ActionType::FsDeleteDirectory => generator.subschema_for::<DeleteArgs>(),
+        ActionType::FsCreateDirectory => generator.subschema_for::<CreateDirectoryArgs>(),
+        ActionType::FsListDirectory => generator.subschema_for::<ListDirectoryArgs>(),
+        ActionType::FsAppendFile => generator.subschema_for::<AppendFileArgs>(),
+        ActionType::FsReplaceText => generator.subschema_for::<ReplaceTextArgs>(),
+        ActionType::FsApplyPatch => generator.subschema_for::<ApplyPatchArgs>(),
+        ActionType::FsRestoreFromTrash => generator.subschema_for::<RestoreFromTrashArgs>(),
+        ActionType::FsSearch => generator.subschema_for::<SearchArgs>(),
+        ActionType::FsStat => generator.subschema_for::<StatArgs>(),
+        ActionType::ArchiveCreate => generator.subschema_for::<ArchiveCreateArgs>(),
+        ActionType::ArchiveExtract => generator.subschema_for::<ArchiveExtractArgs>(),
+    }
+}
+
+/// An action is one of the action types, each with its own args
+impl JsonSchema for Action {
+    fn schema_name() -> Cow<'static, str> {
+        "Action".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let variants: Vec<serde_json::Value> = ActionType::ALL
+            .iter()
+            .map(|action_type| {
+                serde_json::json!({
+                    "type": "object",
+                    "description": action_type.description(),
+                    "properties": {
+                        "id": { "type": "string" },
+                        "type": { "const": action_type.name() },
+                        "args": args_schema(generator, action_type),
+                        "preconditions": generator.subschema_for::<Option<Preconditions>>(),
+                        "metadata": generator.subschema_for::<Option<ActionMetadata>>(),
+                    },
+                    "required": ["id", "type", "args"],
+                })
+            })
+            .collect();
+
+        json_schema!({ "oneOf": variants })
+    }
+}
+
+/// JSON Schema (draft 2020-12) of Action Schema v2 plans
+pub fn action_plan_schema() -> serde_json::Value {
+    SchemaSettings::draft2020_12()
+        .for_deserialize()
+        .into_generator()
+        .into_root_schema_for::<ActionPlan>()
+        .to_value()
+}
+
+/// Type of a schema, looking through optional and enum wrappers:
+/// optional fields are typed as e.g. ["string", "null"] or any of an enum and null
+fn schema_kind(schema: &serde_json::Value) -> Option<&str> {
+    match schema.get("type") {
+        Some(serde_json::Value::String(kind)) => return Some(kind),
+        Some(serde_json::Value::Array(kinds)) => return kinds.iter().filter_map(|k| k.as_str()).find(|k| *k != "null"),
+        _ => {}
+    }
+    ["anyOf", "oneOf"]
+        .iter()
+        .filter_map(|key| schema.get(*key).and_then(|v| v.as_array()))
+        .flatten()
+        .filter_map(schema_kind)
+        .find(|kind| *kind != "null")
+}
+
+/// Argument of an action type, as described to the planner
+#[derive(Debug, Clone)]
+pub struct ArgDescription {
+    pub name: String,
+    pub kind: String,
+    pub required: bool,
+    pub description: String,
+}
+
+/// Arguments of an action type, read from its generated schema
+pub fn describe_args(action_type: &ActionType) -> Vec<ArgDescription> {
+    let mut generator = SchemaSettings::draft2020_12()
+        .with(|settings| settings.inline_subschemas = true)
+        .into_generator();
+    let schema = args_schema(&mut generator, action_type);
+
+    // Types with a single and a bulk form list the args of both;
+    // an arg is only required if every form requires it
+    let variants: Vec<&serde_json::Value> = match schema.get("oneOf").and_then(|v| v.as_array()) {
+        Some(variants) => variants.iter().collect(),
+        None => vec![schema.as_value()],
+    };
+
+    let required_in = |variant: &serde_json::Value, name: &str| {
+        variant
+            .get("required")
+            .and_then(|v| v.as_array())
+            .is_some_and(|names| names.iter().any(|n| n.as_str() == Some(name)))
+    };
+
+    let mut properties: Vec<(&String, &serde_json::Value)> = Vec::new();
+    for variant in &variants {
+        if let Some(props) = variant.get("properties").and_then(|v| v.as_object()) {
+            for (name, property) in props {
+                if !properties.iter().any(|(seen, _)| *seen == name) {
+                    properties.push((name, property));
+                }
+            }
+        }
+    }
+
+    let mut args: Vec<ArgDescription> = properties
+        .into_iter()
+        .map(|(name, property)| {
+            let kind = schema_kind(property).unwrap_or("any").to_string();
+
+            ArgDescription {
+                name: name.clone(),
+                kind,
+                required: variants.iter().all(|variant| required_in(variant, name)),
+                description: property
+                    .get("description")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string(),
+            }
+        })
+        .collect();
+
+    // Required args first
+    args.sort_by_key(|arg| !arg.required);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(pairs: &[(&str, &str)]) -> HashMap<String, serde_json::Value> {
+        pairs.iter().map(|(k, v)| (k.to_string(), serde_json::json!(v))).collect()
+    }
+
+    #[test]
+    fn unknown_conflict_policy_is_rejected() {
+        let copy = |policy| args(&[("source_path", "/a"), ("destination_path", "/b"), ("on_conflict", policy)]);
+        assert!(validate_args(&ActionType::FsCopyFile, &copy("rename")).is_ok());
+        let err = validate_args(&ActionType::FsCopyFile, &copy("clobber")).unwrap_err();
+        assert!(err.starts_with("args.on_conflict: unknown variant `clobber`"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_encoding_is_rejected() {
+        let create = |encoding| args(&[("path", "/a"), ("content", "x"), ("encoding", encoding)]);
+        assert!(validate_args(&ActionType::FsCreateFile, &create("latin1")).is_ok());
+        let err = validate_args(&ActionType::FsCreateFile, &create("utf-16")).unwrap_err();
+        assert!(err.starts_with("args.encoding: unknown variant `utf-16`"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_archive_format_is_rejected() {
+        let extract = |format| args(&[("source_path", "/a.bin"), ("destination_path", "/out"), ("format", format)]);
+        assert!(validate_args(&ActionType::ArchiveExtract, &extract("tgz")).is_ok());
+        let err = validate_args(&ActionType::ArchiveExtract, &extract("rar")).unwrap_err();
+        assert!(err.starts_with("args.format: unknown variant `rar`"), "{}", err);
+    }
+
+    #[test]
+    fn enum_args_are_described_as_strings() {
+        let described = describe_args(&ActionType::ArchiveCreate);
+        let kind = |name: &str| described.iter().find(|arg| arg.name == name).map(|arg| arg.kind.as_str());
+        assert_eq!(kind("on_conflict"), Some("string"));
+        assert_eq!(kind("format"), Some("string"));
+    }
+}
@@ -0,0 +1,149 @@
+use crate::assistant::planner::resolver::PathResolver;
+use crate::assistant::types::*;
+use crate::assistant::validator;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+/// User-configurable assistant settings, stored as JSON in the app data directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssistantSettings {
+    /// Directories the assistant may write to (defaults to the user's home)
+    pub allowed_roots: Vec<String>,
+    /// Largest directory tree a single action may copy, delete or list
+    pub directory_limits: DirectoryLimits,
+    /// Largest chunk of a file a single read returns
+    pub file_limits: FileLimits,
+    /// Most files a single selector may expand to
+    pub selector_limits: SelectorLimits,
+    /// Most results, entries walked, bytes read per file and time a single search may take
+    pub search_limits: SearchLimits,
+    /// Most entries, bytes and compression ratio an archive may unpack to
+    pub archive_limits: ArchiveLimits,
+    /// Most age and space snapshots for undo may take, and whether they are compressed
+    pub snapshot_limits: SnapshotLimits,
+    /// Folder names the user can use at the start of a path, e.g. "projects" -> "~/code"
+    pub path_aliases: BTreeMap<String, String>,
+}
+
+impl Default for AssistantSettings {
+    fn default() -> Self {
+        Self {
+            allowed_roots: dirs::home_dir()
+                .map(|home| vec![home.to_string_lossy().to_string()])
+                .unwrap_or_default(),
+            directory_limits: DirectoryLimits::default(),
+            file_limits: FileLimits::default(),
+            selector_limits: SelectorLimits::default(),
+            search_limits: SearchLimits::default(),
+            archive_limits: ArchiveLimits::default(),
+            snapshot_limits: SnapshotLimits::default(),
+            path_aliases: BTreeMap::new(),
+        }
+    }
+}
+
+fn get_settings_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
+
+    fs::create_dir_all(&app_data_dir)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+
+    Ok(app_data_dir.join("assistant_settings.json"))
+}
+
+/// Load settings, falling back to defaults if none were saved yet
+pub fn load(app: &AppHandle) -> Result<AssistantSettings, String> {
+    let settings_path = get_settings_path(app)?;
+
+    if !settings_path.exists() {
+        return Ok(AssistantSettings::default());
+    }
+
+    let content = fs::read_to_string(&settings_path)
+        .map_err(|e| format!("Failed to read assistant settings: {}", e))?;
+
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse assistant settings: {}", e))
+}
+
+/// Persist settings
+pub fn save(app: &AppHandle, settings: &AssistantSettings) -> Result<(), String> {
+    let settings_path = get_settings_path(app)?;
+
+    let content = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize assistant settings: {}", e))?;
+
+    fs::write(&settings_path, content)
+        .map_err(|e| format!("Failed to write assistant settings: {}", e))
+}
+
+/// Build the execution context from the saved settings. A plan's escalated paths
+/// are only added once the user approves them outside the webview.
+/// The app's own data and config directories are always off limits.
+pub fn execution_context(app: &AppHandle) -> Result<ExecutionContext, String> {
+    let user_home = dirs::home_dir()
+        .ok_or("Failed to get user home directory".to_string())?;
+    let settings = load(app)?;
+
+    let mut ctx = ExecutionContext::new(user_home)
+        .with_allowed_paths(settings.allowed_roots.iter().map(PathBuf::from).collect())
+        .with_directory_limits(settings.directory_limits)
+        .with_file_limits(settings.file_limits)
+        .with_selector_limits(settings.selector_limits)
+        .with_search_limits(settings.search_limits)
+        .with_archive_limits(settings.archive_limits);
+
+    for dir in app_dirs(app) {
+        ctx = ctx.with_denied_path(dir);
+    }
+
+    Ok(ctx)
+}
+
+/// The app's own data and config directories
+fn app_dirs(app: &AppHandle) -> Vec<PathBuf> {
+    [app.path().app_data_dir(), app.path().app_config_dir()]
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect()
+}
+
+/// Refuse an allowed folder that would open up the whole filesystem or a protected location
+pub fn check_allowed_root(app: &AppHandle, root: &str) -> Result<(), String> {
+    let user_home = dirs::home_dir()
+        .ok_or("Failed to get user home directory".to_string())?;
+    let path = Path::new(root);
+
+    if !path.is_absolute() {
+        return Err(format!("Allowed folder must be an absolute path: {}", root));
+    }
+    let resolved = validator::resolve_real_path(path);
+    if resolved.parent().is_none() {
+        return Err(format!("{} is the filesystem root and cannot be an allowed folder", root));
+    }
+
+    let protected = validator::sensitive_paths(&user_home).into_iter().chain(app_dirs(app));
+    for denied in protected {
+        if resolved.starts_with(validator::resolve_real_path(&denied)) {
+            return Err(format!("{} is inside protected location {}", root, denied.display()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Path resolver for the planners, with the user's folder aliases
+pub fn path_resolver(app: &AppHandle) -> Result<PathResolver, String> {
+    let user_home = dirs::home_dir()
+        .ok_or("Failed to get user home directory".to_string())?;
+    let settings = load(app)?;
+
+    Ok(PathResolver::new(user_home).with_aliases(&settings.path_aliases))
+}
@@ -0,0 +1,51 @@
+use rusqlite::Connection;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+/// Database file shared with the frontend (`sqlite:ghost.db` in tauri-plugin-sql)
+const DB_FILE: &str = "ghost.db";
+
+/// Schema for the assistant tables (same files the SQL plugin migrates with)
+const ASSISTANT_SCHEMA: &[&str] = &[
+    include_str!("../db/migrations/assistant.sql"),
+    include_str!("../db/migrations/assistant-journal.sql"),
+    include_str!("../db/migrations/assistant-snapshots.sql"),
+];
+
+/// Resolve the path of the shared SQLite database.
+/// tauri-plugin-sql resolves `sqlite:` URLs relative to the app config directory.
+pub fn db_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let config_dir = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| format!("Failed to get app config directory: {}", e))?;
+
+    std::fs::create_dir_all(&config_dir)
+        .map_err(|e| format!("Failed to create app config directory: {}", e))?;
+
+    Ok(config_dir.join(DB_FILE))
+}
+
+/// Open a connection to the assistant tables.
+/// The schema is applied idempotently so the Rust side works even if the
+/// frontend has not loaded the database yet.
+pub fn open(app: &AppHandle) -> Result<Connection, String> {
+    open_at(&db_path(app)?)
+}
+
+/// Open a connection to the assistant tables at an explicit path
+pub fn open_at(path: &std::path::Path) -> Result<Connection, String> {
+    let conn = Connection::open(path)
+        .map_err(|e| format!("Failed to open database: {}", e))?;
+
+    // The frontend holds its own connection pool to the same file
+    conn.busy_timeout(std::time::Duration::from_secs(5))
+        .map_err(|e| format!("Failed to configure database: {}", e))?;
+
+    for schema in ASSISTANT_SCHEMA {
+        conn.execute_batch(schema)
+            .map_err(|e| format!("Failed to apply assistant schema: {}", e))?;
+    }
+
+    Ok(conn)
+}
@@ -1,8 +1,9 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// Action Schema v2 - Canonical action representation
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ActionSchema {
     pub id: String,
     pub origin: ActionOrigin,
@@ -10,10 +11,12 @@ pub struct ActionSchema {
     pub summary: String,
     pub risk_score: f64,
     pub dry_run: bool,
+    #[serde(default)]
+    pub escalated_paths: Vec<String>, // Paths outside allowed roots the user approved for this plan
 }
 
 /// Origin/provenance of an action request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ActionOrigin {
     pub user_input: String,
     pub source: ActionSource,
@@ -21,7 +24,7 @@ pub struct ActionOrigin {
 }
 
 /// Source of the action request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum ActionSource {
     #[serde(rename = "ui")]
     Ui,
@@ -40,7 +43,7 @@ pub struct Action {
     #[serde(rename = "type")]
     pub action_type: ActionType,
     pub args: HashMap<String, serde_json::Value>,
-    pub preconditions: Option<Precondition>,
+    pub preconditions: Option<Preconditions>,
     pub metadata: Option<ActionMetadata>,
 }
 
@@ -54,25 +57,225 @@ pub enum ActionType {
     FsMoveFile,
     FsDeleteFile,
     FsCreateDirectory,
+    FsListDirectory,
+    FsCopyDirectory,
+    FsMoveDirectory,
+    FsDeleteDirectory,
+    FsAppendFile,
+    FsReplaceText,
+    FsApplyPatch,
+    FsRestoreFromTrash,
+    FsSearch,
+    FsStat,
+    ArchiveCreate,
+    ArchiveExtract,
 }
 
-/// Preconditions for action execution
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ActionType {
+    /// Every supported action type, in the order they are described to the planner
+    pub const ALL: &'static [ActionType] = &[
+        ActionType::FsCreateFile,
+        ActionType::FsReadFile,
+        ActionType::FsCopyFile,
+        ActionType::FsMoveFile,
+        ActionType::FsDeleteFile,
+        ActionType::FsCreateDirectory,
+        ActionType::FsListDirectory,
+        ActionType::FsCopyDirectory,
+        ActionType::FsMoveDirectory,
+        ActionType::FsDeleteDirectory,
+        ActionType::FsAppendFile,
+        ActionType::FsReplaceText,
+        ActionType::FsApplyPatch,
+        ActionType::FsRestoreFromTrash,
+        ActionType::FsSearch,
+        ActionType::FsStat,
+        ActionType::ArchiveCreate,
+        ActionType::ArchiveExtract,
+    ];
+
+    /// Name used in plans, e.g. `fs_create_file`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActionType::FsCreateFile => "fs_create_file",
+            ActionType::FsReadFile => "fs_read_file",
+            ActionType::FsCopyFile => "fs_copy_file",
+            ActionType::FsMoveFile => "fs_move_file",
+            ActionType::FsDeleteFile => "fs_delete_file",
+            ActionType::FsCreateDirectory => "fs_create_directory",
+            ActionType::FsListDirectory => "fs_list_directory",
+            ActionType::FsCopyDirectory => "fs_copy_directory",
+            ActionType::FsMoveDirectory => "fs_move_directory",
+            ActionType::FsDeleteDirectory => "fs_delete_directory",
+            ActionType::FsAppendFile => "fs_append_file",
+            ActionType::FsReplaceText => "fs_replace_text",
+            ActionType::FsApplyPatch => "fs_apply_patch",
+            ActionType::FsRestoreFromTrash => "fs_restore_from_trash",
+            ActionType::FsSearch => "fs_search",
+            ActionType::FsStat => "fs_stat",
+            ActionType::ArchiveCreate => "archive_create",
+            ActionType::ArchiveExtract => "archive_extract",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|t| t.name() == name).cloned()
+    }
+
+    /// One-line description for the planning prompt and the JSON Schema
+    pub fn description(&self) -> &'static str {
+        match self {
+            ActionType::FsCreateFile => "Create or overwrite a file",
+            ActionType::FsReadFile => "Read a file, optionally a byte range",
+            ActionType::FsCopyFile => "Copy a file",
+            ActionType::FsMoveFile => "Move or rename a file",
+            ActionType::FsDeleteFile => "Move a file to the trash",
+            ActionType::FsCreateDirectory => "Create a directory and any missing parents",
+            ActionType::FsListDirectory => "List a directory",
+            ActionType::FsCopyDirectory => "Copy a directory tree to a new location",
+            ActionType::FsMoveDirectory => "Move or rename a directory",
+            ActionType::FsDeleteDirectory => "Move a directory tree to the trash",
+            ActionType::FsAppendFile => "Append text to an existing file",
+            ActionType::FsReplaceText => "Replace text in an existing file",
+            ActionType::FsApplyPatch => "Apply a unified diff to an existing file",
+            ActionType::FsRestoreFromTrash => "Restore a trashed item to its original path",
+            ActionType::FsSearch => "Find files by name, glob or content; the best match is output as `path`",
+            ActionType::FsStat => "Read the metadata of a file or directory",
+            ActionType::ArchiveCreate => "Pack a file or directory into a .zip or .tar.gz archive",
+            ActionType::ArchiveExtract => "Unpack a .zip or .tar.gz archive into a directory",
+        }
+    }
+}
+
+/// What a copy, move or archive does when its destination already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Stop the plan before anything is written
+    #[default]
+    Fail,
+    /// Replace the destination; the executor snapshots it first so undo brings it back
+    Overwrite,
+    /// Write beside it under a numbered name, e.g. `report (1).pdf`
+    Rename,
+    /// Leave the destination as it is and do nothing
+    Skip,
+}
+
+impl OnConflict {
+    pub const ALL: &'static [OnConflict] = &[
+        OnConflict::Fail,
+        OnConflict::Overwrite,
+        OnConflict::Rename,
+        OnConflict::Skip,
+    ];
+
+    /// Name used in the `on_conflict` arg
+    pub fn name(self) -> &'static str {
+        match self {
+            OnConflict::Fail => "fail",
+            OnConflict::Overwrite => "overwrite",
+            OnConflict::Rename => "rename",
+            OnConflict::Skip => "skip",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Self::ALL
+            .iter()
+            .find(|policy| policy.name() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown conflict policy '{}'; use fail, overwrite, rename or skip", name))
+    }
+
+    /// The policy in an action's `on_conflict` arg, fail when it has none
+    pub fn of(action: &Action) -> Result<Self, String> {
+        match action.args.get("on_conflict").and_then(|v| v.as_str()) {
+            Some(name) => Self::parse(name),
+            None => Ok(OnConflict::Fail),
+        }
+    }
+}
+
+/// Preconditions for action execution, checked against one path argument
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Precondition {
     pub writable: Option<bool>,
     pub readable: Option<bool>,
     pub exists: Option<bool>,
     pub directory: Option<bool>,
+    /// Expected SHA-256 of the file content, hex encoded
+    pub sha256: Option<String>,
+    /// Largest size in bytes the file may have
+    pub max_size: Option<u64>,
+}
+
+/// Preconditions of an action: a set per path argument (e.g. `source_path`),
+/// or one set that every path argument must meet
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Preconditions {
+    PerArg(BTreeMap<String, Precondition>),
+    All(Precondition),
+}
+
+impl Preconditions {
+    /// Preconditions on a single path argument
+    pub fn on(arg: &str, precondition: Precondition) -> Self {
+        Preconditions::PerArg(BTreeMap::from([(arg.to_string(), precondition)]))
+    }
+
+    /// Add preconditions on another path argument; `All` already covers every argument
+    pub fn and(self, arg: &str, precondition: Precondition) -> Self {
+        match self {
+            Preconditions::PerArg(mut map) => {
+                map.insert(arg.to_string(), precondition);
+                Preconditions::PerArg(map)
+            }
+            all => all,
+        }
+    }
+
+    pub fn for_arg(&self, arg: &str) -> Option<&Precondition> {
+        match self {
+            Preconditions::PerArg(map) => map.get(arg),
+            Preconditions::All(precondition) => Some(precondition),
+        }
+    }
+}
+
+/// An object of objects is per argument; anything else must be a single `Precondition`.
+/// Decided up front so errors name the offending field instead of "no variant matched".
+impl<'de> Deserialize<'de> for Preconditions {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let per_arg = value
+            .as_object()
+            .is_some_and(|map| !map.is_empty() && map.values().all(|v| v.is_object()));
+
+        if per_arg {
+            serde_json::from_value(value).map(Preconditions::PerArg).map_err(D::Error::custom)
+        } else {
+            serde_json::from_value(value).map(Preconditions::All).map_err(D::Error::custom)
+        }
+    }
 }
 
 /// Action metadata
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ActionMetadata {
     pub confidence: Option<f64>,
+    /// Paths to choose from, by path arg, when what the user typed matched several
+    pub path_candidates: Option<BTreeMap<String, Vec<String>>>,
+    /// The id the planner gave the step; `id` itself is always generated
+    pub step_alias: Option<String>,
 }
 
 /// Complete action plan (before verification)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ActionPlan {
     #[serde(flatten)]
     pub schema: ActionSchema,
@@ -81,9 +284,11 @@ pub struct ActionPlan {
 /// Verified action plan (after verification)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VerifiedPlan {
-    pub plan: ActionPlan,
+    pub plan: ActionPlan, // risk_score holds the computed score, not the planner's
     pub verified_at: i64,
     pub verification_notes: Vec<String>,
+    pub risk_level: RiskScore,
+    pub approval: Approval,
 }
 
 /// Result of executing an action plan
@@ -116,7 +321,50 @@ pub struct PreviewResult {
     pub affected_items: Vec<AffectedItem>,
     pub warnings: Vec<String>,
     pub requires_explicit_confirmation: bool,
-    pub missing_paths: Vec<String>, // Action IDs that need path input
+    pub approval: Approval,
+    pub missing_paths: Vec<MissingPath>,
+    /// How each use of an earlier step's output resolves
+    pub references: Vec<ReferencePreview>,
+    /// Destinations that already exist and what each action will do about them
+    pub conflicts: Vec<ConflictPreview>,
+    /// Paths outside the allowed folders, usable only once approved for this plan
+    pub escalations: Vec<String>,
+}
+
+/// A destination that already exists when its step would run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictPreview {
+    pub action_id: String,
+    /// The destination as planned
+    pub path: String,
+    /// fail, overwrite, rename or skip
+    pub on_conflict: String,
+    /// Where the action writes instead, when it renames
+    pub renamed_to: Option<String>,
+}
+
+/// A step reference in an arg and what it stands for as the disk is now
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferencePreview {
+    pub action_id: String,
+    pub arg: String,
+    /// The reference as written, e.g. `{{steps.find.output.path}}`
+    pub reference: String,
+    /// Position of the referenced step in the plan, from 1
+    pub step: usize,
+    /// The value it resolves to, if it can be resolved before the plan runs
+    pub resolved: Option<String>,
+    /// Why it cannot be resolved yet
+    pub note: Option<String>,
+}
+
+/// A path arg the user has to fill in before the plan can run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MissingPath {
+    pub action_id: String,
+    pub arg: String,
+    /// Existing paths that matched what the user typed, if any
+    pub candidates: Vec<String>,
 }
 
 /// Item affected by an action (for preview)
@@ -128,7 +376,8 @@ pub struct AffectedItem {
 }
 
 /// Risk score calculation
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RiskScore {
     Low = 0,    // 0.0-0.3: Safe operations (read, create in user dir)
     Medium = 1, // 0.3-0.7: Moderate risk (modify, copy)
@@ -157,6 +406,30 @@ impl RiskScore {
             RiskScore::Critical
         }
     }
+
+    /// Approval a plan at this level needs before it may run.
+    /// Without a person at the keyboard to confirm, critical plans need a capability token.
+    pub fn approval(&self, source: &ActionSource) -> Approval {
+        match self {
+            RiskScore::Low => Approval::Automatic,
+            RiskScore::Medium => Approval::Confirm,
+            RiskScore::High => Approval::ExplicitConfirmation,
+            RiskScore::Critical => match source {
+                ActionSource::Ui => Approval::ExplicitConfirmation,
+                _ => Approval::CapabilityToken,
+            },
+        }
+    }
+}
+
+/// How a plan must be approved before execution, from least to most strict
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Approval {
+    Automatic,            // May run without a confirmation step
+    Confirm,              // Approved from the preview
+    ExplicitConfirmation, // The user types the plan summary to approve
+    CapabilityToken,      // Execution must present a capability token covering the plan
 }
 
 /// Capability token for scoped permissions
@@ -191,22 +464,16 @@ impl Scope {
         })
     }
 
-    pub fn matches(&self, action_type: &str, resource: &str) -> bool {
+    pub fn matches(&self, action_type: &str, operation: &str, resource: &str) -> bool {
         if self.action_type != action_type {
             return false;
         }
-        // Simple glob matching (basic implementation)
-        self.resource_pattern
-            .replace("*", ".*")
-            .as_str()
-            .lines()
-            .next()
-            .map(|pattern| {
-                regex::Regex::new(&format!("^{}$", pattern))
-                    .map(|re| re.is_match(resource))
-                    .unwrap_or(false)
-            })
-            .unwrap_or(false)
+        if self.operation != "*" && self.operation != operation {
+            return false;
+        }
+        // Normalize first so "Docs/../.ssh" cannot slip past a "Docs/*" scope
+        let normalized = crate::assistant::validator::normalize_path(std::path::Path::new(resource));
+        crate::assistant::glob::matches(&self.resource_pattern, &normalized.to_string_lossy())
     }
 }
 
@@ -221,6 +488,15 @@ pub struct AuditEntry {
     pub action_id: Option<String>,
 }
 
+/// Result of walking the audit log hash chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditIntegrityReport {
+    pub valid: bool,
+    pub entries_checked: usize,
+    pub first_broken_entry: Option<String>, // ID of the first entry whose link fails
+    pub reason: Option<String>,
+}
+
 /// Action snapshot for undo functionality
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActionSnapshot {
@@ -229,23 +505,144 @@ pub struct ActionSnapshot {
     pub original_path: String,
     pub snapshot_path: String,
     pub created_at: i64,
+    /// Expiry under the retention setting when it was taken; the sweep uses the current one
     pub retention_until: i64,
 }
 
+/// Limits on directory trees that may be copied, deleted or listed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DirectoryLimits {
+    pub max_bytes: u64,
+    pub max_entries: usize,
+}
+
+impl Default for DirectoryLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 1024 * 1024 * 1024, // 1 GiB
+            max_entries: 10_000,
+        }
+    }
+}
+
+/// Limits on how much of a file a single read returns
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileLimits {
+    pub max_read_bytes: u64,
+}
+
+impl Default for FileLimits {
+    fn default() -> Self {
+        Self {
+            max_read_bytes: 4 * 1024 * 1024, // 4 MiB
+        }
+    }
+}
+
+/// Limits on how many actions a selector may expand into
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelectorLimits {
+    pub max_fan_out: usize,
+}
+
+impl Default for SelectorLimits {
+    fn default() -> Self {
+        Self { max_fan_out: 200 }
+    }
+}
+
+/// Limits on a single search, so it cannot walk or read without end
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchLimits {
+    pub max_results: usize,
+    pub max_entries: usize,
+    pub max_file_bytes: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            max_results: 50,
+            max_entries: 50_000,
+            max_file_bytes: 8 * 1024 * 1024, // 8 MiB
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// Limits on what an archive may unpack to, against zip bombs
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveLimits {
+    pub max_entries: usize,
+    pub max_bytes: u64,
+    /// Largest uncompressed-to-compressed size ratio of a single entry
+    pub max_ratio: u64,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_bytes: 1024 * 1024 * 1024, // 1 GiB
+            max_ratio: 100,
+        }
+    }
+}
+
+/// How long snapshots are kept, how much space they may take, and whether they are compressed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotLimits {
+    pub max_age_days: u64,
+    /// Total size of the snapshot store; the oldest snapshots are evicted past it
+    pub max_bytes: u64,
+    pub compress: bool,
+}
+
+impl Default for SnapshotLimits {
+    fn default() -> Self {
+        Self {
+            max_age_days: 7,
+            max_bytes: 2 * 1024 * 1024 * 1024, // 2 GiB
+            compress: true,
+        }
+    }
+}
+
 /// Execution context for actions
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionContext {
     pub capability_token: Option<CapabilityToken>,
     pub user_home: std::path::PathBuf,
     pub allowed_paths: Vec<std::path::PathBuf>,
+    pub denied_paths: Vec<std::path::PathBuf>,
+    pub escalated_paths: Vec<std::path::PathBuf>,
+    pub directory_limits: DirectoryLimits,
+    pub file_limits: FileLimits,
+    pub selector_limits: SelectorLimits,
+    pub search_limits: SearchLimits,
+    pub archive_limits: ArchiveLimits,
 }
 
 impl ExecutionContext {
     pub fn new(user_home: std::path::PathBuf) -> Self {
         Self {
             capability_token: None,
+            denied_paths: crate::assistant::validator::sensitive_paths(&user_home),
             user_home: user_home.clone(),
             allowed_paths: vec![user_home],
+            escalated_paths: Vec::new(),
+            directory_limits: DirectoryLimits::default(),
+            file_limits: FileLimits::default(),
+            selector_limits: SelectorLimits::default(),
+            search_limits: SearchLimits::default(),
+            archive_limits: ArchiveLimits::default(),
         }
     }
 
@@ -253,5 +650,44 @@ impl ExecutionContext {
         self.capability_token = Some(token);
         self
     }
-}
 
+    pub fn with_allowed_paths(mut self, allowed_paths: Vec<std::path::PathBuf>) -> Self {
+        self.allowed_paths = allowed_paths;
+        self
+    }
+
+    pub fn with_denied_path(mut self, path: std::path::PathBuf) -> Self {
+        self.denied_paths.push(path);
+        self
+    }
+
+    pub fn with_escalations(mut self, escalated_paths: Vec<std::path::PathBuf>) -> Self {
+        self.escalated_paths = escalated_paths;
+        self
+    }
+
+    pub fn with_directory_limits(mut self, directory_limits: DirectoryLimits) -> Self {
+        self.directory_limits = directory_limits;
+        self
+    }
+
+    pub fn with_file_limits(mut self, file_limits: FileLimits) -> Self {
+        self.file_limits = file_limits;
+        self
+    }
+
+    pub fn with_selector_limits(mut self, selector_limits: SelectorLimits) -> Self {
+        self.selector_limits = selector_limits;
+        self
+    }
+
+    pub fn with_search_limits(mut self, search_limits: SearchLimits) -> Self {
+        self.search_limits = search_limits;
+        self
+    }
+
+    pub fn with_archive_limits(mut self, archive_limits: ArchiveLimits) -> Self {
+        self.archive_limits = archive_limits;
+        self
+    }
+}
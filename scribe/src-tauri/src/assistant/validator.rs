@@ -1,7 +1,17 @@
+use crate::assistant::types::*;
 use std::path::{Path, PathBuf};
 
-/// Validate path to prevent traversal attacks but allow any directory on the system
-/// Only prevents malicious path traversal (too many ..) and ensures absolute paths are valid
+/// How an action touches a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathAccess {
+    Read,
+    Write,
+    Remove, // Delete, or the source of a move
+}
+
+/// Validate path structure to prevent traversal attacks
+/// Only prevents malicious path traversal (too many ..) and ensures absolute paths are valid;
+/// confinement to allowed roots is enforced by `check_access`
 pub fn validate_path(path_str: &str, _user_home: &Path) -> Result<PathBuf, String> {
     let path = Path::new(path_str);
 
@@ -71,6 +81,26 @@ pub fn validate_path(path_str: &str, _user_home: &Path) -> Result<PathBuf, Strin
     }
 }
 
+/// Lexically resolve `.` and `..` components without touching the filesystem.
+/// `..` never climbs above the root, so "/a/../../b" becomes "/b".
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => continue,
+            std::path::Component::ParentDir => match components.last() {
+                Some(std::path::Component::Normal(_)) => {
+                    components.pop();
+                }
+                Some(std::path::Component::RootDir) | Some(std::path::Component::Prefix(_)) => {}
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+    components.iter().collect()
+}
+
 /// Normalize a path by resolving .. and .
 trait NormalizePath {
     fn normalize_path(&self) -> PathBuf;
@@ -78,17 +108,158 @@ trait NormalizePath {
 
 impl NormalizePath for PathBuf {
     fn normalize_path(&self) -> PathBuf {
-        let mut components = Vec::new();
-        for component in self.components() {
-            match component {
-                std::path::Component::CurDir => continue,
-                std::path::Component::ParentDir => {
-                    components.pop();
-                }
-                _ => components.push(component),
-            }
+        normalize_path(self)
+    }
+}
+
+
+/// Built-in denylist of sensitive locations that actions may never touch
+pub fn sensitive_paths(user_home: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = [".ssh", ".gnupg", ".aws", ".kube", ".docker", ".password-store"]
+        .iter()
+        .map(|dir| user_home.join(dir))
+        .collect();
+
+    #[cfg(unix)]
+    paths.extend(
+        ["/etc", "/boot", "/bin", "/sbin", "/usr", "/lib", "/proc", "/sys", "/dev"]
+            .iter()
+            .map(PathBuf::from),
+    );
+
+    #[cfg(target_os = "macos")]
+    paths.extend(
+        ["/System", "/Library"]
+            .iter()
+            .map(PathBuf::from),
+    );
+
+    #[cfg(windows)]
+    paths.extend(
+        ["C:\\Windows", "C:\\Program Files", "C:\\Program Files (x86)", "C:\\ProgramData"]
+            .iter()
+            .map(PathBuf::from),
+    );
+
+    paths
+}
+
+/// Resolve symlinks in the existing part of a path.
+/// The deepest existing ancestor is canonicalized and the components that do
+/// not exist yet are appended, so a symlinked parent cannot hide the real target.
+pub fn resolve_real_path(path: &Path) -> PathBuf {
+    for ancestor in path.ancestors() {
+        if ancestor.as_os_str().is_empty() {
+            break;
+        }
+        if let Ok(canonical) = ancestor.canonicalize() {
+            let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+            return normalize_path(&canonical.join(rest));
+        }
+    }
+    normalize_path(path)
+}
+
+/// Path-bearing arguments of each action type and how they are accessed
+pub fn path_accesses(action_type: &ActionType) -> &'static [(&'static str, PathAccess)] {
+    match action_type {
+        ActionType::FsReadFile => &[("path", PathAccess::Read)],
+        ActionType::FsCreateFile | ActionType::FsCreateDirectory => &[("path", PathAccess::Write)],
+        ActionType::FsCopyFile => &[("source_path", PathAccess::Read), ("destination_path", PathAccess::Write)],
+        ActionType::FsMoveFile => &[("source_path", PathAccess::Remove), ("destination_path", PathAccess::Write)],
+        ActionType::FsDeleteFile => &[("path", PathAccess::Remove)],
+        ActionType::FsListDirectory | ActionType::FsSearch | ActionType::FsStat => &[("path", PathAccess::Read)],
+        ActionType::FsCopyDirectory => &[("source_path", PathAccess::Read), ("destination_path", PathAccess::Write)],
+        ActionType::FsMoveDirectory => &[("source_path", PathAccess::Remove), ("destination_path", PathAccess::Write)],
+        ActionType::FsDeleteDirectory => &[("path", PathAccess::Remove)],
+        ActionType::FsAppendFile | ActionType::FsReplaceText | ActionType::FsApplyPatch => &[("path", PathAccess::Write)],
+        ActionType::FsRestoreFromTrash => &[("path", PathAccess::Write), ("trash_path", PathAccess::Remove)],
+        ActionType::ArchiveCreate | ActionType::ArchiveExtract => {
+            &[("source_path", PathAccess::Read), ("destination_path", PathAccess::Write)]
         }
-        components.iter().collect()
     }
 }
 
+/// Check a path against the context's denylist and allowed roots.
+/// Returns the resolved path to operate on, or the reason it is refused.
+pub fn check_access(ctx: &ExecutionContext, path_str: &str, access: PathAccess) -> Result<PathBuf, String> {
+    let resolved = resolve_real_path(Path::new(path_str));
+
+    for denied in &ctx.denied_paths {
+        if resolved.starts_with(resolve_real_path(denied)) {
+            return Err(format!(
+                "{} is inside protected location {}",
+                path_str,
+                denied.display()
+            ));
+        }
+    }
+
+    if access == PathAccess::Read {
+        return Ok(resolved);
+    }
+
+    let roots: Vec<PathBuf> = ctx.allowed_paths.iter().map(|p| resolve_real_path(p)).collect();
+
+    // Removing or moving an allowed root itself would take everything under it
+    if access == PathAccess::Remove && roots.contains(&resolved) {
+        return Err(format!("{} is an allowed root and cannot be removed", path_str));
+    }
+
+    // Removing or moving a directory also takes any protected location inside it
+    if access == PathAccess::Remove {
+        check_no_protected_inside(ctx, path_str)?;
+    }
+
+    let escalated = ctx
+        .escalated_paths
+        .iter()
+        .any(|p| resolved.starts_with(resolve_real_path(p)));
+
+    if !escalated && !roots.iter().any(|root| resolved.starts_with(root)) {
+        return Err(format!(
+            "{} is outside the allowed folders; approve it for this plan to continue",
+            path_str
+        ));
+    }
+
+    Ok(resolved)
+}
+
+/// Whether a path is refused only for lying outside the allowed roots,
+/// so approving it for a plan would let the plan use it
+pub fn needs_escalation(ctx: &ExecutionContext, path_str: &str, access: PathAccess) -> bool {
+    let escalated = ctx.clone().with_escalations(vec![PathBuf::from(path_str)]);
+    check_access(ctx, path_str, access).is_err() && check_access(&escalated, path_str, access).is_ok()
+}
+
+/// Refuse a directory that contains a protected location, for operations
+/// that take the whole tree with them (recursive copy, move, delete)
+pub fn check_no_protected_inside(ctx: &ExecutionContext, path_str: &str) -> Result<(), String> {
+    let resolved = resolve_real_path(Path::new(path_str));
+
+    for denied in &ctx.denied_paths {
+        let denied_resolved = resolve_real_path(denied);
+        if denied_resolved != resolved && denied_resolved.starts_with(&resolved) {
+            return Err(format!(
+                "{} contains protected location {}",
+                path_str,
+                denied.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check every path argument of an action against the context
+pub fn check_action_paths(action: &Action, ctx: &ExecutionContext) -> Result<(), String> {
+    for (arg, access) in path_accesses(&action.action_type) {
+        if let Some(path_str) = action.args.get(*arg).and_then(|v| v.as_str()) {
+            if path_str != "__PROMPT_PATH__" {
+                check_access(ctx, path_str, *access)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -24,6 +24,20 @@ pub fn migrations() -> Vec<Migration> {
             sql: include_str!("migrations/assistant.sql"),
             kind: MigrationKind::Up,
         },
+        // Migration 4: Create write-ahead journal tables for plan execution
+        Migration {
+            version: 4,
+            description: "create_assistant_journal_tables",
+            sql: include_str!("migrations/assistant-journal.sql"),
+            kind: MigrationKind::Up,
+        },
+        // Migration 5: Create content-addressed snapshot storage tables
+        Migration {
+            version: 5,
+            description: "create_snapshot_store_tables",
+            sql: include_str!("migrations/assistant-snapshots.sql"),
+            kind: MigrationKind::Up,
+        },
     ]
 }
 
@@ -0,0 +1,20 @@
+-- Create plan_journal table: one row per plan that is currently executing
+CREATE TABLE IF NOT EXISTS plan_journal (
+    plan_id TEXT PRIMARY KEY,
+    plan_json TEXT NOT NULL,
+    started_at INTEGER NOT NULL
+);
+
+-- Create journal_steps table: intent recorded before each action runs
+CREATE TABLE IF NOT EXISTS journal_steps (
+    plan_id TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    action_id TEXT NOT NULL,
+    pre_state_json TEXT NOT NULL,
+    output_json TEXT,
+    completed INTEGER NOT NULL DEFAULT 0,
+    recorded_at INTEGER NOT NULL,
+    PRIMARY KEY (plan_id, seq)
+);
+
+CREATE INDEX IF NOT EXISTS idx_journal_steps_plan_id ON journal_steps(plan_id);
@@ -0,0 +1,22 @@
+-- Create snapshot_blobs table: content-addressed file contents shared by snapshots
+CREATE TABLE IF NOT EXISTS snapshot_blobs (
+    hash TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    stored_size INTEGER NOT NULL,
+    compressed INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+-- Create snapshot_entries table: the files, directories and symlinks saved in each snapshot
+CREATE TABLE IF NOT EXISTS snapshot_entries (
+    snapshot_id TEXT NOT NULL,
+    path TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    blob_hash TEXT,
+    mode INTEGER,
+    link_target TEXT,
+    PRIMARY KEY (snapshot_id, path)
+);
+
+CREATE INDEX IF NOT EXISTS idx_snapshot_entries_blob_hash ON snapshot_entries(blob_hash);
+CREATE INDEX IF NOT EXISTS idx_action_snapshots_created_at ON action_snapshots(created_at);
@@ -27,6 +27,12 @@ pub struct AudioState {
     is_capturing: Arc<Mutex<bool>>,
 }
 
+/// Serve assistant actions if this process was started as the sandbox worker.
+/// Returns `true` when the process ran as a worker and should exit.
+pub fn run_assistant_sandbox_worker() -> bool {
+    assistant::sandbox::run_worker_if_requested()
+}
+
 #[tauri::command]
 fn get_app_version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
@@ -163,6 +169,7 @@ pub fn run() {
         })
         .manage(shortcuts::RegisteredShortcuts::default())
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_keychain::init())
         .plugin(tauri_plugin_shell::init())
@@ -229,7 +236,12 @@ pub fn run() {
             assistant::commands::execute_action_plan,
             assistant::commands::undo_action,
             assistant::commands::get_audit_history,
+            assistant::commands::verify_audit_integrity,
             assistant::commands::mint_capability_token,
+            assistant::commands::revoke_capability_token,
+            assistant::commands::get_assistant_settings,
+            assistant::commands::update_assistant_settings,
+            assistant::commands::get_action_plan_schema,
         ])
         .setup(|app| {
             // Setup main window positioning
@@ -284,6 +296,21 @@ pub fn run() {
             if let Err(e) = shortcuts::setup_global_shortcuts(app.handle()) {
                 eprintln!("Failed to setup global shortcuts: {}", e);
             }
+
+            // Finish or roll back assistant plans interrupted by a crash,
+            // before the frontend can start new ones
+            let recovered = tauri::async_runtime::block_on(
+                assistant::executor::worker::recover_interrupted_plans(app.handle()),
+            );
+            match recovered {
+                Ok(0) => {}
+                Ok(count) => eprintln!("Recovered {} interrupted assistant plan(s)", count),
+                Err(e) => eprintln!("Failed to recover interrupted assistant plans: {}", e),
+            }
+
+            // Evict expired assistant snapshots, and the oldest when they take too much space.
+            // Started after recovery, which may still need the snapshots of interrupted plans
+            assistant::executor::snapshot::start_sweeper(app.handle().clone());
            Ok(())
         });
 
@@ -2,5 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // The assistant re-launches this binary as a confined worker for file actions
+    if ghost_lib::run_assistant_sandbox_worker() {
+        return;
+    }
     ghost_lib::run()
 }
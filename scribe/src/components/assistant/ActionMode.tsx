@@ -37,9 +37,9 @@ export function ActionMode() {
     }
   };
 
-  const handleApprove = async (plan: ActionPlan) => {
+  const handleApprove = async (plan: ActionPlan, confirmation?: string) => {
     try {
-      await executeAction(plan);
+      await executeAction(plan, undefined, confirmation);
       setInput("");
       setPreviewResult(null);
     } catch (err) {
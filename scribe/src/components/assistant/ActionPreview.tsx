@@ -7,7 +7,7 @@ import { PathPromptDialog } from "./PathPromptDialog";
 
 interface ActionPreviewProps {
   preview: PreviewResult;
-  onApprove: (plan: ActionPlan) => void;
+  onApprove: (plan: ActionPlan, confirmation?: string) => void;
   onReject: () => void;
   onEdit?: (plan: ActionPlan) => void;
   requiresExplicitConfirmation?: boolean;
@@ -28,6 +28,7 @@ export function ActionPreview({
   const [currentActionIndex, setCurrentActionIndex] = useState(0);
   const [updatedPlan, setUpdatedPlan] = useState<ActionPlan>(preview.plan);
   const [pathsProvided, setPathsProvided] = useState<Set<string>>(new Set());
+  const [allowEscalations, setAllowEscalations] = useState(false);
 
   // Open path dialog if there are missing paths
   useEffect(() => {
@@ -37,33 +38,19 @@ export function ActionPreview({
   }, [preview.missing_paths, currentActionIndex]);
 
   const handlePathProvided = (path: string) => {
-    const actionId = preview.missing_paths[currentActionIndex];
-    const action = updatedPlan.actions.find((a) => a.id === actionId);
+    const missing = preview.missing_paths[currentActionIndex];
+    const action = updatedPlan.actions.find((a) => a.id === missing.action_id);
     
     if (!action) return;
 
-        // Update the action with the provided path
-        const updatedActions = updatedPlan.actions.map((a) => {
-          if (a.id === actionId) {
-            const newArgs = { ...a.args };
-            // Replace placeholder paths
-            if (a.args.path === "__PROMPT_PATH__" || a.args.path === "[Path needed]") {
-              newArgs.path = path;
-            }
-            if (a.args.destination_path === "__PROMPT_PATH__" || a.args.destination_path === "[Destination path needed]") {
-              newArgs.destination_path = path;
-            }
-            if (a.args.source_path === "__PROMPT_PATH__") {
-              newArgs.source_path = path;
-            }
-            return { ...a, args: newArgs };
-          }
-          return a;
-        });
+    // Fill in only the arg that was missing
+    const updatedActions = updatedPlan.actions.map((a) =>
+      a.id === missing.action_id ? { ...a, args: { ...a.args, [missing.arg]: path } } : a
+    );
 
     const newPlan = { ...updatedPlan, actions: updatedActions };
     setUpdatedPlan(newPlan);
-    setPathsProvided(new Set([...pathsProvided, actionId]));
+    setPathsProvided(new Set([...pathsProvided, `${missing.action_id}:${missing.arg}`]));
 
     // Move to next missing path or close dialog
     if (currentActionIndex < preview.missing_paths.length - 1) {
@@ -73,10 +60,11 @@ export function ActionPreview({
     }
   };
 
+  const currentMissing = preview.missing_paths[currentActionIndex];
+
   const getCurrentAction = (): Action | null => {
-    if (preview.missing_paths.length === 0) return null;
-    const actionId = preview.missing_paths[currentActionIndex];
-    return updatedPlan.actions.find((a) => a.id === actionId) || null;
+    if (!currentMissing) return null;
+    return updatedPlan.actions.find((a) => a.id === currentMissing.action_id) || null;
   };
 
   const currentAction = getCurrentAction();
@@ -121,9 +109,11 @@ export function ActionPreview({
       actions: updatedPlan.actions.filter((a) =>
         selectedActions.has(a.id)
       ),
+      // The app asks again in its own dialog before these are used
+      escalated_paths: allowEscalations ? preview.escalations : [],
     };
 
-    onApprove(approvedPlan);
+    onApprove(approvedPlan, requiresExplicitConfirmation ? confirmationText : undefined);
   };
 
   const allPathsProvided = 
@@ -146,6 +136,9 @@ export function ActionPreview({
           {preview.risk_score > 0.7 && (
             <AlertTriangle className="w-4 h-4 text-red-600" />
           )}
+          {preview.approval === "capability_token" && (
+            <span className="text-xs text-red-600">Needs a capability token</span>
+          )}
         </div>
       </div>
 
@@ -178,6 +171,76 @@ export function ActionPreview({
         </div>
       )}
 
+      {preview.escalations.length > 0 && (
+        <div className="bg-red-50 border border-red-200 rounded p-3">
+          <h4 className="text-sm font-medium text-red-800 mb-2">
+            Outside the Allowed Folders:
+          </h4>
+          <ul className="text-sm text-red-700 space-y-1">
+            {preview.escalations.map((path, idx) => (
+              <li key={idx} className="font-mono text-xs">{path}</li>
+            ))}
+          </ul>
+          <label className="flex items-center gap-2 mt-2 text-sm text-red-800">
+            <input
+              type="checkbox"
+              checked={allowEscalations}
+              onChange={(e) => setAllowEscalations(e.target.checked)}
+              className="rounded"
+            />
+            Allow these paths for this plan only
+          </label>
+        </div>
+      )}
+
+      {preview.conflicts.length > 0 && (
+        <div className="bg-orange-50 border border-orange-200 rounded p-3">
+          <h4 className="text-sm font-medium text-orange-800 mb-2">
+            Destinations That Already Exist:
+          </h4>
+          <ul className="text-sm text-orange-700 space-y-1">
+            {preview.conflicts.map((conflict, idx) => (
+              <li key={idx}>
+                <span className="font-mono text-xs">{conflict.path}</span>{" "}
+                {conflict.on_conflict === "fail" && "exists, so this step will stop the plan"}
+                {conflict.on_conflict === "overwrite" && "will be replaced (saved first so it can be undone)"}
+                {conflict.on_conflict === "rename" && (
+                  <span>
+                    is kept; the new one is written as{" "}
+                    <span className="font-mono text-xs">{conflict.renamed_to}</span>
+                  </span>
+                )}
+                {conflict.on_conflict === "skip" && "is kept and this step is skipped"}
+              </li>
+            ))}
+          </ul>
+        </div>
+      )}
+
+      {preview.references.length > 0 && (
+        <div className="bg-gray-50 border rounded p-3">
+          <h4 className="text-sm font-medium mb-2">Uses Results of Earlier Steps:</h4>
+          <ul className="text-sm space-y-1">
+            {preview.references.map((ref, idx) => (
+              <li key={idx}>
+                <span className="font-mono text-xs">{ref.reference}</span>
+                {" "}in {ref.arg} (from step {ref.step}){" "}
+                {ref.resolved !== undefined && ref.resolved !== null ? (
+                  <span>
+                    resolves to <span className="font-mono text-xs">{ref.resolved}</span> now
+                  </span>
+                ) : (
+                  <span className="text-muted-foreground">{ref.note}</span>
+                )}
+              </li>
+            ))}
+          </ul>
+          <p className="text-xs text-muted-foreground mt-2">
+            References are resolved again when the plan runs.
+          </p>
+        </div>
+      )}
+
       <div className="space-y-2">
         <h4 className="text-sm font-medium">Affected Items:</h4>
         {preview.affected_items.map((item, idx) => {
@@ -200,6 +263,11 @@ export function ActionPreview({
               <div className="flex-1">
                 <p className="text-sm font-mono">{item.path}</p>
                 <p className="text-xs text-muted-foreground">{item.operation}</p>
+                {item.preview && (
+                  <pre className="mt-1 max-h-48 overflow-auto rounded bg-white p-2 text-xs">
+                    {item.preview}
+                  </pre>
+                )}
               </div>
             </div>
           );
@@ -248,7 +316,8 @@ export function ActionPreview({
           actionType={currentAction.type || "file operation"}
           onConfirm={handlePathProvided}
           isDirectory={currentAction.type?.includes("directory") || false}
-          isDestination={currentAction.args?.destination_path === "__PROMPT_PATH__"}
+          isDestination={currentMissing?.arg === "destination_path"}
+          candidates={currentMissing?.candidates ?? []}
         />
       )}
     </Card>
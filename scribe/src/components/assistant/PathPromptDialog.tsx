@@ -19,6 +19,7 @@ interface PathPromptDialogProps {
   onConfirm: (path: string) => void;
   isDirectory?: boolean;
   isDestination?: boolean;
+  candidates?: string[]; // Existing paths that matched what the user typed
 }
 
 export function PathPromptDialog({
@@ -28,6 +29,7 @@ export function PathPromptDialog({
   onConfirm,
   isDirectory = false,
   isDestination = false,
+  candidates = [],
 }: PathPromptDialogProps) {
   const [path, setPath] = useState("");
   const [error, setError] = useState<string | null>(null);
@@ -101,6 +103,26 @@ export function PathPromptDialog({
             {error && (
               <p className="text-sm text-red-600">{error}</p>
             )}
+            {candidates.length > 0 && (
+              <div className="space-y-1">
+                <p className="text-xs text-muted-foreground">Did you mean:</p>
+                {candidates.map((candidate) => (
+                  <Button
+                    key={candidate}
+                    type="button"
+                    variant={candidate === path ? "default" : "outline"}
+                    size="sm"
+                    className="w-full justify-start font-mono text-xs"
+                    onClick={() => {
+                      setPath(candidate);
+                      setError(null);
+                    }}
+                  >
+                    {candidate}
+                  </Button>
+                ))}
+              </div>
+            )}
             <p className="text-xs text-muted-foreground">
               Type or paste an absolute path (e.g., C:\Users\Name\file.txt or /home/user/file.txt)
             </p>
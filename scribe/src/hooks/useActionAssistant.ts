@@ -8,7 +8,6 @@ import type {
 } from "@/types/assistant";
 import { planWithLLM } from "@/lib/functions/action-planner.function";
 import type { Message } from "@/types";
-import { saveAuditLogCache } from "@/lib/database/action-history.action";
 
 interface ActionAssistantState {
   currentPlan: ActionPlan | null;
@@ -68,11 +67,9 @@ export function useActionAssistant() {
         let plan: ActionPlan;
 
         if (useLLM) {
-          // Use LLM planner
-          plan = await planWithLLM(input, history);
-          
-          // Validate the plan via backend
-          plan = await invoke<ActionPlan>("verify_action_plan", { plan });
+          // Use LLM planner (planned and verified by the backend)
+          const verified = await planWithLLM(input, history);
+          plan = verified.plan;
         } else {
           // Use deterministic parser
           plan = await parseIntent(input);
@@ -125,32 +122,25 @@ export function useActionAssistant() {
     []
   );
 
-  /// Execute an action plan
+  /// Execute an action plan; `confirmation` is the summary the user typed
+  /// when the preview required explicit confirmation
   const executeAction = useCallback(
-    async (plan: ActionPlan, confirmToken?: string): Promise<ActionResult> => {
+    async (
+      plan: ActionPlan,
+      confirmToken?: string,
+      confirmation?: string
+    ): Promise<ActionResult> => {
       setState((prev) => ({ ...prev, isExecuting: true, error: null }));
 
       try {
+        // Plans are planned as dry runs; running an approved plan changes disk for real
         const result = await invoke<ActionResult>("execute_action_plan", {
-          plan,
+          plan: { ...plan, dry_run: false },
           confirmToken: confirmToken || null,
+          confirmation: confirmation ?? null,
         });
 
-        // Save audit log entry to database (with hash chain)
-        try {
-          const auditEntry: AuditEntry = {
-            id: crypto.randomUUID(),
-            entry_json: JSON.stringify({ action_plan: plan, result }),
-            timestamp: Math.floor(Date.now() / 1000),
-            prev_hash: "", // Will be calculated by saveAuditLogCache from last entry
-            signature: "", // Will be calculated by saveAuditLogCache
-            action_id: plan.id,
-          };
-          await saveAuditLogCache(auditEntry);
-        } catch (auditError) {
-          console.warn("Failed to save audit log:", auditError);
-          // Don't fail the action if audit save fails
-        }
+        // Audit log entry is appended (with hash chain) by the Rust side
 
         setState((prev) => ({
           ...prev,
@@ -175,6 +165,28 @@ export function useActionAssistant() {
     []
   );
 
+  /// Simulate an action plan without touching disk; the result lists what
+  /// each step would create, overwrite or remove and where it would fail
+  const simulateAction = useCallback(
+    async (plan: ActionPlan): Promise<ActionResult> => {
+      try {
+        return await invoke<ActionResult>("execute_action_plan", {
+          plan: { ...plan, dry_run: true },
+          confirmToken: null,
+        });
+      } catch (error) {
+        const errorMsg =
+          error instanceof Error ? error.message : String(error);
+        setState((prev) => ({
+          ...prev,
+          error: errorMsg,
+        }));
+        throw new Error(errorMsg);
+      }
+    },
+    []
+  );
+
   /// Undo an action
   const undoAction = useCallback(async (actionId: string): Promise<void> => {
     try {
@@ -198,9 +210,7 @@ export function useActionAssistant() {
   const getAuditHistory = useCallback(
     async (limit: number = 50): Promise<AuditEntry[]> => {
       try {
-        // Use frontend database function instead of IPC
-        const { getAuditLogsFromDB } = await import("@/lib/database/action-history.action");
-        return await getAuditLogsFromDB(limit);
+        return await invoke<AuditEntry[]>("get_audit_history", { limit });
       } catch (error) {
         const errorMsg =
           error instanceof Error ? error.message : String(error);
@@ -223,6 +233,7 @@ export function useActionAssistant() {
     planAction,
     previewAction,
     executeAction,
+    simulateAction,
     undoAction,
     getAuditHistory,
     clearError,
@@ -97,49 +97,6 @@ export async function deleteSnapshot(snapshotId: string): Promise<void> {
   await db.execute("DELETE FROM action_snapshots WHERE id = ?", [snapshotId]);
 }
 
-/**
- * Save audit log entry with hash chain
- */
-export async function saveAuditLogCache(
-  entry: AuditEntry
-): Promise<void> {
-  const db = await getDatabase();
-
-  // Calculate prev_hash from last entry if not provided
-  let prevHash = entry.prev_hash;
-  if (!prevHash) {
-    const lastEntry = await db.select<Array<{
-      signature: string;
-    }>>(
-      "SELECT signature FROM audit_logs ORDER BY timestamp DESC LIMIT 1"
-    );
-    prevHash = lastEntry.length > 0 ? lastEntry[0].signature : "";
-  }
-
-  // Calculate signature if not provided
-  let signature = entry.signature;
-  if (!signature) {
-    // Simple hash using Web Crypto API
-    const encoder = new TextEncoder();
-    const data = encoder.encode(entry.entry_json + prevHash);
-    const hashBuffer = await crypto.subtle.digest("SHA-256", data);
-    const hashArray = Array.from(new Uint8Array(hashBuffer));
-    signature = hashArray.map((b) => b.toString(16).padStart(2, "0")).join("");
-  }
-
-  await db.execute(
-    "INSERT INTO audit_logs (id, entry_json, timestamp, prev_hash, signature, action_id) VALUES (?, ?, ?, ?, ?, ?)",
-    [
-      entry.id,
-      entry.entry_json,
-      entry.timestamp,
-      prevHash,
-      signature,
-      entry.action_id || null,
-    ]
-  );
-}
-
 /**
  * Get audit logs from database (fallback if IPC fails)
  */
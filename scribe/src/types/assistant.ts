@@ -11,10 +11,30 @@ export interface Precondition {
   readable?: boolean;
   exists?: boolean;
   directory?: boolean;
+  sha256?: string; // Expected content hash, hex
+  max_size?: number; // Bytes
+}
+
+// Per path argument (e.g. "source_path"), or one set for every path argument
+export type Preconditions = Record<string, Precondition> | Precondition;
+
+// Files selected by fs_copy_file, fs_move_file and fs_delete_file in place of a single path.
+// Preview expands a selector into one action per matching file.
+export interface Selector {
+  directory: string;
+  glob?: string; // Matched against the file name, or the relative path if it contains "/"
+  extensions?: string[];
+  modified_before?: string; // RFC 3339 or YYYY-MM-DD
+  modified_after?: string;
+  min_size?: number; // Bytes
+  max_size?: number;
+  recursive?: boolean;
 }
 
 export interface ActionMetadata {
   confidence?: number;
+  path_candidates?: Record<string, string[]>; // By path arg, when the typed path matched several
+  step_alias?: string; // Id the planner gave the step; references use the generated id
 }
 
 export type ActionType =
@@ -23,13 +43,25 @@ export type ActionType =
   | "fs_copy_file"
   | "fs_move_file"
   | "fs_delete_file"
-  | "fs_create_directory";
+  | "fs_create_directory"
+  | "fs_list_directory"
+  | "fs_copy_directory"
+  | "fs_move_directory"
+  | "fs_delete_directory"
+  | "fs_append_file"
+  | "fs_replace_text"
+  | "fs_apply_patch"
+  | "fs_restore_from_trash"
+  | "fs_search"
+  | "fs_stat"
+  | "archive_create"
+  | "archive_extract";
 
 export interface Action {
   id: string;
   type: ActionType;
   args: Record<string, any>;
-  preconditions?: Precondition;
+  preconditions?: Preconditions;
   metadata?: ActionMetadata;
 }
 
@@ -39,7 +71,8 @@ export interface ActionSchema {
   actions: Action[];
   summary: string;
   risk_score: number;
-  dry_run: boolean;
+  dry_run: boolean; // Executing a dry run only simulates it against a virtual filesystem
+  escalated_paths?: string[]; // Paths outside allowed roots approved for this plan
 }
 
 export interface ActionPlan {
@@ -48,13 +81,25 @@ export interface ActionPlan {
   actions: Action[];
   summary: string;
   risk_score: number;
-  dry_run: boolean;
+  dry_run: boolean; // Executing a dry run only simulates it against a virtual filesystem
+  escalated_paths?: string[]; // Paths outside allowed roots approved for this plan
 }
 
+export type RiskLevel = "low" | "medium" | "high" | "critical";
+
+// How a plan must be approved before it runs, set by its risk level
+export type Approval =
+  | "automatic"
+  | "confirm"
+  | "explicit_confirmation"
+  | "capability_token";
+
 export interface VerifiedPlan {
   plan: ActionPlan;
   verified_at: number;
   verification_notes: string[];
+  risk_level: RiskLevel;
+  approval: Approval;
 }
 
 export interface ActionExecutionResult {
@@ -84,10 +129,42 @@ export interface AffectedItem {
 export interface PreviewResult {
   plan: ActionPlan;
   risk_score: number;
+  approval: Approval;
   affected_items: AffectedItem[];
   warnings: string[];
   requires_explicit_confirmation: boolean;
-  missing_paths: string[]; // Action IDs that need path input
+  missing_paths: MissingPath[];
+  references: ReferencePreview[];
+  conflicts: ConflictPreview[];
+  escalations: string[]; // Paths outside the allowed folders, usable once approved for this plan
+}
+
+// How a copy, move or archive handles a destination that already exists
+export type OnConflict = "fail" | "overwrite" | "rename" | "skip";
+
+// A destination that already exists when its step would run
+export interface ConflictPreview {
+  action_id: string;
+  path: string;
+  on_conflict: OnConflict;
+  renamed_to?: string;
+}
+
+// A use of an earlier step's output ({{steps.<id>.output.<field>}}) and what it resolves to now
+export interface ReferencePreview {
+  action_id: string;
+  arg: string;
+  reference: string;
+  step: number;
+  resolved?: string;
+  note?: string;
+}
+
+// A path arg the user has to fill in, with existing paths that matched what they typed
+export interface MissingPath {
+  action_id: string;
+  arg: string;
+  candidates: string[];
 }
 
 export interface AuditEntry {
@@ -99,6 +176,13 @@ export interface AuditEntry {
   action_id?: string;
 }
 
+export interface AuditIntegrityReport {
+  valid: boolean;
+  entries_checked: number;
+  first_broken_entry?: string;
+  reason?: string;
+}
+
 export interface CapabilityToken {
   nonce: string;
   scopes: string[];
@@ -108,3 +192,45 @@ export interface CapabilityToken {
   issued_at: number;
 }
 
+export interface DirectoryLimits {
+  max_bytes: number;
+  max_entries: number;
+}
+
+export interface FileLimits {
+  max_read_bytes: number;
+}
+
+export interface SelectorLimits {
+  max_fan_out: number;
+}
+
+export interface SearchLimits {
+  max_results: number;
+  max_entries: number;
+  max_file_bytes: number;
+  timeout_ms: number;
+}
+
+export interface ArchiveLimits {
+  max_entries: number;
+  max_bytes: number;
+  max_ratio: number; // Uncompressed-to-compressed size of one entry
+}
+
+export interface SnapshotLimits {
+  max_age_days: number;
+  max_bytes: number; // Oldest snapshots are evicted past this total
+  compress: boolean;
+}
+
+export interface AssistantSettings {
+  allowed_roots: string[];
+  directory_limits: DirectoryLimits;
+  file_limits: FileLimits;
+  selector_limits: SelectorLimits;
+  search_limits: SearchLimits;
+  archive_limits: ArchiveLimits;
+  snapshot_limits: SnapshotLimits;
+  path_aliases: Record<string, string>; // e.g. { projects: "~/code" }
+}
@@ -1,6 +1,6 @@
 /**
  * Security tests for actionable assistant
- * Tests path traversal prevention and capability token validation
+ * Tests path traversal prevention and plan validation
  */
 
 import { describe, it, expect } from "vitest";
@@ -48,27 +48,6 @@ describe("Action Assistant Security", () => {
     }
   });
 
-  it("should validate capability token structure", async () => {
-    try {
-      const token = await invoke("mint_capability_token", {
-        scopes: ["fs:create:/home/user/Documents/*"],
-        ttlSeconds: 300,
-        sessionId: "test-session",
-      });
-
-      expect(token).toBeDefined();
-      expect(typeof token).toBe("string");
-      
-      // Token should be valid JSON
-      const parsed = JSON.parse(token);
-      expect(parsed.nonce).toBeDefined();
-      expect(parsed.scopes).toBeDefined();
-    } catch (error) {
-      // If minting fails, that's a problem
-      throw error;
-    }
-  });
-
   it("should reject invalid action plans", async () => {
     // Try to verify an invalid plan
     const invalidPlan: ActionPlan = {
@@ -99,5 +78,7 @@ describe("Action Assistant Security", () => {
       expect(error).toBeDefined();
     }
   });
-});
 
+  // Minting a token asks for approval in a native dialog, so token and scope
+  // checks are covered by the tests in src-tauri/src/assistant/policy.rs
+});